@@ -17,8 +17,14 @@ $(cat "${SCRIPT_DIR}/tsffs-gcc-riscv32.h")
 $(cat "${SCRIPT_DIR}/tsffs-gcc-riscv64.h")
 #elif __aarch64__
 $(cat "${SCRIPT_DIR}/tsffs-gcc-aarch64.h")
+#elif __arm__ && defined(__ARM_ARCH_PROFILE) && __ARM_ARCH_PROFILE == 'M'
+$(cat "${SCRIPT_DIR}/tsffs-gcc-cortex-m.h")
 #elif __arm__
 $(cat "${SCRIPT_DIR}/tsffs-gcc-arm32.h")
+#elif __powerpc__ || __powerpc64__
+$(cat "${SCRIPT_DIR}/tsffs-gcc-ppc.h")
+#elif __mips__
+$(cat "${SCRIPT_DIR}/tsffs-gcc-mips.h")
 #else
 #error "Unsupported platform!"
 #endif
@@ -11,8 +11,14 @@
 #include "tsffs-gcc-riscv64.h"
 #elif __aarch64__
 #include "tsffs-gcc-aarch64.h"
+#elif __arm__ && defined(__ARM_ARCH_PROFILE) && __ARM_ARCH_PROFILE == 'M'
+#include "tsffs-gcc-cortex-m.h"
 #elif __arm__
 #include "tsffs-gcc-arm32.h"
+#elif __powerpc__ || __powerpc64__
+#include "tsffs-gcc-ppc.h"
+#elif __mips__
+#include "tsffs-gcc-mips.h"
 #endif
 #endif
 
@@ -25,11 +25,26 @@ rm -f "${SCRIPT_DIR}/test_x86_64-gcc-single-file.o" || exit 0
 rm -f "${SCRIPT_DIR}/test_x86-gcc-single-file.o" || exit 0
 rm -rf "${SCRIPT_DIR}test_aarch64-clang-single-file.o" || exit 0
 rm -rf "${SCRIPT_DIR}test_arm32-clang-single-file.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_cortex-m-clang.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_cortex-m-clang-single-file.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_ppc-clang.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_ppc-clang-single-file.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_ppc64-clang.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_ppc64-clang-single-file.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_mips-clang.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_mips-clang-single-file.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_mips64-clang.o" || exit 0
+rm -rf "${SCRIPT_DIR}/test_mips64-clang-single-file.o" || exit 0
+rm -f "${SCRIPT_DIR}/test_x86_16-clang.o" || exit 0
+rm -f "${SCRIPT_DIR}/test_x86_16-clang-single-file.o" || exit 0
+rm -f "${SCRIPT_DIR}/test_x86_16-gcc.o" || exit 0
 
 echo "Testing x86_64 (single file)..."
 clang -target x86_64-unknown-linux-gnu -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_64-clang-single-file.o"
 echo "Testing i386 (single file)..."
 clang -target i386-unknown-linux-gnu -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86-clang-single-file.o"
+echo "Testing i386 16-bit (single file)..."
+clang -target i386-unknown-linux-gnu -m16 -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_16-clang-single-file.o"
 echo "Testing riscv32 (single file)..."
 clang -target riscv32-unknown-linux-gnu -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_riscv32-clang-single-file.o"
 echo "Testing riscv64 (single file)..."
@@ -38,6 +53,16 @@ echo "Testing aarch64 (single file)..."
 clang -target aarch64-unknown-linux-gnu -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_aarch64-clang-single-file.o"
 echo "Testing arm (single file)..."
 clang -target arm-unknown-linux-gnu -mfloat-abi=soft -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_arm32-clang-single-file.o"
+echo "Testing cortex-m (single file)..."
+clang -target thumbv7m-none-eabi -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_cortex-m-clang-single-file.o"
+echo "Testing ppc (single file)..."
+clang -target powerpc-unknown-linux-gnu -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_ppc-clang-single-file.o"
+echo "Testing ppc64 (single file)..."
+clang -target powerpc64-unknown-linux-gnu -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_ppc64-clang-single-file.o"
+echo "Testing mips (single file)..."
+clang -target mips-unknown-linux-gnu -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_mips-clang-single-file.o"
+echo "Testing mips64 (single file)..."
+clang -target mips64-unknown-linux-gnuabi64 -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_mips64-clang-single-file.o"
 echo "Testing x86_64 (single file, gcc)..."
 gcc -DSINGLE_FILE=1 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_64-gcc.o"
 echo "Testing i386 (single file, gcc)..."
@@ -46,6 +71,8 @@ echo "Testing x86_64 (multi file)..."
 clang -target x86_64-unknown-linux-gnu -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_64-clang.o"
 echo "Testing i386 (multi file)..."
 clang -target i386-unknown-linux-gnu -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86-clang.o"
+echo "Testing i386 16-bit (multi file)..."
+clang -target i386-unknown-linux-gnu -m16 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_16-clang.o"
 echo "Testing riscv32(multi file)..."
 clang -target riscv32-unknown-linux-gnu -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_riscv32-clang.o"
 echo "Testing riscv64(multi file)..."
@@ -54,9 +81,21 @@ echo "Testing aarch64 (multi file)..."
 clang -target aarch64-unknown-linux-gnu -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_aarch64-clang.o"
 echo "Testing arm (multi file)..."
 clang -target arm-unknown-linux-gnu -mfloat-abi=soft -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_arm32-clang.o"
+echo "Testing cortex-m (multi file)..."
+clang -target thumbv7m-none-eabi -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_cortex-m-clang.o"
+echo "Testing ppc (multi file)..."
+clang -target powerpc-unknown-linux-gnu -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_ppc-clang.o"
+echo "Testing ppc64 (multi file)..."
+clang -target powerpc64-unknown-linux-gnu -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_ppc64-clang.o"
+echo "Testing mips (multi file)..."
+clang -target mips-unknown-linux-gnu -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_mips-clang.o"
+echo "Testing mips64 (multi file)..."
+clang -target mips64-unknown-linux-gnuabi64 -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_mips64-clang.o"
 echo "Testing x86_64 (multi file, gcc)..."
 gcc -g -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_64-gcc.o"
 gcc -g "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_64-gcc"
 echo "Testing i386(multi file, gcc)..."
 gcc -g -m32 -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86-gcc.o"
+echo "Testing i386 16-bit (multi file, gcc)..."
+gcc -g -m16 -c "${SCRIPT_DIR}/test.c" -o "${SCRIPT_DIR}/test_x86_16-gcc.o"
 
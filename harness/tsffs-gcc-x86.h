@@ -3,6 +3,10 @@
 
 /// Definitions and macros for compiled-in harnessing of C and C++ target
 /// software for the X86 architecture.
+///
+/// The macros can also be used from 16-bit real mode and 16-bit protected mode
+/// code compiled with `-m16`. In 16-bit code, the testcase buffer and size
+/// pointers are offsets into the data segment.
 
 #ifndef TSFFS_H
 #define TSFFS_H
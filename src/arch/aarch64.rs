@@ -11,15 +11,55 @@ use anyhow::{anyhow, bail, Result};
 use libafl::prelude::CmpValues;
 use raw_cstr::AsRawCstr;
 use simics::api::{
-    get_interface, read_phys_memory, sys::instruction_handle_t, Access, ConfObject,
-    CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface, CycleInterface,
-    IntRegisterInterface, ProcessorInfoV2Interface,
+    get_interface, sys::instruction_handle_t, Access, ConfObject, CpuInstructionQueryInterface,
+    CpuInstrumentationSubscribeInterface, CycleInterface, IntRegisterInterface,
+    ProcessorInfoV2Interface,
+};
+use std::{ffi::CStr, mem::size_of};
+use yaxpeax_arch::{Decoder, LengthedInstruction, U8Reader};
+use yaxpeax_arm::armv8::a64::{
+    InstDecoder, Instruction, Opcode, Operand, SIMDSizeCode, ShiftStyle, SizeCode,
+};
+
+use super::{
+    decode_cache::DecodeCache, model_exceptions, ArchitectureOperations, CallingConvention,
+    Endianness,
 };
-use std::{ffi::CStr, mem::size_of, slice::from_raw_parts};
-use yaxpeax_arch::{Decoder, U8Reader};
-use yaxpeax_arm::armv8::a64::{InstDecoder, Instruction, Opcode, Operand, ShiftStyle, SizeCode};
 
-use super::ArchitectureOperations;
+/// The architecture-level names of the AArch64 exceptions, each with the names processor models use
+/// for the exception. SIMICS exception numbers differ between AArch64 processor models, so they
+/// are looked up by name when the processor is added.
+const EXCEPTIONS: &[(&str, &[&str])] = &[
+    (
+        "undefined-instruction",
+        &["Undefined_Instruction", "Undefined"],
+    ),
+    (
+        "illegal-instruction",
+        &["Undefined_Instruction", "Undefined"],
+    ),
+    (
+        "illegal-execution-state",
+        &["Illegal_Execution_State", "Illegal_State"],
+    ),
+    ("supervisor-call", &["Supervisor_Call", "SVC"]),
+    (
+        "instruction-abort",
+        &["Instruction_Abort", "Prefetch_Abort"],
+    ),
+    ("prefetch-abort", &["Instruction_Abort", "Prefetch_Abort"]),
+    ("data-abort", &["Data_Abort"]),
+    (
+        "pc-alignment-fault",
+        &["PC_Alignment_Fault", "PC_Alignment"],
+    ),
+    (
+        "sp-alignment-fault",
+        &["SP_Alignment_Fault", "SP_Alignment"],
+    ),
+    ("breakpoint", &["Breakpoint_Instruction", "BRK"]),
+    ("serror", &["SError", "System_Error"]),
+];
 
 pub(crate) struct AArch64ArchitectureOperations {
     cpu: *mut ConfObject,
@@ -30,6 +70,16 @@ pub(crate) struct AArch64ArchitectureOperations {
     #[allow(dead_code)]
     cpu_instrumentation_subscribe: CpuInstrumentationSubscribeInterface,
     cycle: CycleInterface,
+    decode_cache: DecodeCache,
+    endianness: Endianness,
+    /// The architecture-level names of the exceptions of the processor model and their numbers
+    exceptions: Vec<(&'static str, i64)>,
+    /// The address and operands of the last instruction which set flags, logged if the flags
+    /// are consumed by a conditional instruction
+    flags_cmp: Option<(u64, CmpValues)>,
+    /// The operands of the last comparison, which may be the bounds check of a jump table
+    /// index if an indirect jump follows
+    jump_table_bounds: Option<CmpValues>,
 }
 
 impl ArchitectureOperations for AArch64ArchitectureOperations {
@@ -62,6 +112,11 @@ impl ArchitectureOperations for AArch64ArchitectureOperations {
                 cpu_instruction_query: get_interface(cpu)?,
                 cpu_instrumentation_subscribe: get_interface(cpu)?,
                 cycle: get_interface(cpu)?,
+                decode_cache: DecodeCache::default(),
+                endianness: Endianness::of(&mut get_interface(cpu)?)?,
+                exceptions: model_exceptions(cpu, EXCEPTIONS),
+                flags_cmp: None,
+                jump_table_bounds: None,
             })
         } else {
             bail!("Architecture {} is not aarch64", arch);
@@ -80,6 +135,11 @@ impl ArchitectureOperations for AArch64ArchitectureOperations {
             cpu_instruction_query: get_interface(cpu)?,
             cpu_instrumentation_subscribe: get_interface(cpu)?,
             cycle: get_interface(cpu)?,
+            decode_cache: DecodeCache::default(),
+            endianness: Endianness::of(&mut get_interface(cpu)?)?,
+            exceptions: model_exceptions(cpu, EXCEPTIONS),
+            flags_cmp: None,
+            jump_table_bounds: None,
         })
     }
 
@@ -111,78 +171,111 @@ impl ArchitectureOperations for AArch64ArchitectureOperations {
         &mut self.cycle
     }
 
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        &mut self.decode_cache
+    }
+
+    fn endianness(&mut self) -> &mut Endianness {
+        &mut self.endianness
+    }
+
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        &self.exceptions
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        const REGISTERS: &[&str] = &["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7"];
+
+        if convention != CallingConvention::Default {
+            bail!("Calling convention {convention} is not supported");
+        }
+
+        if let Some(register) = REGISTERS.get(index) {
+            self.read_register(register)
+        } else {
+            let address =
+                self.read_register("sp")? + ((index - REGISTERS.len()) * size_of::<u64>()) as u64;
+            self.read_virtual_integer(address, size_of::<u64>())
+        }
+    }
+
     fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
+        let decoded = self.decode(instruction_query)?;
 
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
+        if decoded.is_edge() {
+            let source = self
+                .cpu_instruction_query
+                .logical_address(instruction_query)?;
 
-        if self.disassembler.last_was_call()
-            || self.disassembler.last_was_control_flow()
-            || self.disassembler.last_was_ret()
-        {
-            Ok(TraceEntry::builder()
-                .edge(self.processor_info_v2.get_program_counter()?)
-                .build())
+            // The bounds check of a jump table index is followed by a conditional branch to
+            // the default case and then the indirect jump through the table
+            let jump_table = if decoded.indirect_jump {
+                self.jump_table_bounds.take().map(|bounds| (source, bounds))
+            } else {
+                if !decoded.conditional {
+                    self.jump_table_bounds = None;
+                }
+                None
+            };
+
+            let edge = self.processor_info_v2.get_program_counter()?;
+
+            match self.flags_cmp.take() {
+                Some((pc, cmp)) if decoded.conditional => Ok(TraceEntry::builder()
+                    .edge(edge)
+                    .source(source)
+                    .cmp((pc, decoded.cmp_types.clone(), cmp))
+                    .jump_table(jump_table)
+                    .call(decoded.call)
+                    .ret(decoded.ret)
+                    .build()),
+                _ => Ok(TraceEntry::builder()
+                    .edge(edge)
+                    .source(source)
+                    .jump_table(jump_table)
+                    .call(decoded.call)
+                    .ret(decoded.ret)
+                    .build()),
+            }
         } else {
             Ok(TraceEntry::default())
         }
     }
 
     fn trace_cmp(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
+        let decoded = self.decode(instruction_query)?;
 
         let pc = self.processor_info_v2.get_program_counter()?;
 
         let mut cmp_values = Vec::new();
 
-        for expr in self.disassembler.cmp() {
-            if let Ok(value) = self.simplify(&expr) {
+        for expr in decoded.cmp_exprs.iter() {
+            if let Ok(value) = self.simplify(expr) {
                 cmp_values.push(value);
             }
         }
 
         let cmp_value = if let (Some(l), Some(r)) = (cmp_values.first(), cmp_values.get(1)) {
-            match (l, r) {
-                (CmpValue::U8(l), CmpValue::U8(r)) => Some(CmpValues::U8((*l, *r))),
-                (CmpValue::I8(l), CmpValue::I8(r)) => Some(CmpValues::U8((
-                    u8::from_le_bytes(l.to_le_bytes()),
-                    u8::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U16(l), CmpValue::U16(r)) => Some(CmpValues::U16((*l, *r))),
-                (CmpValue::I16(l), CmpValue::I16(r)) => Some(CmpValues::U16((
-                    u16::from_le_bytes(l.to_le_bytes()),
-                    u16::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U32(l), CmpValue::U32(r)) => Some(CmpValues::U32((*l, *r))),
-                (CmpValue::I32(l), CmpValue::I32(r)) => Some(CmpValues::U32((
-                    u32::from_le_bytes(l.to_le_bytes()),
-                    u32::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U64(l), CmpValue::U64(r)) => Some(CmpValues::U64((*l, *r))),
-                (CmpValue::I64(l), CmpValue::I64(r)) => Some(CmpValues::U64((
-                    u64::from_le_bytes(l.to_le_bytes()),
-                    u64::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::Expr(_), CmpValue::Expr(_)) => None,
-                _ => None,
-            }
+            CmpValue::pair(l, r)
         } else {
             None
         };
 
+        if cmp_value.is_some() && !decoded.cmp_types.contains(&CmpType::Fp) {
+            self.jump_table_bounds.clone_from(&cmp_value);
+        }
+
+        if decoded.sets_flags {
+            // `cmp`, `cmn` and `tst` are aliases of flag setting arithmetic, and are only a
+            // comparison if a conditional instruction consumes the flags
+            self.flags_cmp = cmp_value.map(|cmp| (pc, cmp));
+            return Ok(TraceEntry::default());
+        }
+
         Ok(TraceEntry::builder()
             .cmp((
                 pc,
-                self.disassembler.cmp_type(),
+                decoded.cmp_types.clone(),
                 cmp_value.ok_or_else(|| anyhow!("No cmp value available"))?,
             ))
             .build())
@@ -199,20 +292,18 @@ impl AArch64ArchitectureOperations {
                         let address = self
                             .processor_info_v2
                             .logical_to_physical(a, Access::Sim_Access_Read)?;
-                        Ok(CmpValue::U64(read_phys_memory(
-                            self.cpu,
+                        Ok(CmpValue::U64(self.read_physical_integer(
                             address.address,
-                            size_of::<u64>() as i32,
+                            size_of::<u64>(),
                         )?))
                     }
                     CmpValue::U32(a) => {
                         let address = self
                             .processor_info_v2
                             .logical_to_physical(a as u64, Access::Sim_Access_Read)?;
-                        Ok(CmpValue::U64(read_phys_memory(
-                            self.cpu,
+                        Ok(CmpValue::U64(self.read_physical_integer(
                             address.address,
-                            size_of::<u32>() as i32,
+                            size_of::<u32>(),
                         )?))
                     }
                     _ => bail!("Invalid dereference size {:?}", v),
@@ -1016,9 +1107,13 @@ impl AArch64ArchitectureOperations {
                     }
                 }
             }
+            CmpExpr::U8(u) => Ok(CmpValue::U8(*u)),
+            CmpExpr::U16(u) => Ok(CmpValue::U16(*u)),
             CmpExpr::I16(i) => Ok(CmpValue::I16(*i)),
             CmpExpr::U32(u) => Ok(CmpValue::U32(*u)),
             CmpExpr::I32(i) => Ok(CmpValue::I32(*i)),
+            CmpExpr::U64(u) => Ok(CmpValue::U64(*u)),
+            CmpExpr::Float((expr, width)) => self.simplify(expr)?.into_float(*width),
             _ => bail!("Unsupported expression {:?}", expr),
         }
     }
@@ -1036,6 +1131,55 @@ impl Disassembler {
             last: None,
         }
     }
+
+    /// The expression for a general purpose register operand
+    fn register(operand: &Operand) -> Option<CmpExpr> {
+        match operand {
+            Operand::Register(s, r) | Operand::RegisterOrSP(s, r) => Some(match s {
+                SizeCode::X => CmpExpr::Reg((format!("x{r}"), 64)),
+                SizeCode::W => CmpExpr::Reg((format!("w{r}"), 32)),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The operands of a compare and branch instruction. `cbz` and `cbnz` compare a register
+    /// with an implicit zero, and `tbz` and `tbnz` compare a register with the bit they test.
+    fn branch_cmp(&self, last: &Instruction) -> Vec<CmpExpr> {
+        let Some(register) = Self::register(&last.operands[0]) else {
+            return vec![];
+        };
+
+        match (last.opcode, &last.operands[1]) {
+            (Opcode::CBZ | Opcode::CBNZ, _) => vec![register, CmpExpr::U64(0)],
+            (Opcode::TBZ | Opcode::TBNZ, Operand::Immediate(bit)) => {
+                vec![register, CmpExpr::U64(1u64.wrapping_shl(*bit))]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The operands of an `fcmp` or `fcmpe` floating point comparison, which compares two
+    /// scalar registers or a register with zero
+    fn fp_cmp(&self, last: &Instruction) -> Vec<CmpExpr> {
+        let width = match last.operands[0] {
+            Operand::SIMDRegister(SIMDSizeCode::S, _) => 4,
+            Operand::SIMDRegister(SIMDSizeCode::D, _) => 8,
+            // Half precision values are not logged
+            _ => return vec![],
+        };
+
+        last.operands
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::SIMDRegister(_, r) => Some(CmpExpr::Reg((format!("d{r}"), 64))),
+                Operand::ImmediateDouble(_) => Some(CmpExpr::U32(0)),
+                _ => None,
+            })
+            .take(2)
+            .map(|expr| CmpExpr::Float((Box::new(expr), width)))
+            .collect()
+    }
 }
 
 impl Default for Disassembler {
@@ -1067,6 +1211,12 @@ impl TracerDisassembler for Disassembler {
         }
     }
 
+    fn last_len(&self) -> Option<usize> {
+        self.last
+            .as_ref()
+            .map(|last| last.len().to_const() as usize)
+    }
+
     fn last_was_control_flow(&self) -> bool {
         if let Some(last) = self.last.as_ref() {
             // NOTE: This is imprecise on ARM because PC is not restricted
@@ -1095,6 +1245,38 @@ impl TracerDisassembler for Disassembler {
         false
     }
 
+    fn last_was_conditional(&self) -> bool {
+        if let Some(last) = self.last.as_ref() {
+            return matches!(
+                last.opcode,
+                Opcode::Bcc(_)
+                    | Opcode::CBNZ
+                    | Opcode::CBZ
+                    | Opcode::CCMN
+                    | Opcode::CCMP
+                    | Opcode::CSEL
+                    | Opcode::CSINC
+                    | Opcode::CSINV
+                    | Opcode::CSNEG
+                    | Opcode::TBNZ
+                    | Opcode::TBZ
+            );
+        }
+
+        false
+    }
+
+    fn last_was_indirect_jump(&self) -> bool {
+        if let Some(last) = self.last.as_ref() {
+            return matches!(
+                last.opcode,
+                Opcode::BR | Opcode::BRAA | Opcode::BRAAZ | Opcode::BRABZ
+            );
+        }
+
+        false
+    }
+
     // TODO: Make call/ret distinction more accurate, all three can ret/call far or near, but
     // there are semantic versions based on operands:
     // https://inst.eecs.berkeley.edu/~cs61c/fa20/pdfs/lectures/lec12-bw.pdf
@@ -1136,17 +1318,42 @@ impl TracerDisassembler for Disassembler {
                         | Opcode::CSINC
                         | Opcode::CSINV
                         | Opcode::CSNEG
+                        | Opcode::FCMP
+                        | Opcode::FCMPE
                 );
         }
 
         false
     }
 
+    fn last_sets_flags(&self) -> bool {
+        if let Some(last) = self.last.as_ref() {
+            return matches!(last.opcode, Opcode::ADDS | Opcode::ANDS | Opcode::SUBS);
+        }
+
+        false
+    }
+
     fn cmp(&self) -> Vec<CmpExpr> {
         let mut cmp_exprs = Vec::new();
-        if self.last_was_cmp() {
+        if self.last_was_cmp() || self.last_sets_flags() {
             if let Some(last) = self.last.as_ref() {
-                for operand in &last.operands {
+                match last.opcode {
+                    Opcode::FCMP | Opcode::FCMPE => return self.fp_cmp(last),
+                    Opcode::CBZ | Opcode::CBNZ | Opcode::TBZ | Opcode::TBNZ => {
+                        return self.branch_cmp(last)
+                    }
+                    _ => {}
+                }
+
+                // Flag setting arithmetic compares its sources, not its destination
+                let operands = if self.last_sets_flags() {
+                    &last.operands[1..]
+                } else {
+                    &last.operands[..]
+                };
+
+                for operand in operands {
                     match operand {
                         Operand::Register(s, r) => match s {
                             SizeCode::X => cmp_exprs.push(CmpExpr::Reg((format!("x{r}"), 64))),
@@ -1248,6 +1455,17 @@ impl TracerDisassembler for Disassembler {
                         _ => {}
                     }
                 }
+
+                // Conditional compares set the flags given by their last operands if their
+                // condition fails, which are not compared. Adding compares with the negated
+                // second operand.
+                cmp_exprs.truncate(2);
+
+                if matches!(last.opcode, Opcode::ADDS | Opcode::CCMN) {
+                    if let Some(right) = cmp_exprs.pop() {
+                        cmp_exprs.push(CmpExpr::Sub((Box::new(CmpExpr::U64(0)), Box::new(right))));
+                    }
+                }
             }
         }
 
@@ -1256,9 +1474,11 @@ impl TracerDisassembler for Disassembler {
 
     // NOTE: CmpType is not well suited for arm
     fn cmp_type(&self) -> Vec<CmpType> {
-        if self.last_was_cmp() {
+        if self.last_was_cmp() || self.last_sets_flags() {
             if let Some(last) = self.last.as_ref() {
                 return match last.opcode {
+                    Opcode::ADDS => vec![CmpType::Equal, CmpType::Greater, CmpType::Lesser],
+                    Opcode::ANDS => vec![CmpType::Equal],
                     Opcode::Bcc(_) => vec![CmpType::Equal, CmpType::Greater, CmpType::Lesser],
                     Opcode::CBNZ => vec![CmpType::Equal],
                     Opcode::CBZ => vec![CmpType::Equal],
@@ -1276,6 +1496,11 @@ impl TracerDisassembler for Disassembler {
                     Opcode::CSINC => vec![CmpType::Equal],
                     Opcode::CSINV => vec![CmpType::Equal],
                     Opcode::CSNEG => vec![CmpType::Equal],
+                    Opcode::FCMP => vec![CmpType::Equal, CmpType::Fp],
+                    Opcode::FCMPE => vec![CmpType::Equal, CmpType::Fp],
+                    Opcode::SUBS => vec![CmpType::Equal, CmpType::Greater, CmpType::Lesser],
+                    Opcode::TBNZ => vec![CmpType::Equal],
+                    Opcode::TBZ => vec![CmpType::Equal],
                     _ => vec![],
                 };
             }
@@ -4,15 +4,14 @@
 //! Architecture-specific implementation for ARM architecture
 
 use anyhow::{anyhow, bail, Result};
-use libafl::prelude::CmpValues;
 use raw_cstr::AsRawCstr;
 use simics::api::{
-    get_interface, read_phys_memory, sys::instruction_handle_t, Access, ConfObject,
-    CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface, CycleInterface,
-    IntRegisterInterface, ProcessorInfoV2Interface,
+    get_interface, sys::instruction_handle_t, Access, ConfObject, CpuInstructionQueryInterface,
+    CpuInstrumentationSubscribeInterface, CycleInterface, IntRegisterInterface,
+    ProcessorInfoV2Interface,
 };
-use std::{ffi::CStr, mem::size_of, slice::from_raw_parts};
-use yaxpeax_arch::{Decoder, U8Reader};
+use std::{collections::BTreeMap, ffi::CStr, mem::size_of};
+use yaxpeax_arch::{Decoder, LengthedInstruction, U8Reader};
 use yaxpeax_arm::armv7::{InstDecoder, Instruction, Opcode, Operand, RegShiftStyle, ShiftStyle};
 
 use crate::{
@@ -20,7 +19,108 @@ use crate::{
     traits::TracerDisassembler,
 };
 
-use super::ArchitectureOperations;
+use super::{
+    decode_cache::DecodeCache, model_exceptions, ArchitectureOperations, CallingConvention,
+    Endianness, Fault,
+};
+
+/// The architecture-level names of the ARM exceptions, including the faults of M-profile
+/// processors, each with the names processor models use for the exception. SIMICS exception
+/// numbers differ between ARM processor models, so they are looked up by name when the
+/// processor is added.
+const EXCEPTIONS: &[(&str, &[&str])] = &[
+    (
+        "undefined-instruction",
+        &["Undefined_Instruction", "Undefined"],
+    ),
+    (
+        "illegal-instruction",
+        &["Undefined_Instruction", "Undefined"],
+    ),
+    ("supervisor-call", &["Supervisor_Call", "SVC", "SWI"]),
+    ("prefetch-abort", &["Prefetch_Abort"]),
+    ("data-abort", &["Data_Abort"]),
+    ("hard-fault", &["HardFault", "Hard_Fault"]),
+    ("mem-manage", &["MemManage", "MemManage_Fault"]),
+    ("bus-fault", &["BusFault", "Bus_Fault"]),
+    ("usage-fault", &["UsageFault", "Usage_Fault"]),
+    ("secure-fault", &["SecureFault", "Secure_Fault"]),
+];
+
+/// The M-profile fault exceptions and their architectural exception numbers, which are the
+/// exception numbers of processor models which do not name their exceptions and the value of
+/// the IPSR while the fault handler is active
+const M_PROFILE_FAULTS: &[(&str, i64)] = &[
+    ("hard-fault", 3),
+    ("mem-manage", 4),
+    ("bus-fault", 5),
+    ("usage-fault", 6),
+    ("secure-fault", 7),
+];
+
+/// The Configurable Fault Status Register, which combines the MemManage, BusFault and
+/// UsageFault status registers
+const CFSR: u64 = 0xe000_ed28;
+/// The HardFault Status Register
+const HFSR: u64 = 0xe000_ed2c;
+/// The MemManage Fault Address Register
+const MMFAR: u64 = 0xe000_ed34;
+/// The BusFault Address Register
+const BFAR: u64 = 0xe000_ed38;
+/// The SecureFault Status Register of ARMv8-M processors with the security extension
+const SFSR: u64 = 0xe000_ede4;
+/// The SecureFault Address Register of ARMv8-M processors with the security extension
+const SFAR: u64 = 0xe000_ede8;
+
+/// The bit of the CFSR which is set if the MMFAR holds the faulting address
+const CFSR_MMARVALID: u64 = 1 << 7;
+/// The bit of the CFSR which is set if the BFAR holds the faulting address
+const CFSR_BFARVALID: u64 = 1 << 15;
+/// The bit of the SFSR which is set if the SFAR holds the faulting address
+const SFSR_SFARVALID: u64 = 1 << 6;
+
+/// The fault status bits of the CFSR
+const CFSR_CAUSES: &[(u32, &str)] = &[
+    (0, "IACCVIOL"),
+    (1, "DACCVIOL"),
+    (3, "MUNSTKERR"),
+    (4, "MSTKERR"),
+    (5, "MLSPERR"),
+    (8, "IBUSERR"),
+    (9, "PRECISERR"),
+    (10, "IMPRECISERR"),
+    (11, "UNSTKERR"),
+    (12, "STKERR"),
+    (13, "LSPERR"),
+    (16, "UNDEFINSTR"),
+    (17, "INVSTATE"),
+    (18, "INVPC"),
+    (19, "NOCP"),
+    (20, "STKOF"),
+    (24, "UNALIGNED"),
+    (25, "DIVBYZERO"),
+];
+/// The fault status bits of the HFSR
+const HFSR_CAUSES: &[(u32, &str)] = &[(1, "VECTTBL"), (30, "FORCED"), (31, "DEBUGEVT")];
+/// The fault status bits of the SFSR
+const SFSR_CAUSES: &[(u32, &str)] = &[
+    (0, "INVEP"),
+    (1, "INVIS"),
+    (2, "INVER"),
+    (3, "AUVIOL"),
+    (4, "INVTRAN"),
+    (5, "LSPERR"),
+    (7, "LSERR"),
+];
+
+/// The active exception number field of the xPSR
+const XPSR_IPSR: u64 = 0x1ff;
+/// The bit of the EXC_RETURN value in LR on exception entry which is set if the exception
+/// frame was stacked on the process stack rather than the main stack
+const EXC_RETURN_SPSEL: u64 = 1 << 2;
+/// The registers of the exception frame stacked on exception entry, in the order they are
+/// stacked from the stack pointer
+const EXCEPTION_FRAME: &[&str] = &["r0", "r1", "r2", "r3", "r12", "lr", "pc", "xpsr"];
 
 pub(crate) struct ARMArchitectureOperations {
     cpu: *mut ConfObject,
@@ -31,8 +131,18 @@ pub(crate) struct ARMArchitectureOperations {
     #[allow(dead_code)]
     cpu_instrumentation_subscribe: CpuInstrumentationSubscribeInterface,
     cycle: CycleInterface,
+    decode_cache: DecodeCache,
+    endianness: Endianness,
+    /// The architecture-level names of the exceptions of the processor model and their numbers
+    exceptions: Vec<(&'static str, i64)>,
+    /// Whether the processor implements the microcontroller (M) profile, which only executes
+    /// Thumb code and has no CPSR
+    m_profile: bool,
 }
 
+/// The Thumb execution state bit of the CPSR
+const CPSR_T: u64 = 1 << 5;
+
 impl ArchitectureOperations for ARMArchitectureOperations {
     const INDEX_SELECTOR_REGISTER: &'static str = "r10";
 
@@ -49,9 +159,20 @@ impl ArchitectureOperations for ARMArchitectureOperations {
             .to_str()?
             .to_string();
 
-        if arch == "arm" || arch == "armv7" || arch == "armv6" || arch == "armv5" || arch == "arm32"
+        let m_profile = matches!(
+            arch.as_str(),
+            "cortex-m" | "armv6-m" | "armv7-m" | "armv7e-m"
+        ) || arch.starts_with("armv8-m")
+            || arch.starts_with("armv8.1-m");
+
+        if arch == "arm"
+            || arch == "armv7"
+            || arch == "armv6"
+            || arch == "armv5"
+            || arch == "arm32"
+            || m_profile
         {
-            Ok(Self {
+            let operations = Self {
                 cpu,
                 disassembler: Disassembler::new(),
                 int_register: get_interface(cpu)?,
@@ -59,6 +180,16 @@ impl ArchitectureOperations for ARMArchitectureOperations {
                 cpu_instruction_query: get_interface(cpu)?,
                 cpu_instrumentation_subscribe: get_interface(cpu)?,
                 cycle: get_interface(cpu)?,
+                decode_cache: DecodeCache::default(),
+                endianness: Endianness::of(&mut get_interface(cpu)?)?,
+                exceptions: model_exceptions(cpu, EXCEPTIONS),
+                m_profile: false,
+            };
+
+            Ok(if m_profile {
+                operations.with_m_profile()
+            } else {
+                operations
             })
         } else {
             bail!("Architecture {} is not arm", arch);
@@ -77,6 +208,10 @@ impl ArchitectureOperations for ARMArchitectureOperations {
             cpu_instruction_query: get_interface(cpu)?,
             cpu_instrumentation_subscribe: get_interface(cpu)?,
             cycle: get_interface(cpu)?,
+            decode_cache: DecodeCache::default(),
+            endianness: Endianness::of(&mut get_interface(cpu)?)?,
+            exceptions: model_exceptions(cpu, EXCEPTIONS),
+            m_profile: false,
         })
     }
 
@@ -108,21 +243,146 @@ impl ArchitectureOperations for ARMArchitectureOperations {
         &mut self.cycle
     }
 
-    fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        &mut self.decode_cache
+    }
+
+    fn endianness(&mut self) -> &mut Endianness {
+        &mut self.endianness
+    }
 
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        &self.exceptions
+    }
 
-        if self.disassembler.last_was_call()
-            || self.disassembler.last_was_control_flow()
-            || self.disassembler.last_was_ret()
-        {
+    fn fault(&mut self, exception: i64) -> Result<Option<Fault>> {
+        if !self.m_profile {
+            return Ok(None);
+        }
+
+        let Some((name, number)) = self.exception_name(exception).and_then(|name| {
+            M_PROFILE_FAULTS
+                .iter()
+                .find(|(fault, _)| *fault == name)
+                .copied()
+        }) else {
+            return Ok(None);
+        };
+
+        let mut causes = Vec::new();
+        let mut registers = BTreeMap::new();
+
+        // The fault status registers are read on a best-effort basis, because processor models
+        // may not implement all of them
+        if let Ok(hfsr) = self.read_physical_integer(HFSR, size_of::<u32>()) {
+            causes.extend(Self::fault_causes(hfsr, HFSR_CAUSES));
+            registers.insert("hfsr".to_string(), hfsr);
+        }
+
+        if let Ok(cfsr) = self.read_physical_integer(CFSR, size_of::<u32>()) {
+            causes.extend(Self::fault_causes(cfsr, CFSR_CAUSES));
+            registers.insert("cfsr".to_string(), cfsr);
+
+            if cfsr & CFSR_MMARVALID != 0 {
+                if let Ok(mmfar) = self.read_physical_integer(MMFAR, size_of::<u32>()) {
+                    registers.insert("mmfar".to_string(), mmfar);
+                }
+            }
+
+            if cfsr & CFSR_BFARVALID != 0 {
+                if let Ok(bfar) = self.read_physical_integer(BFAR, size_of::<u32>()) {
+                    registers.insert("bfar".to_string(), bfar);
+                }
+            }
+        }
+
+        if name == "secure-fault" {
+            if let Ok(sfsr) = self.read_physical_integer(SFSR, size_of::<u32>()) {
+                causes.extend(Self::fault_causes(sfsr, SFSR_CAUSES));
+                registers.insert("sfsr".to_string(), sfsr);
+
+                if sfsr & SFSR_SFARVALID != 0 {
+                    if let Ok(sfar) = self.read_physical_integer(SFAR, size_of::<u32>()) {
+                        registers.insert("sfar".to_string(), sfar);
+                    }
+                }
+            }
+        }
+
+        // The exception may be reported before the processor stacks the exception frame and
+        // enters the handler, in which case the faulting instruction is still the current one
+        let handler_active = self
+            .read_register("xpsr")
+            .or_else(|_| self.read_register("ipsr"))
+            .is_ok_and(|xpsr| xpsr & XPSR_IPSR == number as u64);
+
+        let pc = if handler_active {
+            let exc_return = self.read_register("lr")?;
+            let sp = if exc_return & EXC_RETURN_SPSEL != 0 {
+                self.read_register("psp")
+            } else {
+                self.read_register("msp")
+            }
+            .or_else(|_| self.read_register("sp"))?;
+
+            for (i, register) in EXCEPTION_FRAME.iter().enumerate() {
+                let value = self
+                    .read_virtual_integer(sp + (i * size_of::<u32>()) as u64, size_of::<u32>())?;
+                registers.insert(format!("stacked_{register}"), value);
+            }
+
+            registers
+                .get("stacked_pc")
+                .copied()
+                .ok_or_else(|| anyhow!("No stacked pc in exception frame"))?
+        } else {
+            self.processor_info_v2.get_program_counter()?
+        };
+
+        Ok(Some(Fault {
+            name: name.to_string(),
+            pc,
+            causes,
+            registers,
+        }))
+    }
+
+    fn update_decoder_mode(&mut self) -> Result<u8> {
+        // Processors without a readable CPSR are assumed to be executing ARM code unless they
+        // implement the M profile
+        let thumb = self.m_profile || self.read_register("cpsr").is_ok_and(|c| c & CPSR_T != 0);
+        self.disassembler.set_thumb(thumb);
+        Ok(thumb as u8)
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        const REGISTERS: &[&str] = &["r0", "r1", "r2", "r3"];
+
+        if convention != CallingConvention::Default {
+            bail!("Calling convention {convention} is not supported");
+        }
+
+        if let Some(register) = REGISTERS.get(index) {
+            self.read_register(register)
+        } else {
+            let address =
+                self.read_register("sp")? + ((index - REGISTERS.len()) * size_of::<u32>()) as u64;
+            self.read_virtual_integer(address, size_of::<u32>())
+        }
+    }
+
+    fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
+        let decoded = self.decode(instruction_query)?;
+
+        if decoded.is_edge() {
             Ok(TraceEntry::builder()
                 .edge(self.processor_info_v2.get_program_counter()?)
+                .source(
+                    self.cpu_instruction_query
+                        .logical_address(instruction_query)?,
+                )
+                .call(decoded.call)
+                .ret(decoded.ret)
                 .build())
         } else {
             Ok(TraceEntry::default())
@@ -130,48 +390,20 @@ impl ArchitectureOperations for ARMArchitectureOperations {
     }
 
     fn trace_cmp(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
+        let decoded = self.decode(instruction_query)?;
 
         let pc = self.processor_info_v2.get_program_counter()?;
 
         let mut cmp_values = Vec::new();
 
-        for expr in self.disassembler.cmp() {
-            if let Ok(value) = self.simplify(&expr) {
+        for expr in decoded.cmp_exprs.iter() {
+            if let Ok(value) = self.simplify(expr) {
                 cmp_values.push(value);
             }
         }
 
         let cmp_value = if let (Some(l), Some(r)) = (cmp_values.first(), cmp_values.get(1)) {
-            match (l, r) {
-                (CmpValue::U8(l), CmpValue::U8(r)) => Some(CmpValues::U8((*l, *r))),
-                (CmpValue::I8(l), CmpValue::I8(r)) => Some(CmpValues::U8((
-                    u8::from_le_bytes(l.to_le_bytes()),
-                    u8::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U16(l), CmpValue::U16(r)) => Some(CmpValues::U16((*l, *r))),
-                (CmpValue::I16(l), CmpValue::I16(r)) => Some(CmpValues::U16((
-                    u16::from_le_bytes(l.to_le_bytes()),
-                    u16::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U32(l), CmpValue::U32(r)) => Some(CmpValues::U32((*l, *r))),
-                (CmpValue::I32(l), CmpValue::I32(r)) => Some(CmpValues::U32((
-                    u32::from_le_bytes(l.to_le_bytes()),
-                    u32::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U64(l), CmpValue::U64(r)) => Some(CmpValues::U64((*l, *r))),
-                (CmpValue::I64(l), CmpValue::I64(r)) => Some(CmpValues::U64((
-                    u64::from_le_bytes(l.to_le_bytes()),
-                    u64::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::Expr(_), CmpValue::Expr(_)) => None,
-                _ => None,
-            }
+            CmpValue::pair(l, r)
         } else {
             None
         };
@@ -179,7 +411,7 @@ impl ArchitectureOperations for ARMArchitectureOperations {
         Ok(TraceEntry::builder()
             .cmp((
                 pc,
-                self.disassembler.cmp_type(),
+                decoded.cmp_types.clone(),
                 cmp_value.ok_or_else(|| anyhow!("No cmp value available"))?,
             ))
             .build())
@@ -187,6 +419,31 @@ impl ArchitectureOperations for ARMArchitectureOperations {
 }
 
 impl ARMArchitectureOperations {
+    /// Treat the processor as implementing the microcontroller (M) profile, for models which
+    /// do not report it as their architecture
+    pub fn with_m_profile(mut self) -> Self {
+        self.m_profile = true;
+
+        if !self
+            .exceptions
+            .iter()
+            .any(|(name, _)| M_PROFILE_FAULTS.iter().any(|(fault, _)| fault == name))
+        {
+            self.exceptions.extend_from_slice(M_PROFILE_FAULTS);
+        }
+
+        self
+    }
+
+    /// The names of the fault status bits which are set in the value of a fault status
+    /// register
+    fn fault_causes(value: u64, causes: &'static [(u32, &str)]) -> impl Iterator<Item = String> {
+        causes
+            .iter()
+            .filter(move |(bit, _)| value & (1u64 << bit) != 0)
+            .map(|(_, cause)| cause.to_string())
+    }
+
     fn simplify(&mut self, expr: &CmpExpr) -> Result<CmpValue> {
         match expr {
             CmpExpr::Deref((b, _)) => {
@@ -196,20 +453,18 @@ impl ARMArchitectureOperations {
                         let address = self
                             .processor_info_v2
                             .logical_to_physical(a, Access::Sim_Access_Read)?;
-                        Ok(CmpValue::U64(read_phys_memory(
-                            self.cpu,
+                        Ok(CmpValue::U64(self.read_physical_integer(
                             address.address,
-                            size_of::<u64>() as i32,
+                            size_of::<u64>(),
                         )?))
                     }
                     CmpValue::U32(a) => {
                         let address = self
                             .processor_info_v2
                             .logical_to_physical(a as u64, Access::Sim_Access_Read)?;
-                        Ok(CmpValue::U64(read_phys_memory(
-                            self.cpu,
+                        Ok(CmpValue::U64(self.read_physical_integer(
                             address.address,
-                            size_of::<u32>() as i32,
+                            size_of::<u32>(),
                         )?))
                     }
                     _ => bail!("Invalid dereference size {:?}", v),
@@ -1033,6 +1288,11 @@ impl Disassembler {
             last: None,
         }
     }
+
+    /// Set whether instructions are decoded as Thumb (and Thumb-2) instead of ARM
+    pub fn set_thumb(&mut self, thumb: bool) {
+        self.decoder.set_thumb_mode(thumb);
+    }
 }
 
 impl Default for Disassembler {
@@ -1064,11 +1324,20 @@ impl TracerDisassembler for Disassembler {
         }
     }
 
+    fn last_len(&self) -> Option<usize> {
+        self.last
+            .as_ref()
+            .map(|last| last.len().to_const() as usize)
+    }
+
     fn last_was_control_flow(&self) -> bool {
         if let Some(last) = self.last.as_ref() {
             // NOTE: This is imprecise on ARM because PC is not restricted
             // TODO: Are there any other control flow instructions?
-            return matches!(last.opcode, Opcode::B);
+            return matches!(
+                last.opcode,
+                Opcode::B | Opcode::CBZ | Opcode::CBNZ | Opcode::TBB | Opcode::TBH
+            );
         }
 
         false
@@ -1114,7 +1383,7 @@ impl TracerDisassembler for Disassembler {
         if let Some(last) = self.last.as_ref() {
             return matches!(
                 last.opcode,
-                Opcode::CMN | Opcode::CMP | Opcode::TST | Opcode::TEQ
+                Opcode::CBNZ | Opcode::CBZ | Opcode::CMN | Opcode::CMP | Opcode::TST | Opcode::TEQ
             );
         }
 
@@ -1307,6 +1576,12 @@ impl TracerDisassembler for Disassembler {
                         _ => {}
                     }
                 }
+
+                // Thumb compare and branch instructions compare a register with an implicit
+                // zero
+                if matches!(last.opcode, Opcode::CBZ | Opcode::CBNZ) {
+                    cmp_exprs.push(CmpExpr::U32(0));
+                }
             }
         }
 
@@ -1318,6 +1593,8 @@ impl TracerDisassembler for Disassembler {
         if self.last_was_cmp() {
             if let Some(last) = self.last.as_ref() {
                 return match last.opcode {
+                    Opcode::CBNZ => vec![CmpType::Equal],
+                    Opcode::CBZ => vec![CmpType::Equal],
                     Opcode::CMP => vec![CmpType::Equal, CmpType::Lesser, CmpType::Greater],
                     Opcode::CMN => vec![CmpType::Equal, CmpType::Lesser, CmpType::Greater],
                     Opcode::TST => vec![CmpType::Equal, CmpType::Lesser, CmpType::Greater],
@@ -1330,3 +1607,41 @@ impl TracerDisassembler for Disassembler {
         vec![]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Disassembler;
+    use crate::{
+        arch::decode_cache::{DecodeCache, DecodedInstruction},
+        traits::TracerDisassembler,
+    };
+
+    #[test]
+    fn test_decode_mode_switch() {
+        // `bx lr` in Thumb state, `andeq r4, r0, r0, ror r7` in ARM state
+        let bytes = [0x70, 0x47, 0x00, 0x00];
+        let mut disassembler = Disassembler::new();
+        let mut cache = DecodeCache::default();
+
+        let mut decode = |thumb: bool| {
+            if let Some(decoded) = cache.get(0x1000, thumb as u8, &bytes) {
+                return decoded;
+            }
+
+            disassembler.set_thumb(thumb);
+            disassembler
+                .disassemble(&bytes)
+                .expect("Failed to disassemble");
+            let decoded = DecodedInstruction::from_disassembler(&bytes, &disassembler);
+            cache.insert(0x1000, thumb as u8, decoded)
+        };
+
+        // Each state keeps its own decoding of the same code after switching back and forth
+        for _ in 0..2 {
+            let thumb = decode(true);
+            assert!(thumb.ret && thumb.is_edge());
+            let arm = decode(false);
+            assert!(!arm.ret && !arm.is_edge());
+        }
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cache of decoded instructions to avoid disassembling each executed instruction more than
+//! once
+
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+use super::PAGE_SIZE;
+use crate::{
+    tracer::{CmpExpr, CmpType},
+    traits::TracerDisassembler,
+};
+
+/// The maximum number of instructions cached per processor
+const MAX_DECODE_CACHE_ENTRIES: usize = 1 << 20;
+
+#[derive(Debug)]
+/// The classification and pre-lowered comparison operands of a decoded instruction
+pub(crate) struct DecodedInstruction {
+    /// The bytes of the instruction
+    pub bytes: Vec<u8>,
+    /// Whether the instruction is a (possibly conditional) control flow instruction
+    pub control_flow: bool,
+    /// Whether the instruction is a call instruction
+    pub call: bool,
+    /// Whether the instruction is a ret instruction
+    pub ret: bool,
+    /// Whether the instruction is a compare instruction
+    pub cmp: bool,
+    /// Whether the instruction is arithmetic which sets flags a conditional branch may compare
+    pub sets_flags: bool,
+    /// Whether the instruction is a conditional branch on flags
+    pub conditional: bool,
+    /// Whether the instruction is a jump to a computed address, which may dispatch through a
+    /// jump table
+    pub indirect_jump: bool,
+    /// The operands of the comparison, if the instruction is a compare instruction
+    pub cmp_exprs: Vec<CmpExpr>,
+    /// The types of the comparison, if the instruction is a compare instruction
+    pub cmp_types: Vec<CmpType>,
+}
+
+impl DecodedInstruction {
+    /// Create a decoded instruction from the last instruction disassembled by a disassembler
+    pub fn from_disassembler(bytes: &[u8], disassembler: &dyn TracerDisassembler) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+            control_flow: disassembler.last_was_control_flow(),
+            call: disassembler.last_was_call(),
+            ret: disassembler.last_was_ret(),
+            cmp: disassembler.last_was_cmp(),
+            sets_flags: disassembler.last_sets_flags(),
+            conditional: disassembler.last_was_conditional(),
+            indirect_jump: disassembler.last_was_indirect_jump(),
+            cmp_exprs: disassembler.cmp(),
+            cmp_types: disassembler.cmp_type(),
+        }
+    }
+
+    /// Whether the instruction ends a basic block
+    pub fn is_edge(&self) -> bool {
+        self.control_flow || self.call || self.ret
+    }
+}
+
+#[derive(Debug)]
+/// A cached decoded instruction
+struct CacheEntry {
+    decoded: Rc<DecodedInstruction>,
+    /// Whether the instruction has been looked up since its page was last considered for
+    /// eviction
+    referenced: bool,
+}
+
+#[derive(Debug)]
+/// A per-processor cache of decoded instructions keyed by physical address, decoding mode, and
+/// instruction bytes. Because the bytes are part of the key, code which is modified after it is cached
+/// (e.g. by a loader, self-modifying code, or restoring a snapshot taken before the code was
+/// written) is decoded again, so entries never need to be explicitly invalidated and the cache
+/// stays warm across snapshot restores.
+///
+/// At most `MAX_DECODE_CACHE_ENTRIES` instructions are cached by default. When the cache is
+/// full, whole physical pages are evicted in the order they were first cached, skipping (once)
+/// pages with instructions which have been used since they were last considered, so hot code
+/// stays cached.
+pub(crate) struct DecodeCache {
+    /// The maximum number of cached instructions
+    capacity: usize,
+    entries: HashMap<(u64, u8), CacheEntry>,
+    /// The keys of the cached instructions in each physical page
+    pages: HashMap<u64, Vec<(u64, u8)>>,
+    /// Cached pages, in the order they are considered for eviction
+    eviction_order: VecDeque<u64>,
+    /// The decoding of the instruction about to be traced, if the processor model already
+    /// provided it
+    current: Option<Rc<DecodedInstruction>>,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self::with_capacity(MAX_DECODE_CACHE_ENTRIES)
+    }
+}
+
+impl DecodeCache {
+    /// Create a cache holding at most `capacity` instructions
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            pages: HashMap::new(),
+            eviction_order: VecDeque::new(),
+            current: None,
+        }
+    }
+
+    /// Look up the cached decoding of the instruction with the given bytes at a physical address
+    /// in a decoding mode
+    pub fn get(
+        &mut self,
+        physical_address: u64,
+        mode: u8,
+        bytes: &[u8],
+    ) -> Option<Rc<DecodedInstruction>> {
+        let entry = self
+            .entries
+            .get_mut(&(physical_address, mode))
+            .filter(|e| e.decoded.bytes == bytes)?;
+
+        entry.referenced = true;
+
+        Some(entry.decoded.clone())
+    }
+
+    /// Insert the decoding of an instruction at a physical address in a decoding mode,
+    /// replacing any previous decoding at the same address in the same mode
+    pub fn insert(
+        &mut self,
+        physical_address: u64,
+        mode: u8,
+        decoded: DecodedInstruction,
+    ) -> Rc<DecodedInstruction> {
+        let key = (physical_address, mode);
+        let decoded = Rc::new(decoded);
+
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.decoded = decoded.clone();
+            return decoded;
+        }
+
+        while self.entries.len() >= self.capacity && !self.eviction_order.is_empty() {
+            self.evict();
+        }
+
+        let page_address = physical_address & !(PAGE_SIZE - 1);
+
+        self.pages
+            .entry(page_address)
+            .or_insert_with(|| {
+                self.eviction_order.push_back(page_address);
+                Vec::new()
+            })
+            .push(key);
+
+        self.entries.insert(
+            key,
+            CacheEntry {
+                decoded: decoded.clone(),
+                referenced: false,
+            },
+        );
+
+        decoded
+    }
+
+    /// Evict the first page in the eviction order with no instructions used since it was
+    /// last considered, giving pages with used instructions a second chance
+    fn evict(&mut self) {
+        while let Some(page_address) = self.eviction_order.pop_front() {
+            let Some(keys) = self.pages.get(&page_address) else {
+                continue;
+            };
+
+            let mut referenced = false;
+
+            for key in keys {
+                if let Some(entry) = self.entries.get_mut(key) {
+                    referenced |= entry.referenced;
+                    entry.referenced = false;
+                }
+            }
+
+            if referenced {
+                self.eviction_order.push_back(page_address);
+            } else if let Some(keys) = self.pages.remove(&page_address) {
+                keys.iter().for_each(|key| {
+                    self.entries.remove(key);
+                });
+                return;
+            }
+        }
+    }
+
+    /// Provide the decoding of the instruction about to be traced, which is returned by the
+    /// next call to `take_current` instead of looking the instruction up
+    pub fn set_current(&mut self, decoded: Rc<DecodedInstruction>) {
+        self.current = Some(decoded);
+    }
+
+    /// Take the decoding of the instruction about to be traced, if one was provided
+    pub fn take_current(&mut self) -> Option<Rc<DecodedInstruction>> {
+        self.current.take()
+    }
+
+    /// The number of cached instructions
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodeCache, DecodedInstruction, PAGE_SIZE};
+
+    fn decoded(bytes: &[u8]) -> DecodedInstruction {
+        DecodedInstruction {
+            bytes: bytes.to_vec(),
+            control_flow: false,
+            call: false,
+            ret: false,
+            cmp: false,
+            sets_flags: false,
+            conditional: false,
+            indirect_jump: false,
+            cmp_exprs: Vec::new(),
+            cmp_types: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_decode_cache_key() {
+        let mut cache = DecodeCache::default();
+
+        cache.insert(0x1000, 0, decoded(&[0x90]));
+
+        assert!(cache.get(0x1000, 0, &[0x90]).is_some());
+        // Modified code is decoded again
+        assert!(cache.get(0x1000, 0, &[0xc3]).is_none());
+        // The same bytes decode differently in another mode (e.g. Thumb or 16-bit x86)
+        assert!(cache.get(0x1000, 1, &[0x90]).is_none());
+        assert!(cache.get(0x1001, 0, &[0x90]).is_none());
+
+        cache.insert(0x1000, 0, decoded(&[0xc3]));
+        cache.insert(0x1000, 1, decoded(&[0x90]));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(0x1000, 0, &[0xc3]).is_some());
+        assert!(cache.get(0x1000, 1, &[0x90]).is_some());
+    }
+
+    #[test]
+    fn test_decode_cache_eviction() {
+        let mut cache = DecodeCache::with_capacity(4);
+        let (a, b, c) = (0x1000, 0x2000, 0x3000);
+
+        cache.insert(a, 0, decoded(&[0]));
+        cache.insert(a + 4, 0, decoded(&[1]));
+        cache.insert(b, 0, decoded(&[2]));
+        cache.insert(b + 4, 0, decoded(&[3]));
+
+        // The first page is used, so the second page is evicted instead when the cache is full
+        assert!(cache.get(a, 0, &[0]).is_some());
+
+        cache.insert(c, 0, decoded(&[4]));
+
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(a, 0, &[0]).is_some());
+        assert!(cache.get(a + 4, 0, &[1]).is_some());
+        assert!(cache.get(b, 0, &[2]).is_none());
+        assert!(cache.get(c, 0, &[4]).is_some());
+
+        // When every page has been used, the oldest is evicted after its second chance
+        cache.insert(c + 4, 0, decoded(&[5]));
+        cache.insert(c + 8, 0, decoded(&[6]));
+
+        assert!(cache.len() <= 4);
+        assert!(cache.get(a, 0, &[0]).is_none());
+        assert!(cache.get(c + 8, 0, &[6]).is_some());
+    }
+
+    #[test]
+    fn test_decode_cache_pages() {
+        let mut cache = DecodeCache::with_capacity(2);
+
+        // Instructions in the same page are evicted together
+        cache.insert(PAGE_SIZE - 4, 0, decoded(&[0]));
+        cache.insert(PAGE_SIZE, 0, decoded(&[1]));
+        cache.insert(PAGE_SIZE + 4, 0, decoded(&[2]));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(PAGE_SIZE - 4, 0, &[0]).is_none());
+        assert!(cache.get(PAGE_SIZE, 0, &[1]).is_some());
+        assert!(cache.get(PAGE_SIZE + 4, 0, &[2]).is_some());
+    }
+
+    #[test]
+    fn test_decode_cache_current() {
+        let mut cache = DecodeCache::default();
+        let current = cache.insert(0x1000, 0, decoded(&[0x90]));
+
+        assert!(cache.take_current().is_none());
+
+        cache.set_current(current);
+
+        assert_eq!(
+            cache.take_current().map(|d| d.bytes.clone()),
+            Some(vec![0x90])
+        );
+        assert!(cache.take_current().is_none());
+    }
+}
@@ -0,0 +1,1247 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Architecture-specific implementation for the MIPS architecture
+
+use anyhow::{anyhow, bail, Result};
+use libafl::prelude::CmpValues;
+use simics::api::{
+    get_interface, sys::instruction_handle_t, ConfObject, CpuInstructionQueryInterface,
+    CpuInstrumentationSubscribeInterface, CycleInterface, IntRegisterInterface,
+    ProcessorInfoV2Interface,
+};
+use std::{
+    ffi::CStr,
+    fmt::{Display, Formatter},
+    mem::size_of,
+};
+
+use crate::{
+    tracer::{CmpExpr, CmpType, CmpValue, TraceEntry},
+    traits::TracerDisassembler,
+};
+
+use super::{
+    decode_cache::DecodeCache, model_exceptions, ArchitectureOperations, CallingConvention,
+    Endianness,
+};
+
+/// The size of every MIPS instruction, and the offset of a branch's delay slot from the
+/// branch
+const INSTRUCTION_SIZE: u64 = 4;
+
+/// The architecture-level names of the MIPS exceptions, each with the names processor models use
+/// for the exception. SIMICS exception numbers differ between MIPS processor models, so they
+/// are looked up by name when the processor is added.
+const EXCEPTIONS: &[(&str, &[&str])] = &[
+    ("tlb-modified", &["TLB_Modified", "Mod"]),
+    ("tlb-load", &["TLB_Load", "TLBL"]),
+    ("tlb-store", &["TLB_Store", "TLBS"]),
+    ("address-error-load", &["Address_Error_Load", "AdEL"]),
+    ("address-error-store", &["Address_Error_Store", "AdES"]),
+    ("instruction-bus-error", &["Instruction_Bus_Error", "IBE"]),
+    ("data-bus-error", &["Data_Bus_Error", "DBE"]),
+    ("system-call", &["Syscall", "Sys"]),
+    ("breakpoint", &["Breakpoint", "Bp"]),
+    ("reserved-instruction", &["Reserved_Instruction", "RI"]),
+    ("illegal-instruction", &["Reserved_Instruction", "RI"]),
+    ("coprocessor-unusable", &["Coprocessor_Unusable", "CpU"]),
+    ("overflow", &["Integer_Overflow", "Ov"]),
+    ("trap", &["Trap", "Tr"]),
+    ("floating-point", &["Floating_Point", "FPE"]),
+];
+
+pub(crate) struct MIPSArchitectureOperations {
+    cpu: *mut ConfObject,
+    disassembler: Disassembler,
+    int_register: IntRegisterInterface,
+    processor_info_v2: ProcessorInfoV2Interface,
+    cpu_instruction_query: CpuInstructionQueryInterface,
+    #[allow(dead_code)]
+    cpu_instrumentation_subscribe: CpuInstrumentationSubscribeInterface,
+    cycle: CycleInterface,
+    decode_cache: DecodeCache,
+    /// The operands of the last comparison, which may be the bounds check of a jump table
+    /// index if an indirect jump follows
+    jump_table_bounds: Option<CmpValues>,
+    endianness: Endianness,
+    /// The architecture-level names of the exceptions of the processor model and their numbers
+    exceptions: Vec<(&'static str, i64)>,
+}
+
+impl ArchitectureOperations for MIPSArchitectureOperations {
+    const INDEX_SELECTOR_REGISTER: &'static str = "a0";
+
+    const ARGUMENT_REGISTER_0: &'static str = "a1";
+
+    const ARGUMENT_REGISTER_1: &'static str = "a2";
+
+    const ARGUMENT_REGISTER_2: &'static str = "a3";
+
+    fn new(cpu: *mut ConfObject) -> Result<Self> {
+        let mut processor_info_v2: ProcessorInfoV2Interface = get_interface(cpu)?;
+
+        let arch = unsafe { CStr::from_ptr(processor_info_v2.architecture()?) }
+            .to_str()?
+            .to_string();
+
+        if arch.starts_with("mips") {
+            Self::new_unchecked(cpu)
+        } else {
+            bail!("Architecture {} is not mips", arch);
+        }
+    }
+
+    fn new_unchecked(cpu: *mut ConfObject) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut processor_info_v2: ProcessorInfoV2Interface = get_interface(cpu)?;
+        let endianness = Endianness::of(&mut processor_info_v2)?;
+        let mips64 = processor_info_v2.get_logical_address_width()? == 64;
+
+        Ok(Self {
+            cpu,
+            disassembler: Disassembler::new(endianness == Endianness::Big, mips64),
+            int_register: get_interface(cpu)?,
+            processor_info_v2,
+            cpu_instruction_query: get_interface(cpu)?,
+            cpu_instrumentation_subscribe: get_interface(cpu)?,
+            cycle: get_interface(cpu)?,
+            decode_cache: DecodeCache::default(),
+            jump_table_bounds: None,
+            endianness,
+            exceptions: model_exceptions(cpu, EXCEPTIONS),
+        })
+    }
+
+    fn cpu(&self) -> *mut ConfObject {
+        self.cpu
+    }
+
+    fn disassembler(&mut self) -> &mut dyn TracerDisassembler {
+        &mut self.disassembler
+    }
+
+    fn int_register(&mut self) -> &mut IntRegisterInterface {
+        &mut self.int_register
+    }
+
+    fn processor_info_v2(&mut self) -> &mut ProcessorInfoV2Interface {
+        &mut self.processor_info_v2
+    }
+
+    fn cpu_instruction_query(&mut self) -> &mut CpuInstructionQueryInterface {
+        &mut self.cpu_instruction_query
+    }
+
+    fn cpu_instrumentation_subscribe(&mut self) -> &mut CpuInstrumentationSubscribeInterface {
+        &mut self.cpu_instrumentation_subscribe
+    }
+
+    fn cycle(&mut self) -> &mut CycleInterface {
+        &mut self.cycle
+    }
+
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        &mut self.decode_cache
+    }
+
+    fn endianness(&mut self) -> &mut Endianness {
+        &mut self.endianness
+    }
+
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        &self.exceptions
+    }
+
+    fn update_decoder_mode(&mut self) -> Result<u8> {
+        self.disassembler
+            .set_big_endian(self.endianness == Endianness::Big);
+        Ok(0)
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        // The o32 ABI passes four arguments in registers, n64 passes eight
+        const REGISTERS: &[&str] = &["a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3"];
+
+        if convention != CallingConvention::Default {
+            bail!("Calling convention {convention} is not supported");
+        }
+
+        let width =
+            self.processor_info_v2().get_logical_address_width()? as usize / u8::BITS as usize;
+        let register_count = if width == size_of::<u32>() { 4 } else { 8 };
+
+        if index < register_count {
+            self.read_register(REGISTERS[index])
+        } else {
+            let sp = self.read_register("sp")?;
+            // o32 reserves stack space for the register arguments, n64 does not
+            let address = if width == size_of::<u32>() {
+                sp + (index * width) as u64
+            } else {
+                sp + ((index - register_count) * width) as u64
+            };
+            self.read_virtual_integer(address, width)
+        }
+    }
+
+    fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
+        let decoded = self.decode(instruction_query)?;
+
+        if !decoded.is_edge() {
+            return Ok(TraceEntry::default());
+        }
+
+        let source = self
+            .cpu_instruction_query
+            .logical_address(instruction_query)?;
+        let instruction = self.disassembler.decode(&decoded.bytes)?;
+
+        // The branch has executed but its delay slot has not, so the registers the branch read
+        // still hold the values it used. The edge is to the instruction executed after the
+        // delay slot, which is the target if the branch is taken and the instruction after the
+        // delay slot if it is not, so the delay slot does not need to be instrumented.
+        let target = match instruction {
+            Instruction::JumpRegister { rs, .. } => {
+                Some(self.read_register(REGISTER_NAMES[rs as usize])?)
+            }
+            Instruction::Branch {
+                condition, rs, rt, ..
+            } => {
+                if condition.holds(self.read_signed(rs)?, self.read_signed(rt)?) {
+                    instruction.target(source)
+                } else {
+                    None
+                }
+            }
+            _ => instruction.target(source),
+        };
+
+        // The bounds check of a jump table index is followed by a conditional branch to
+        // the default case and then the indirect jump through the table
+        let jump_table = if decoded.indirect_jump {
+            self.jump_table_bounds.take().map(|bounds| (source, bounds))
+        } else {
+            if !decoded.conditional {
+                self.jump_table_bounds = None;
+            }
+            None
+        };
+
+        Ok(TraceEntry::builder()
+            .edge(target.unwrap_or(source.wrapping_add(2 * INSTRUCTION_SIZE)))
+            .source(source)
+            .jump_table(jump_table)
+            // A linking branch which is not taken makes no call
+            .call(decoded.call && target.is_some())
+            .ret(decoded.ret)
+            .build())
+    }
+
+    fn trace_cmp(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
+        let decoded = self.decode(instruction_query)?;
+
+        if !decoded.cmp {
+            return Ok(TraceEntry::default());
+        }
+
+        let pc = self.processor_info_v2.get_program_counter()?;
+
+        let mut cmp_values = Vec::new();
+
+        for expr in decoded.cmp_exprs.iter() {
+            if let Ok(value) = self.simplify(expr) {
+                cmp_values.push(value);
+            }
+        }
+
+        let cmp_value = if let (Some(l), Some(r)) = (cmp_values.first(), cmp_values.get(1)) {
+            CmpValue::pair(l, r)
+        } else {
+            None
+        };
+
+        if cmp_value.is_some() {
+            self.jump_table_bounds.clone_from(&cmp_value);
+        }
+
+        Ok(TraceEntry::builder()
+            .cmp((
+                pc,
+                decoded.cmp_types.clone(),
+                cmp_value.ok_or_else(|| anyhow!("No cmp value available"))?,
+            ))
+            .build())
+    }
+}
+
+impl MIPSArchitectureOperations {
+    /// Read a general purpose register as a signed integer of the width of the registers
+    fn read_signed(&mut self, register: u8) -> Result<i64> {
+        if register == 0 {
+            return Ok(0);
+        }
+
+        let value = self.read_register(REGISTER_NAMES[register as usize])?;
+
+        Ok(if self.disassembler.mips64 {
+            value as i64
+        } else {
+            value as u32 as i32 as i64
+        })
+    }
+
+    fn simplify(&mut self, expr: &CmpExpr) -> Result<CmpValue> {
+        match expr {
+            CmpExpr::Reg((name, 32)) => Ok(CmpValue::U32(self.read_register(name)? as u32)),
+            CmpExpr::Reg((name, _)) => Ok(CmpValue::U64(self.read_register(name)?)),
+            _ => CmpValue::try_from(expr),
+        }
+    }
+}
+
+/// The ABI names of the general purpose registers
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+    "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
+    "ra",
+];
+
+/// The return address register, written by linking branches and jumps
+const RA: u8 = 31;
+
+/// The SPECIAL instructions with a destination and two source registers by function
+const SPECIAL_REGISTERS: &[(u32, &str)] = &[
+    (10, "movz"),
+    (11, "movn"),
+    (32, "add"),
+    (33, "addu"),
+    (34, "sub"),
+    (35, "subu"),
+    (36, "and"),
+    (37, "or"),
+    (38, "xor"),
+    (39, "nor"),
+    (44, "dadd"),
+    (45, "daddu"),
+    (46, "dsub"),
+    (47, "dsubu"),
+];
+
+/// The SPECIAL shifts by an immediate amount by function
+const SPECIAL_SHIFTS: &[(u32, &str)] = &[
+    (0, "sll"),
+    (2, "srl"),
+    (3, "sra"),
+    (56, "dsll"),
+    (58, "dsrl"),
+    (59, "dsra"),
+    (60, "dsll32"),
+    (62, "dsrl32"),
+    (63, "dsra32"),
+];
+
+/// The SPECIAL shifts by a register amount by function
+const SPECIAL_VARIABLE_SHIFTS: &[(u32, &str)] = &[
+    (4, "sllv"),
+    (6, "srlv"),
+    (7, "srav"),
+    (20, "dsllv"),
+    (22, "dsrlv"),
+    (23, "dsrav"),
+];
+
+/// The SPECIAL multiplications, divisions, and traps of two source registers by function
+const SPECIAL_SOURCES: &[(u32, &str)] = &[
+    (24, "mult"),
+    (25, "multu"),
+    (26, "div"),
+    (27, "divu"),
+    (28, "dmult"),
+    (29, "dmultu"),
+    (30, "ddiv"),
+    (31, "ddivu"),
+    (48, "tge"),
+    (49, "tgeu"),
+    (50, "tlt"),
+    (51, "tltu"),
+    (52, "teq"),
+    (54, "tne"),
+];
+
+/// The REGIMM traps with an immediate operand by the `rt` field
+const REGIMM_TRAPS: &[(u32, &str)] = &[
+    (8, "tgei"),
+    (9, "tgeiu"),
+    (10, "tlti"),
+    (11, "tltiu"),
+    (12, "teqi"),
+    (14, "tnei"),
+];
+
+/// The instructions with a signed immediate operand by opcode
+const SIGNED_IMMEDIATES: &[(u32, &str)] =
+    &[(8, "addi"), (9, "addiu"), (24, "daddi"), (25, "daddiu")];
+
+/// The logical instructions with an unsigned immediate operand by opcode
+const UNSIGNED_IMMEDIATES: &[(u32, &str)] = &[(12, "andi"), (13, "ori"), (14, "xori")];
+
+/// The loads and stores by opcode, and whether they transfer a floating point register
+const LOADS_STORES: &[(u32, &str, bool)] = &[
+    (26, "ldl", false),
+    (27, "ldr", false),
+    (32, "lb", false),
+    (33, "lh", false),
+    (34, "lwl", false),
+    (35, "lw", false),
+    (36, "lbu", false),
+    (37, "lhu", false),
+    (38, "lwr", false),
+    (39, "lwu", false),
+    (40, "sb", false),
+    (41, "sh", false),
+    (42, "swl", false),
+    (43, "sw", false),
+    (44, "sdl", false),
+    (45, "sdr", false),
+    (46, "swr", false),
+    (48, "ll", false),
+    (49, "lwc1", true),
+    (52, "lld", false),
+    (53, "ldc1", true),
+    (55, "ld", false),
+    (56, "sc", false),
+    (57, "swc1", true),
+    (60, "scd", false),
+    (61, "sdc1", true),
+    (63, "sd", false),
+];
+
+/// The coprocessor 0 operations without operands by function
+const COP0_OPERATIONS: &[(u32, &str)] = &[
+    (1, "tlbr"),
+    (2, "tlbwi"),
+    (6, "tlbwr"),
+    (8, "tlbp"),
+    (24, "eret"),
+    (31, "deret"),
+    (32, "wait"),
+];
+
+/// Look up the name of an instruction by opcode or function in a table of instructions
+fn lookup<T: Copy>(table: &[(u32, T)], opcode: u32) -> Option<T> {
+    table.iter().find(|(o, _)| *o == opcode).map(|(_, v)| *v)
+}
+
+/// Format the target of a branch with a word offset from its delay slot, relative to the
+/// branch
+fn branch_target(offset: i16) -> String {
+    let displacement = INSTRUCTION_SIZE as i64 + ((offset as i64) << 2);
+
+    if displacement < 0 {
+        format!(".-{:#x}", displacement.unsigned_abs())
+    } else {
+        format!(".+{displacement:#x}")
+    }
+}
+
+/// Write an instruction which is not traced. Instructions which are not recognized are written
+/// as data.
+fn write_other(f: &mut Formatter<'_>, insn: u32) -> std::fmt::Result {
+    let field = |shift: u32| (insn >> shift) & 0x1f;
+    let register = |shift: u32| REGISTER_NAMES[field(shift) as usize];
+    let (rs, rt, rd) = (register(21), register(16), register(11));
+    let sa = field(6);
+    let function = insn & 0x3f;
+    let imm = insn as u16 as i16;
+    let uimm = insn as u16;
+
+    match insn >> 26 {
+        // SPECIAL
+        0 => {
+            if let Some(name) = lookup(SPECIAL_REGISTERS, function) {
+                if matches!(name, "addu" | "or" | "daddu") && field(16) == 0 {
+                    write!(f, "move ${rd}, ${rs}")
+                } else {
+                    write!(f, "{name} ${rd}, ${rs}, ${rt}")
+                }
+            } else if let Some(name) = lookup(SPECIAL_SHIFTS, function) {
+                match (name, field(21)) {
+                    ("sll", _) if insn == 0 => write!(f, "nop"),
+                    ("srl", 1) => write!(f, "rotr ${rd}, ${rt}, {sa}"),
+                    _ => write!(f, "{name} ${rd}, ${rt}, {sa}"),
+                }
+            } else if let Some(name) = lookup(SPECIAL_VARIABLE_SHIFTS, function) {
+                write!(f, "{name} ${rd}, ${rt}, ${rs}")
+            } else if let Some(name) = lookup(SPECIAL_SOURCES, function) {
+                write!(f, "{name} ${rs}, ${rt}")
+            } else {
+                match function {
+                    12 => write!(f, "syscall"),
+                    13 => write!(f, "break"),
+                    15 => write!(f, "sync"),
+                    16 => write!(f, "mfhi ${rd}"),
+                    17 => write!(f, "mthi ${rs}"),
+                    18 => write!(f, "mflo ${rd}"),
+                    19 => write!(f, "mtlo ${rs}"),
+                    _ => write!(f, ".word {insn:#x}"),
+                }
+            }
+        }
+        // REGIMM, where rt selects the operation
+        1 => match (lookup(REGIMM_TRAPS, field(16)), field(16)) {
+            (Some(name), _) => write!(f, "{name} ${rs}, {imm}"),
+            (None, 31) => write!(f, "synci {imm}(${rs})"),
+            _ => write!(f, ".word {insn:#x}"),
+        },
+        opcode @ (8 | 9 | 24 | 25) => match lookup(SIGNED_IMMEDIATES, opcode) {
+            Some("addiu" | "daddiu") if field(21) == 0 => write!(f, "li ${rt}, {imm}"),
+            Some(name) => write!(f, "{name} ${rt}, ${rs}, {imm}"),
+            None => write!(f, ".word {insn:#x}"),
+        },
+        opcode @ 12..=14 => match lookup(UNSIGNED_IMMEDIATES, opcode) {
+            Some(name) => write!(f, "{name} ${rt}, ${rs}, {uimm:#x}"),
+            None => write!(f, ".word {insn:#x}"),
+        },
+        15 => write!(f, "lui ${rt}, {uimm:#x}"),
+        // COP0
+        16 => match field(21) {
+            0 => write!(f, "mfc0 ${rt}, ${}, {}", field(11), insn & 7),
+            1 => write!(f, "dmfc0 ${rt}, ${}, {}", field(11), insn & 7),
+            4 => write!(f, "mtc0 ${rt}, ${}, {}", field(11), insn & 7),
+            5 => write!(f, "dmtc0 ${rt}, ${}, {}", field(11), insn & 7),
+            co if co & 0x10 != 0 => match lookup(COP0_OPERATIONS, function) {
+                Some(name) => write!(f, "{name}"),
+                None => write!(f, ".word {insn:#x}"),
+            },
+            _ => write!(f, ".word {insn:#x}"),
+        },
+        // COP1 moves
+        17 => match field(21) {
+            0 => write!(f, "mfc1 ${rt}, $f{}", field(11)),
+            1 => write!(f, "dmfc1 ${rt}, $f{}", field(11)),
+            4 => write!(f, "mtc1 ${rt}, $f{}", field(11)),
+            5 => write!(f, "dmtc1 ${rt}, $f{}", field(11)),
+            _ => write!(f, ".word {insn:#x}"),
+        },
+        // SPECIAL2
+        28 => match function {
+            0 => write!(f, "madd ${rs}, ${rt}"),
+            1 => write!(f, "maddu ${rs}, ${rt}"),
+            2 => write!(f, "mul ${rd}, ${rs}, ${rt}"),
+            4 => write!(f, "msub ${rs}, ${rt}"),
+            5 => write!(f, "msubu ${rs}, ${rt}"),
+            32 => write!(f, "clz ${rd}, ${rs}"),
+            33 => write!(f, "clo ${rd}, ${rs}"),
+            63 => write!(f, "sdbbp"),
+            _ => write!(f, ".word {insn:#x}"),
+        },
+        // SPECIAL3
+        31 => match (function, sa) {
+            (0, _) => write!(f, "ext ${rt}, ${rs}, {sa}, {}", field(11) + 1),
+            (4, _) => write!(
+                f,
+                "ins ${rt}, ${rs}, {sa}, {}",
+                (field(11) + 1).saturating_sub(sa)
+            ),
+            (32, 2) => write!(f, "wsbh ${rd}, ${rt}"),
+            (32, 16) => write!(f, "seb ${rd}, ${rt}"),
+            (32, 24) => write!(f, "seh ${rd}, ${rt}"),
+            (59, _) => write!(f, "rdhwr ${rt}, ${}", field(11)),
+            _ => write!(f, ".word {insn:#x}"),
+        },
+        47 => write!(f, "cache {:#x}, {imm}(${rs})", field(16)),
+        51 => write!(f, "pref {}, {imm}(${rs})", field(16)),
+        opcode => match LOADS_STORES.iter().find(|(o, _, _)| *o == opcode) {
+            Some((_, name, true)) => write!(f, "{name} $f{}, {imm}(${rs})", field(16)),
+            Some((_, name, false)) => write!(f, "{name} ${rt}, {imm}(${rs})"),
+            None => write!(f, ".word {insn:#x}"),
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The condition tested by a conditional branch
+enum Condition {
+    /// `beq`: `rs == rt`
+    Equal,
+    /// `bne`: `rs != rt`
+    NotEqual,
+    /// `blez`: `rs <= 0`
+    LessThanOrEqualZero,
+    /// `bgtz`: `rs > 0`
+    GreaterThanZero,
+    /// `bltz`: `rs < 0`
+    LessThanZero,
+    /// `bgez`: `rs >= 0`
+    GreaterThanOrEqualZero,
+}
+
+impl Condition {
+    /// Whether the condition holds for the values of `rs` and `rt`, as signed integers of the
+    /// width of the registers
+    fn holds(&self, rs: i64, rt: i64) -> bool {
+        match self {
+            Self::Equal => rs == rt,
+            Self::NotEqual => rs != rt,
+            Self::LessThanOrEqualZero => rs <= 0,
+            Self::GreaterThanZero => rs > 0,
+            Self::LessThanZero => rs < 0,
+            Self::GreaterThanOrEqualZero => rs >= 0,
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Equal => "beq",
+                Self::NotEqual => "bne",
+                Self::LessThanOrEqualZero => "blez",
+                Self::GreaterThanZero => "bgtz",
+                Self::LessThanZero => "bltz",
+                Self::GreaterThanOrEqualZero => "bgez",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An instruction decoded from its 32-bit encoding. The instructions which are traced keep the
+/// fields needed for tracing and display, and other instructions keep their encoding so they
+/// can be displayed.
+enum Instruction {
+    /// `j` and `jal`
+    Jump { index: u32, link: bool },
+    /// `jr` and `jalr`
+    JumpRegister { rs: u8, rd: u8, link: bool },
+    /// `beq`, `bne`, `blez`, `bgtz`, `bltz`, `bgez`, and their linking and likely forms
+    Branch {
+        condition: Condition,
+        rs: u8,
+        rt: u8,
+        offset: i16,
+        link: bool,
+        likely: bool,
+    },
+    /// `slt` and `sltu`
+    SetLessThan {
+        rd: u8,
+        rs: u8,
+        rt: u8,
+        unsigned: bool,
+    },
+    /// `slti` and `sltiu`
+    SetLessThanImmediate {
+        rt: u8,
+        rs: u8,
+        imm: i16,
+        unsigned: bool,
+    },
+    /// Any other instruction
+    Other(u32),
+}
+
+impl Instruction {
+    /// Decode an instruction from its 32-bit encoding
+    fn decode(insn: u32) -> Self {
+        let rs = ((insn >> 21) & 0x1f) as u8;
+        let rt = ((insn >> 16) & 0x1f) as u8;
+        let rd = ((insn >> 11) & 0x1f) as u8;
+        let imm = insn as u16 as i16;
+        let branch = |condition, link, likely| Self::Branch {
+            condition,
+            rs,
+            rt,
+            offset: imm,
+            link,
+            likely,
+        };
+
+        match insn >> 26 {
+            // SPECIAL
+            0 => match insn & 0x3f {
+                8 => Self::JumpRegister {
+                    rs,
+                    rd,
+                    link: false,
+                },
+                9 => Self::JumpRegister { rs, rd, link: true },
+                42 => Self::SetLessThan {
+                    rd,
+                    rs,
+                    rt,
+                    unsigned: false,
+                },
+                43 => Self::SetLessThan {
+                    rd,
+                    rs,
+                    rt,
+                    unsigned: true,
+                },
+                _ => Self::Other(insn),
+            },
+            // REGIMM, where rt selects the branch
+            1 => match rt {
+                0 => branch(Condition::LessThanZero, false, false),
+                1 => branch(Condition::GreaterThanOrEqualZero, false, false),
+                2 => branch(Condition::LessThanZero, false, true),
+                3 => branch(Condition::GreaterThanOrEqualZero, false, true),
+                16 => branch(Condition::LessThanZero, true, false),
+                17 => branch(Condition::GreaterThanOrEqualZero, true, false),
+                18 => branch(Condition::LessThanZero, true, true),
+                19 => branch(Condition::GreaterThanOrEqualZero, true, true),
+                _ => Self::Other(insn),
+            },
+            2 => Self::Jump {
+                index: insn & 0x03ff_ffff,
+                link: false,
+            },
+            3 => Self::Jump {
+                index: insn & 0x03ff_ffff,
+                link: true,
+            },
+            4 => branch(Condition::Equal, false, false),
+            5 => branch(Condition::NotEqual, false, false),
+            6 => branch(Condition::LessThanOrEqualZero, false, false),
+            7 => branch(Condition::GreaterThanZero, false, false),
+            10 => Self::SetLessThanImmediate {
+                rt,
+                rs,
+                imm,
+                unsigned: false,
+            },
+            11 => Self::SetLessThanImmediate {
+                rt,
+                rs,
+                imm,
+                unsigned: true,
+            },
+            20 => branch(Condition::Equal, false, true),
+            21 => branch(Condition::NotEqual, false, true),
+            22 => branch(Condition::LessThanOrEqualZero, false, true),
+            23 => branch(Condition::GreaterThanZero, false, true),
+            _ => Self::Other(insn),
+        }
+    }
+
+    /// Whether the instruction is a conditional branch. `b` and `bal` are encoded as
+    /// `beq $zero, $zero` and `bgezal $zero` and are always taken.
+    fn is_conditional(&self) -> bool {
+        match self {
+            Self::Branch {
+                condition: Condition::Equal,
+                rs,
+                rt,
+                ..
+            } => rs != rt,
+            Self::Branch {
+                condition: Condition::GreaterThanOrEqualZero,
+                rs,
+                ..
+            } => *rs != 0,
+            Self::Branch { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// The address a branch or jump at `address` continues at after its delay slot when it is
+    /// taken, unless its target is in a register. Jumps stay in the 256 MiB region of their
+    /// delay slot.
+    fn target(&self, address: u64) -> Option<u64> {
+        let delay_slot = address.wrapping_add(INSTRUCTION_SIZE);
+
+        match self {
+            Self::Jump { index, .. } => Some((delay_slot & !0x0fff_ffff) | ((*index as u64) << 2)),
+            Self::Branch { offset, .. } => {
+                Some(delay_slot.wrapping_add_signed((*offset as i64) << 2))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let register = |r: &u8| REGISTER_NAMES[*r as usize];
+        let unsigned = |u: &bool| if *u { "u" } else { "" };
+
+        match self {
+            // The target of a jump is shown as its offset into the 256 MiB region of the jump
+            Self::Jump { index, link } => {
+                write!(f, "j{} {:#x}", if *link { "al" } else { "" }, index << 2)
+            }
+            Self::JumpRegister {
+                rs, link: false, ..
+            } => write!(f, "jr ${}", register(rs)),
+            Self::JumpRegister {
+                rs,
+                rd: RA,
+                link: true,
+            } => write!(f, "jalr ${}", register(rs)),
+            Self::JumpRegister { rs, rd, link: true } => {
+                write!(f, "jalr ${}, ${}", register(rd), register(rs))
+            }
+            Self::Branch {
+                condition,
+                rs,
+                rt,
+                offset,
+                link,
+                likely,
+            } => {
+                if !self.is_conditional() {
+                    return write!(
+                        f,
+                        "{} {}",
+                        if *link { "bal" } else { "b" },
+                        branch_target(*offset)
+                    );
+                }
+
+                write!(f, "{condition}")?;
+                if *link {
+                    write!(f, "al")?;
+                }
+                if *likely {
+                    write!(f, "l")?;
+                }
+                write!(f, " ${}", register(rs))?;
+                if matches!(condition, Condition::Equal | Condition::NotEqual) {
+                    write!(f, ", ${}", register(rt))?;
+                }
+                write!(f, ", {}", branch_target(*offset))
+            }
+            Self::SetLessThan {
+                rd,
+                rs,
+                rt,
+                unsigned: u,
+            } => write!(
+                f,
+                "slt{} ${}, ${}, ${}",
+                unsigned(u),
+                register(rd),
+                register(rs),
+                register(rt)
+            ),
+            Self::SetLessThanImmediate {
+                rt,
+                rs,
+                imm,
+                unsigned: u,
+            } => write!(
+                f,
+                "slti{} ${}, ${}, {imm}",
+                unsigned(u),
+                register(rt),
+                register(rs)
+            ),
+            Self::Other(insn) => write_other(f, *insn),
+        }
+    }
+}
+
+pub(crate) struct Disassembler {
+    last: Option<Instruction>,
+    big_endian: bool,
+    mips64: bool,
+}
+
+impl Disassembler {
+    pub fn new(big_endian: bool, mips64: bool) -> Self {
+        Self {
+            last: None,
+            big_endian,
+            mips64,
+        }
+    }
+
+    /// Set whether instructions are encoded big-endian or little-endian
+    pub fn set_big_endian(&mut self, big_endian: bool) {
+        self.big_endian = big_endian;
+    }
+
+    /// Decode an instruction in the byte order of the processor
+    fn decode(&self, bytes: &[u8]) -> Result<Instruction> {
+        let bytes: [u8; 4] = bytes
+            .get(..size_of::<u32>())
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| anyhow!("Could not disassemble {:?}", bytes))?;
+
+        Ok(Instruction::decode(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }))
+    }
+
+    fn register(&self, r: u8) -> CmpExpr {
+        CmpExpr::Reg((
+            REGISTER_NAMES[r as usize].to_string(),
+            if self.mips64 { 64 } else { 32 },
+        ))
+    }
+}
+
+impl TracerDisassembler for Disassembler {
+    fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
+        self.last = Some(self.decode(bytes)?);
+        Ok(())
+    }
+
+    fn disassemble_to_string(&mut self, bytes: &[u8]) -> Result<String> {
+        Ok(self.decode(bytes)?.to_string())
+    }
+
+    fn last_len(&self) -> Option<usize> {
+        self.last.map(|_| size_of::<u32>())
+    }
+
+    fn last_was_control_flow(&self) -> bool {
+        match self.last {
+            Some(Instruction::Jump { link: false, .. })
+            | Some(Instruction::Branch { link: false, .. }) => true,
+            Some(Instruction::JumpRegister {
+                rs, link: false, ..
+            }) => rs != RA,
+            _ => false,
+        }
+    }
+
+    fn last_was_call(&self) -> bool {
+        matches!(
+            self.last,
+            Some(
+                Instruction::Jump { link: true, .. }
+                    | Instruction::JumpRegister { link: true, .. }
+                    | Instruction::Branch { link: true, .. }
+            )
+        )
+    }
+
+    fn last_was_ret(&self) -> bool {
+        matches!(
+            self.last,
+            Some(Instruction::JumpRegister {
+                rs: RA,
+                link: false,
+                ..
+            })
+        )
+    }
+
+    fn last_was_cmp(&self) -> bool {
+        match self.last {
+            Some(Instruction::SetLessThan { .. } | Instruction::SetLessThanImmediate { .. }) => {
+                true
+            }
+            Some(branch @ Instruction::Branch { .. }) => branch.is_conditional(),
+            _ => false,
+        }
+    }
+
+    fn last_was_conditional(&self) -> bool {
+        self.last.is_some_and(|last| last.is_conditional())
+    }
+
+    fn last_was_indirect_jump(&self) -> bool {
+        self.last_was_control_flow() && matches!(self.last, Some(Instruction::JumpRegister { .. }))
+    }
+
+    fn cmp(&self) -> Vec<CmpExpr> {
+        if !self.last_was_cmp() {
+            return vec![];
+        }
+
+        match self.last {
+            Some(Instruction::Branch {
+                condition: Condition::Equal | Condition::NotEqual,
+                rs,
+                rt,
+                ..
+            })
+            | Some(Instruction::SetLessThan { rs, rt, .. }) => {
+                vec![self.register(rs), self.register(rt)]
+            }
+            Some(Instruction::Branch { rs, .. }) => vec![self.register(rs), CmpExpr::I16(0)],
+            Some(Instruction::SetLessThanImmediate { rs, imm, .. }) => {
+                vec![self.register(rs), CmpExpr::I16(imm)]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn cmp_type(&self) -> Vec<CmpType> {
+        if !self.last_was_cmp() {
+            return vec![];
+        }
+
+        match self.last {
+            Some(Instruction::Branch {
+                condition: Condition::Equal | Condition::NotEqual,
+                ..
+            }) => vec![CmpType::Equal],
+            Some(Instruction::Branch {
+                condition: Condition::LessThanOrEqualZero,
+                ..
+            }) => vec![CmpType::Equal, CmpType::Lesser],
+            Some(Instruction::Branch {
+                condition: Condition::GreaterThanOrEqualZero,
+                ..
+            }) => vec![CmpType::Equal, CmpType::Greater],
+            Some(Instruction::Branch {
+                condition: Condition::GreaterThanZero,
+                ..
+            }) => vec![CmpType::Greater],
+            Some(Instruction::Branch { .. })
+            | Some(Instruction::SetLessThan { .. })
+            | Some(Instruction::SetLessThanImmediate { .. }) => vec![CmpType::Lesser],
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Condition, Disassembler, Instruction};
+    use crate::{
+        tracer::{CmpExpr, CmpType},
+        traits::TracerDisassembler,
+    };
+
+    /// Encodings with their disassembly and whether they are control flow, a call, a return,
+    /// a comparison, a conditional branch, and an indirect jump
+    const INSTRUCTIONS: &[(u32, &str, [bool; 6])] = &[
+        (
+            0x08100040,
+            "j 0x400100",
+            [true, false, false, false, false, false],
+        ),
+        (
+            0x0c100040,
+            "jal 0x400100",
+            [false, true, false, false, false, false],
+        ),
+        (
+            0x03e00008,
+            "jr $ra",
+            [false, false, true, false, false, false],
+        ),
+        (
+            0x03200008,
+            "jr $t9",
+            [true, false, false, false, false, true],
+        ),
+        (
+            0x0320f809,
+            "jalr $t9",
+            [false, true, false, false, false, false],
+        ),
+        (
+            0x03201009,
+            "jalr $v0, $t9",
+            [false, true, false, false, false, false],
+        ),
+        (
+            0x10850003,
+            "beq $a0, $a1, .+0x10",
+            [true, false, false, true, true, false],
+        ),
+        (
+            0x10000001,
+            "b .+0x8",
+            [true, false, false, false, false, false],
+        ),
+        (
+            0x1440fffe,
+            "bne $v0, $zero, .-0x4",
+            [true, false, false, true, true, false],
+        ),
+        (
+            0x04800001,
+            "bltz $a0, .+0x8",
+            [true, false, false, true, true, false],
+        ),
+        (
+            0x04110001,
+            "bal .+0x8",
+            [false, true, false, false, false, false],
+        ),
+        (
+            0x04910001,
+            "bgezal $a0, .+0x8",
+            [false, true, false, true, true, false],
+        ),
+        (
+            0x50850003,
+            "beql $a0, $a1, .+0x10",
+            [true, false, false, true, true, false],
+        ),
+        (
+            0x18800001,
+            "blez $a0, .+0x8",
+            [true, false, false, true, true, false],
+        ),
+        (
+            0x0085102a,
+            "slt $v0, $a0, $a1",
+            [false, false, false, true, false, false],
+        ),
+        (
+            0x2c82000a,
+            "sltiu $v0, $a0, 10",
+            [false, false, false, true, false, false],
+        ),
+        (0x00000000, "nop", [false; 6]),
+        (0x27bdffe0, "addiu $sp, $sp, -32", [false; 6]),
+        (0x24020001, "li $v0, 1", [false; 6]),
+        (0x8fbf001c, "lw $ra, 28($sp)", [false; 6]),
+        (0xafbf001c, "sw $ra, 28($sp)", [false; 6]),
+        (0xd7a00008, "ldc1 $f0, 8($sp)", [false; 6]),
+        (0x3c1c0042, "lui $gp, 0x42", [false; 6]),
+        (0x03a0f025, "move $fp, $sp", [false; 6]),
+        (0x00851021, "addu $v0, $a0, $a1", [false; 6]),
+        (0x00041080, "sll $v0, $a0, 2", [false; 6]),
+        (0x70851002, "mul $v0, $a0, $a1", [false; 6]),
+        (0x40086000, "mfc0 $t0, $12, 0", [false; 6]),
+        (0x42000018, "eret", [false; 6]),
+        (0x0000000c, "syscall", [false; 6]),
+        (0x48000000, ".word 0x48000000", [false; 6]),
+    ];
+
+    #[test]
+    fn test_decode_instructions() {
+        for (insn, disassembly, classification) in INSTRUCTIONS {
+            for big_endian in [true, false] {
+                let mut disassembler = Disassembler::new(big_endian, false);
+                let bytes = if big_endian {
+                    insn.to_be_bytes()
+                } else {
+                    insn.to_le_bytes()
+                };
+
+                disassembler
+                    .disassemble(&bytes)
+                    .expect("Failed to disassemble");
+
+                assert_eq!(disassembler.last_len(), Some(4));
+                assert_eq!(
+                    [
+                        disassembler.last_was_control_flow(),
+                        disassembler.last_was_call(),
+                        disassembler.last_was_ret(),
+                        disassembler.last_was_cmp(),
+                        disassembler.last_was_conditional(),
+                        disassembler.last_was_indirect_jump(),
+                    ],
+                    *classification,
+                    "{disassembly}"
+                );
+                assert_eq!(
+                    disassembler
+                        .disassemble_to_string(&bytes)
+                        .expect("Failed to disassemble"),
+                    *disassembly,
+                    "{insn:#010x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_operands() {
+        let register = |name: &str, width| CmpExpr::Reg((name.to_string(), width));
+        let operands = |insn: u32, mips64| {
+            let mut disassembler = Disassembler::new(true, mips64);
+            disassembler
+                .disassemble(&insn.to_be_bytes())
+                .expect("Failed to disassemble");
+            (disassembler.cmp(), disassembler.cmp_type())
+        };
+
+        // beq $a0, $a1, .+0x10
+        assert_eq!(
+            operands(0x10850003, false),
+            (
+                vec![register("a0", 32), register("a1", 32)],
+                vec![CmpType::Equal]
+            )
+        );
+        // bltz $a0, .+0x8
+        assert_eq!(
+            operands(0x04800001, true),
+            (
+                vec![register("a0", 64), CmpExpr::I16(0)],
+                vec![CmpType::Lesser]
+            )
+        );
+        // blez $a0, .+0x8
+        assert_eq!(
+            operands(0x18800001, false).1,
+            vec![CmpType::Equal, CmpType::Lesser]
+        );
+        // sltiu $v0, $a0, 10
+        assert_eq!(
+            operands(0x2c82000a, false),
+            (
+                vec![register("a0", 32), CmpExpr::I16(10)],
+                vec![CmpType::Lesser]
+            )
+        );
+        // b .+0x8 and bal .+0x8 are always taken
+        assert_eq!(operands(0x10000001, false), (vec![], vec![]));
+        assert_eq!(operands(0x04110001, false), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_branch_targets() {
+        // beq $a0, $a1, .+0x10
+        assert_eq!(
+            Instruction::decode(0x10850003).target(0x400000),
+            Some(0x400010)
+        );
+        // bne $v0, $zero, .-0x4
+        assert_eq!(
+            Instruction::decode(0x1440fffe).target(0x400008),
+            Some(0x400004)
+        );
+        // Jumps stay in the 256 MiB region of their delay slot, even when the jump is the
+        // last instruction of the previous region
+        assert_eq!(
+            Instruction::decode(0x08100040).target(0x0ffffffc),
+            Some(0x10400100)
+        );
+        assert_eq!(
+            Instruction::decode(0x08100040).target(0xffffffff80001000),
+            Some(0xffffffff80400100)
+        );
+        // jr $t9
+        assert_eq!(Instruction::decode(0x03200008).target(0x400000), None);
+    }
+
+    #[test]
+    fn test_branch_conditions() {
+        let conditions = [
+            (Condition::Equal, [false, true, false]),
+            (Condition::NotEqual, [true, false, true]),
+            (Condition::LessThanOrEqualZero, [true, true, false]),
+            (Condition::GreaterThanZero, [false, false, true]),
+            (Condition::LessThanZero, [true, false, false]),
+            (Condition::GreaterThanOrEqualZero, [false, true, true]),
+        ];
+
+        for (condition, holds) in conditions {
+            assert_eq!(
+                [-1, 0, 1].map(|rs| condition.holds(rs, 0)),
+                holds,
+                "{condition}"
+            );
+        }
+    }
+}
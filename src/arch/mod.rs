@@ -4,33 +4,54 @@
 //! Architecture specific data and definitions
 
 use self::{
-    aarch64::AArch64ArchitectureOperations, arm::ARMArchitectureOperations,
-    risc_v::RISCVArchitectureOperations, x86::X86ArchitectureOperations,
+    aarch64::AArch64ArchitectureOperations,
+    arm::ARMArchitectureOperations,
+    decode_cache::{DecodeCache, DecodedInstruction},
+    mips::MIPSArchitectureOperations,
+    powerpc::PowerPCArchitectureOperations,
+    risc_v::RISCVArchitectureOperations,
+    x86::X86ArchitectureOperations,
     x86_64::X86_64ArchitectureOperations,
 };
 use crate::{
     tracer::TraceEntry, traits::TracerDisassembler, ManualStartAddress, ManualStartInfo, StartInfo,
-    StartPhysicalAddress, StartSize,
+    StartPage, StartPhysicalAddress, StartSize,
 };
 use anyhow::anyhow;
 use anyhow::{bail, ensure, Error, Result};
 use raw_cstr::AsRawCstr;
+use serde::{Deserialize, Serialize};
 use simics::{
     api::{
-        read_phys_memory, sys::instruction_handle_t, write_byte, Access, AttrValueType, ConfObject,
-        CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface, CycleInterface,
+        get_interface,
+        sys::{cpu_endian_t, instruction_handle_t, physical_block_t},
+        write_byte, Access, AttrValueType, ConfObject, CpuInstructionQueryInterface,
+        CpuInstrumentationSubscribeInterface, CycleInterface, ExceptionInterface,
         IntRegisterInterface, ProcessorInfoV2Interface,
     },
     read_byte,
 };
-use std::{fmt::Debug, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    mem::size_of,
+    rc::Rc,
+    slice::from_raw_parts,
+    str::FromStr,
+};
 
 pub mod aarch64;
 pub mod arm;
+pub mod decode_cache;
+pub mod mips;
+pub mod powerpc;
 pub mod risc_v;
 pub mod x86;
 pub mod x86_64;
 
+/// The size of the pages virtual memory is translated in
+pub(crate) const PAGE_SIZE: u64 = 0x1000;
+
 #[derive(Debug, Clone)]
 /// An architecture hint that can be parsed from a string
 pub(crate) enum ArchitectureHint {
@@ -38,12 +59,30 @@ pub(crate) enum ArchitectureHint {
     X86_64,
     /// The architecture is i386
     I386,
+    /// The architecture is an i386 executing 16-bit real mode code, such as early BIOS code,
+    /// option ROMs and bootloaders
+    I8086,
     /// The architecture is RISCV
     Riscv,
     /// The architecture is arm
     Arm,
+    /// The architecture is an arm microcontroller profile (Cortex-M), which only executes
+    /// Thumb code
+    CortexM,
+    /// The architecture is a big-endian (BE8) arm
+    ArmBigEndian,
     /// The architecture is aarch64
     Aarch64,
+    /// The architecture is a big-endian aarch64
+    Aarch64BigEndian,
+    /// The architecture is PowerPC (32 or 64-bit)
+    PowerPC,
+    /// The architecture is a little-endian PowerPC (32 or 64-bit)
+    PowerPCLittleEndian,
+    /// The architecture is MIPS (32 or 64-bit)
+    Mips,
+    /// The architecture is a little-endian MIPS (32 or 64-bit)
+    MipsLittleEndian,
 }
 
 impl FromStr for ArchitectureHint {
@@ -53,9 +92,17 @@ impl FromStr for ArchitectureHint {
         Ok(match s {
             "x86-64" => Self::X86_64,
             "i386" | "i486" | "i586" | "i686" | "ia-32" | "x86" => Self::I386,
+            "i8086" | "8086" | "real-mode" | "x86-16" => Self::I8086,
             "riscv" | "risc-v" | "riscv32" | "riscv64" => Self::Riscv,
             "armv4" | "armv5" | "armv6" | "armv7" | "arm" | "arm32" => Self::Arm,
+            "cortex-m" | "armv6-m" | "armv7-m" | "armv7e-m" | "armv8-m" => Self::CortexM,
+            "armeb" | "armbe" | "arm-be8" => Self::ArmBigEndian,
             "aarch64" | "armv8" | "arm64" => Self::Aarch64,
+            "aarch64_be" | "aarch64-be" | "arm64be" => Self::Aarch64BigEndian,
+            "ppc" | "ppc32" | "ppc64" | "powerpc" | "powerpc64" => Self::PowerPC,
+            "ppcle" | "ppc64le" | "powerpcle" | "powerpc64le" => Self::PowerPCLittleEndian,
+            "mips" | "mips32" | "mips64" => Self::Mips,
+            "mipsel" | "mips64el" => Self::MipsLittleEndian,
             _ => bail!("Unknown hint: {}", s),
         })
     }
@@ -66,9 +113,17 @@ impl From<ArchitectureHint> for AttrValueType {
         match val {
             ArchitectureHint::X86_64 => "x86-64",
             ArchitectureHint::I386 => "i386",
+            ArchitectureHint::I8086 => "i8086",
             ArchitectureHint::Riscv => "risc-v",
             ArchitectureHint::Arm => "arm",
+            ArchitectureHint::CortexM => "cortex-m",
+            ArchitectureHint::ArmBigEndian => "armeb",
             ArchitectureHint::Aarch64 => "aarch64",
+            ArchitectureHint::Aarch64BigEndian => "aarch64_be",
+            ArchitectureHint::PowerPC => "ppc",
+            ArchitectureHint::PowerPCLittleEndian => "ppc64le",
+            ArchitectureHint::Mips => "mips",
+            ArchitectureHint::MipsLittleEndian => "mipsel",
         }
         .into()
     }
@@ -77,26 +132,272 @@ impl From<ArchitectureHint> for AttrValueType {
 impl ArchitectureHint {
     /// Return the architecture for the given CPU object
     pub fn architecture(&self, cpu: *mut ConfObject) -> Result<Architecture> {
-        Ok(match self {
+        let mut architecture = match self {
             ArchitectureHint::X86_64 => {
                 Architecture::X86_64(X86_64ArchitectureOperations::new_unchecked(cpu)?)
             }
             ArchitectureHint::I386 => {
                 Architecture::I386(X86ArchitectureOperations::new_unchecked(cpu)?)
             }
+            ArchitectureHint::I8086 => {
+                Architecture::I386(X86ArchitectureOperations::new_unchecked(cpu)?.with_real_mode())
+            }
             ArchitectureHint::Riscv => {
                 Architecture::Riscv(RISCVArchitectureOperations::new_unchecked(cpu)?)
             }
-            ArchitectureHint::Arm => {
+            ArchitectureHint::Arm | ArchitectureHint::ArmBigEndian => {
                 Architecture::Arm(ARMArchitectureOperations::new_unchecked(cpu)?)
             }
-            ArchitectureHint::Aarch64 => {
+            ArchitectureHint::CortexM => {
+                Architecture::Arm(ARMArchitectureOperations::new_unchecked(cpu)?.with_m_profile())
+            }
+            ArchitectureHint::Aarch64 | ArchitectureHint::Aarch64BigEndian => {
                 Architecture::Aarch64(AArch64ArchitectureOperations::new_unchecked(cpu)?)
             }
+            ArchitectureHint::PowerPC | ArchitectureHint::PowerPCLittleEndian => {
+                Architecture::PowerPC(PowerPCArchitectureOperations::new_unchecked(cpu)?)
+            }
+            ArchitectureHint::Mips | ArchitectureHint::MipsLittleEndian => {
+                Architecture::Mips(MIPSArchitectureOperations::new_unchecked(cpu)?)
+            }
+        };
+
+        if let Some(endianness) = self.endianness() {
+            *architecture.endianness() = endianness;
+        }
+
+        Ok(architecture)
+    }
+
+    /// The byte order named by the hint, if it names one. Otherwise, the byte order
+    /// reported by the processor is used.
+    fn endianness(&self) -> Option<Endianness> {
+        match self {
+            ArchitectureHint::ArmBigEndian | ArchitectureHint::Aarch64BigEndian => {
+                Some(Endianness::Big)
+            }
+            ArchitectureHint::PowerPCLittleEndian | ArchitectureHint::MipsLittleEndian => {
+                Some(Endianness::Little)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The byte order of integers in target memory
+pub(crate) enum Endianness {
+    /// The least significant byte is stored first
+    Little,
+    /// The most significant byte is stored first
+    Big,
+}
+
+impl FromStr for Endianness {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "little" | "le" | "little-endian" => Self::Little,
+            "big" | "be" | "big-endian" => Self::Big,
+            _ => bail!("Unknown endianness: {}", s),
+        })
+    }
+}
+
+impl Display for Endianness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Endianness::Little => "little",
+                Endianness::Big => "big",
+            }
+        )
+    }
+}
+
+impl Endianness {
+    /// The byte order the processor reports for its data accesses
+    pub fn of(processor_info_v2: &mut ProcessorInfoV2Interface) -> Result<Self> {
+        Ok(
+            if processor_info_v2.get_endian()? == cpu_endian_t::Sim_Endian_Big {
+                Self::Big
+            } else {
+                Self::Little
+            },
+        )
+    }
+
+    /// Encode the low `width` bytes of a value in this byte order
+    pub fn encode(self, value: u64, width: usize) -> Vec<u8> {
+        match self {
+            Endianness::Little => value.to_le_bytes()[..width].to_vec(),
+            Endianness::Big => value.to_be_bytes()[size_of::<u64>() - width..].to_vec(),
+        }
+    }
+
+    /// Decode a value of up to eight bytes in this byte order
+    pub fn decode(self, bytes: &[u8]) -> u64 {
+        let mut buffer = [0u8; size_of::<u64>()];
+        let width = bytes.len().min(buffer.len());
+
+        match self {
+            Endianness::Little => {
+                buffer[..width].copy_from_slice(&bytes[..width]);
+                u64::from_le_bytes(buffer)
+            }
+            Endianness::Big => {
+                buffer[size_of::<u64>() - width..].copy_from_slice(&bytes[..width]);
+                u64::from_be_bytes(buffer)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
+/// A calling convention used to locate the arguments of a function on entry
+pub(crate) enum CallingConvention {
+    /// The default calling convention for the architecture: System V on x86_64 (Microsoft
+    /// x64 for routines under Windows OS awareness), cdecl on i386, and the standard procedure
+    /// call standard on RISC-V, ARM, and AArch64
+    #[default]
+    Default,
+    /// The System V AMD64 calling convention, with arguments in rdi, rsi, rdx, rcx, r8, r9
+    SysV64,
+    /// The Microsoft x64 calling convention, with arguments in rcx, rdx, r8, r9 and then on
+    /// the stack
+    Win64,
+    /// The cdecl (and stdcall) calling convention, with all arguments on the stack
+    Cdecl,
+}
+
+impl FromStr for CallingConvention {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "default" => Self::Default,
+            "sysv64" | "sysv" => Self::SysV64,
+            "win64" | "ms64" | "efiapi" => Self::Win64,
+            "cdecl" | "stdcall" => Self::Cdecl,
+            _ => bail!("Unknown calling convention: {}", s),
         })
     }
 }
 
+impl Display for CallingConvention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                CallingConvention::Default => "default",
+                CallingConvention::SysV64 => "sysv64",
+                CallingConvention::Win64 => "win64",
+                CallingConvention::Cdecl => "cdecl",
+            }
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A fault taken by a processor which handles faults in guest fault handlers, which would
+/// otherwise only be detected as a timeout once the handler hangs
+pub(crate) struct Fault {
+    /// The architecture-level name of the fault exception
+    pub name: String,
+    /// The address of the instruction which faulted
+    pub pc: u64,
+    /// The causes of the fault reported by the fault status registers
+    pub causes: Vec<String>,
+    /// The values of the fault status and address registers and the stacked exception frame
+    pub registers: BTreeMap<String, u64>,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {:#x}", self.name, self.pc)?;
+
+        if !self.causes.is_empty() {
+            write!(f, " ({})", self.causes.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolve architecture-level exception names to the exception numbers of a processor model
+/// using the processor's exception interface, for architectures whose SIMICS exception numbers
+/// are not the architectural exception vectors. Each name is given with the names the processor
+/// models of the architecture use for the exception, and names the model does not implement are
+/// skipped.
+pub(crate) fn model_exceptions(
+    cpu: *mut ConfObject,
+    names: &[(&'static str, &[&str])],
+) -> Vec<(&'static str, i64)> {
+    let Ok(mut exception) = get_interface::<ExceptionInterface>(cpu) else {
+        return Vec::new();
+    };
+
+    names
+        .iter()
+        .flat_map(|(name, model_names)| {
+            model_names
+                .iter()
+                .filter_map(|model_name| {
+                    model_name
+                        .as_raw_cstr()
+                        .ok()
+                        .and_then(|model_name| exception.get_number(model_name).ok())
+                        .filter(|number| *number >= 0)
+                })
+                .map(|number| (*name, number as i64))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Split a virtual buffer into the parts of it in each page and translate the address of
+/// each part with `translate`, which returns `None` for unmapped addresses
+pub(crate) fn translate_pages<F>(
+    address: u64,
+    size: usize,
+    mut translate: F,
+) -> Result<Vec<StartPage>>
+where
+    F: FnMut(u64) -> Result<Option<u64>>,
+{
+    let end = address.checked_add(size as u64).ok_or_else(|| {
+        anyhow!("Testcase buffer at {address:#x} of size {size:#x} overflows the address space")
+    })?;
+    let mut pages = Vec::new();
+    let mut current = address;
+
+    while current < end {
+        let page_end = ((current / PAGE_SIZE) + 1)
+            .saturating_mul(PAGE_SIZE)
+            .min(end);
+        let physical_address = translate(current)?.ok_or_else(|| {
+            anyhow!(
+                "Testcase buffer address {current:#x} ({:#x} bytes into the buffer at {address:#x}) is not mapped",
+                current - address
+            )
+        })?;
+
+        pages.push(StartPage {
+            offset: (current - address) as usize,
+            physical_address,
+            size: (page_end - current) as usize,
+        });
+
+        current = page_end;
+    }
+
+    Ok(pages)
+}
+
 pub(crate) enum Architecture {
     /// The x86_64 architecture
     X86_64(X86_64ArchitectureOperations),
@@ -108,6 +409,10 @@ pub(crate) enum Architecture {
     Arm(ARMArchitectureOperations),
     /// The AARCH64 architecture (v8 and above)
     Aarch64(AArch64ArchitectureOperations),
+    /// The PowerPC architecture (32 and 64-bit)
+    PowerPC(PowerPCArchitectureOperations),
+    /// The MIPS architecture (32 and 64-bit)
+    Mips(MIPSArchitectureOperations),
 }
 
 impl Debug for Architecture {
@@ -121,6 +426,8 @@ impl Debug for Architecture {
                 Architecture::Riscv(_) => "risc-v",
                 Architecture::Arm(_) => "arm",
                 Architecture::Aarch64(_) => "aarch64",
+                Architecture::PowerPC(_) => "ppc",
+                Architecture::Mips(_) => "mips",
             }
         )
     }
@@ -168,6 +475,194 @@ pub trait ArchitectureOperations {
     /// Return a mutable reference to the interface for querying CPU cycles and timing
     fn cycle(&mut self) -> &mut CycleInterface;
 
+    /// Return a mutable reference to the cache of decoded instructions for this processor
+    fn decode_cache(&mut self) -> &mut DecodeCache;
+
+    /// Return a mutable reference to the byte order of integers in target memory, which
+    /// defaults to the byte order reported by the processor and can be overridden
+    fn endianness(&mut self) -> &mut Endianness;
+
+    /// Return the architecture-level names of the exceptions this processor raises, each with
+    /// the SIMICS exception number of the exception on this processor model. An exception may
+    /// have more than one name, for example an architecture-specific name and a name common to
+    /// several architectures like `illegal-instruction`.
+    fn exceptions(&self) -> &[(&'static str, i64)];
+
+    /// Return the SIMICS exception numbers of the exceptions with an architecture-level name
+    fn exception_numbers(&self, name: &str) -> Vec<i64> {
+        self.exceptions()
+            .iter()
+            .filter(|(n, _)| *n == name)
+            .map(|(_, number)| *number)
+            .collect()
+    }
+
+    /// Return the architecture-level name of a SIMICS exception number, if it has one
+    fn exception_name(&self, number: i64) -> Option<&'static str> {
+        self.exceptions()
+            .iter()
+            .find(|(_, n)| *n == number)
+            .map(|(name, _)| *name)
+    }
+
+    /// Decode the fault an exception reports, if the exception enters a fault handler of the
+    /// guest. By default no faults are decoded, and faults are only detected as solutions by
+    /// their exception number.
+    fn fault(&mut self, _exception: i64) -> Result<Option<Fault>> {
+        Ok(None)
+    }
+
+    /// Whether edges can be traced when only control flow and comparison instructions are
+    /// instrumented. Architectures which need to observe the instructions following a branch
+    /// to trace its edge must instrument every instruction.
+    fn decode_time_filtering(&self) -> bool {
+        true
+    }
+
+    /// Update the disassembler for the current execution state of the processor before each
+    /// instruction is decoded, for architectures with more than one instruction set. Returns
+    /// the decoding mode, which distinguishes cached decodings of the same bytes in different
+    /// instruction sets.
+    fn update_decoder_mode(&mut self) -> Result<u8> {
+        Ok(0)
+    }
+
+    /// Decode the instruction being executed, using the decoding provided by the processor
+    /// model when the instruction was cached, or the cached decoding if the same bytes have
+    /// already been decoded at the same physical address in the same mode
+    fn decode(
+        &mut self,
+        instruction_query: *mut instruction_handle_t,
+    ) -> Result<Rc<DecodedInstruction>> {
+        // Tracing may depend on the execution state updated with the decoding mode, so it is
+        // updated even when the decoding is already known
+        let mode = self.update_decoder_mode()?;
+
+        if let Some(decoded) = self.decode_cache().take_current() {
+            return Ok(decoded);
+        }
+
+        let physical_address = self
+            .cpu_instruction_query()
+            .physical_address(instruction_query)?;
+        let instruction_bytes = self
+            .cpu_instruction_query()
+            .get_instruction_bytes(instruction_query)?;
+        let bytes = unsafe { from_raw_parts(instruction_bytes.data, instruction_bytes.size) };
+
+        if let Some(decoded) = self.decode_cache().get(physical_address, mode, bytes) {
+            return Ok(decoded);
+        }
+
+        self.disassembler().disassemble(bytes)?;
+        let decoded = DecodedInstruction::from_disassembler(bytes, self.disassembler());
+
+        Ok(self.decode_cache().insert(physical_address, mode, decoded))
+    }
+
+    /// Read the value of an integer register by name
+    fn read_register(&mut self, name: &str) -> Result<u64> {
+        Ok(self
+            .int_register()
+            .get_number(name.as_raw_cstr()?)
+            .and_then(|n| self.int_register().read(n))?)
+    }
+
+    /// Translate a virtual address to a physical address for an access of a given type
+    fn virtual_to_physical(&mut self, address: u64, access: Access) -> Result<physical_block_t> {
+        Ok(self
+            .processor_info_v2()
+            .logical_to_physical(address, access)?)
+    }
+
+    /// Convert a pointer passed by the target in a register to the virtual address it points
+    /// to. Pointers are virtual addresses except in segmented code, where they are offsets
+    /// into a segment.
+    fn pointer_address(&mut self, pointer: u64) -> Result<u64> {
+        Ok(pointer)
+    }
+
+    /// Read memory at a virtual address, translating the address once for each page
+    fn read_virtual_memory(&mut self, address: u64, size: usize) -> Result<Vec<u8>> {
+        let physical_memory = self.processor_info_v2().get_physical_memory()?;
+        let end = address.checked_add(size as u64).ok_or_else(|| {
+            anyhow!("Reading {size:#x} bytes at {address:#x} overflows the address space")
+        })?;
+        let mut bytes = Vec::with_capacity(size);
+        let mut current = address;
+
+        while current < end {
+            let page_end = ((current / PAGE_SIZE) + 1).saturating_mul(PAGE_SIZE);
+            let physical_address = self.virtual_to_physical(current, Access::Sim_Access_Read)?;
+
+            if physical_address.valid == 0 {
+                bail!("Address {current:#x} is not mapped");
+            }
+
+            for offset in 0..(page_end.min(end) - current) {
+                bytes.push(read_byte(
+                    physical_memory,
+                    physical_address.address + offset,
+                )?);
+            }
+
+            current = page_end.min(end);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Translate a virtual testcase buffer one page at a time for writing, because pages
+    /// which are contiguous in virtual memory may be scattered in physical memory. Fails if
+    /// any page of the buffer is unmapped.
+    fn translate_buffer(&mut self, address: u64, size: usize) -> Result<Vec<StartPage>> {
+        translate_pages(address, size, |page| {
+            let physical_address = self.virtual_to_physical(page, Access::Sim_Access_Write)?;
+            Ok((physical_address.valid != 0).then_some(physical_address.address))
+        })
+    }
+
+    /// Read the contents of a testcase buffer one page at a time from the physical pages it
+    /// is mapped to
+    fn read_buffer(&mut self, pages: &[StartPage]) -> Result<Vec<u8>> {
+        let physical_memory = self.processor_info_v2().get_physical_memory()?;
+        let mut contents = Vec::with_capacity(pages.iter().map(|page| page.size).sum());
+
+        for page in pages {
+            for offset in 0..page.size as u64 {
+                let address = page.physical_address + offset;
+
+                contents.push(read_byte(physical_memory, address).map_err(|e| {
+                    anyhow!(
+                        "Failed to read byte at {address:#x} ({:#x} bytes into the buffer): {e}",
+                        page.offset as u64 + offset
+                    )
+                })?);
+            }
+        }
+
+        Ok(contents)
+    }
+
+    /// Read an integer of `size` bytes at a virtual address in the target's byte order
+    fn read_virtual_integer(&mut self, address: u64, size: usize) -> Result<u64> {
+        let bytes = self.read_virtual_memory(address, size)?;
+        Ok(self.endianness().decode(&bytes))
+    }
+
+    /// Read an integer of `size` bytes at a physical address in the target's byte order
+    fn read_physical_integer(&mut self, address: u64, size: usize) -> Result<u64> {
+        let physical_memory = self.processor_info_v2().get_physical_memory()?;
+        let bytes = (0..size as u64)
+            .map(|i| read_byte(physical_memory, address + i))
+            .collect::<simics::Result<Vec<_>>>()?;
+        Ok(self.endianness().decode(&bytes))
+    }
+
+    /// Return the value of an integer or pointer argument of a function on entry to the
+    /// function, according to a calling convention
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64>;
+
     /// Return the value of the magic index selector register, which is used to determine
     /// whether a magic instruction should be used or skipped.
     fn get_magic_index_selector(&mut self) -> Result<u64> {
@@ -189,13 +684,13 @@ pub trait ArchitectureOperations {
             .int_register()
             .get_number(Self::ARGUMENT_REGISTER_1.as_raw_cstr()?)?;
         let buffer_logical_address = self.int_register().read(buffer_register_number)?;
+        let buffer_logical_address = self.pointer_address(buffer_logical_address)?;
         let size_ptr_logical_address = self.int_register().read(size_ptr_register_number)?;
-        let buffer_physical_address_block = self
-            .processor_info_v2()
-            .logical_to_physical(buffer_logical_address, Access::Sim_Access_Read)?;
-        let size_ptr_physical_address_block = self
-            .processor_info_v2()
-            .logical_to_physical(size_ptr_logical_address, Access::Sim_Access_Read)?;
+        let size_ptr_logical_address = self.pointer_address(size_ptr_logical_address)?;
+        let buffer_physical_address_block =
+            self.virtual_to_physical(buffer_logical_address, Access::Sim_Access_Write)?;
+        let size_ptr_physical_address_block =
+            self.virtual_to_physical(size_ptr_logical_address, Access::Sim_Access_Write)?;
 
         ensure!(
             buffer_physical_address_block.valid != 0,
@@ -212,27 +707,11 @@ pub trait ArchitectureOperations {
             self.processor_info_v2().get_logical_address_width()? / u8::BITS as i32
         };
 
-        let size = read_phys_memory(
-            self.cpu(),
-            size_ptr_physical_address_block.address,
-            size_size,
-        )?;
-
-        let contents = (0..size)
-            .map(|i| {
-                read_byte(
-                    self.processor_info_v2().get_physical_memory()?,
-                    buffer_physical_address_block.address + i,
-                )
-                .map_err(|e| {
-                    anyhow!(
-                        "Failed to read byte at {:#x}: {}",
-                        buffer_physical_address_block.address + i,
-                        e
-                    )
-                })
-            })
-            .collect::<Result<Vec<_>>>()?;
+        let size = self
+            .read_physical_integer(size_ptr_physical_address_block.address, size_size as usize)?;
+
+        let pages = self.translate_buffer(buffer_logical_address, size as usize)?;
+        let contents = self.read_buffer(&pages)?;
 
         Ok(StartInfo::builder()
             .address(
@@ -243,6 +722,7 @@ pub trait ArchitectureOperations {
                 },
             )
             .contents(contents)
+            .pages(pages)
             .size(StartSize::SizePtr {
                 address: if size_ptr_physical_address_block.address != size_ptr_logical_address {
                     StartPhysicalAddress::WasVirtual(size_ptr_physical_address_block.address)
@@ -266,31 +746,18 @@ pub trait ArchitectureOperations {
             .int_register()
             .get_number(Self::ARGUMENT_REGISTER_1.as_raw_cstr()?)?;
         let buffer_logical_address = self.int_register().read(buffer_register_number)?;
+        let buffer_logical_address = self.pointer_address(buffer_logical_address)?;
         let size_val = self.int_register().read(size_val_register_number)?;
-        let buffer_physical_address_block = self
-            .processor_info_v2()
-            .logical_to_physical(buffer_logical_address, Access::Sim_Access_Read)?;
+        let buffer_physical_address_block =
+            self.virtual_to_physical(buffer_logical_address, Access::Sim_Access_Write)?;
 
         ensure!(
             buffer_physical_address_block.valid != 0,
             "Invalid linear address found in magic start buffer register {buffer_register_number}: {buffer_logical_address:#x}"
         );
 
-        let contents = (0..size_val)
-            .map(|i| {
-                read_byte(
-                    self.processor_info_v2().get_physical_memory()?,
-                    buffer_physical_address_block.address + i,
-                )
-                .map_err(|e| {
-                    anyhow!(
-                        "Failed to read byte at {:#x}: {}",
-                        buffer_physical_address_block.address + i,
-                        e
-                    )
-                })
-            })
-            .collect::<Result<Vec<_>>>()?;
+        let pages = self.translate_buffer(buffer_logical_address, size_val as usize)?;
+        let contents = self.read_buffer(&pages)?;
 
         Ok(StartInfo::builder()
             .address(
@@ -301,6 +768,7 @@ pub trait ArchitectureOperations {
                 },
             )
             .contents(contents)
+            .pages(pages)
             .size(StartSize::MaxSize(size_val as usize))
             .build())
     }
@@ -322,16 +790,16 @@ pub trait ArchitectureOperations {
             .get_number(Self::ARGUMENT_REGISTER_2.as_raw_cstr()?)?;
 
         let buffer_logical_address = self.int_register().read(buffer_register_number)?;
+        let buffer_logical_address = self.pointer_address(buffer_logical_address)?;
         let size_ptr_logical_address = self.int_register().read(size_ptr_register_number)?;
+        let size_ptr_logical_address = self.pointer_address(size_ptr_logical_address)?;
         let size_val = self.int_register().read(size_val_register_number)?;
 
-        let buffer_physical_address_block = self
-            .processor_info_v2()
-            .logical_to_physical(buffer_logical_address, Access::Sim_Access_Read)?;
+        let buffer_physical_address_block =
+            self.virtual_to_physical(buffer_logical_address, Access::Sim_Access_Write)?;
 
-        let size_ptr_physical_address_block = self
-            .processor_info_v2()
-            .logical_to_physical(size_ptr_logical_address, Access::Sim_Access_Read)?;
+        let size_ptr_physical_address_block =
+            self.virtual_to_physical(size_ptr_logical_address, Access::Sim_Access_Write)?;
 
         ensure!(
             buffer_physical_address_block.valid != 0,
@@ -342,21 +810,8 @@ pub trait ArchitectureOperations {
             "Invalid linear address found in magic start size register {size_ptr_register_number}: {size_ptr_logical_address:#x}"
         );
 
-        let contents = (0..size_val)
-            .map(|i| {
-                read_byte(
-                    self.processor_info_v2().get_physical_memory()?,
-                    buffer_physical_address_block.address + i,
-                )
-                .map_err(|e| {
-                    anyhow!(
-                        "Failed to read byte at {:#x}: {}",
-                        buffer_physical_address_block.address + i,
-                        e
-                    )
-                })
-            })
-            .collect::<Result<Vec<_>>>()?;
+        let pages = self.translate_buffer(buffer_logical_address, size_val as usize)?;
+        let contents = self.read_buffer(&pages)?;
 
         Ok(StartInfo::builder()
             .address(
@@ -367,6 +822,7 @@ pub trait ArchitectureOperations {
                 },
             )
             .contents(contents)
+            .pages(pages)
             .size(StartSize::SizePtrAndMaxSize {
                 address: if size_ptr_physical_address_block.address != size_ptr_logical_address {
                     StartPhysicalAddress::WasVirtual(size_ptr_physical_address_block.address)
@@ -382,16 +838,13 @@ pub trait ArchitectureOperations {
     /// the manual start functionality
     fn get_manual_start_info(&mut self, info: &ManualStartInfo) -> Result<StartInfo> {
         let buffer_physical_address = if matches!(info.address, ManualStartAddress::Virtual(_)) {
-            let physical_address_block = self
-                .processor_info_v2()
-                // NOTE: Do we need to support segmented memory via logical_to_physical?
-                .logical_to_physical(
-                    match info.address {
-                        ManualStartAddress::Virtual(address) => address,
-                        ManualStartAddress::Physical(address) => address,
-                    },
-                    Access::Sim_Access_Read,
-                )?;
+            let physical_address_block = self.virtual_to_physical(
+                match info.address {
+                    ManualStartAddress::Virtual(address) => address,
+                    ManualStartAddress::Physical(address) => address,
+                },
+                Access::Sim_Access_Write,
+            )?;
 
             if physical_address_block.valid == 0 {
                 bail!(
@@ -411,9 +864,8 @@ pub trait ArchitectureOperations {
             crate::ManualStartSize::SizePtr { address } => {
                 let address = match address {
                     ManualStartAddress::Virtual(v) => {
-                        let physical_address = self
-                            .processor_info_v2()
-                            .logical_to_physical(*v, Access::Sim_Access_Read)?;
+                        let physical_address =
+                            self.virtual_to_physical(*v, Access::Sim_Access_Write)?;
 
                         if physical_address.valid == 0 {
                             bail!("Invalid linear address given for start buffer : {v:#x}");
@@ -430,7 +882,7 @@ pub trait ArchitectureOperations {
                     self.processor_info_v2().get_logical_address_width()? / u8::BITS as i32
                 };
                 let maximum_size =
-                    read_phys_memory(self.cpu(), address.physical_address(), size_size)?;
+                    self.read_physical_integer(address.physical_address(), size_size as usize)?;
                 StartSize::SizePtr {
                     address,
                     maximum_size: maximum_size as usize,
@@ -443,9 +895,8 @@ pub trait ArchitectureOperations {
             } => {
                 let address = match address {
                     ManualStartAddress::Virtual(v) => {
-                        let physical_address = self
-                            .processor_info_v2()
-                            .logical_to_physical(*v, Access::Sim_Access_Read)?;
+                        let physical_address =
+                            self.virtual_to_physical(*v, Access::Sim_Access_Write)?;
 
                         if physical_address.valid == 0 {
                             bail!("Invalid linear address given for start buffer : {v:#x}");
@@ -463,25 +914,22 @@ pub trait ArchitectureOperations {
             }
         };
 
-        let contents = (0..size.maximum_size())
-            .map(|i| {
-                read_byte(
-                    self.processor_info_v2().get_physical_memory()?,
-                    buffer_physical_address + i as u64,
-                )
-                .map_err(|e| {
-                    anyhow!(
-                        "Failed to read byte at {:#x}: {}",
-                        buffer_physical_address + i as u64,
-                        e
-                    )
-                })
-            })
-            .collect::<Result<Vec<_>>>()?;
+        let pages = match info.address {
+            ManualStartAddress::Virtual(address) => {
+                self.translate_buffer(address, size.maximum_size())?
+            }
+            ManualStartAddress::Physical(_) => vec![StartPage {
+                offset: 0,
+                physical_address: buffer_physical_address,
+                size: size.maximum_size(),
+            }],
+        };
+        let contents = self.read_buffer(&pages)?;
 
         Ok(StartInfo::builder()
             .address(address)
             .contents(contents)
+            .pages(pages)
             .size(size)
             .build())
     }
@@ -496,17 +944,23 @@ pub trait ArchitectureOperations {
 
         testcase.truncate(info.size.maximum_size());
 
-        testcase.iter().enumerate().try_for_each(|(i, c)| {
-            let physical_address = info.address.physical_address() + (i as u64);
-            write_byte(physical_memory, physical_address, *c)
-        })?;
+        for page in info.physical_pages() {
+            let Some(part) = testcase.get(page.offset..) else {
+                break;
+            };
+
+            part.iter()
+                .take(page.size)
+                .enumerate()
+                .try_for_each(|(i, c)| {
+                    write_byte(physical_memory, page.physical_address + i as u64, *c)
+                })?;
+        }
 
         if let Some(size_address) = info.size.physical_address().map(|s| s.physical_address()) {
-            testcase
-                .len()
-                .to_le_bytes()
+            self.endianness()
+                .encode(testcase.len() as u64, addr_size)
                 .iter()
-                .take(addr_size)
                 .enumerate()
                 .try_for_each(|(i, c)| {
                     let physical_address = size_address + (i as u64);
@@ -541,6 +995,10 @@ impl ArchitectureOperations for Architecture {
             Ok(Self::Arm(arm))
         } else if let Ok(aarch64) = AArch64ArchitectureOperations::new(cpu) {
             Ok(Self::Aarch64(aarch64))
+        } else if let Ok(powerpc) = PowerPCArchitectureOperations::new(cpu) {
+            Ok(Self::PowerPC(powerpc))
+        } else if let Ok(mips) = MIPSArchitectureOperations::new(cpu) {
+            Ok(Self::Mips(mips))
         } else {
             bail!("Unsupported architecture");
         }
@@ -553,6 +1011,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.cpu(),
             Architecture::Arm(arm) => arm.cpu(),
             Architecture::Aarch64(aarch64) => aarch64.cpu(),
+            Architecture::PowerPC(powerpc) => powerpc.cpu(),
+            Architecture::Mips(mips) => mips.cpu(),
         }
     }
 
@@ -563,6 +1023,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.disassembler(),
             Architecture::Arm(arm) => arm.disassembler(),
             Architecture::Aarch64(aarch64) => aarch64.disassembler(),
+            Architecture::PowerPC(powerpc) => powerpc.disassembler(),
+            Architecture::Mips(mips) => mips.disassembler(),
         }
     }
 
@@ -573,6 +1035,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.int_register(),
             Architecture::Arm(arm) => arm.int_register(),
             Architecture::Aarch64(aarch64) => aarch64.int_register(),
+            Architecture::PowerPC(powerpc) => powerpc.int_register(),
+            Architecture::Mips(mips) => mips.int_register(),
         }
     }
 
@@ -583,6 +1047,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.processor_info_v2(),
             Architecture::Arm(arm) => arm.processor_info_v2(),
             Architecture::Aarch64(aarch64) => aarch64.processor_info_v2(),
+            Architecture::PowerPC(powerpc) => powerpc.processor_info_v2(),
+            Architecture::Mips(mips) => mips.processor_info_v2(),
         }
     }
 
@@ -593,6 +1059,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.cpu_instruction_query(),
             Architecture::Arm(arm) => arm.cpu_instruction_query(),
             Architecture::Aarch64(aarch64) => aarch64.cpu_instruction_query(),
+            Architecture::PowerPC(powerpc) => powerpc.cpu_instruction_query(),
+            Architecture::Mips(mips) => mips.cpu_instruction_query(),
         }
     }
 
@@ -603,6 +1071,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.cpu_instrumentation_subscribe(),
             Architecture::Arm(arm) => arm.cpu_instrumentation_subscribe(),
             Architecture::Aarch64(aarch64) => aarch64.cpu_instrumentation_subscribe(),
+            Architecture::PowerPC(powerpc) => powerpc.cpu_instrumentation_subscribe(),
+            Architecture::Mips(mips) => mips.cpu_instrumentation_subscribe(),
         }
     }
 
@@ -613,6 +1083,119 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.cycle(),
             Architecture::Arm(arm) => arm.cycle(),
             Architecture::Aarch64(aarch64) => aarch64.cycle(),
+            Architecture::PowerPC(powerpc) => powerpc.cycle(),
+            Architecture::Mips(mips) => mips.cycle(),
+        }
+    }
+
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.decode_cache(),
+            Architecture::I386(i386) => i386.decode_cache(),
+            Architecture::Riscv(riscv) => riscv.decode_cache(),
+            Architecture::Arm(arm) => arm.decode_cache(),
+            Architecture::Aarch64(aarch64) => aarch64.decode_cache(),
+            Architecture::PowerPC(powerpc) => powerpc.decode_cache(),
+            Architecture::Mips(mips) => mips.decode_cache(),
+        }
+    }
+
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.exceptions(),
+            Architecture::I386(i386) => i386.exceptions(),
+            Architecture::Riscv(riscv) => riscv.exceptions(),
+            Architecture::Arm(arm) => arm.exceptions(),
+            Architecture::Aarch64(aarch64) => aarch64.exceptions(),
+            Architecture::PowerPC(powerpc) => powerpc.exceptions(),
+            Architecture::Mips(mips) => mips.exceptions(),
+        }
+    }
+
+    fn decode_time_filtering(&self) -> bool {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.decode_time_filtering(),
+            Architecture::I386(i386) => i386.decode_time_filtering(),
+            Architecture::Riscv(riscv) => riscv.decode_time_filtering(),
+            Architecture::Arm(arm) => arm.decode_time_filtering(),
+            Architecture::Aarch64(aarch64) => aarch64.decode_time_filtering(),
+            Architecture::PowerPC(powerpc) => powerpc.decode_time_filtering(),
+            Architecture::Mips(mips) => mips.decode_time_filtering(),
+        }
+    }
+
+    fn endianness(&mut self) -> &mut Endianness {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.endianness(),
+            Architecture::I386(i386) => i386.endianness(),
+            Architecture::Riscv(riscv) => riscv.endianness(),
+            Architecture::Arm(arm) => arm.endianness(),
+            Architecture::Aarch64(aarch64) => aarch64.endianness(),
+            Architecture::PowerPC(powerpc) => powerpc.endianness(),
+            Architecture::Mips(mips) => mips.endianness(),
+        }
+    }
+
+    fn fault(&mut self, exception: i64) -> Result<Option<Fault>> {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.fault(exception),
+            Architecture::I386(i386) => i386.fault(exception),
+            Architecture::Riscv(riscv) => riscv.fault(exception),
+            Architecture::Arm(arm) => arm.fault(exception),
+            Architecture::Aarch64(aarch64) => aarch64.fault(exception),
+            Architecture::PowerPC(powerpc) => powerpc.fault(exception),
+            Architecture::Mips(mips) => mips.fault(exception),
+        }
+    }
+
+    fn decode(
+        &mut self,
+        instruction_query: *mut instruction_handle_t,
+    ) -> Result<Rc<DecodedInstruction>> {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.decode(instruction_query),
+            Architecture::I386(i386) => i386.decode(instruction_query),
+            Architecture::Riscv(riscv) => riscv.decode(instruction_query),
+            Architecture::Arm(arm) => arm.decode(instruction_query),
+            Architecture::Aarch64(aarch64) => aarch64.decode(instruction_query),
+            Architecture::PowerPC(powerpc) => powerpc.decode(instruction_query),
+            Architecture::Mips(mips) => mips.decode(instruction_query),
+        }
+    }
+
+    fn virtual_to_physical(&mut self, address: u64, access: Access) -> Result<physical_block_t> {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.virtual_to_physical(address, access),
+            Architecture::I386(i386) => i386.virtual_to_physical(address, access),
+            Architecture::Riscv(riscv) => riscv.virtual_to_physical(address, access),
+            Architecture::Arm(arm) => arm.virtual_to_physical(address, access),
+            Architecture::Aarch64(aarch64) => aarch64.virtual_to_physical(address, access),
+            Architecture::PowerPC(powerpc) => powerpc.virtual_to_physical(address, access),
+            Architecture::Mips(mips) => mips.virtual_to_physical(address, access),
+        }
+    }
+
+    fn pointer_address(&mut self, pointer: u64) -> Result<u64> {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.pointer_address(pointer),
+            Architecture::I386(i386) => i386.pointer_address(pointer),
+            Architecture::Riscv(riscv) => riscv.pointer_address(pointer),
+            Architecture::Arm(arm) => arm.pointer_address(pointer),
+            Architecture::Aarch64(aarch64) => aarch64.pointer_address(pointer),
+            Architecture::PowerPC(powerpc) => powerpc.pointer_address(pointer),
+            Architecture::Mips(mips) => mips.pointer_address(pointer),
+        }
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        match self {
+            Architecture::X86_64(x86_64) => x86_64.function_argument(convention, index),
+            Architecture::I386(i386) => i386.function_argument(convention, index),
+            Architecture::Riscv(riscv) => riscv.function_argument(convention, index),
+            Architecture::Arm(arm) => arm.function_argument(convention, index),
+            Architecture::Aarch64(aarch64) => aarch64.function_argument(convention, index),
+            Architecture::PowerPC(powerpc) => powerpc.function_argument(convention, index),
+            Architecture::Mips(mips) => mips.function_argument(convention, index),
         }
     }
 
@@ -623,6 +1206,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.get_magic_index_selector(),
             Architecture::Arm(arm) => arm.get_magic_index_selector(),
             Architecture::Aarch64(aarch64) => aarch64.get_magic_index_selector(),
+            Architecture::PowerPC(powerpc) => powerpc.get_magic_index_selector(),
+            Architecture::Mips(mips) => mips.get_magic_index_selector(),
         }
     }
 
@@ -633,6 +1218,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.get_magic_start_buffer_ptr_size_ptr(),
             Architecture::Arm(arm) => arm.get_magic_start_buffer_ptr_size_ptr(),
             Architecture::Aarch64(aarch64) => aarch64.get_magic_start_buffer_ptr_size_ptr(),
+            Architecture::PowerPC(powerpc) => powerpc.get_magic_start_buffer_ptr_size_ptr(),
+            Architecture::Mips(mips) => mips.get_magic_start_buffer_ptr_size_ptr(),
         }
     }
 
@@ -643,6 +1230,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.get_magic_start_buffer_ptr_size_val(),
             Architecture::Arm(arm) => arm.get_magic_start_buffer_ptr_size_val(),
             Architecture::Aarch64(aarch64) => aarch64.get_magic_start_buffer_ptr_size_val(),
+            Architecture::PowerPC(powerpc) => powerpc.get_magic_start_buffer_ptr_size_val(),
+            Architecture::Mips(mips) => mips.get_magic_start_buffer_ptr_size_val(),
         }
     }
 
@@ -653,6 +1242,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.get_magic_start_buffer_ptr_size_ptr(),
             Architecture::Arm(arm) => arm.get_magic_start_buffer_ptr_size_ptr_val(),
             Architecture::Aarch64(aarch64) => aarch64.get_magic_start_buffer_ptr_size_ptr_val(),
+            Architecture::PowerPC(powerpc) => powerpc.get_magic_start_buffer_ptr_size_ptr_val(),
+            Architecture::Mips(mips) => mips.get_magic_start_buffer_ptr_size_ptr_val(),
         }
     }
 
@@ -663,6 +1254,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.get_manual_start_info(info),
             Architecture::Arm(arm) => arm.get_manual_start_info(info),
             Architecture::Aarch64(aarch64) => aarch64.get_manual_start_info(info),
+            Architecture::PowerPC(powerpc) => powerpc.get_manual_start_info(info),
+            Architecture::Mips(mips) => mips.get_manual_start_info(info),
         }
     }
 
@@ -673,6 +1266,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.write_start(testcase, info),
             Architecture::Arm(arm) => arm.write_start(testcase, info),
             Architecture::Aarch64(aarch64) => aarch64.write_start(testcase, info),
+            Architecture::PowerPC(powerpc) => powerpc.write_start(testcase, info),
+            Architecture::Mips(mips) => mips.write_start(testcase, info),
         }
     }
 
@@ -683,6 +1278,8 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.trace_pc(instruction_query),
             Architecture::Arm(arm) => arm.trace_pc(instruction_query),
             Architecture::Aarch64(aarch64) => aarch64.trace_pc(instruction_query),
+            Architecture::PowerPC(powerpc) => powerpc.trace_pc(instruction_query),
+            Architecture::Mips(mips) => mips.trace_pc(instruction_query),
         }
     }
 
@@ -693,6 +1290,89 @@ impl ArchitectureOperations for Architecture {
             Architecture::Riscv(riscv) => riscv.trace_cmp(instruction_query),
             Architecture::Arm(arm) => arm.trace_cmp(instruction_query),
             Architecture::Aarch64(aarch64) => aarch64.trace_cmp(instruction_query),
+            Architecture::PowerPC(powerpc) => powerpc.trace_cmp(instruction_query),
+            Architecture::Mips(mips) => mips.trace_cmp(instruction_query),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{translate_pages, Endianness, PAGE_SIZE};
+    use crate::StartPage;
+
+    #[test]
+    fn test_translate_pages() {
+        // Map each virtual page to a physical page in reverse order, so contiguous virtual
+        // pages are not physically contiguous
+        let translate = |address: u64| {
+            Ok(Some(
+                0x1_0000 - (address & !(PAGE_SIZE - 1)) + (address & (PAGE_SIZE - 1)),
+            ))
+        };
+        let page = |offset, physical_address, size| StartPage {
+            offset,
+            physical_address,
+            size,
+        };
+
+        assert_eq!(
+            translate_pages(0x1100, 0x100, translate).expect("Failed to translate buffer"),
+            vec![page(0, 0xf100, 0x100)]
+        );
+        assert_eq!(
+            translate_pages(0x1f00, 0x1200, translate).expect("Failed to translate buffer"),
+            vec![
+                page(0, 0xff00, 0x100),
+                page(0x100, 0xe000, 0x1000),
+                page(0x1100, 0xd000, 0x100)
+            ]
+        );
+        assert_eq!(
+            translate_pages(0x2000, 0x1000, translate).expect("Failed to translate buffer"),
+            vec![page(0, 0xe000, 0x1000)]
+        );
+        assert!(translate_pages(0x1000, 0, translate)
+            .expect("Failed to translate buffer")
+            .is_empty());
+        assert!(translate_pages(u64::MAX, 2, translate).is_err());
+        assert!(translate_pages(0x1f00, 0x200, |address| Ok(
+            (address < 0x2000).then_some(address)
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_endianness_encoding() {
+        assert_eq!(
+            Endianness::Little.encode(0x1122334455667788, 4),
+            vec![0x88, 0x77, 0x66, 0x55]
+        );
+        assert_eq!(
+            Endianness::Big.encode(0x1122334455667788, 4),
+            vec![0x55, 0x66, 0x77, 0x88]
+        );
+        assert_eq!(
+            Endianness::Big.encode(0x1122334455667788, 8),
+            0x1122334455667788u64.to_be_bytes().to_vec()
+        );
+        assert_eq!(Endianness::Little.decode(&[0x88, 0x77]), 0x7788);
+        assert_eq!(Endianness::Big.decode(&[0x88, 0x77]), 0x8877);
+        assert_eq!(
+            Endianness::Big.decode(&[1, 2, 3, 4, 5, 6, 7, 8, 9]),
+            0x0102030405060708
+        );
+        assert_eq!(Endianness::Little.decode(&[]), 0);
+
+        for endianness in [Endianness::Little, Endianness::Big] {
+            for width in 1..=8 {
+                let value = 0x8877665544332211u64 & (u64::MAX >> (64 - width * 8));
+                assert_eq!(
+                    endianness.decode(&endianness.encode(value, width)),
+                    value,
+                    "{endianness} {width}"
+                );
+            }
         }
     }
 }
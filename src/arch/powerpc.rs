@@ -0,0 +1,1232 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Architecture-specific implementation for the PowerPC architecture
+
+use anyhow::{anyhow, bail, Result};
+use libafl::prelude::CmpValues;
+use simics::api::{
+    get_interface, sys::instruction_handle_t, ConfObject, CpuInstructionQueryInterface,
+    CpuInstrumentationSubscribeInterface, CycleInterface, IntRegisterInterface,
+    ProcessorInfoV2Interface,
+};
+use std::{
+    ffi::CStr,
+    fmt::{Display, Formatter},
+    mem::size_of,
+};
+
+use crate::{
+    tracer::{CmpExpr, CmpType, CmpValue, TraceEntry},
+    traits::TracerDisassembler,
+};
+
+use super::{
+    decode_cache::DecodeCache, model_exceptions, ArchitectureOperations, CallingConvention,
+    Endianness,
+};
+
+/// The architecture-level names of the PowerPC exceptions, each with the names processor models use
+/// for the exception. SIMICS exception numbers differ between PowerPC processor models, so they
+/// are looked up by name when the processor is added.
+const EXCEPTIONS: &[(&str, &[&str])] = &[
+    ("machine-check", &["Machine_Check", "Machine_check"]),
+    ("data-storage", &["Data_Storage", "DSI"]),
+    ("instruction-storage", &["Instruction_Storage", "ISI"]),
+    ("alignment", &["Alignment"]),
+    ("program", &["Program"]),
+    ("illegal-instruction", &["Program"]),
+    (
+        "floating-point-unavailable",
+        &["Floating-point_Unavailable", "FP_Unavailable"],
+    ),
+    ("system-call", &["System_Call", "System_call"]),
+    ("data-tlb-error", &["Data_TLB_Error", "Data_TLB_Miss"]),
+    (
+        "instruction-tlb-error",
+        &["Instruction_TLB_Error", "Instruction_TLB_Miss"],
+    ),
+];
+
+pub(crate) struct PowerPCArchitectureOperations {
+    cpu: *mut ConfObject,
+    disassembler: Disassembler,
+    int_register: IntRegisterInterface,
+    processor_info_v2: ProcessorInfoV2Interface,
+    cpu_instruction_query: CpuInstructionQueryInterface,
+    #[allow(dead_code)]
+    cpu_instrumentation_subscribe: CpuInstrumentationSubscribeInterface,
+    cycle: CycleInterface,
+    decode_cache: DecodeCache,
+    endianness: Endianness,
+    /// The architecture-level names of the exceptions of the processor model and their numbers
+    exceptions: Vec<(&'static str, i64)>,
+    /// The operands of the last comparison, which may be the bounds check of a jump table
+    /// index if an indirect jump follows
+    jump_table_bounds: Option<CmpValues>,
+}
+
+impl ArchitectureOperations for PowerPCArchitectureOperations {
+    const INDEX_SELECTOR_REGISTER: &'static str = "r3";
+
+    const ARGUMENT_REGISTER_0: &'static str = "r4";
+
+    const ARGUMENT_REGISTER_1: &'static str = "r5";
+
+    const ARGUMENT_REGISTER_2: &'static str = "r6";
+
+    fn new(cpu: *mut ConfObject) -> Result<Self> {
+        let mut processor_info_v2: ProcessorInfoV2Interface = get_interface(cpu)?;
+
+        let arch = unsafe { CStr::from_ptr(processor_info_v2.architecture()?) }
+            .to_str()?
+            .to_string();
+
+        if matches!(
+            arch.as_str(),
+            "ppc" | "ppc32" | "ppc64" | "powerpc" | "powerpc64"
+        ) {
+            Self::new_unchecked(cpu)
+        } else {
+            bail!("Architecture {} is not powerpc", arch);
+        }
+    }
+
+    fn new_unchecked(cpu: *mut ConfObject) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            cpu,
+            disassembler: Disassembler::new(),
+            int_register: get_interface(cpu)?,
+            processor_info_v2: get_interface(cpu)?,
+            cpu_instruction_query: get_interface(cpu)?,
+            cpu_instrumentation_subscribe: get_interface(cpu)?,
+            cycle: get_interface(cpu)?,
+            decode_cache: DecodeCache::default(),
+            endianness: Endianness::of(&mut get_interface(cpu)?)?,
+            exceptions: model_exceptions(cpu, EXCEPTIONS),
+            jump_table_bounds: None,
+        })
+    }
+
+    fn cpu(&self) -> *mut ConfObject {
+        self.cpu
+    }
+
+    fn disassembler(&mut self) -> &mut dyn TracerDisassembler {
+        &mut self.disassembler
+    }
+
+    fn int_register(&mut self) -> &mut IntRegisterInterface {
+        &mut self.int_register
+    }
+
+    fn processor_info_v2(&mut self) -> &mut ProcessorInfoV2Interface {
+        &mut self.processor_info_v2
+    }
+
+    fn cpu_instruction_query(&mut self) -> &mut CpuInstructionQueryInterface {
+        &mut self.cpu_instruction_query
+    }
+
+    fn cpu_instrumentation_subscribe(&mut self) -> &mut CpuInstrumentationSubscribeInterface {
+        &mut self.cpu_instrumentation_subscribe
+    }
+
+    fn cycle(&mut self) -> &mut CycleInterface {
+        &mut self.cycle
+    }
+
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        &mut self.decode_cache
+    }
+
+    fn endianness(&mut self) -> &mut Endianness {
+        &mut self.endianness
+    }
+
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        &self.exceptions
+    }
+
+    fn update_decoder_mode(&mut self) -> Result<u8> {
+        self.disassembler
+            .set_big_endian(self.endianness == Endianness::Big);
+        Ok(0)
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        const REGISTERS: &[&str] = &["r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10"];
+
+        if convention != CallingConvention::Default {
+            bail!("Calling convention {convention} is not supported");
+        }
+
+        if let Some(register) = REGISTERS.get(index) {
+            self.read_register(register)
+        } else {
+            let width =
+                self.processor_info_v2().get_logical_address_width()? as usize / u8::BITS as usize;
+            let sp = self.read_register("r1")?;
+            // 32-bit stack arguments follow the back chain and LR save words. The 64-bit
+            // parameter save area follows the 32 byte frame header and reserves space for the
+            // register arguments.
+            let address = if width == size_of::<u32>() {
+                sp + 8 + ((index - REGISTERS.len()) * width) as u64
+            } else {
+                sp + 32 + (index * width) as u64
+            };
+            self.read_virtual_integer(address, width)
+        }
+    }
+
+    fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
+        let decoded = self.decode(instruction_query)?;
+
+        if decoded.is_edge() {
+            let source = self
+                .cpu_instruction_query
+                .logical_address(instruction_query)?;
+
+            // The bounds check of a jump table index is followed by a conditional branch to
+            // the default case and then the indirect jump through the table
+            let jump_table = if decoded.indirect_jump {
+                self.jump_table_bounds.take().map(|bounds| (source, bounds))
+            } else {
+                if !decoded.conditional {
+                    self.jump_table_bounds = None;
+                }
+                None
+            };
+
+            Ok(TraceEntry::builder()
+                .edge(self.processor_info_v2.get_program_counter()?)
+                .source(source)
+                .jump_table(jump_table)
+                .call(decoded.call)
+                .ret(decoded.ret)
+                .build())
+        } else {
+            Ok(TraceEntry::default())
+        }
+    }
+
+    fn trace_cmp(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
+        let decoded = self.decode(instruction_query)?;
+
+        if !decoded.cmp {
+            return Ok(TraceEntry::default());
+        }
+
+        let pc = self.processor_info_v2.get_program_counter()?;
+
+        let mut cmp_values = Vec::new();
+
+        for expr in decoded.cmp_exprs.iter() {
+            if let Ok(value) = self.simplify(expr) {
+                cmp_values.push(value);
+            }
+        }
+
+        let cmp_value = if let (Some(l), Some(r)) = (cmp_values.first(), cmp_values.get(1)) {
+            CmpValue::pair(l, r)
+        } else {
+            None
+        };
+
+        if cmp_value.is_some() {
+            self.jump_table_bounds.clone_from(&cmp_value);
+        }
+
+        Ok(TraceEntry::builder()
+            .cmp((
+                pc,
+                decoded.cmp_types.clone(),
+                cmp_value.ok_or_else(|| anyhow!("No cmp value available"))?,
+            ))
+            .build())
+    }
+}
+
+impl PowerPCArchitectureOperations {
+    fn simplify(&mut self, expr: &CmpExpr) -> Result<CmpValue> {
+        match expr {
+            // Word compares only compare the low 32 bits of their registers
+            CmpExpr::Reg((name, 32)) => Ok(CmpValue::U32(self.read_register(name)? as u32)),
+            CmpExpr::Reg((name, _)) => Ok(CmpValue::U64(self.read_register(name)?)),
+            _ => CmpValue::try_from(expr),
+        }
+    }
+}
+
+/// The BO field bits of a conditional branch which mean the condition is ignored and the
+/// count register is not decremented, so the branch is always taken
+const BO_ALWAYS: u8 = 0b10100;
+
+/// The BO field bit which means the condition register bit is ignored
+const BO_IGNORE_CONDITION: u8 = 0b10000;
+
+/// The BO field bit which means the count register is not decremented
+const BO_IGNORE_COUNT: u8 = 0b00100;
+
+/// The BO field bit giving the value of the condition register bit which takes the branch
+const BO_CONDITION_SET: u8 = 0b01000;
+
+/// The BO field bit which means the branch is taken when the decremented count register is
+/// zero rather than nonzero
+const BO_COUNT_ZERO: u8 = 0b00010;
+
+/// The names of the conditions a branch tests for each bit of a condition register field,
+/// when the branch is taken if the bit is set and if it is clear
+const CONDITIONS: [(&str, &str); 4] = [("lt", "ge"), ("gt", "le"), ("eq", "ne"), ("so", "ns")];
+
+/// The D-form loads and stores by primary opcode, and whether they transfer a floating point
+/// register
+const LOADS_STORES: &[(u32, &str, bool)] = &[
+    (32, "lwz", false),
+    (33, "lwzu", false),
+    (34, "lbz", false),
+    (35, "lbzu", false),
+    (36, "stw", false),
+    (37, "stwu", false),
+    (38, "stb", false),
+    (39, "stbu", false),
+    (40, "lhz", false),
+    (41, "lhzu", false),
+    (42, "lha", false),
+    (43, "lhau", false),
+    (44, "sth", false),
+    (45, "sthu", false),
+    (46, "lmw", false),
+    (47, "stmw", false),
+    (48, "lfs", true),
+    (49, "lfsu", true),
+    (50, "lfd", true),
+    (51, "lfdu", true),
+    (52, "stfs", true),
+    (53, "stfsu", true),
+    (54, "stfd", true),
+    (55, "stfdu", true),
+];
+
+/// The XO-form arithmetic instructions of primary opcode 31 by extended opcode, and whether
+/// they have only one source register
+const ARITHMETIC: &[(u32, &str, bool)] = &[
+    (266, "add", false),
+    (10, "addc", false),
+    (138, "adde", false),
+    (234, "addme", true),
+    (202, "addze", true),
+    (40, "subf", false),
+    (8, "subfc", false),
+    (136, "subfe", false),
+    (232, "subfme", true),
+    (200, "subfze", true),
+    (104, "neg", true),
+    (235, "mullw", false),
+    (233, "mulld", false),
+    (75, "mulhw", false),
+    (11, "mulhwu", false),
+    (73, "mulhd", false),
+    (9, "mulhdu", false),
+    (491, "divw", false),
+    (459, "divwu", false),
+    (489, "divd", false),
+    (457, "divdu", false),
+];
+
+/// The X-form logical, shift, and extension instructions of primary opcode 31 by extended
+/// opcode, which write `ra`, and whether they have a second source register
+const LOGICAL: &[(u32, &str, bool)] = &[
+    (28, "and", true),
+    (60, "andc", true),
+    (444, "or", true),
+    (124, "nor", true),
+    (316, "xor", true),
+    (476, "nand", true),
+    (412, "orc", true),
+    (284, "eqv", true),
+    (24, "slw", true),
+    (536, "srw", true),
+    (792, "sraw", true),
+    (27, "sld", true),
+    (539, "srd", true),
+    (794, "srad", true),
+    (26, "cntlzw", false),
+    (58, "cntlzd", false),
+    (954, "extsb", false),
+    (922, "extsh", false),
+    (986, "extsw", false),
+];
+
+/// The X-form indexed loads and stores of primary opcode 31 by extended opcode
+const INDEXED: &[(u32, &str)] = &[
+    (23, "lwzx"),
+    (55, "lwzux"),
+    (87, "lbzx"),
+    (119, "lbzux"),
+    (279, "lhzx"),
+    (311, "lhzux"),
+    (343, "lhax"),
+    (341, "lwax"),
+    (21, "ldx"),
+    (53, "ldux"),
+    (151, "stwx"),
+    (183, "stwux"),
+    (215, "stbx"),
+    (247, "stbux"),
+    (407, "sthx"),
+    (439, "sthux"),
+    (149, "stdx"),
+    (181, "stdux"),
+    (534, "lwbrx"),
+    (662, "stwbrx"),
+    (790, "lhbrx"),
+    (918, "sthbrx"),
+    (20, "lwarx"),
+    (84, "ldarx"),
+    (150, "stwcx."),
+    (214, "stdcx."),
+];
+
+/// The X-form cache management instructions of primary opcode 31 by extended opcode
+const CACHE: &[(u32, &str)] = &[
+    (86, "dcbf"),
+    (54, "dcbst"),
+    (278, "dcbt"),
+    (246, "dcbtst"),
+    (1014, "dcbz"),
+    (982, "icbi"),
+];
+
+/// The condition register logical instructions of primary opcode 19 by extended opcode
+const CONDITION_REGISTER: &[(u32, &str)] = &[
+    (257, "crand"),
+    (449, "cror"),
+    (193, "crxor"),
+    (225, "crnand"),
+    (33, "crnor"),
+    (289, "creqv"),
+    (129, "crandc"),
+    (417, "crorc"),
+];
+
+/// The A-form floating point arithmetic instructions of primary opcodes 59 and 63 by extended
+/// opcode, and the source registers they use
+const FLOATING_ARITHMETIC: &[(u32, &str, &str)] = &[
+    (18, "fdiv", "ab"),
+    (20, "fsub", "ab"),
+    (21, "fadd", "ab"),
+    (22, "fsqrt", "b"),
+    (25, "fmul", "ac"),
+    (28, "fmsub", "acb"),
+    (29, "fmadd", "acb"),
+    (30, "fnmsub", "acb"),
+    (31, "fnmadd", "acb"),
+];
+
+/// The X-form floating point instructions of primary opcode 63 with one source register by
+/// extended opcode
+const FLOATING_UNARY: &[(u32, &str)] = &[
+    (72, "fmr"),
+    (40, "fneg"),
+    (264, "fabs"),
+    (136, "fnabs"),
+    (12, "frsp"),
+    (14, "fctiw"),
+    (15, "fctiwz"),
+    (814, "fctid"),
+    (815, "fctidz"),
+    (846, "fcfid"),
+];
+
+/// Look up the entry for an opcode in a table of instructions
+fn lookup<T: Copy>(table: &[(u32, T)], opcode: u32) -> Option<T> {
+    table.iter().find(|(o, _)| *o == opcode).map(|(_, v)| *v)
+}
+
+/// Look up the entry for an opcode in a table of instructions with an extra property
+fn lookup_with<T: Copy, U: Copy>(table: &[(u32, T, U)], opcode: u32) -> Option<(T, U)> {
+    table
+        .iter()
+        .find(|(o, _, _)| *o == opcode)
+        .map(|(_, v, w)| (*v, *w))
+}
+
+/// Format the target of a branch with a displacement, which is relative to the branch unless
+/// it is absolute
+fn branch_target(displacement: i64, absolute: bool) -> String {
+    if absolute {
+        format!("{displacement:#x}")
+    } else if displacement < 0 {
+        format!(".-{:#x}", displacement.unsigned_abs())
+    } else {
+        format!(".+{displacement:#x}")
+    }
+}
+
+/// Format a base register of a load, store, or cache instruction, where `r0` means zero
+fn base_register(ra: u32) -> String {
+    if ra == 0 {
+        "0".to_string()
+    } else {
+        format!("r{ra}")
+    }
+}
+
+/// Write a conditional branch using its extended mnemonic where there is one. `register` is
+/// the `lr` or `ctr` suffix of a branch to a register, and `target` is the displacement of a
+/// branch to an address and whether it is absolute.
+fn write_conditional_branch(
+    f: &mut Formatter<'_>,
+    bo: u8,
+    bi: u8,
+    register: &str,
+    link: bool,
+    target: Option<(i16, bool)>,
+) -> std::fmt::Result {
+    let mut operands = Vec::new();
+
+    let mnemonic = if bo & BO_ALWAYS == BO_ALWAYS {
+        "b".to_string()
+    } else if bo & BO_IGNORE_CONDITION != 0 {
+        if bo & BO_COUNT_ZERO != 0 {
+            "bdz".to_string()
+        } else {
+            "bdnz".to_string()
+        }
+    } else if bo & BO_IGNORE_COUNT != 0 {
+        let (set, clear) = CONDITIONS[(bi % 4) as usize];
+
+        if bi / 4 != 0 {
+            operands.push(format!("cr{}", bi / 4));
+        }
+
+        format!(
+            "b{}",
+            if bo & BO_CONDITION_SET != 0 {
+                set
+            } else {
+                clear
+            }
+        )
+    } else {
+        operands.push(bo.to_string());
+        operands.push(bi.to_string());
+        "bc".to_string()
+    };
+
+    operands.extend(target.map(|(bd, absolute)| branch_target(bd as i64, absolute)));
+
+    write!(
+        f,
+        "{mnemonic}{register}{}{}",
+        if link { "l" } else { "" },
+        if target.is_some_and(|(_, absolute)| absolute) {
+            "a"
+        } else {
+            ""
+        }
+    )?;
+
+    if !operands.is_empty() {
+        write!(f, " {}", operands.join(", "))?;
+    }
+
+    Ok(())
+}
+
+/// Write an instruction which is not traced. Instructions which are not recognized are written
+/// as data.
+fn write_other(f: &mut Formatter<'_>, insn: u32) -> std::fmt::Result {
+    let field = |shift: u32, bits: u32| (insn >> shift) & ((1 << bits) - 1);
+    let rt = field(21, 5);
+    let ra = field(16, 5);
+    let rb = field(11, 5);
+    let rc = if insn & 1 != 0 { "." } else { "" };
+    let si = insn as u16 as i16;
+    let ui = insn as u16;
+    let xo = field(1, 10);
+
+    match insn >> 26 {
+        2 => write!(f, "tdi {rt}, r{ra}, {si}"),
+        3 => write!(f, "twi {rt}, r{ra}, {si}"),
+        7 => write!(f, "mulli r{rt}, r{ra}, {si}"),
+        8 => write!(f, "subfic r{rt}, r{ra}, {si}"),
+        12 => write!(f, "addic r{rt}, r{ra}, {si}"),
+        13 => write!(f, "addic. r{rt}, r{ra}, {si}"),
+        14 if ra == 0 => write!(f, "li r{rt}, {si}"),
+        14 => write!(f, "addi r{rt}, r{ra}, {si}"),
+        15 if ra == 0 => write!(f, "lis r{rt}, {si}"),
+        15 => write!(f, "addis r{rt}, r{ra}, {si}"),
+        17 if insn & 2 != 0 => write!(f, "sc"),
+        20 | 21 | 23 => {
+            let name = match insn >> 26 {
+                20 => "rlwimi",
+                21 => "rlwinm",
+                _ => "rlwnm",
+            };
+            let shift = if insn >> 26 == 23 {
+                format!("r{rb}")
+            } else {
+                rb.to_string()
+            };
+            write!(
+                f,
+                "{name}{rc} r{ra}, r{rt}, {shift}, {}, {}",
+                field(6, 5),
+                field(1, 5)
+            )
+        }
+        24 if insn == 0x6000_0000 => write!(f, "nop"),
+        24 => write!(f, "ori r{ra}, r{rt}, {ui:#x}"),
+        25 => write!(f, "oris r{ra}, r{rt}, {ui:#x}"),
+        26 => write!(f, "xori r{ra}, r{rt}, {ui:#x}"),
+        27 => write!(f, "xoris r{ra}, r{rt}, {ui:#x}"),
+        28 => write!(f, "andi. r{ra}, r{rt}, {ui:#x}"),
+        29 => write!(f, "andis. r{ra}, r{rt}, {ui:#x}"),
+        30 => {
+            // The 6-bit shift and mask fields are split, with their high bit stored apart
+            let shift = rb | (field(1, 1) << 5);
+            let mask = field(6, 5) | (field(5, 1) << 5);
+            let name = match field(2, 3) {
+                0 => "rldicl",
+                1 => "rldicr",
+                2 => "rldic",
+                3 => "rldimi",
+                4 if field(1, 4) == 8 => return write!(f, "rldcl{rc} r{ra}, r{rt}, r{rb}, {mask}"),
+                4 if field(1, 4) == 9 => return write!(f, "rldcr{rc} r{ra}, r{rt}, r{rb}, {mask}"),
+                _ => return write!(f, ".long {insn:#x}"),
+            };
+            write!(f, "{name}{rc} r{ra}, r{rt}, {shift}, {mask}")
+        }
+        19 => match xo {
+            0 => write!(f, "mcrf cr{}, cr{}", field(23, 3), field(18, 3)),
+            18 => write!(f, "rfid"),
+            50 => write!(f, "rfi"),
+            150 => write!(f, "isync"),
+            _ => match lookup(CONDITION_REGISTER, xo) {
+                Some(name) => write!(f, "{name} {rt}, {ra}, {rb}"),
+                None => write!(f, ".long {insn:#x}"),
+            },
+        },
+        31 => {
+            let spr = ra | (rb << 5);
+            let register_name = match spr {
+                1 => Some("xer"),
+                8 => Some("lr"),
+                9 => Some("ctr"),
+                _ => None,
+            };
+
+            if let Some((name, binary)) = lookup_with(LOGICAL, xo) {
+                if name == "or" && rt == rb {
+                    write!(f, "mr{rc} r{ra}, r{rt}")
+                } else if name == "nor" && rt == rb {
+                    write!(f, "not{rc} r{ra}, r{rt}")
+                } else if binary {
+                    write!(f, "{name}{rc} r{ra}, r{rt}, r{rb}")
+                } else {
+                    write!(f, "{name}{rc} r{ra}, r{rt}")
+                }
+            } else if let Some(name) = lookup(INDEXED, xo) {
+                write!(f, "{name} r{rt}, {}, r{rb}", base_register(ra))
+            } else if let Some(name) = lookup(CACHE, xo) {
+                write!(f, "{name} {}, r{rb}", base_register(ra))
+            } else if let Some((name, unary)) = lookup_with(ARITHMETIC, field(1, 9)) {
+                let overflow = if field(10, 1) != 0 { "o" } else { "" };
+
+                if unary {
+                    write!(f, "{name}{overflow}{rc} r{rt}, r{ra}")
+                } else {
+                    write!(f, "{name}{overflow}{rc} r{rt}, r{ra}, r{rb}")
+                }
+            } else if field(2, 9) == 413 {
+                write!(f, "sradi{rc} r{ra}, r{rt}, {}", rb | (field(1, 1) << 5))
+            } else {
+                match (xo, register_name) {
+                    (4, _) if rt == 31 && ra == 0 && rb == 0 => write!(f, "trap"),
+                    (4, _) => write!(f, "tw {rt}, r{ra}, r{rb}"),
+                    (68, _) => write!(f, "td {rt}, r{ra}, r{rb}"),
+                    (19, _) => write!(f, "mfcr r{rt}"),
+                    (83, _) => write!(f, "mfmsr r{rt}"),
+                    (144, _) => write!(f, "mtcrf {:#x}, r{rt}", field(12, 8)),
+                    (146, _) => write!(f, "mtmsr r{rt}"),
+                    (178, _) => write!(f, "mtmsrd r{rt}"),
+                    (306, _) => write!(f, "tlbie r{rb}"),
+                    (339, Some(name)) => write!(f, "mf{name} r{rt}"),
+                    (339, None) => write!(f, "mfspr r{rt}, {spr}"),
+                    (371, _) => write!(f, "mftb r{rt}"),
+                    (467, Some(name)) => write!(f, "mt{name} r{rt}"),
+                    (467, None) => write!(f, "mtspr {spr}, r{rt}"),
+                    (566, _) => write!(f, "tlbsync"),
+                    (598, _) if field(21, 2) == 1 => write!(f, "lwsync"),
+                    (598, _) => write!(f, "sync"),
+                    (824, _) => write!(f, "srawi{rc} r{ra}, r{rt}, {rb}"),
+                    (854, _) => write!(f, "eieio"),
+                    _ => write!(f, ".long {insn:#x}"),
+                }
+            }
+        }
+        opcode @ 32..=55 => match lookup_with(LOADS_STORES, opcode) {
+            Some((name, floating)) => write!(
+                f,
+                "{name} {}{rt}, {si}({})",
+                if floating { "f" } else { "r" },
+                base_register(ra)
+            ),
+            None => write!(f, ".long {insn:#x}"),
+        },
+        58 | 62 => {
+            let ds = (insn & 0xfffc) as u16 as i16;
+            let name = match (insn >> 26, insn & 3) {
+                (58, 0) => "ld",
+                (58, 1) => "ldu",
+                (58, 2) => "lwa",
+                (62, 0) => "std",
+                (62, 1) => "stdu",
+                _ => return write!(f, ".long {insn:#x}"),
+            };
+            write!(f, "{name} r{rt}, {ds}({})", base_register(ra))
+        }
+        opcode @ (59 | 63) => {
+            let single = if opcode == 59 { "s" } else { "" };
+
+            if let Some((name, sources)) = lookup_with(FLOATING_ARITHMETIC, field(1, 5)) {
+                write!(f, "{name}{single}{rc} f{rt}")?;
+                for source in sources.chars() {
+                    match source {
+                        'a' => write!(f, ", f{ra}")?,
+                        'b' => write!(f, ", f{rb}")?,
+                        _ => write!(f, ", f{}", field(6, 5))?,
+                    }
+                }
+                Ok(())
+            } else if opcode == 59 {
+                write!(f, ".long {insn:#x}")
+            } else if let Some(name) = lookup(FLOATING_UNARY, xo) {
+                write!(f, "{name}{rc} f{rt}, f{rb}")
+            } else {
+                match xo {
+                    0 => write!(f, "fcmpu cr{}, f{ra}, f{rb}", field(23, 3)),
+                    32 => write!(f, "fcmpo cr{}, f{ra}, f{rb}", field(23, 3)),
+                    583 => write!(f, "mffs{rc} f{rt}"),
+                    711 => write!(f, "mtfsf{rc} {:#x}, f{rb}", field(17, 8)),
+                    _ => write!(f, ".long {insn:#x}"),
+                }
+            }
+        }
+        _ => write!(f, ".long {insn:#x}"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An instruction decoded from its 32-bit encoding. The instructions which are traced keep the
+/// fields needed for tracing and display, and other instructions keep their encoding so they
+/// can be displayed.
+enum Instruction {
+    /// `b`, `ba`, `bl`, and `bla`
+    Branch { li: i32, absolute: bool, link: bool },
+    /// `bc` and its extended mnemonics like `beq` and `bdnz`
+    BranchConditional {
+        bo: u8,
+        bi: u8,
+        bd: i16,
+        absolute: bool,
+        link: bool,
+    },
+    /// `bclr` and its extended mnemonics like `blr` and `beqlr`
+    BranchConditionalToLinkRegister { bo: u8, bi: u8, link: bool },
+    /// `bcctr` and its extended mnemonics like `bctr` and `bctrl`
+    BranchConditionalToCountRegister { bo: u8, bi: u8, link: bool },
+    /// `cmp`, `cmpw`, and `cmpd`
+    Compare {
+        bf: u8,
+        doubleword: bool,
+        ra: u8,
+        rb: u8,
+    },
+    /// `cmpi`, `cmpwi`, and `cmpdi`
+    CompareImmediate {
+        bf: u8,
+        doubleword: bool,
+        ra: u8,
+        si: i16,
+    },
+    /// `cmpl`, `cmplw`, and `cmpld`
+    CompareLogical {
+        bf: u8,
+        doubleword: bool,
+        ra: u8,
+        rb: u8,
+    },
+    /// `cmpli`, `cmplwi`, and `cmpldi`
+    CompareLogicalImmediate {
+        bf: u8,
+        doubleword: bool,
+        ra: u8,
+        ui: u16,
+    },
+    /// Any other instruction
+    Other(u32),
+}
+
+impl Instruction {
+    /// Decode an instruction from its 32-bit encoding. PowerPC numbers bits from the most
+    /// significant bit, so field offsets here are from the least significant bit.
+    fn decode(insn: u32) -> Self {
+        let field = |shift: u32, bits: u32| ((insn >> shift) & ((1 << bits) - 1)) as u8;
+        let link = insn & 1 != 0;
+        let absolute = insn & 2 != 0;
+        let bo = field(21, 5);
+        let bi = field(16, 5);
+        let bf = field(23, 3);
+        let doubleword = field(21, 1) != 0;
+        let ra = field(16, 5);
+        let rb = field(11, 5);
+
+        match (insn >> 26, (insn >> 1) & 0x3ff) {
+            (18, _) => Self::Branch {
+                // The displacement is a signed 24-bit word offset
+                li: ((insn & 0x03ff_fffc) << 6) as i32 >> 6,
+                absolute,
+                link,
+            },
+            (16, _) => Self::BranchConditional {
+                bo,
+                bi,
+                bd: (insn & 0xfffc) as u16 as i16,
+                absolute,
+                link,
+            },
+            (19, 16) => Self::BranchConditionalToLinkRegister { bo, bi, link },
+            (19, 528) => Self::BranchConditionalToCountRegister { bo, bi, link },
+            (11, _) => Self::CompareImmediate {
+                bf,
+                doubleword,
+                ra,
+                si: insn as u16 as i16,
+            },
+            (10, _) => Self::CompareLogicalImmediate {
+                bf,
+                doubleword,
+                ra,
+                ui: insn as u16,
+            },
+            (31, 0) => Self::Compare {
+                bf,
+                doubleword,
+                ra,
+                rb,
+            },
+            (31, 32) => Self::CompareLogical {
+                bf,
+                doubleword,
+                ra,
+                rb,
+            },
+            _ => Self::Other(insn),
+        }
+    }
+
+    /// Whether the instruction is a conditional branch
+    fn is_conditional(&self) -> bool {
+        match self {
+            Self::BranchConditional { bo, .. }
+            | Self::BranchConditionalToLinkRegister { bo, .. }
+            | Self::BranchConditionalToCountRegister { bo, .. } => bo & BO_ALWAYS != BO_ALWAYS,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = |doubleword: &bool| if *doubleword { "d" } else { "w" };
+        let field = |bf: &u8| {
+            if *bf != 0 {
+                format!("cr{bf}, ")
+            } else {
+                String::new()
+            }
+        };
+
+        match self {
+            Self::Branch { li, absolute, link } => write!(
+                f,
+                "b{}{} {}",
+                if *link { "l" } else { "" },
+                if *absolute { "a" } else { "" },
+                branch_target(*li as i64, *absolute)
+            ),
+            Self::BranchConditional {
+                bo,
+                bi,
+                bd,
+                absolute,
+                link,
+            } => write_conditional_branch(f, *bo, *bi, "", *link, Some((*bd, *absolute))),
+            Self::BranchConditionalToLinkRegister { bo, bi, link } => {
+                write_conditional_branch(f, *bo, *bi, "lr", *link, None)
+            }
+            Self::BranchConditionalToCountRegister { bo, bi, link } => {
+                write_conditional_branch(f, *bo, *bi, "ctr", *link, None)
+            }
+            Self::Compare {
+                bf,
+                doubleword,
+                ra,
+                rb,
+            } => write!(f, "cmp{} {}r{ra}, r{rb}", width(doubleword), field(bf)),
+            Self::CompareImmediate {
+                bf,
+                doubleword,
+                ra,
+                si,
+            } => write!(f, "cmp{}i {}r{ra}, {si}", width(doubleword), field(bf)),
+            Self::CompareLogical {
+                bf,
+                doubleword,
+                ra,
+                rb,
+            } => write!(f, "cmpl{} {}r{ra}, r{rb}", width(doubleword), field(bf)),
+            Self::CompareLogicalImmediate {
+                bf,
+                doubleword,
+                ra,
+                ui,
+            } => write!(f, "cmpl{}i {}r{ra}, {ui}", width(doubleword), field(bf)),
+            Self::Other(insn) => write_other(f, *insn),
+        }
+    }
+}
+
+pub(crate) struct Disassembler {
+    last: Option<Instruction>,
+    big_endian: bool,
+}
+
+impl Disassembler {
+    pub fn new() -> Self {
+        Self {
+            last: None,
+            big_endian: true,
+        }
+    }
+
+    /// Set whether instructions are encoded big-endian, which is the default, or
+    /// little-endian
+    pub fn set_big_endian(&mut self, big_endian: bool) {
+        self.big_endian = big_endian;
+    }
+
+    /// Decode an instruction in the byte order of the processor
+    fn decode(&self, bytes: &[u8]) -> Result<Instruction> {
+        let bytes: [u8; 4] = bytes
+            .get(..size_of::<u32>())
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| anyhow!("Could not disassemble {:?}", bytes))?;
+
+        Ok(Instruction::decode(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }))
+    }
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TracerDisassembler for Disassembler {
+    fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
+        self.last = Some(self.decode(bytes)?);
+        Ok(())
+    }
+
+    fn disassemble_to_string(&mut self, bytes: &[u8]) -> Result<String> {
+        Ok(self.decode(bytes)?.to_string())
+    }
+
+    fn last_len(&self) -> Option<usize> {
+        self.last.map(|_| size_of::<u32>())
+    }
+
+    fn last_was_control_flow(&self) -> bool {
+        matches!(
+            self.last,
+            Some(
+                Instruction::Branch { link: false, .. }
+                    | Instruction::BranchConditional { link: false, .. }
+                    | Instruction::BranchConditionalToCountRegister { link: false, .. }
+            )
+        )
+    }
+
+    fn last_was_call(&self) -> bool {
+        matches!(
+            self.last,
+            Some(
+                Instruction::Branch { link: true, .. }
+                    | Instruction::BranchConditional { link: true, .. }
+                    | Instruction::BranchConditionalToLinkRegister { link: true, .. }
+                    | Instruction::BranchConditionalToCountRegister { link: true, .. }
+            )
+        )
+    }
+
+    fn last_was_ret(&self) -> bool {
+        matches!(
+            self.last,
+            Some(Instruction::BranchConditionalToLinkRegister { link: false, .. })
+        )
+    }
+
+    fn last_was_cmp(&self) -> bool {
+        matches!(
+            self.last,
+            Some(
+                Instruction::Compare { .. }
+                    | Instruction::CompareImmediate { .. }
+                    | Instruction::CompareLogical { .. }
+                    | Instruction::CompareLogicalImmediate { .. }
+            )
+        )
+    }
+
+    fn last_was_conditional(&self) -> bool {
+        self.last.is_some_and(|last| last.is_conditional())
+    }
+
+    fn last_was_indirect_jump(&self) -> bool {
+        matches!(
+            self.last,
+            Some(Instruction::BranchConditionalToCountRegister { link: false, .. })
+        )
+    }
+
+    fn cmp(&self) -> Vec<CmpExpr> {
+        let register = |r: &u8, doubleword: &bool| {
+            CmpExpr::Reg((format!("r{r}"), if *doubleword { 64 } else { 32 }))
+        };
+
+        match &self.last {
+            Some(Instruction::Compare {
+                doubleword, ra, rb, ..
+            })
+            | Some(Instruction::CompareLogical {
+                doubleword, ra, rb, ..
+            }) => {
+                vec![register(ra, doubleword), register(rb, doubleword)]
+            }
+            Some(Instruction::CompareImmediate {
+                doubleword, ra, si, ..
+            }) => {
+                vec![register(ra, doubleword), CmpExpr::I16(*si)]
+            }
+            Some(Instruction::CompareLogicalImmediate {
+                doubleword, ra, ui, ..
+            }) => {
+                vec![register(ra, doubleword), CmpExpr::U16(*ui)]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn cmp_type(&self) -> Vec<CmpType> {
+        if self.last_was_cmp() {
+            vec![CmpType::Equal, CmpType::Greater, CmpType::Lesser]
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Disassembler;
+    use crate::{
+        tracer::{CmpExpr, CmpType},
+        traits::TracerDisassembler,
+    };
+
+    /// Encodings with their disassembly and whether they are control flow, a call, a return,
+    /// a comparison, a conditional branch, and an indirect jump
+    const INSTRUCTIONS: &[(u32, &str, [bool; 6])] = &[
+        (
+            0x48000010,
+            "b .+0x10",
+            [true, false, false, false, false, false],
+        ),
+        (
+            0x4bfffff9,
+            "bl .-0x8",
+            [false, true, false, false, false, false],
+        ),
+        (
+            0x48000102,
+            "ba 0x100",
+            [true, false, false, false, false, false],
+        ),
+        (
+            0x41820008,
+            "beq .+0x8",
+            [true, false, false, false, true, false],
+        ),
+        (
+            0x4086fff0,
+            "bne cr1, .-0x10",
+            [true, false, false, false, true, false],
+        ),
+        (
+            0x4200fffc,
+            "bdnz .-0x4",
+            [true, false, false, false, true, false],
+        ),
+        (0x4e800020, "blr", [false, false, true, false, false, false]),
+        (
+            0x4e800021,
+            "blrl",
+            [false, true, false, false, false, false],
+        ),
+        (
+            0x4d820020,
+            "beqlr",
+            [false, false, true, false, true, false],
+        ),
+        (0x4e800420, "bctr", [true, false, false, false, false, true]),
+        (
+            0x4e800421,
+            "bctrl",
+            [false, true, false, false, false, false],
+        ),
+        (
+            0x7c032000,
+            "cmpw r3, r4",
+            [false, false, false, true, false, false],
+        ),
+        (
+            0x7fa32000,
+            "cmpd cr7, r3, r4",
+            [false, false, false, true, false, false],
+        ),
+        (
+            0x7c053040,
+            "cmplw r5, r6",
+            [false, false, false, true, false, false],
+        ),
+        (
+            0x2c03ffff,
+            "cmpwi r3, -1",
+            [false, false, false, true, false, false],
+        ),
+        (
+            0x2823000a,
+            "cmpldi r3, 10",
+            [false, false, false, true, false, false],
+        ),
+        (0x38600000, "li r3, 0", [false; 6]),
+        (0x3821fff0, "addi r1, r1, -16", [false; 6]),
+        (0x60000000, "nop", [false; 6]),
+        (0x7c0802a6, "mflr r0", [false; 6]),
+        (0x7d2903a6, "mtctr r9", [false; 6]),
+        (0x7c3f0b78, "mr r31, r1", [false; 6]),
+        (0x7c642a14, "add r3, r4, r5", [false; 6]),
+        (0x5483103a, "rlwinm r3, r4, 2, 0, 29", [false; 6]),
+        (0x9421ffe0, "stwu r1, -32(r1)", [false; 6]),
+        (0x80010024, "lwz r0, 36(r1)", [false; 6]),
+        (0xfbe1fff8, "std r31, -8(r1)", [false; 6]),
+        (0xfc21102a, "fadd f1, f1, f2", [false; 6]),
+        (0x44000002, "sc", [false; 6]),
+        (0x7fe00008, "trap", [false; 6]),
+        (0x00000000, ".long 0x0", [false; 6]),
+    ];
+
+    #[test]
+    fn test_decode_instructions() {
+        let mut disassembler = Disassembler::new();
+
+        for (insn, disassembly, classification) in INSTRUCTIONS {
+            for big_endian in [true, false] {
+                let bytes = if big_endian {
+                    insn.to_be_bytes()
+                } else {
+                    insn.to_le_bytes()
+                };
+
+                disassembler.set_big_endian(big_endian);
+                disassembler
+                    .disassemble(&bytes)
+                    .expect("Failed to disassemble");
+
+                assert_eq!(disassembler.last_len(), Some(4));
+                assert_eq!(
+                    [
+                        disassembler.last_was_control_flow(),
+                        disassembler.last_was_call(),
+                        disassembler.last_was_ret(),
+                        disassembler.last_was_cmp(),
+                        disassembler.last_was_conditional(),
+                        disassembler.last_was_indirect_jump(),
+                    ],
+                    *classification,
+                    "{disassembly}"
+                );
+                assert_eq!(
+                    disassembler
+                        .disassemble_to_string(&bytes)
+                        .expect("Failed to disassemble"),
+                    *disassembly,
+                    "{insn:#010x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_operands() {
+        let mut disassembler = Disassembler::new();
+        let mut operands = |insn: u32| {
+            disassembler
+                .disassemble(&insn.to_be_bytes())
+                .expect("Failed to disassemble");
+            (disassembler.cmp(), disassembler.cmp_type())
+        };
+        let register = |name: &str, width| CmpExpr::Reg((name.to_string(), width));
+        let ordered = vec![CmpType::Equal, CmpType::Greater, CmpType::Lesser];
+
+        // cmpw r3, r4
+        assert_eq!(
+            operands(0x7c032000),
+            (
+                vec![register("r3", 32), register("r4", 32)],
+                ordered.clone()
+            )
+        );
+        // cmpd cr7, r3, r4
+        assert_eq!(
+            operands(0x7fa32000),
+            (
+                vec![register("r3", 64), register("r4", 64)],
+                ordered.clone()
+            )
+        );
+        // cmpwi r3, -1
+        assert_eq!(
+            operands(0x2c03ffff),
+            (vec![register("r3", 32), CmpExpr::I16(-1)], ordered.clone())
+        );
+        // cmpldi r3, 10
+        assert_eq!(
+            operands(0x2823000a),
+            (vec![register("r3", 64), CmpExpr::U16(10)], ordered)
+        );
+        // beq .+0x8
+        assert_eq!(operands(0x41820008), (vec![], vec![]));
+    }
+}
@@ -11,8 +11,8 @@ use simics::api::{
     CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface, CycleInterface,
     IntRegisterInterface, ProcessorInfoV2Interface,
 };
-use std::{ffi::CStr, mem::size_of, slice::from_raw_parts};
-use yaxpeax_arch::{Decoder, U8Reader};
+use std::{ffi::CStr, mem::size_of};
+use yaxpeax_arch::{Decoder, LengthedInstruction, U8Reader};
 use yaxpeax_riscv::{Instruction, Opcode, Operand, RiscVDecoder};
 
 use crate::{
@@ -20,7 +20,26 @@ use crate::{
     traits::TracerDisassembler,
 };
 
-use super::ArchitectureOperations;
+use super::{decode_cache::DecodeCache, ArchitectureOperations, CallingConvention, Endianness};
+
+/// The architecture-level names of the RISC-V exceptions. SIMICS numbers RISC-V exceptions by
+/// their exception code in `mcause`, which is the same on every processor model.
+const EXCEPTIONS: &[(&str, i64)] = &[
+    ("instruction-address-misaligned", 0),
+    ("instruction-access-fault", 1),
+    ("illegal-instruction", 2),
+    ("breakpoint", 3),
+    ("load-address-misaligned", 4),
+    ("load-access-fault", 5),
+    ("store-address-misaligned", 6),
+    ("store-access-fault", 7),
+    ("environment-call-from-u-mode", 8),
+    ("environment-call-from-s-mode", 9),
+    ("environment-call-from-m-mode", 11),
+    ("instruction-page-fault", 12),
+    ("load-page-fault", 13),
+    ("store-page-fault", 15),
+];
 
 pub(crate) struct RISCVArchitectureOperations {
     cpu: *mut ConfObject,
@@ -31,6 +50,11 @@ pub(crate) struct RISCVArchitectureOperations {
     #[allow(dead_code)]
     cpu_instrumentation_subscribe: CpuInstrumentationSubscribeInterface,
     cycle: CycleInterface,
+    decode_cache: DecodeCache,
+    endianness: Endianness,
+    /// The operands of the last comparison, which may be the bounds check of a jump table
+    /// index if an indirect jump follows
+    jump_table_bounds: Option<CmpValues>,
 }
 
 impl ArchitectureOperations for RISCVArchitectureOperations {
@@ -50,14 +74,20 @@ impl ArchitectureOperations for RISCVArchitectureOperations {
             .to_string();
 
         if arch == "risc-v" || arch == "riscv" || arch == "riscv32" || arch == "riscv64" {
+            let mut disassembler = Disassembler::new();
+            disassembler.set_rv32(processor_info_v2.get_logical_address_width()? == 32);
+
             Ok(Self {
                 cpu,
-                disassembler: Disassembler::new(),
+                disassembler,
                 int_register: get_interface(cpu)?,
                 processor_info_v2,
                 cpu_instruction_query: get_interface(cpu)?,
                 cpu_instrumentation_subscribe: get_interface(cpu)?,
                 cycle: get_interface(cpu)?,
+                decode_cache: DecodeCache::default(),
+                endianness: Endianness::Little,
+                jump_table_bounds: None,
             })
         } else {
             bail!("Architecture {} is not risc-v", arch);
@@ -68,14 +98,21 @@ impl ArchitectureOperations for RISCVArchitectureOperations {
     where
         Self: Sized,
     {
+        let mut processor_info_v2: ProcessorInfoV2Interface = get_interface(cpu)?;
+        let mut disassembler = Disassembler::new();
+        disassembler.set_rv32(processor_info_v2.get_logical_address_width()? == 32);
+
         Ok(Self {
             cpu,
-            disassembler: Disassembler::new(),
+            disassembler,
             int_register: get_interface(cpu)?,
-            processor_info_v2: get_interface(cpu)?,
+            processor_info_v2,
             cpu_instruction_query: get_interface(cpu)?,
             cpu_instrumentation_subscribe: get_interface(cpu)?,
             cycle: get_interface(cpu)?,
+            decode_cache: DecodeCache::default(),
+            endianness: Endianness::Little,
+            jump_table_bounds: None,
         })
     }
 
@@ -107,21 +144,61 @@ impl ArchitectureOperations for RISCVArchitectureOperations {
         &mut self.cycle
     }
 
-    fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        &mut self.decode_cache
+    }
 
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
+    fn endianness(&mut self) -> &mut Endianness {
+        &mut self.endianness
+    }
+
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        EXCEPTIONS
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        const REGISTERS: &[&str] = &["x10", "x11", "x12", "x13", "x14", "x15", "x16", "x17"];
+
+        if convention != CallingConvention::Default {
+            bail!("Calling convention {convention} is not supported");
+        }
+
+        if let Some(register) = REGISTERS.get(index) {
+            self.read_register(register)
+        } else {
+            // NOTE: We have to handle both riscv64 and riscv32 here
+            let xlen =
+                self.processor_info_v2().get_logical_address_width()? as usize / u8::BITS as usize;
+            let address = self.read_register("x2")? + ((index - REGISTERS.len()) * xlen) as u64;
+            self.read_virtual_integer(address, xlen)
+        }
+    }
+
+    fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
+        let decoded = self.decode(instruction_query)?;
+
+        if decoded.is_edge() {
+            let source = self
+                .cpu_instruction_query
+                .logical_address(instruction_query)?;
+
+            // The bounds check of a jump table index is followed by a conditional branch to
+            // the default case and then the indirect jump through the table
+            let jump_table = if decoded.indirect_jump {
+                self.jump_table_bounds.take().map(|bounds| (source, bounds))
+            } else {
+                if !decoded.conditional {
+                    self.jump_table_bounds = None;
+                }
+                None
+            };
 
-        if self.disassembler.last_was_call()
-            || self.disassembler.last_was_control_flow()
-            || self.disassembler.last_was_ret()
-        {
             Ok(TraceEntry::builder()
                 .edge(self.processor_info_v2.get_program_counter()?)
+                .source(source)
+                .jump_table(jump_table)
+                .call(decoded.call)
+                .ret(decoded.ret)
                 .build())
         } else {
             Ok(TraceEntry::default())
@@ -129,56 +206,34 @@ impl ArchitectureOperations for RISCVArchitectureOperations {
     }
 
     fn trace_cmp(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
+        let decoded = self.decode(instruction_query)?;
 
         let pc = self.processor_info_v2.get_program_counter()?;
 
         let mut cmp_values = Vec::new();
 
-        for expr in self.disassembler.cmp() {
-            if let Ok(value) = self.simplify(&expr) {
+        for expr in decoded.cmp_exprs.iter() {
+            if let Ok(value) = self.simplify(expr) {
                 cmp_values.push(value);
             }
         }
 
+        // Registers are compared with sign extended immediates and the implicit zero of
+        // compressed branches, so operands are extended to a common width
         let cmp_value = if let (Some(l), Some(r)) = (cmp_values.first(), cmp_values.get(1)) {
-            match (l, r) {
-                (CmpValue::U8(l), CmpValue::U8(r)) => Some(CmpValues::U8((*l, *r))),
-                (CmpValue::I8(l), CmpValue::I8(r)) => Some(CmpValues::U8((
-                    u8::from_le_bytes(l.to_le_bytes()),
-                    u8::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U16(l), CmpValue::U16(r)) => Some(CmpValues::U16((*l, *r))),
-                (CmpValue::I16(l), CmpValue::I16(r)) => Some(CmpValues::U16((
-                    u16::from_le_bytes(l.to_le_bytes()),
-                    u16::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U32(l), CmpValue::U32(r)) => Some(CmpValues::U32((*l, *r))),
-                (CmpValue::I32(l), CmpValue::I32(r)) => Some(CmpValues::U32((
-                    u32::from_le_bytes(l.to_le_bytes()),
-                    u32::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::U64(l), CmpValue::U64(r)) => Some(CmpValues::U64((*l, *r))),
-                (CmpValue::I64(l), CmpValue::I64(r)) => Some(CmpValues::U64((
-                    u64::from_le_bytes(l.to_le_bytes()),
-                    u64::from_le_bytes(r.to_le_bytes()),
-                ))),
-                (CmpValue::Expr(_), CmpValue::Expr(_)) => None,
-                _ => None,
-            }
+            CmpValue::pair(l, r)
         } else {
             None
         };
 
+        if cmp_value.is_some() && !decoded.cmp_types.contains(&CmpType::Fp) {
+            self.jump_table_bounds.clone_from(&cmp_value);
+        }
+
         Ok(TraceEntry::builder()
             .cmp((
                 pc,
-                self.disassembler.cmp_type(),
+                decoded.cmp_types.clone(),
                 cmp_value.ok_or_else(|| anyhow!("No cmp value available"))?,
             ))
             .build())
@@ -427,9 +482,69 @@ impl RISCVArchitectureOperations {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A control flow instruction from the compressed (C) extension, which the decoder does not
+/// support. Only the fields needed for tracing are kept.
+enum CompressedInstruction {
+    /// `c.beqz rs1', offset`, with the full register number of `rs1'`
+    Beqz(u8),
+    /// `c.bnez rs1', offset`, with the full register number of `rs1'`
+    Bnez(u8),
+    /// `c.j offset`
+    J,
+    /// `c.jal offset`, which is only defined on RV32
+    Jal,
+    /// `c.jr rs1`
+    Jr(u8),
+    /// `c.jalr rs1`
+    Jalr(u8),
+}
+
+impl CompressedInstruction {
+    /// Decode a compressed control flow instruction. The encoding of `c.jal` on RV32 is used
+    /// by `c.addiw` on RV64, so it is only decoded if `rv32` is set.
+    fn decode(bytes: &[u8], rv32: bool) -> Option<Self> {
+        let insn = u16::from_le_bytes(bytes.get(..2)?.try_into().ok()?);
+        let quadrant = insn & 0b11;
+        let funct3 = insn >> 13;
+        // The 3 bit register fields of the CB format address x8 through x15
+        let rs1_prime = ((insn >> 7) & 0b111) as u8 + 8;
+        let rs1 = ((insn >> 7) & 0b11111) as u8;
+        let rs2 = ((insn >> 2) & 0b11111) as u8;
+        let bit12 = (insn >> 12) & 1;
+
+        match (quadrant, funct3) {
+            (0b01, 0b110) => Some(Self::Beqz(rs1_prime)),
+            (0b01, 0b111) => Some(Self::Bnez(rs1_prime)),
+            (0b01, 0b101) => Some(Self::J),
+            (0b01, 0b001) if rv32 => Some(Self::Jal),
+            (0b10, 0b100) if rs1 != 0 && rs2 == 0 && bit12 == 0 => Some(Self::Jr(rs1)),
+            (0b10, 0b100) if rs1 != 0 && rs2 == 0 && bit12 == 1 => Some(Self::Jalr(rs1)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CompressedInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Beqz(r) => write!(f, "c.beqz x{r}"),
+            Self::Bnez(r) => write!(f, "c.bnez x{r}"),
+            Self::J => write!(f, "c.j"),
+            Self::Jal => write!(f, "c.jal"),
+            Self::Jr(r) => write!(f, "c.jr x{r}"),
+            Self::Jalr(r) => write!(f, "c.jalr x{r}"),
+        }
+    }
+}
+
 pub(crate) struct Disassembler {
     decoder: RiscVDecoder,
     last: Option<Instruction>,
+    /// The last instruction, if it was a compressed instruction
+    last_compressed: Option<CompressedInstruction>,
+    /// Whether the processor is RV32, which changes the meaning of some compressed encodings
+    rv32: bool,
 }
 
 impl Disassembler {
@@ -437,8 +552,21 @@ impl Disassembler {
         Self {
             decoder: RiscVDecoder::default(),
             last: None,
+            last_compressed: None,
+            rv32: false,
         }
     }
+
+    /// Set whether the processor is RV32, instead of RV64
+    pub fn set_rv32(&mut self, rv32: bool) {
+        self.rv32 = rv32;
+    }
+
+    /// Whether an instruction is a compressed instruction, which is identified by the low two
+    /// bits of the first halfword not both being set
+    fn is_compressed(bytes: &[u8]) -> bool {
+        bytes.first().is_some_and(|b| b & 0b11 != 0b11)
+    }
 }
 
 impl Default for Disassembler {
@@ -451,8 +579,16 @@ impl TracerDisassembler for Disassembler {
     fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
         let mut r = U8Reader::new(bytes);
 
+        self.last_compressed = None;
+
         if let Ok(insn) = self.decoder.decode(&mut r) {
             self.last = Some(insn);
+        } else if let Some(insn) = Self::is_compressed(bytes)
+            .then(|| CompressedInstruction::decode(bytes, self.rv32))
+            .flatten()
+        {
+            self.last = None;
+            self.last_compressed = Some(insn);
         } else {
             bail!("Could not disassemble {:?}", bytes);
         }
@@ -465,18 +601,47 @@ impl TracerDisassembler for Disassembler {
 
         if let Ok(insn) = self.decoder.decode(&mut r) {
             Ok(insn.to_string())
+        } else if let Some(insn) = Self::is_compressed(bytes)
+            .then(|| CompressedInstruction::decode(bytes, self.rv32))
+            .flatten()
+        {
+            Ok(insn.to_string())
         } else {
             bail!("Could not disassemble {:?}", bytes);
         }
     }
 
+    fn last_len(&self) -> Option<usize> {
+        if self.last_compressed.is_some() {
+            return Some(2);
+        }
+
+        self.last
+            .as_ref()
+            .map(|last| last.len().to_const() as usize)
+    }
+
     fn last_was_control_flow(&self) -> bool {
+        if let Some(last) = self.last_compressed {
+            return matches!(
+                last,
+                CompressedInstruction::Beqz(_)
+                    | CompressedInstruction::Bnez(_)
+                    | CompressedInstruction::J
+                    | CompressedInstruction::Jal
+                    | CompressedInstruction::Jr(_)
+                    | CompressedInstruction::Jalr(_)
+            );
+        }
+
         if let Some(last) = self.last.as_ref() {
             if matches!(last.opcode(), |Opcode::BEQ| Opcode::BNE
                 | Opcode::BLT
                 | Opcode::BGE
                 | Opcode::BLTU
-                | Opcode::BGEU)
+                | Opcode::BGEU
+                | Opcode::JAL
+                | Opcode::JALR)
             {
                 return true;
             }
@@ -485,27 +650,63 @@ impl TracerDisassembler for Disassembler {
         false
     }
 
-    // TODO: Make call/ret distinction more accurate, all three can ret/call far or near, but
-    // there are semantic versions based on operands:
-    // https://inst.eecs.berkeley.edu/~cs61c/fa20/pdfs/lectures/lec12-bw.pdf
+    // NOTE: Calls and returns are both jumps, and are told apart by the link registers of the
+    // standard calling convention, which are x1 (ra) and x5 (t0, used by millicode calls). A
+    // call links the return address in a link register, and a return jumps through a link
+    // register while discarding its own return address in x0.
 
     fn last_was_call(&self) -> bool {
+        if let Some(last) = self.last_compressed {
+            // c.jal and c.jalr link to x1
+            return matches!(
+                last,
+                CompressedInstruction::Jal | CompressedInstruction::Jalr(_)
+            );
+        }
+
         if let Some(last) = self.last.as_ref() {
-            return matches!(last.opcode(), Opcode::JALR | Opcode::JAL | Opcode::AUIPC);
+            return matches!(last.opcode(), Opcode::JAL | Opcode::JALR)
+                && matches!(
+                    last.operands().into_iter().next(),
+                    Some(Some(Operand::Reg(1 | 5)))
+                );
         }
 
         false
     }
 
     fn last_was_ret(&self) -> bool {
+        if let Some(last) = self.last_compressed {
+            // c.jr links to x0
+            return matches!(last, CompressedInstruction::Jr(1 | 5));
+        }
+
         if let Some(last) = self.last.as_ref() {
-            return matches!(last.opcode(), Opcode::JALR | Opcode::JAL | Opcode::AUIPC);
+            if !matches!(last.opcode(), Opcode::JALR) {
+                return false;
+            }
+
+            let mut operands = last.operands().into_iter();
+            return matches!(operands.next(), Some(Some(Operand::Reg(0))))
+                && operands.any(|operand| {
+                    matches!(
+                        operand,
+                        Some(Operand::Reg(1 | 5)) | Some(Operand::BaseOffset(1 | 5, _))
+                    )
+                });
         }
 
         false
     }
 
     fn last_was_cmp(&self) -> bool {
+        if let Some(last) = self.last_compressed {
+            return matches!(
+                last,
+                CompressedInstruction::Beqz(_) | CompressedInstruction::Bnez(_)
+            );
+        }
+
         if let Some(last) = self.last.as_ref() {
             return matches!(
                 last.opcode(),
@@ -515,6 +716,7 @@ impl TracerDisassembler for Disassembler {
                     | Opcode::SLTIU
                     | Opcode::BEQ
                     | Opcode::BNE
+                    | Opcode::BLT
                     | Opcode::BGE
                     | Opcode::BLTU
                     | Opcode::BGEU
@@ -524,8 +726,54 @@ impl TracerDisassembler for Disassembler {
         false
     }
 
+    fn last_was_conditional(&self) -> bool {
+        if let Some(last) = self.last_compressed {
+            return matches!(
+                last,
+                CompressedInstruction::Beqz(_) | CompressedInstruction::Bnez(_)
+            );
+        }
+
+        if let Some(last) = self.last.as_ref() {
+            return matches!(
+                last.opcode(),
+                Opcode::BEQ | Opcode::BNE | Opcode::BLT | Opcode::BGE | Opcode::BLTU | Opcode::BGEU
+            );
+        }
+
+        false
+    }
+
+    fn last_was_indirect_jump(&self) -> bool {
+        // A jump discards the return address by linking to x0, and is not a return
+        if let Some(last) = self.last_compressed {
+            return matches!(last, CompressedInstruction::Jr(_)) && !self.last_was_ret();
+        }
+
+        if let Some(last) = self.last.as_ref() {
+            return matches!(last.opcode(), Opcode::JALR)
+                && matches!(
+                    last.operands().into_iter().next(),
+                    Some(Some(Operand::Reg(0)))
+                )
+                && !self.last_was_ret();
+        }
+
+        false
+    }
+
     fn cmp(&self) -> Vec<CmpExpr> {
         let mut cmp_exprs = Vec::new();
+
+        // Compressed branches compare a register with an implicit zero
+        if let Some(CompressedInstruction::Beqz(r) | CompressedInstruction::Bnez(r)) =
+            self.last_compressed
+        {
+            cmp_exprs.push(CmpExpr::Reg((format!("x{r}"), 0)));
+            cmp_exprs.push(CmpExpr::I32(0));
+            return cmp_exprs;
+        }
+
         if self.last_was_cmp() {
             if let Some(last) = self.last.as_ref() {
                 for operand in last.operands() {
@@ -561,6 +809,14 @@ impl TracerDisassembler for Disassembler {
     }
 
     fn cmp_type(&self) -> Vec<CmpType> {
+        if self.last_compressed.is_some() {
+            return if self.last_was_cmp() {
+                vec![CmpType::Equal]
+            } else {
+                vec![]
+            };
+        }
+
         if self.last_was_cmp() {
             if let Some(last) = self.last.as_ref() {
                 return match last.opcode() {
@@ -570,6 +826,7 @@ impl TracerDisassembler for Disassembler {
                     Opcode::SLTIU => vec![CmpType::Lesser],
                     Opcode::BEQ => vec![CmpType::Equal],
                     Opcode::BNE => vec![CmpType::Equal],
+                    Opcode::BLT => vec![CmpType::Lesser],
                     Opcode::BGE => vec![CmpType::Greater, CmpType::Equal],
                     Opcode::BLTU => vec![CmpType::Lesser],
                     Opcode::BGEU => vec![CmpType::Greater, CmpType::Equal],
@@ -3,26 +3,64 @@
 
 //! Architecture-specific implementation for x86 architecture
 
-use std::{ffi::CStr, mem::size_of, slice::from_raw_parts};
+use std::{ffi::CStr, mem::size_of};
 
-use super::ArchitectureOperations;
+use super::{
+    decode_cache::DecodeCache, ArchitectureOperations, CallingConvention, Endianness, PAGE_SIZE,
+};
 use crate::{
-    tracer::{CmpExpr, CmpType, CmpValue, TraceEntry},
+    tracer::{routines::ROUTINE_OPERAND_SIZE, CmpExpr, CmpType, CmpValue, TraceEntry},
     traits::TracerDisassembler,
     CLASS_NAME,
 };
-use anyhow::{anyhow, bail, Error, Result};
+use anyhow::{anyhow, bail, Result};
 use libafl::prelude::CmpValues;
 use raw_cstr::AsRawCstr;
 use simics::{
     api::{
-        get_interface, get_object, read_phys_memory, sys::instruction_handle_t, Access, ConfObject,
-        CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface, CycleInterface,
-        IntRegisterInterface, ProcessorInfoV2Interface,
+        get_attribute, get_interface, get_object, read_phys_memory,
+        sys::{instruction_handle_t, physical_block_t, x86_seg_t},
+        Access, ConfObject, CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface,
+        CycleInterface, IntRegisterInterface, ProcessorInfoV2Interface, X86RegAccessInterface,
     },
     trace,
 };
-use yaxpeax_x86::protected_mode::{ConditionCode, InstDecoder, Instruction, Opcode, Operand};
+
+/// The protection enable bit of CR0, which is clear in real mode
+const CR0_PE: u64 = 1 << 0;
+/// The paging bit of CR0
+const CR0_PG: u64 = 1 << 31;
+/// The virtual-8086 mode bit of EFLAGS
+const EFLAGS_VM: u64 = 1 << 17;
+/// The default operation size bit of the attributes of a segment, which is clear for 16-bit
+/// code segments
+const SEGMENT_ATTRIBUTE_DB: u32 = 1 << 14;
+
+/// The architecture-level names of the x86 exceptions. SIMICS numbers x86 exceptions by their
+/// interrupt vector, which is the same on every processor model.
+pub(crate) const EXCEPTIONS: &[(&str, i64)] = &[
+    ("divide-error", 0),
+    ("debug", 1),
+    ("nmi", 2),
+    ("breakpoint", 3),
+    ("overflow", 4),
+    ("bound-range-exceeded", 5),
+    ("invalid-opcode", 6),
+    ("illegal-instruction", 6),
+    ("device-not-available", 7),
+    ("double-fault", 8),
+    ("invalid-tss", 10),
+    ("segment-not-present", 11),
+    ("stack-segment-fault", 12),
+    ("general-protection", 13),
+    ("page-fault", 14),
+    ("x87-floating-point", 16),
+    ("alignment-check", 17),
+    ("machine-check", 18),
+    ("simd-floating-point", 19),
+    ("virtualization", 20),
+    ("control-protection", 21),
+];
 
 pub(crate) struct X86ArchitectureOperations {
     cpu: *mut ConfObject,
@@ -33,6 +71,24 @@ pub(crate) struct X86ArchitectureOperations {
     #[allow(dead_code)]
     cpu_instrumentation_subscribe: CpuInstrumentationSubscribeInterface,
     cycle: CycleInterface,
+    decode_cache: DecodeCache,
+    endianness: Endianness,
+    /// The segment registers and descriptor caches, if the processor model provides them
+    x86_reg_access: Option<X86RegAccessInterface>,
+    /// Whether the processor is always treated as executing real mode code
+    real_mode: bool,
+    /// Whether the processor is executing 16-bit code, whose addresses are offsets into
+    /// segments
+    sixteen_bit: bool,
+    /// The base of the code segment at the last edge, which instructions up to the next edge
+    /// are fetched from
+    code_segment_base: Option<u64>,
+    /// The address and operands of the last arithmetic instruction which set flags, logged if
+    /// the flags are consumed by a conditional branch
+    flags_cmp: Option<(u64, CmpValues)>,
+    /// The operands of the last comparison, which may be the bounds check of a jump table
+    /// index if an indirect jump follows
+    jump_table_bounds: Option<CmpValues>,
 }
 
 impl ArchitectureOperations for X86ArchitectureOperations {
@@ -101,6 +157,14 @@ impl ArchitectureOperations for X86ArchitectureOperations {
                     cpu_instruction_query: get_interface(cpu)?,
                     cpu_instrumentation_subscribe: get_interface(cpu)?,
                     cycle: get_interface(cpu)?,
+                    decode_cache: DecodeCache::default(),
+                    endianness: Endianness::Little,
+                    x86_reg_access: get_interface(cpu).ok(),
+                    real_mode: false,
+                    sixteen_bit: false,
+                    code_segment_base: None,
+                    flags_cmp: None,
+                    jump_table_bounds: None,
                 })
             } else {
                 unreachable!("Register set must either contain a 64-bit register or no registers may be 64-bit");
@@ -118,6 +182,14 @@ impl ArchitectureOperations for X86ArchitectureOperations {
                 cpu_instruction_query: get_interface(cpu)?,
                 cpu_instrumentation_subscribe: get_interface(cpu)?,
                 cycle: get_interface(cpu)?,
+                decode_cache: DecodeCache::default(),
+                endianness: Endianness::Little,
+                x86_reg_access: get_interface(cpu).ok(),
+                real_mode: false,
+                sixteen_bit: false,
+                code_segment_base: None,
+                flags_cmp: None,
+                jump_table_bounds: None,
             })
         } else {
             bail!("Unsupported architecture {arch}");
@@ -136,6 +208,14 @@ impl ArchitectureOperations for X86ArchitectureOperations {
             cpu_instruction_query: get_interface(cpu)?,
             cpu_instrumentation_subscribe: get_interface(cpu)?,
             cycle: get_interface(cpu)?,
+            decode_cache: DecodeCache::default(),
+            endianness: Endianness::Little,
+            x86_reg_access: get_interface(cpu).ok(),
+            real_mode: false,
+            sixteen_bit: false,
+            code_segment_base: None,
+            flags_cmp: None,
+            jump_table_bounds: None,
         })
     }
 
@@ -167,75 +247,171 @@ impl ArchitectureOperations for X86ArchitectureOperations {
         &mut self.cycle
     }
 
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        &mut self.decode_cache
+    }
+
+    fn endianness(&mut self) -> &mut Endianness {
+        &mut self.endianness
+    }
+
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        EXCEPTIONS
+    }
+
+    fn update_decoder_mode(&mut self) -> Result<u8> {
+        self.update_mode()?;
+        Ok(self.sixteen_bit as u8)
+    }
+
+    fn virtual_to_physical(&mut self, address: u64, access: Access) -> Result<physical_block_t> {
+        // Without paging, linear addresses are physical addresses
+        if self.read_register("cr0").is_ok_and(|cr0| cr0 & CR0_PG == 0) {
+            let block_start = address & !(PAGE_SIZE - 1);
+            return Ok(physical_block_t {
+                valid: 1,
+                address,
+                block_start,
+                block_end: block_start + PAGE_SIZE - 1,
+            });
+        }
+
+        Ok(self
+            .processor_info_v2
+            .logical_to_physical(address, access)?)
+    }
+
+    fn pointer_address(&mut self, pointer: u64) -> Result<u64> {
+        self.update_mode()?;
+
+        if self.sixteen_bit {
+            // Pointers in 16-bit code are offsets into the data segment
+            Ok(self.segment_base("ds")? + pointer)
+        } else {
+            Ok(pointer)
+        }
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        match convention {
+            CallingConvention::Default | CallingConvention::Cdecl if self.sixteen_bit => {
+                // Arguments of 16-bit code are in 2-byte stack slots, after a near return
+                // address
+                let offset = (self.read_register("esp")? + ((index + 1) * size_of::<u16>()) as u64)
+                    & u16::MAX as u64;
+                let address = self.segment_base("ss")? + offset;
+                self.read_virtual_integer(address, size_of::<u16>())
+            }
+            CallingConvention::Default | CallingConvention::Cdecl => {
+                // All arguments are on the stack, after the return address
+                let address = self.read_register("esp")? + ((index + 1) * size_of::<u32>()) as u64;
+                self.read_virtual_integer(address, size_of::<u32>())
+            }
+            _ => bail!("Calling convention {convention} is not supported"),
+        }
+    }
+
     fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
-        if self.disassembler.last_was_call()
-            || self.disassembler.last_was_control_flow()
-            || self.disassembler.last_was_ret()
-        {
-            Ok(TraceEntry::builder()
-                .edge(self.processor_info_v2.get_program_counter()?)
-                .build())
+        let decoded = self.decode(instruction_query)?;
+
+        if decoded.is_edge() {
+            let mut edge = self.processor_info_v2.get_program_counter()?;
+            let mut source = self
+                .cpu_instruction_query
+                .logical_address(instruction_query)?;
+
+            if self.sixteen_bit {
+                source += match self.code_segment_base {
+                    Some(base) => base,
+                    None => self.segment_base("cs")?,
+                };
+            }
+
+            // A far transfer may load a code segment of a different width
+            self.update_mode()?;
+
+            if self.sixteen_bit {
+                let base = self.segment_base("cs")?;
+                self.code_segment_base = Some(base);
+                edge += base;
+            } else {
+                self.code_segment_base = None;
+            }
+
+            // The bounds check of a jump table index is followed by a conditional branch to
+            // the default case and then the indirect jump through the table
+            let jump_table = if decoded.indirect_jump {
+                self.jump_table_bounds.take().map(|bounds| (source, bounds))
+            } else {
+                if !decoded.conditional {
+                    self.jump_table_bounds = None;
+                }
+                None
+            };
+
+            match self.flags_cmp.take() {
+                Some((pc, cmp)) if decoded.conditional => Ok(TraceEntry::builder()
+                    .edge(edge)
+                    .source(source)
+                    .cmp((pc, decoded.cmp_types.clone(), cmp))
+                    .jump_table(jump_table)
+                    .call(decoded.call)
+                    .ret(decoded.ret)
+                    .build()),
+                _ => Ok(TraceEntry::builder()
+                    .edge(edge)
+                    .source(source)
+                    .jump_table(jump_table)
+                    .call(decoded.call)
+                    .ret(decoded.ret)
+                    .build()),
+            }
         } else {
             Ok(TraceEntry::default())
         }
     }
 
     fn trace_cmp(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
-        if self.disassembler.last_was_cmp() {
-            let pc = self.processor_info_v2.get_program_counter()?;
+        let decoded = self.decode(instruction_query)?;
+
+        if decoded.cmp || decoded.sets_flags {
+            let mut pc = self.processor_info_v2.get_program_counter()?;
+
+            if self.sixteen_bit {
+                pc += self.segment_base("cs")?;
+            }
             let mut cmp_values = Vec::new();
 
-            for expr in self.disassembler.cmp() {
-                if let Ok(value) = self.simplify(&expr) {
+            for expr in decoded.cmp_exprs.iter() {
+                if let Ok(value) = self.simplify(expr) {
                     cmp_values.push(value);
                 }
             }
 
             let cmp_value = if let (Some(l), Some(r)) = (cmp_values.first(), cmp_values.get(1)) {
-                match (l, r) {
-                    (CmpValue::U8(l), CmpValue::U8(r)) => Some(CmpValues::U8((*l, *r))),
-                    (CmpValue::I8(l), CmpValue::I8(r)) => Some(CmpValues::U8((
-                        u8::from_le_bytes(l.to_le_bytes()),
-                        u8::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::U16(l), CmpValue::U16(r)) => Some(CmpValues::U16((*l, *r))),
-                    (CmpValue::I16(l), CmpValue::I16(r)) => Some(CmpValues::U16((
-                        u16::from_le_bytes(l.to_le_bytes()),
-                        u16::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::U32(l), CmpValue::U32(r)) => Some(CmpValues::U32((*l, *r))),
-                    (CmpValue::I32(l), CmpValue::I32(r)) => Some(CmpValues::U32((
-                        u32::from_le_bytes(l.to_le_bytes()),
-                        u32::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::U64(l), CmpValue::U64(r)) => Some(CmpValues::U64((*l, *r))),
-                    (CmpValue::I64(l), CmpValue::I64(r)) => Some(CmpValues::U64((
-                        u64::from_le_bytes(l.to_le_bytes()),
-                        u64::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::Expr(_), CmpValue::Expr(_)) => None,
-                    _ => None,
-                }
+                CmpValue::pair(l, r)
             } else {
                 None
             };
 
+            if decoded.sets_flags {
+                // The operands are only a comparison if a conditional branch consumes the
+                // flags, so they are held until the next edge
+                self.flags_cmp = cmp_value.map(|cmp| (pc, cmp));
+                return Ok(TraceEntry::default());
+            }
+
+            // A comparison replaces the flags set by earlier arithmetic
+            self.flags_cmp = None;
+
+            if !decoded.cmp_types.contains(&CmpType::Fp) {
+                self.jump_table_bounds.clone_from(&cmp_value);
+            }
+
             Ok(TraceEntry::builder()
                 .cmp((
                     pc,
-                    self.disassembler.cmp_type(),
+                    decoded.cmp_types.clone(),
                     cmp_value.ok_or_else(|| anyhow!("No cmp value available"))?,
                 ))
                 .build())
@@ -244,17 +420,126 @@ impl ArchitectureOperations for X86ArchitectureOperations {
         }
     }
 }
+
 impl X86ArchitectureOperations {
+    /// Treat the processor as executing 16-bit real mode code regardless of its state, for
+    /// code which runs before the processor model reports its mode correctly
+    pub fn with_real_mode(mut self) -> Self {
+        self.real_mode = true;
+        self.sixteen_bit = true;
+        self.disassembler.set_sixteen_bit(true);
+        self
+    }
+
+    /// Whether the processor is executing 16-bit code, which it does in real mode,
+    /// virtual-8086 mode and 16-bit protected mode code segments
+    fn executing_sixteen_bit_code(&mut self) -> Result<bool> {
+        if self.real_mode {
+            return Ok(true);
+        }
+
+        let Ok(cr0) = self.read_register("cr0") else {
+            return Ok(false);
+        };
+
+        if cr0 & CR0_PE == 0 || self.read_register("eflags")? & EFLAGS_VM != 0 {
+            return Ok(true);
+        }
+
+        Ok(match self.x86_reg_access.as_mut() {
+            Some(x86_reg_access) => {
+                x86_reg_access.get_seg(x86_seg_t::X86_Cs)?.attr & SEGMENT_ATTRIBUTE_DB == 0
+            }
+            None => false,
+        })
+    }
+
+    /// Decode instructions as 16-bit or 32-bit code according to the state of the processor
+    fn update_mode(&mut self) -> Result<()> {
+        let sixteen_bit = self.executing_sixteen_bit_code()?;
+
+        if sixteen_bit != self.sixteen_bit {
+            self.sixteen_bit = sixteen_bit;
+            self.disassembler.set_sixteen_bit(sixteen_bit);
+        }
+
+        Ok(())
+    }
+
+    /// The linear base address of the segment named by a segment register
+    fn segment_base(&mut self, segment: &str) -> Result<u64> {
+        let Some(x86_reg_access) = self.x86_reg_access.as_mut() else {
+            // Without the descriptor caches, the base is computed from the selector as in
+            // real mode
+            return Ok(self.read_register(segment)? << 4);
+        };
+
+        let segment = match segment {
+            "es" => x86_seg_t::X86_Es,
+            "cs" => x86_seg_t::X86_Cs,
+            "ss" => x86_seg_t::X86_Ss,
+            "ds" => x86_seg_t::X86_Ds,
+            "fs" => x86_seg_t::X86_Fs,
+            "gs" => x86_seg_t::X86_Gs,
+            _ => bail!("Unknown segment register {segment}"),
+        };
+
+        Ok(x86_reg_access.get_seg(segment)?.base)
+    }
+
+    /// Read the bytes of an xmm, ymm or zmm register, which are not available through the
+    /// integer register interface
+    fn read_vector_register(&mut self, name: &str, width: u8) -> Result<Vec<u8>> {
+        let (attribute, index) = name
+            .find(|c: char| c.is_ascii_digit())
+            .map(|i| name.split_at(i))
+            .ok_or_else(|| anyhow!("Invalid vector register {name}"))?;
+        let index: usize = index.parse()?;
+        let registers: Vec<Vec<u64>> = get_attribute(self.cpu, attribute)?.try_into()?;
+        let lanes = registers
+            .get(index)
+            .ok_or_else(|| anyhow!("No vector register {name}"))?;
+
+        Ok(lanes
+            .iter()
+            .flat_map(|lane| lane.to_le_bytes())
+            .take(width as usize)
+            .collect())
+    }
+
+    /// Read the value of an x87 register `st(i)`, converted to double precision
+    fn read_x87_register(&mut self, name: &str) -> Result<CmpValue> {
+        let index: usize = name
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse()?;
+        // The stack registers are numbered relative to the top of stack field of the status
+        // word
+        let status: u64 = get_attribute(self.cpu, "fpu_status")?.try_into()?;
+        let top = ((status >> 11) & 0x7) as usize;
+        let registers: Vec<Vec<u8>> = get_attribute(self.cpu, "fpu_regs")?.try_into()?;
+        let bytes: [u8; 10] = registers
+            .get((top + index) % 8)
+            .and_then(|r| r.get(..10))
+            .ok_or_else(|| anyhow!("No x87 register {name}"))?
+            .try_into()?;
+
+        Ok(CmpValue::F64(f80_to_f64(&bytes).to_bits()))
+    }
+
     fn simplify(&mut self, expr: &CmpExpr) -> Result<CmpValue> {
         match expr {
             CmpExpr::Deref((expr, width)) => {
-                let v = self.simplify(expr)?;
+                let v = match self.simplify(expr)? {
+                    // Addresses computed from 32-bit registers are 32 bits wide
+                    CmpValue::U32(a) => CmpValue::U64(a as u64),
+                    v => v,
+                };
 
                 match v {
                     CmpValue::U64(a) => {
-                        let address = self
-                            .processor_info_v2
-                            .logical_to_physical(a, Access::Sim_Access_Read)?;
+                        let address = self.virtual_to_physical(a, Access::Sim_Access_Read)?;
                         let casted = match width {
                             Some(1) => CmpValue::U8(
                                 read_phys_memory(self.cpu, address.address, size_of::<u8>() as i32)
@@ -292,6 +577,10 @@ impl X86ArchitectureOperations {
                                 .map_err(|e| anyhow!("Error reading bytes from {:#x}: {}", a, e))?
                                 .to_le_bytes(),
                             )),
+                            // Vector operands are compared as bytes
+                            Some(width @ (16 | 32 | 64)) => {
+                                CmpValue::Bytes(self.read_virtual_memory(a, *width as usize)?)
+                            }
                             _ => bail!("Can't cast to non-power-of-2 width {:?}", width),
                         };
                         Ok(casted)
@@ -299,6 +588,26 @@ impl X86ArchitectureOperations {
                     _ => bail!("Can't dereference non-address"),
                 }
             }
+            CmpExpr::Reg((name, width)) if matches!(width, 16 | 32 | 64) => {
+                Ok(CmpValue::Bytes(self.read_vector_register(name, *width)?))
+            }
+            CmpExpr::Reg((name, 10)) => self.read_x87_register(name),
+            CmpExpr::Segment((segment, offset)) => {
+                let offset = match self.simplify(offset)? {
+                    CmpValue::U8(o) => o as u64,
+                    CmpValue::I8(o) => o as u8 as u64,
+                    CmpValue::U16(o) => o as u64,
+                    CmpValue::I16(o) => o as u16 as u64,
+                    CmpValue::U32(o) => o as u64,
+                    CmpValue::I32(o) => o as u32 as u64,
+                    CmpValue::U64(o) => o,
+                    CmpValue::I64(o) => o as u64,
+                    _ => bail!("Can't use non-integral segment offset"),
+                };
+
+                Ok(CmpValue::U64(self.segment_base(segment)? + offset))
+            }
+            CmpExpr::Float((expr, width)) => self.simplify(expr)?.into_float(*width),
             CmpExpr::Reg((name, width)) => {
                 let reg_number = self.int_register.get_number(name.as_raw_cstr()?)?;
                 let value = self.int_register.read(reg_number).map_err(|e| {
@@ -722,10 +1031,29 @@ impl X86ArchitectureOperations {
             | CmpExpr::I32(_)
             | CmpExpr::U64(_)
             | CmpExpr::I64(_) => Ok(CmpValue::try_from(expr)?),
+            CmpExpr::Bytes((address, size)) => {
+                let address = match self.simplify(address)? {
+                    CmpValue::U64(a) => a,
+                    CmpValue::U32(a) => a as u64,
+                    _ => bail!("Can't dereference non-address"),
+                };
+                let size = match self.simplify(size)? {
+                    CmpValue::U64(s) => s as usize,
+                    CmpValue::U32(s) => s as usize,
+                    _ => bail!("Can't read non-integral number of bytes"),
+                };
+
+                if size == 0 {
+                    bail!("Can't compare empty byte sequence");
+                }
+
+                Ok(CmpValue::Bytes(self.read_virtual_memory(
+                    address,
+                    size.min(ROUTINE_OPERAND_SIZE),
+                )?))
+            }
             CmpExpr::Addr(a) => {
-                let address = self
-                    .processor_info_v2
-                    .logical_to_physical(*a, Access::Sim_Access_Read)?;
+                let address = self.virtual_to_physical(*a, Access::Sim_Access_Read)?;
                 let bytes: [u8; 8] =
                     read_phys_memory(self.cpu, address.address, size_of::<u64>() as i32)?
                         .to_le_bytes();
@@ -739,16 +1067,703 @@ impl X86ArchitectureOperations {
     }
 }
 
+/// Convert an x87 80-bit extended precision value to double precision
+pub(crate) fn f80_to_f64(bytes: &[u8; 10]) -> f64 {
+    let mantissa = u64::from_le_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ]);
+    let sign_exponent = u16::from_le_bytes([bytes[8], bytes[9]]);
+    let sign = if sign_exponent & 0x8000 != 0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let exponent = (sign_exponent & 0x7fff) as i32;
+
+    if exponent == 0x7fff {
+        // The explicit integer bit is ignored for infinities and NaNs
+        return if mantissa << 1 == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        };
+    }
+
+    // The mantissa has an explicit integer bit, so it is scaled to [0, 2) before applying
+    // the exponent
+    sign * (mantissa as f64 / (1u64 << 63) as f64) * 2f64.powi(exponent.max(1) - 16383)
+}
+
+/// Define a disassembler module for one of the operating modes of the decoder, whose
+/// instruction types are distinct but have the same interface. Memory operands of segmented
+/// code are addressed relative to their segment, and string instructions use the source,
+/// destination and count registers of the mode's address size.
+macro_rules! x86_disassembler {
+    (
+        $mode:ident,
+        $segmented:literal,
+        [$source:literal, $destination:literal, $count:literal],
+        $address_width:literal
+    ) => {
+        mod $mode {
+            use crate::{
+                tracer::{CmpExpr, CmpType},
+                traits::TracerDisassembler,
+            };
+            use anyhow::{bail, Error, Result};
+            use yaxpeax_arch::LengthedInstruction;
+            use yaxpeax_x86::$mode::{
+                ConditionCode, InstDecoder, Instruction, Opcode, Operand, Segment,
+            };
+
+            pub(crate) struct Disassembler {
+                decoder: InstDecoder,
+                last: Option<Instruction>,
+            }
+
+            impl Disassembler {
+                pub fn new() -> Self {
+                    Self {
+                        decoder: InstDecoder::default(),
+                        last: None,
+                    }
+                }
+
+                /// The expression for an operand of an instruction. In segmented code, memory
+                /// operands are addressed relative to the segment they are accessed through, which
+                /// is the stack segment for addresses based on the stack or frame pointer unless
+                /// overridden.
+                fn operand_expr(
+                    &self,
+                    last: &Instruction,
+                    index: u8,
+                    width: Option<u8>,
+                ) -> Result<CmpExpr> {
+                    let op = last.operand(index);
+                    let expr = CmpExpr::try_from((&op, width))?;
+
+                    if !$segmented {
+                        return Ok(expr);
+                    }
+
+                    let segment = match last.segment_override_for_op(index) {
+                        Some(Segment::CS) => "cs",
+                        Some(Segment::DS) => "ds",
+                        Some(Segment::ES) => "es",
+                        Some(Segment::FS) => "fs",
+                        Some(Segment::GS) => "gs",
+                        Some(Segment::SS) => "ss",
+                        None => match op {
+                            Operand::MemDeref { base }
+                            | Operand::Disp { base, .. }
+                            | Operand::MemBaseIndexScale { base, .. }
+                            | Operand::MemBaseIndexScaleDisp { base, .. }
+                                if matches!(base.name(), "bp" | "ebp" | "sp" | "esp") =>
+                            {
+                                "ss"
+                            }
+                            _ => "ds",
+                        },
+                    };
+                    let segmented =
+                        |address| Box::new(CmpExpr::Segment((segment.to_string(), address)));
+
+                    Ok(match expr {
+                        CmpExpr::Deref((address, width)) => {
+                            CmpExpr::Deref((segmented(address), width))
+                        }
+                        CmpExpr::Addr(address) => {
+                            CmpExpr::Deref((segmented(Box::new(CmpExpr::U64(address))), width))
+                        }
+                        expr => expr,
+                    })
+                }
+
+                /// The operands of a `cmps` or `scas` string comparison. A repeated comparison
+                /// compares the sequence of elements covered by the repeat count, assuming the
+                /// direction flag is clear.
+                fn string_cmp(&self, last: &Instruction) -> Vec<CmpExpr> {
+                    let Some(width) = last.mem_size().and_then(|s| s.bytes_size()) else {
+                        return vec![];
+                    };
+                    let reg =
+                        |name: &str| Box::new(CmpExpr::Reg((name.to_string(), $address_width)));
+                    // The destination of a string instruction is always in the extra segment
+                    let element = |address: &str, segment: &str| {
+                        let address = if $segmented {
+                            Box::new(CmpExpr::Segment((segment.to_string(), reg(address))))
+                        } else {
+                            reg(address)
+                        };
+
+                        if last.prefixes.rep_any() {
+                            CmpExpr::Bytes((
+                                address,
+                                Box::new(CmpExpr::Mul((reg($count), Box::new(CmpExpr::U8(width))))),
+                            ))
+                        } else {
+                            CmpExpr::Deref((address, Some(width)))
+                        }
+                    };
+                    let source_segment = match last.segment_override_for_op(0) {
+                        Some(Segment::CS) => "cs",
+                        Some(Segment::ES) => "es",
+                        Some(Segment::FS) => "fs",
+                        Some(Segment::GS) => "gs",
+                        Some(Segment::SS) => "ss",
+                        _ => "ds",
+                    };
+
+                    if last.opcode() == Opcode::CMPS {
+                        vec![
+                            element($source, source_segment),
+                            element($destination, "es"),
+                        ]
+                    } else {
+                        vec![
+                            CmpExpr::Reg(("eax".to_string(), width)),
+                            element($destination, "es"),
+                        ]
+                    }
+                }
+
+                /// The width in bytes of the floating point operands of the last instruction, if it
+                /// is a floating point comparison. x87 registers are compared in double precision.
+                fn fp_cmp_width(&self) -> Option<u8> {
+                    let last = self.last?;
+
+                    match last.opcode() {
+                        Opcode::CMPPD
+                        | Opcode::CMPSD
+                        | Opcode::COMISD
+                        | Opcode::UCOMISD
+                        | Opcode::VCOMISD
+                        | Opcode::VUCOMISD => Some(8),
+                        Opcode::CMPPS
+                        | Opcode::CMPSS
+                        | Opcode::COMISS
+                        | Opcode::UCOMISS
+                        | Opcode::VCOMISS
+                        | Opcode::VUCOMISS => Some(4),
+                        // Integer operands of FICOM are not logged
+                        Opcode::FCOM
+                        | Opcode::FCOMI
+                        | Opcode::FCOMIP
+                        | Opcode::FCOMP
+                        | Opcode::FCOMPP
+                        | Opcode::FTST
+                        | Opcode::FUCOM
+                        | Opcode::FUCOMI
+                        | Opcode::FUCOMIP
+                        | Opcode::FUCOMP
+                        | Opcode::FUCOMPP => {
+                            Some(last.mem_size().and_then(|s| s.bytes_size()).unwrap_or(8))
+                        }
+                        _ => None,
+                    }
+                }
+
+                /// The operands of a floating point comparison. x87 comparisons implicitly compare
+                /// `st(0)`, and packed comparisons compare only their lowest elements.
+                fn fp_cmp(&self, last: &Instruction, width: u8) -> Vec<CmpExpr> {
+                    let st = |i: u8| CmpExpr::Reg((format!("st({i})"), 10));
+                    let mut operands = (0..last.operand_count())
+                        .filter_map(|i| {
+                            let op = last.operand(i);
+                            let width = op
+                                .width()
+                                .or_else(|| last.mem_size().and_then(|s| s.bytes_size()));
+                            match op {
+                                // The comparison predicate is not an operand
+                                Operand::ImmediateI8 { .. } | Operand::ImmediateU8 { .. } => None,
+                                _ => self.operand_expr(last, i, width).ok(),
+                            }
+                        })
+                        .collect::<Vec<_>>();
+
+                    match last.opcode() {
+                        Opcode::FTST => operands = vec![st(0), CmpExpr::U32(0)],
+                        _ if operands.is_empty() => operands = vec![st(0), st(1)],
+                        _ if operands.len() == 1 => operands.insert(0, st(0)),
+                        _ => {}
+                    }
+
+                    operands
+                        .into_iter()
+                        .take(2)
+                        .map(|expr| CmpExpr::Float((Box::new(expr), width)))
+                        .collect()
+                }
+            }
+
+            impl Default for Disassembler {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            impl TryFrom<(&Operand, Option<u8>)> for CmpExpr {
+                type Error = Error;
+
+                fn try_from(value: (&Operand, Option<u8>)) -> Result<Self> {
+                    let width = value.1;
+                    let value = value.0;
+
+                    let expr = match value {
+                        Operand::ImmediateI8 { imm } => CmpExpr::I8(*imm),
+                        Operand::ImmediateU8 { imm } => CmpExpr::U8(*imm),
+                        Operand::ImmediateI16 { imm } => CmpExpr::I16(*imm),
+                        Operand::ImmediateU16 { imm } => CmpExpr::U16(*imm),
+                        Operand::ImmediateI32 { imm } => CmpExpr::I32(*imm),
+                        Operand::ImmediateU32 { imm } => CmpExpr::U32(*imm),
+                        Operand::Register { reg } => {
+                            CmpExpr::Reg((reg.name().to_string(), reg.width()))
+                        }
+                        Operand::AbsoluteU32 { addr } => CmpExpr::Addr(*addr as u64),
+                        Operand::MemDeref { base } => CmpExpr::Deref((
+                            Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
+                            width,
+                        )),
+                        Operand::Disp { base, disp } => CmpExpr::Deref((
+                            Box::new(CmpExpr::Add((
+                                Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
+                                Box::new(CmpExpr::I32(*disp)),
+                            ))),
+                            width,
+                        )),
+                        Operand::MemIndexScale { index, scale } => CmpExpr::Deref((
+                            Box::new(CmpExpr::Mul((
+                                Box::new(CmpExpr::Reg((index.name().to_string(), index.width()))),
+                                Box::new(CmpExpr::U8(*scale)),
+                            ))),
+                            width,
+                        )),
+                        Operand::MemIndexScaleDisp { index, scale, disp } => CmpExpr::Deref((
+                            Box::new(CmpExpr::Add((
+                                Box::new(CmpExpr::Mul((
+                                    Box::new(CmpExpr::Reg((
+                                        index.name().to_string(),
+                                        index.width(),
+                                    ))),
+                                    Box::new(CmpExpr::U8(*scale)),
+                                ))),
+                                Box::new(CmpExpr::I32(*disp)),
+                            ))),
+                            width,
+                        )),
+                        Operand::MemBaseIndexScale { base, index, scale } => CmpExpr::Deref((
+                            Box::new(CmpExpr::Add((
+                                Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
+                                Box::new(CmpExpr::Add((
+                                    Box::new(CmpExpr::Reg((
+                                        index.name().to_string(),
+                                        index.width(),
+                                    ))),
+                                    Box::new(CmpExpr::U8(*scale)),
+                                ))),
+                            ))),
+                            width,
+                        )),
+                        Operand::MemBaseIndexScaleDisp {
+                            base,
+                            index,
+                            scale,
+                            disp,
+                        } => CmpExpr::Deref((
+                            Box::new(CmpExpr::Add((
+                                Box::new(CmpExpr::Add((
+                                    Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
+                                    Box::new(CmpExpr::Add((
+                                        Box::new(CmpExpr::Reg((
+                                            index.name().to_string(),
+                                            index.width(),
+                                        ))),
+                                        Box::new(CmpExpr::U8(*scale)),
+                                    ))),
+                                ))),
+                                Box::new(CmpExpr::I32(*disp)),
+                            ))),
+                            width,
+                        )),
+                        _ => {
+                            bail!("Unsupported operand type for cmplog");
+                        }
+                    };
+                    Ok(expr)
+                }
+            }
+
+            impl TracerDisassembler for Disassembler {
+                /// Check if an instruction is a control flow instruction
+                fn last_was_control_flow(&self) -> bool {
+                    if let Some(last) = self.last {
+                        return matches!(
+                            last.opcode(),
+                            Opcode::JMP
+                                | Opcode::JA
+                                | Opcode::JB
+                                | Opcode::JG
+                                | Opcode::JGE
+                                | Opcode::JL
+                                | Opcode::JLE
+                                | Opcode::JNA
+                                | Opcode::JNB
+                                | Opcode::JNO
+                                | Opcode::JNP
+                                | Opcode::JNS
+                                | Opcode::JNZ
+                                | Opcode::JO
+                                | Opcode::JP
+                                | Opcode::JS
+                                | Opcode::JZ
+                                | Opcode::LOOP
+                                | Opcode::LOOPNZ
+                                | Opcode::LOOPZ
+                        );
+                    }
+
+                    false
+                }
+
+                /// Check if an instruction is a call instruction
+                fn last_was_call(&self) -> bool {
+                    if let Some(last) = self.last {
+                        return matches!(
+                            last.opcode(),
+                            Opcode::CALL
+                                | Opcode::CALLF
+                                | Opcode::INT
+                                | Opcode::INTO
+                                | Opcode::SYSCALL
+                                | Opcode::SYSENTER
+                        );
+                    }
+
+                    false
+                }
+
+                /// Check if an instruction is a ret instruction
+                fn last_was_ret(&self) -> bool {
+                    if let Some(last) = self.last {
+                        return matches!(
+                            last.opcode(),
+                            Opcode::RETF
+                                | Opcode::RETURN
+                                | Opcode::IRET
+                                | Opcode::IRETD
+                                | Opcode::IRETQ
+                                | Opcode::SYSRET
+                                | Opcode::SYSEXIT
+                        );
+                    }
+
+                    false
+                }
+
+                /// Check if an instruction is a cmp instruction
+                fn last_was_cmp(&self) -> bool {
+                    if let Some(last) = self.last {
+                        return matches!(
+                            last.opcode(),
+                            Opcode::CMP
+                                | Opcode::CMPPD
+                                | Opcode::CMPPS
+                                | Opcode::CMPS
+                                | Opcode::CMPSD
+                                | Opcode::CMPSS
+                                | Opcode::CMPXCHG16B
+                                | Opcode::COMISD
+                                | Opcode::COMISS
+                                | Opcode::FCOM
+                                | Opcode::FCOMI
+                                | Opcode::FCOMIP
+                                | Opcode::FCOMP
+                                | Opcode::FCOMPP
+                                | Opcode::FICOM
+                                | Opcode::FICOMP
+                                | Opcode::FTST
+                                | Opcode::FUCOM
+                                | Opcode::FUCOMI
+                                | Opcode::FUCOMIP
+                                | Opcode::FUCOMP
+                                | Opcode::FUCOMPP
+                                | Opcode::FXAM
+                                | Opcode::PCMPEQB
+                                | Opcode::PCMPEQD
+                                | Opcode::PCMPEQQ
+                                | Opcode::PCMPEQW
+                                | Opcode::PCMPGTB
+                                | Opcode::PCMPGTD
+                                | Opcode::PCMPGTQ
+                                | Opcode::PCMPGTW
+                                | Opcode::PMAXSB
+                                | Opcode::PMAXSD
+                                | Opcode::PMAXUD
+                                | Opcode::PMAXUW
+                                | Opcode::PMINSB
+                                | Opcode::PMINSD
+                                | Opcode::PMINUD
+                                | Opcode::PMINUW
+                                | Opcode::SCAS
+                                | Opcode::TEST
+                                | Opcode::UCOMISD
+                                | Opcode::UCOMISS
+                                | Opcode::VCOMISD
+                                | Opcode::VCOMISS
+                                | Opcode::VPCMPB
+                                | Opcode::VPCMPD
+                                | Opcode::VPCMPEQB
+                                | Opcode::VPCMPEQD
+                                | Opcode::VPCMPEQQ
+                                | Opcode::VPCMPEQW
+                                | Opcode::VPCMPGTB
+                                | Opcode::VPCMPGTD
+                                | Opcode::VPCMPGTQ
+                                | Opcode::VPCMPGTW
+                                | Opcode::VPCMPQ
+                                | Opcode::VPCMPUB
+                                | Opcode::VPCMPUD
+                                | Opcode::VPCMPUQ
+                                | Opcode::VPCMPUW
+                                | Opcode::VPCMPW
+                                | Opcode::VUCOMISD
+                                | Opcode::VUCOMISS
+                        );
+                    }
+
+                    false
+                }
+
+                /// Check if an instruction is arithmetic which sets flags that are commonly tested
+                /// by a following conditional branch
+                fn last_sets_flags(&self) -> bool {
+                    if let Some(last) = self.last {
+                        return matches!(
+                            last.opcode(),
+                            Opcode::AND | Opcode::DEC | Opcode::INC | Opcode::SUB | Opcode::XOR
+                        );
+                    }
+
+                    false
+                }
+
+                /// Check if an instruction is a conditional branch
+                fn last_was_conditional(&self) -> bool {
+                    if let Some(last) = self.last {
+                        return self.last_was_control_flow() && last.opcode().condition().is_some();
+                    }
+
+                    false
+                }
+
+                fn last_was_indirect_jump(&self) -> bool {
+                    if let Some(last) = self.last {
+                        // Direct jumps encode their target as a relative immediate
+                        return last.opcode() == Opcode::JMP
+                            && !matches!(
+                                last.operand(0),
+                                Operand::ImmediateI8 { .. }
+                                    | Operand::ImmediateI16 { .. }
+                                    | Operand::ImmediateI32 { .. }
+                            );
+                    }
+
+                    false
+                }
+
+                fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
+                    if let Ok(insn) = self.decoder.decode_slice(bytes) {
+                        self.last = Some(insn);
+                    } else {
+                        bail!("Could not disassemble {:?}", bytes);
+                    }
+
+                    Ok(())
+                }
+
+                fn disassemble_to_string(&mut self, bytes: &[u8]) -> Result<String> {
+                    if let Ok(insn) = self.decoder.decode_slice(bytes) {
+                        Ok(insn.to_string())
+                    } else {
+                        bail!("Could not disassemble {:?}", bytes);
+                    }
+                }
+
+                fn last_len(&self) -> Option<usize> {
+                    self.last
+                        .as_ref()
+                        .map(|last| last.len().to_const() as usize)
+                }
+
+                fn cmp(&self) -> Vec<CmpExpr> {
+                    let mut cmp_exprs = Vec::new();
+                    if self.last_was_cmp() || self.last_sets_flags() {
+                        if let Some(last) = self.last {
+                            if matches!(last.opcode(), Opcode::CMPS | Opcode::SCAS) {
+                                return self.string_cmp(&last);
+                            }
+
+                            if let Some(width) = self.fp_cmp_width() {
+                                return self.fp_cmp(&last, width);
+                            }
+
+                            // AVX compares have a destination operand before the compared operands
+                            let first = if matches!(
+                                last.opcode(),
+                                Opcode::VPCMPB
+                                    | Opcode::VPCMPD
+                                    | Opcode::VPCMPEQB
+                                    | Opcode::VPCMPEQD
+                                    | Opcode::VPCMPEQQ
+                                    | Opcode::VPCMPEQW
+                                    | Opcode::VPCMPGTB
+                                    | Opcode::VPCMPGTD
+                                    | Opcode::VPCMPGTQ
+                                    | Opcode::VPCMPGTW
+                                    | Opcode::VPCMPQ
+                                    | Opcode::VPCMPUB
+                                    | Opcode::VPCMPUD
+                                    | Opcode::VPCMPUQ
+                                    | Opcode::VPCMPUW
+                                    | Opcode::VPCMPW
+                            ) {
+                                1
+                            } else {
+                                0
+                            };
+
+                            for op_idx in first..last.operand_count() {
+                                let op = last.operand(op_idx);
+                                let width = if let Some(width) = op.width() {
+                                    Some(width)
+                                } else if let Some(width) = last.mem_size() {
+                                    width.bytes_size()
+                                } else {
+                                    None
+                                };
+                                if let Ok(expr) = self.operand_expr(&last, op_idx, width) {
+                                    cmp_exprs.push(expr);
+                                }
+                            }
+
+                            // The flags set by `dec` and `inc` compare the operand with the value
+                            // which becomes zero
+                            let value = match last.opcode() {
+                                Opcode::DEC => Some(1),
+                                Opcode::INC => Some(u64::MAX),
+                                _ => None,
+                            };
+
+                            if let Some(value) = value {
+                                let op = last.operand(0);
+                                let width = op
+                                    .width()
+                                    .or_else(|| last.mem_size().and_then(|s| s.bytes_size()));
+
+                                match width {
+                                    Some(1) => cmp_exprs.push(CmpExpr::U8(value as u8)),
+                                    Some(2) => cmp_exprs.push(CmpExpr::U16(value as u16)),
+                                    Some(4) => cmp_exprs.push(CmpExpr::U32(value as u32)),
+                                    Some(8) => cmp_exprs.push(CmpExpr::U64(value)),
+                                    _ => {}
+                                }
+                            }
+                        }
+                    }
+                    cmp_exprs
+                }
+
+                fn cmp_type(&self) -> Vec<CmpType> {
+                    if self.fp_cmp_width().is_some() {
+                        return vec![CmpType::Equal, CmpType::Fp];
+                    }
+
+                    if self.last_was_cmp() || self.last_was_conditional() {
+                        if let Some(last) = self.last {
+                            if let Some(condition) = last.opcode().condition() {
+                                return match condition {
+                                    // Overflow
+                                    ConditionCode::O => vec![],
+                                    // No Overflow
+                                    ConditionCode::NO => vec![],
+                                    // Below
+                                    ConditionCode::B => vec![CmpType::Lesser],
+                                    // Above or Equal
+                                    ConditionCode::AE => vec![CmpType::Greater, CmpType::Equal],
+                                    // Zero
+                                    ConditionCode::Z => vec![],
+                                    // Not Zero
+                                    ConditionCode::NZ => vec![],
+                                    // Above
+                                    ConditionCode::A => vec![CmpType::Greater],
+                                    // Below or Equal
+                                    ConditionCode::BE => vec![CmpType::Lesser, CmpType::Equal],
+                                    // Signed
+                                    ConditionCode::S => vec![],
+                                    // Not Signed
+                                    ConditionCode::NS => vec![],
+                                    // Parity
+                                    ConditionCode::P => vec![],
+                                    // No Parity
+                                    ConditionCode::NP => vec![],
+                                    // Less
+                                    ConditionCode::L => vec![CmpType::Lesser],
+                                    // Greater or Equal
+                                    ConditionCode::GE => vec![CmpType::Greater, CmpType::Equal],
+                                    // Greater
+                                    ConditionCode::G => vec![CmpType::Greater],
+                                    // Less or Equal
+                                    ConditionCode::LE => vec![CmpType::Lesser, CmpType::Equal],
+                                };
+                            }
+                        }
+                    }
+
+                    vec![]
+                }
+            }
+        }
+    };
+}
+
+x86_disassembler!(protected_mode, false, ["esi", "edi", "ecx"], 4);
+x86_disassembler!(real_mode, true, ["si", "di", "cx"], 2);
+
+/// A disassembler for 32-bit code and for the 16-bit code executed in real mode,
+/// virtual-8086 mode and 16-bit protected mode code segments
 pub(crate) struct Disassembler {
-    decoder: InstDecoder,
-    last: Option<Instruction>,
+    sixteen_bit: bool,
+    protected_mode: protected_mode::Disassembler,
+    real_mode: real_mode::Disassembler,
 }
 
 impl Disassembler {
     pub fn new() -> Self {
         Self {
-            decoder: InstDecoder::default(),
-            last: None,
+            sixteen_bit: false,
+            protected_mode: protected_mode::Disassembler::new(),
+            real_mode: real_mode::Disassembler::new(),
+        }
+    }
+
+    /// Decode subsequent instructions as 16-bit or 32-bit code
+    pub fn set_sixteen_bit(&mut self, sixteen_bit: bool) {
+        self.sixteen_bit = sixteen_bit;
+    }
+
+    fn current(&self) -> &dyn TracerDisassembler {
+        if self.sixteen_bit {
+            &self.real_mode
+        } else {
+            &self.protected_mode
+        }
+    }
+
+    fn current_mut(&mut self) -> &mut dyn TracerDisassembler {
+        if self.sixteen_bit {
+            &mut self.real_mode
+        } else {
+            &mut self.protected_mode
         }
     }
 }
@@ -759,293 +1774,52 @@ impl Default for Disassembler {
     }
 }
 
-impl TryFrom<(&Operand, Option<u8>)> for CmpExpr {
-    type Error = Error;
-
-    fn try_from(value: (&Operand, Option<u8>)) -> Result<Self> {
-        let width = value.1;
-        let value = value.0;
-
-        let expr = match value {
-            Operand::ImmediateI8 { imm } => CmpExpr::I8(*imm),
-            Operand::ImmediateU8 { imm } => CmpExpr::U8(*imm),
-            Operand::ImmediateI16 { imm } => CmpExpr::I16(*imm),
-            Operand::ImmediateU16 { imm } => CmpExpr::U16(*imm),
-            Operand::ImmediateI32 { imm } => CmpExpr::I32(*imm),
-            Operand::ImmediateU32 { imm } => CmpExpr::U32(*imm),
-            Operand::Register { reg } => CmpExpr::Reg((reg.name().to_string(), reg.width())),
-            Operand::AbsoluteU32 { addr } => CmpExpr::Addr(*addr as u64),
-            Operand::MemDeref { base } => CmpExpr::Deref((
-                Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
-                width,
-            )),
-            Operand::Disp { base, disp } => CmpExpr::Deref((
-                Box::new(CmpExpr::Add((
-                    Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
-                    Box::new(CmpExpr::I32(*disp)),
-                ))),
-                width,
-            )),
-            Operand::MemIndexScale { index, scale } => CmpExpr::Deref((
-                Box::new(CmpExpr::Mul((
-                    Box::new(CmpExpr::Reg((index.name().to_string(), index.width()))),
-                    Box::new(CmpExpr::U8(*scale)),
-                ))),
-                width,
-            )),
-            Operand::MemIndexScaleDisp { index, scale, disp } => CmpExpr::Deref((
-                Box::new(CmpExpr::Add((
-                    Box::new(CmpExpr::Mul((
-                        Box::new(CmpExpr::Reg((index.name().to_string(), index.width()))),
-                        Box::new(CmpExpr::U8(*scale)),
-                    ))),
-                    Box::new(CmpExpr::I32(*disp)),
-                ))),
-                width,
-            )),
-            Operand::MemBaseIndexScale { base, index, scale } => CmpExpr::Deref((
-                Box::new(CmpExpr::Add((
-                    Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
-                    Box::new(CmpExpr::Add((
-                        Box::new(CmpExpr::Reg((index.name().to_string(), index.width()))),
-                        Box::new(CmpExpr::U8(*scale)),
-                    ))),
-                ))),
-                width,
-            )),
-            Operand::MemBaseIndexScaleDisp {
-                base,
-                index,
-                scale,
-                disp,
-            } => CmpExpr::Deref((
-                Box::new(CmpExpr::Add((
-                    Box::new(CmpExpr::Add((
-                        Box::new(CmpExpr::Reg((base.name().to_string(), base.width()))),
-                        Box::new(CmpExpr::Add((
-                            Box::new(CmpExpr::Reg((index.name().to_string(), index.width()))),
-                            Box::new(CmpExpr::U8(*scale)),
-                        ))),
-                    ))),
-                    Box::new(CmpExpr::I32(*disp)),
-                ))),
-                width,
-            )),
-            _ => {
-                bail!("Unsupported operand type for cmplog");
-            }
-        };
-        Ok(expr)
+impl TracerDisassembler for Disassembler {
+    fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
+        self.current_mut().disassemble(bytes)
     }
-}
 
-impl TracerDisassembler for Disassembler {
-    /// Check if an instruction is a control flow instruction
-    fn last_was_control_flow(&self) -> bool {
-        if let Some(last) = self.last {
-            return matches!(
-                last.opcode(),
-                Opcode::JMP
-                    | Opcode::JA
-                    | Opcode::JB
-                    | Opcode::JG
-                    | Opcode::JGE
-                    | Opcode::JL
-                    | Opcode::JLE
-                    | Opcode::JNA
-                    | Opcode::JNB
-                    | Opcode::JNO
-                    | Opcode::JNP
-                    | Opcode::JNS
-                    | Opcode::JNZ
-                    | Opcode::JO
-                    | Opcode::JP
-                    | Opcode::JS
-                    | Opcode::JZ
-                    | Opcode::LOOP
-                    | Opcode::LOOPNZ
-                    | Opcode::LOOPZ
-            );
-        }
+    fn disassemble_to_string(&mut self, bytes: &[u8]) -> Result<String> {
+        self.current_mut().disassemble_to_string(bytes)
+    }
 
-        false
+    fn last_len(&self) -> Option<usize> {
+        self.current().last_len()
     }
 
-    /// Check if an instruction is a call instruction
-    fn last_was_call(&self) -> bool {
-        if let Some(last) = self.last {
-            return matches!(
-                last.opcode(),
-                Opcode::CALL
-                    | Opcode::CALLF
-                    | Opcode::INT
-                    | Opcode::INTO
-                    | Opcode::SYSCALL
-                    | Opcode::SYSENTER
-            );
-        }
+    fn last_was_control_flow(&self) -> bool {
+        self.current().last_was_control_flow()
+    }
 
-        false
+    fn last_was_call(&self) -> bool {
+        self.current().last_was_call()
     }
 
-    /// Check if an instruction is a ret instruction
     fn last_was_ret(&self) -> bool {
-        if let Some(last) = self.last {
-            return matches!(
-                last.opcode(),
-                Opcode::RETF
-                    | Opcode::RETURN
-                    | Opcode::IRET
-                    | Opcode::IRETD
-                    | Opcode::IRETQ
-                    | Opcode::SYSRET
-                    | Opcode::SYSEXIT
-            );
-        }
-
-        false
+        self.current().last_was_ret()
     }
 
-    /// Check if an instruction is a cmp instruction
     fn last_was_cmp(&self) -> bool {
-        if let Some(last) = self.last {
-            return matches!(
-                last.opcode(),
-                Opcode::CMP
-                    | Opcode::CMPPD
-                    | Opcode::CMPS
-                    | Opcode::CMPSD
-                    | Opcode::CMPSS
-                    | Opcode::CMPXCHG16B
-                    | Opcode::COMISD
-                    | Opcode::COMISS
-                    | Opcode::FCOM
-                    | Opcode::FCOMI
-                    | Opcode::FCOMIP
-                    | Opcode::FCOMP
-                    | Opcode::FCOMPP
-                    | Opcode::FICOM
-                    | Opcode::FICOMP
-                    | Opcode::FTST
-                    | Opcode::FUCOM
-                    | Opcode::FUCOMI
-                    | Opcode::FUCOMIP
-                    | Opcode::FUCOMP
-                    | Opcode::FXAM
-                    | Opcode::PCMPEQB
-                    | Opcode::PCMPEQD
-                    | Opcode::PCMPEQW
-                    | Opcode::PCMPGTB
-                    | Opcode::PCMPGTD
-                    | Opcode::PCMPGTQ
-                    | Opcode::PCMPGTW
-                    | Opcode::PMAXSB
-                    | Opcode::PMAXSD
-                    | Opcode::PMAXUD
-                    | Opcode::PMAXUW
-                    | Opcode::PMINSB
-                    | Opcode::PMINSD
-                    | Opcode::PMINUD
-                    | Opcode::PMINUW
-                    | Opcode::TEST
-                    | Opcode::UCOMISD
-                    | Opcode::UCOMISS
-                    | Opcode::VPCMPB
-                    | Opcode::VPCMPD
-                    | Opcode::VPCMPQ
-                    | Opcode::VPCMPUB
-                    | Opcode::VPCMPUD
-                    | Opcode::VPCMPUQ
-                    | Opcode::VPCMPUW
-                    | Opcode::VPCMPW
-            );
-        }
-
-        false
+        self.current().last_was_cmp()
     }
 
-    fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
-        if let Ok(insn) = self.decoder.decode_slice(bytes) {
-            self.last = Some(insn);
-        } else {
-            bail!("Could not disassemble {:?}", bytes);
-        }
+    fn last_sets_flags(&self) -> bool {
+        self.current().last_sets_flags()
+    }
 
-        Ok(())
+    fn last_was_conditional(&self) -> bool {
+        self.current().last_was_conditional()
     }
 
-    fn disassemble_to_string(&mut self, bytes: &[u8]) -> Result<String> {
-        if let Ok(insn) = self.decoder.decode_slice(bytes) {
-            Ok(insn.to_string())
-        } else {
-            bail!("Could not disassemble {:?}", bytes);
-        }
+    fn last_was_indirect_jump(&self) -> bool {
+        self.current().last_was_indirect_jump()
     }
 
     fn cmp(&self) -> Vec<CmpExpr> {
-        let mut cmp_exprs = Vec::new();
-        if self.last_was_cmp() {
-            if let Some(last) = self.last {
-                for op_idx in 0..last.operand_count() {
-                    let op = last.operand(op_idx);
-                    let width = if let Some(width) = op.width() {
-                        Some(width)
-                    } else if let Some(width) = last.mem_size() {
-                        width.bytes_size()
-                    } else {
-                        None
-                    };
-                    if let Ok(expr) = CmpExpr::try_from((&op, width)) {
-                        cmp_exprs.push(expr);
-                    }
-                }
-            }
-        }
-        cmp_exprs
+        self.current().cmp()
     }
 
     fn cmp_type(&self) -> Vec<CmpType> {
-        if self.last_was_cmp() {
-            if let Some(last) = self.last {
-                if let Some(condition) = last.opcode().condition() {
-                    return match condition {
-                        // Overflow
-                        ConditionCode::O => vec![],
-                        // No Overflow
-                        ConditionCode::NO => vec![],
-                        // Below
-                        ConditionCode::B => vec![CmpType::Lesser],
-                        // Above or Equal
-                        ConditionCode::AE => vec![CmpType::Greater, CmpType::Equal],
-                        // Zero
-                        ConditionCode::Z => vec![],
-                        // Not Zero
-                        ConditionCode::NZ => vec![],
-                        // Above
-                        ConditionCode::A => vec![CmpType::Greater],
-                        // Below or Equal
-                        ConditionCode::BE => vec![CmpType::Lesser, CmpType::Equal],
-                        // Signed
-                        ConditionCode::S => vec![],
-                        // Not Signed
-                        ConditionCode::NS => vec![],
-                        // Parity
-                        ConditionCode::P => vec![],
-                        // No Parity
-                        ConditionCode::NP => vec![],
-                        // Less
-                        ConditionCode::L => vec![CmpType::Lesser],
-                        // Greater or Equal
-                        ConditionCode::GE => vec![CmpType::Greater, CmpType::Equal],
-                        // Greater
-                        ConditionCode::G => vec![CmpType::Greater],
-                        // Less or Equal
-                        ConditionCode::LE => vec![CmpType::Lesser, CmpType::Equal],
-                    };
-                }
-            }
-        }
-
-        vec![]
+        self.current().cmp_type()
     }
 }
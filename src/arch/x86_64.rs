@@ -3,23 +3,55 @@
 
 //! Architecture-specific implementation for x86-64 architecture
 
-use std::{ffi::CStr, mem::size_of, slice::from_raw_parts};
+use std::{ffi::CStr, mem::size_of};
 
 use crate::{
-    tracer::{CmpExpr, CmpType, CmpValue, TraceEntry},
+    tracer::{routines::ROUTINE_OPERAND_SIZE, CmpExpr, CmpType, CmpValue, TraceEntry},
     traits::TracerDisassembler,
 };
 use anyhow::{anyhow, bail, Error, Result};
 use libafl::prelude::CmpValues;
 use raw_cstr::AsRawCstr;
 use simics::api::{
-    get_interface, read_phys_memory, sys::instruction_handle_t, Access, ConfObject,
-    CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface, CycleInterface,
-    IntRegisterInterface, ProcessorInfoV2Interface,
+    get_attribute, get_interface, read_phys_memory,
+    sys::{instruction_handle_t, physical_block_t, x86_seg_t},
+    Access, ConfObject, CpuInstructionQueryInterface, CpuInstrumentationSubscribeInterface,
+    CycleInterface, IntRegisterInterface, ProcessorInfoV2Interface, X86RegAccessInterface,
 };
+use yaxpeax_arch::LengthedInstruction;
 use yaxpeax_x86::amd64::{ConditionCode, InstDecoder, Instruction, Opcode, Operand};
 
-use super::ArchitectureOperations;
+use super::{
+    decode_cache::DecodeCache,
+    x86::{self, f80_to_f64},
+    ArchitectureOperations, CallingConvention, Endianness, PAGE_SIZE,
+};
+
+/// The protection enable bit of CR0, which is clear in real mode
+const CR0_PE: u64 = 1 << 0;
+/// The paging bit of CR0
+const CR0_PG: u64 = 1 << 31;
+/// The virtual-8086 mode bit of EFLAGS
+const EFLAGS_VM: u64 = 1 << 17;
+/// The long mode active bit of EFER
+const EFER_LMA: u64 = 1 << 10;
+/// The 64-bit code segment bit of the attributes of a segment
+const SEGMENT_ATTRIBUTE_L: u32 = 1 << 13;
+/// The default operation size bit of the attributes of a segment, which is clear for 16-bit
+/// code segments
+const SEGMENT_ATTRIBUTE_DB: u32 = 1 << 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The default operand and address size of the code being executed, which depends on the mode
+/// of the processor and the code segment
+pub(crate) enum CodeWidth {
+    /// Real mode, virtual-8086 mode and 16-bit protected mode or compatibility mode code
+    Bits16,
+    /// 32-bit protected mode or compatibility mode code
+    Bits32,
+    /// 64-bit mode code
+    Bits64,
+}
 
 pub(crate) struct X86_64ArchitectureOperations {
     cpu: *mut ConfObject,
@@ -30,6 +62,21 @@ pub(crate) struct X86_64ArchitectureOperations {
     #[allow(dead_code)]
     cpu_instrumentation_subscribe: CpuInstrumentationSubscribeInterface,
     cycle: CycleInterface,
+    decode_cache: DecodeCache,
+    endianness: Endianness,
+    /// The segment registers and descriptor caches, if the processor model provides them
+    x86_reg_access: Option<X86RegAccessInterface>,
+    /// The width of the code being executed, which selects the decoder
+    code_width: CodeWidth,
+    /// The base of the code segment at the last edge in 16-bit code, which instructions up to
+    /// the next edge are fetched from
+    code_segment_base: Option<u64>,
+    /// The address and operands of the last arithmetic instruction which set flags, logged if
+    /// the flags are consumed by a conditional branch
+    flags_cmp: Option<(u64, CmpValues)>,
+    /// The operands of the last comparison, which may be the bounds check of a jump table
+    /// index if an indirect jump follows
+    jump_table_bounds: Option<CmpValues>,
 }
 
 impl ArchitectureOperations for X86_64ArchitectureOperations {
@@ -80,6 +127,13 @@ impl ArchitectureOperations for X86_64ArchitectureOperations {
                     cpu_instruction_query: get_interface(cpu)?,
                     cpu_instrumentation_subscribe: get_interface(cpu)?,
                     cycle: get_interface(cpu)?,
+                    decode_cache: DecodeCache::default(),
+                    endianness: Endianness::Little,
+                    x86_reg_access: get_interface(cpu).ok(),
+                    code_width: CodeWidth::Bits64,
+                    code_segment_base: None,
+                    flags_cmp: None,
+                    jump_table_bounds: None,
                 })
             } else if reg_names.iter().all(|n| {
                 ![
@@ -109,6 +163,13 @@ impl ArchitectureOperations for X86_64ArchitectureOperations {
             cpu_instruction_query: get_interface(cpu)?,
             cpu_instrumentation_subscribe: get_interface(cpu)?,
             cycle: get_interface(cpu)?,
+            decode_cache: DecodeCache::default(),
+            endianness: Endianness::Little,
+            x86_reg_access: get_interface(cpu).ok(),
+            code_width: CodeWidth::Bits64,
+            code_segment_base: None,
+            flags_cmp: None,
+            jump_table_bounds: None,
         })
     }
 
@@ -140,75 +201,175 @@ impl ArchitectureOperations for X86_64ArchitectureOperations {
         &mut self.cycle
     }
 
+    fn decode_cache(&mut self) -> &mut DecodeCache {
+        &mut self.decode_cache
+    }
+
+    fn endianness(&mut self) -> &mut Endianness {
+        &mut self.endianness
+    }
+
+    fn exceptions(&self) -> &[(&'static str, i64)] {
+        super::x86::EXCEPTIONS
+    }
+
+    fn update_decoder_mode(&mut self) -> Result<u8> {
+        self.update_mode()?;
+        Ok(self.code_width as u8)
+    }
+
+    fn virtual_to_physical(&mut self, address: u64, access: Access) -> Result<physical_block_t> {
+        // Without paging, linear addresses are physical addresses
+        if self.read_register("cr0").is_ok_and(|cr0| cr0 & CR0_PG == 0) {
+            let block_start = address & !(PAGE_SIZE - 1);
+            return Ok(physical_block_t {
+                valid: 1,
+                address,
+                block_start,
+                block_end: block_start + PAGE_SIZE - 1,
+            });
+        }
+
+        Ok(self
+            .processor_info_v2
+            .logical_to_physical(address, access)?)
+    }
+
+    fn pointer_address(&mut self, pointer: u64) -> Result<u64> {
+        self.update_mode()?;
+
+        if self.code_width == CodeWidth::Bits16 {
+            // Pointers in 16-bit code are offsets into the data segment
+            Ok(self.segment_base("ds")? + pointer)
+        } else {
+            Ok(pointer)
+        }
+    }
+
+    fn function_argument(&mut self, convention: CallingConvention, index: usize) -> Result<u64> {
+        let (registers, stack_offset): (&[&str], u64) = match convention {
+            CallingConvention::Default | CallingConvention::SysV64 => {
+                (&["rdi", "rsi", "rdx", "rcx", "r8", "r9"], 0)
+            }
+            // Stack arguments follow the 32 byte shadow space
+            CallingConvention::Win64 => (&["rcx", "rdx", "r8", "r9"], 32),
+            CallingConvention::Cdecl => bail!("Calling convention {convention} is not supported"),
+        };
+
+        if let Some(register) = registers.get(index) {
+            self.read_register(register)
+        } else {
+            // Stack arguments start after the return address
+            let address = self.read_register("rsp")?
+                + size_of::<u64>() as u64
+                + stack_offset
+                + ((index - registers.len()) * size_of::<u64>()) as u64;
+            self.read_virtual_integer(address, size_of::<u64>())
+        }
+    }
+
     fn trace_pc(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
-        if self.disassembler.last_was_call()
-            || self.disassembler.last_was_control_flow()
-            || self.disassembler.last_was_ret()
-        {
-            Ok(TraceEntry::builder()
-                .edge(self.processor_info_v2.get_program_counter()?)
-                .build())
+        let decoded = self.decode(instruction_query)?;
+
+        if decoded.is_edge() {
+            let mut edge = self.processor_info_v2.get_program_counter()?;
+            let mut source = self
+                .cpu_instruction_query
+                .logical_address(instruction_query)?;
+
+            if self.code_width == CodeWidth::Bits16 {
+                source += match self.code_segment_base {
+                    Some(base) => base,
+                    None => self.segment_base("cs")?,
+                };
+            }
+
+            // A far transfer, system call or interrupt return may load a code segment of a
+            // different width
+            self.update_mode()?;
+
+            if self.code_width == CodeWidth::Bits16 {
+                let base = self.segment_base("cs")?;
+                self.code_segment_base = Some(base);
+                edge += base;
+            } else {
+                self.code_segment_base = None;
+            }
+
+            // The bounds check of a jump table index is followed by a conditional branch to
+            // the default case and then the indirect jump through the table
+            let jump_table = if decoded.indirect_jump {
+                self.jump_table_bounds.take().map(|bounds| (source, bounds))
+            } else {
+                if !decoded.conditional {
+                    self.jump_table_bounds = None;
+                }
+                None
+            };
+
+            match self.flags_cmp.take() {
+                Some((pc, cmp)) if decoded.conditional => Ok(TraceEntry::builder()
+                    .edge(edge)
+                    .source(source)
+                    .cmp((pc, decoded.cmp_types.clone(), cmp))
+                    .jump_table(jump_table)
+                    .call(decoded.call)
+                    .ret(decoded.ret)
+                    .build()),
+                _ => Ok(TraceEntry::builder()
+                    .edge(edge)
+                    .source(source)
+                    .jump_table(jump_table)
+                    .call(decoded.call)
+                    .ret(decoded.ret)
+                    .build()),
+            }
         } else {
             Ok(TraceEntry::default())
         }
     }
 
     fn trace_cmp(&mut self, instruction_query: *mut instruction_handle_t) -> Result<TraceEntry> {
-        let instruction_bytes = self
-            .cpu_instruction_query
-            .get_instruction_bytes(instruction_query)?;
-        self.disassembler.disassemble(unsafe {
-            from_raw_parts(instruction_bytes.data, instruction_bytes.size)
-        })?;
-        if self.disassembler.last_was_cmp() {
-            let pc = self.processor_info_v2.get_program_counter()?;
+        let decoded = self.decode(instruction_query)?;
+
+        if decoded.cmp || decoded.sets_flags {
+            let mut pc = self.processor_info_v2.get_program_counter()?;
+
+            if self.code_width == CodeWidth::Bits16 {
+                pc += self.segment_base("cs")?;
+            }
             let mut cmp_values = Vec::new();
 
-            for expr in self.disassembler.cmp() {
-                if let Ok(value) = self.simplify(&expr) {
+            for expr in decoded.cmp_exprs.iter() {
+                if let Ok(value) = self.simplify(expr) {
                     cmp_values.push(value);
                 }
             }
 
             let cmp_value = if let (Some(l), Some(r)) = (cmp_values.first(), cmp_values.get(1)) {
-                match (l, r) {
-                    (CmpValue::U8(l), CmpValue::U8(r)) => Some(CmpValues::U8((*l, *r))),
-                    (CmpValue::I8(l), CmpValue::I8(r)) => Some(CmpValues::U8((
-                        u8::from_le_bytes(l.to_le_bytes()),
-                        u8::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::U16(l), CmpValue::U16(r)) => Some(CmpValues::U16((*l, *r))),
-                    (CmpValue::I16(l), CmpValue::I16(r)) => Some(CmpValues::U16((
-                        u16::from_le_bytes(l.to_le_bytes()),
-                        u16::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::U32(l), CmpValue::U32(r)) => Some(CmpValues::U32((*l, *r))),
-                    (CmpValue::I32(l), CmpValue::I32(r)) => Some(CmpValues::U32((
-                        u32::from_le_bytes(l.to_le_bytes()),
-                        u32::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::U64(l), CmpValue::U64(r)) => Some(CmpValues::U64((*l, *r))),
-                    (CmpValue::I64(l), CmpValue::I64(r)) => Some(CmpValues::U64((
-                        u64::from_le_bytes(l.to_le_bytes()),
-                        u64::from_le_bytes(r.to_le_bytes()),
-                    ))),
-                    (CmpValue::Expr(_), CmpValue::Expr(_)) => None,
-                    _ => None,
-                }
+                CmpValue::pair(l, r)
             } else {
                 None
             };
 
+            if decoded.sets_flags {
+                // The operands are only a comparison if a conditional branch consumes the
+                // flags, so they are held until the next edge
+                self.flags_cmp = cmp_value.map(|cmp| (pc, cmp));
+                return Ok(TraceEntry::default());
+            }
+
+            // A comparison replaces the flags set by earlier arithmetic
+            self.flags_cmp = None;
+
+            if !decoded.cmp_types.contains(&CmpType::Fp) {
+                self.jump_table_bounds.clone_from(&cmp_value);
+            }
+
             Ok(TraceEntry::builder()
                 .cmp((
                     pc,
-                    self.disassembler.cmp_type(),
+                    decoded.cmp_types.clone(),
                     cmp_value.ok_or_else(|| anyhow!("No cmp value available"))?,
                 ))
                 .build())
@@ -219,16 +380,131 @@ impl ArchitectureOperations for X86_64ArchitectureOperations {
 }
 
 impl X86_64ArchitectureOperations {
+    /// The width of the code the processor is executing, from the mode of the processor and
+    /// the attributes of the code segment. Processors whose segments are not available are
+    /// assumed to execute 64-bit code.
+    fn executing_code_width(&mut self) -> Result<CodeWidth> {
+        let Some(x86_reg_access) = self.x86_reg_access.as_mut() else {
+            return Ok(CodeWidth::Bits64);
+        };
+        let code_segment = x86_reg_access.get_seg(x86_seg_t::X86_Cs)?;
+        let default_width = if code_segment.attr & SEGMENT_ATTRIBUTE_DB != 0 {
+            CodeWidth::Bits32
+        } else {
+            CodeWidth::Bits16
+        };
+
+        if self
+            .read_register("efer")
+            .is_ok_and(|efer| efer & EFER_LMA != 0)
+        {
+            // In long mode, code segments are either 64-bit or compatibility mode segments
+            return Ok(if code_segment.attr & SEGMENT_ATTRIBUTE_L != 0 {
+                CodeWidth::Bits64
+            } else {
+                default_width
+            });
+        }
+
+        let real_mode = self.read_register("cr0").is_ok_and(|cr0| cr0 & CR0_PE == 0);
+        let virtual_8086_mode = self
+            .read_register("rflags")
+            .is_ok_and(|rflags| rflags & EFLAGS_VM != 0);
+
+        if real_mode || virtual_8086_mode {
+            Ok(CodeWidth::Bits16)
+        } else {
+            Ok(default_width)
+        }
+    }
+
+    /// Decode instructions with the decoder for the width of the code being executed
+    fn update_mode(&mut self) -> Result<()> {
+        let code_width = self.executing_code_width()?;
+
+        if code_width != self.code_width {
+            self.code_width = code_width;
+            self.disassembler.set_code_width(code_width);
+        }
+
+        Ok(())
+    }
+
+    /// The linear base address of the segment named by a segment register
+    fn segment_base(&mut self, segment: &str) -> Result<u64> {
+        let Some(x86_reg_access) = self.x86_reg_access.as_mut() else {
+            // Without the descriptor caches, the base is computed from the selector as in
+            // real mode
+            return Ok(self.read_register(segment)? << 4);
+        };
+
+        let segment = match segment {
+            "es" => x86_seg_t::X86_Es,
+            "cs" => x86_seg_t::X86_Cs,
+            "ss" => x86_seg_t::X86_Ss,
+            "ds" => x86_seg_t::X86_Ds,
+            "fs" => x86_seg_t::X86_Fs,
+            "gs" => x86_seg_t::X86_Gs,
+            _ => bail!("Unknown segment register {segment}"),
+        };
+
+        Ok(x86_reg_access.get_seg(segment)?.base)
+    }
+
+    /// Read the bytes of an xmm, ymm or zmm register, which are not available through the
+    /// integer register interface
+    fn read_vector_register(&mut self, name: &str, width: u8) -> Result<Vec<u8>> {
+        let (attribute, index) = name
+            .find(|c: char| c.is_ascii_digit())
+            .map(|i| name.split_at(i))
+            .ok_or_else(|| anyhow!("Invalid vector register {name}"))?;
+        let index: usize = index.parse()?;
+        let registers: Vec<Vec<u64>> = get_attribute(self.cpu, attribute)?.try_into()?;
+        let lanes = registers
+            .get(index)
+            .ok_or_else(|| anyhow!("No vector register {name}"))?;
+
+        Ok(lanes
+            .iter()
+            .flat_map(|lane| lane.to_le_bytes())
+            .take(width as usize)
+            .collect())
+    }
+
+    /// Read the value of an x87 register `st(i)`, converted to double precision
+    fn read_x87_register(&mut self, name: &str) -> Result<CmpValue> {
+        let index: usize = name
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse()?;
+        // The stack registers are numbered relative to the top of stack field of the status
+        // word
+        let status: u64 = get_attribute(self.cpu, "fpu_status")?.try_into()?;
+        let top = ((status >> 11) & 0x7) as usize;
+        let registers: Vec<Vec<u8>> = get_attribute(self.cpu, "fpu_regs")?.try_into()?;
+        let bytes: [u8; 10] = registers
+            .get((top + index) % 8)
+            .and_then(|r| r.get(..10))
+            .ok_or_else(|| anyhow!("No x87 register {name}"))?
+            .try_into()?;
+
+        Ok(CmpValue::F64(f80_to_f64(&bytes).to_bits()))
+    }
+
     fn simplify(&mut self, expr: &CmpExpr) -> Result<CmpValue> {
         match expr {
             CmpExpr::Deref((expr, width)) => {
-                let v = self.simplify(expr)?;
+                let v = match self.simplify(expr)? {
+                    // Addresses computed from 32-bit registers in compatibility mode are 32
+                    // bits wide
+                    CmpValue::U32(a) => CmpValue::U64(a as u64),
+                    v => v,
+                };
 
                 match v {
                     CmpValue::U64(a) => {
-                        let address = self
-                            .processor_info_v2
-                            .logical_to_physical(a, Access::Sim_Access_Read)?;
+                        let address = self.virtual_to_physical(a, Access::Sim_Access_Read)?;
                         let casted = match width {
                             Some(1) => CmpValue::U8(
                                 read_phys_memory(self.cpu, address.address, size_of::<u8>() as i32)
@@ -266,6 +542,10 @@ impl X86_64ArchitectureOperations {
                                 .map_err(|e| anyhow!("Error reading bytes from {:#x}: {}", a, e))?
                                 .to_le_bytes(),
                             )),
+                            // Vector operands are compared as bytes
+                            Some(width @ (16 | 32 | 64)) => {
+                                CmpValue::Bytes(self.read_virtual_memory(a, *width as usize)?)
+                            }
                             _ => bail!("Can't cast to non-power-of-2 width {:?}", width),
                         };
                         Ok(casted)
@@ -273,6 +553,26 @@ impl X86_64ArchitectureOperations {
                     _ => bail!("Can't dereference non-address"),
                 }
             }
+            CmpExpr::Reg((name, width)) if matches!(width, 16 | 32 | 64) => {
+                Ok(CmpValue::Bytes(self.read_vector_register(name, *width)?))
+            }
+            CmpExpr::Reg((name, 10)) => self.read_x87_register(name),
+            CmpExpr::Segment((segment, offset)) => {
+                let offset = match self.simplify(offset)? {
+                    CmpValue::U8(o) => o as u64,
+                    CmpValue::I8(o) => o as u8 as u64,
+                    CmpValue::U16(o) => o as u64,
+                    CmpValue::I16(o) => o as u16 as u64,
+                    CmpValue::U32(o) => o as u64,
+                    CmpValue::I32(o) => o as u32 as u64,
+                    CmpValue::U64(o) => o,
+                    CmpValue::I64(o) => o as u64,
+                    _ => bail!("Can't use non-integral segment offset"),
+                };
+
+                Ok(CmpValue::U64(self.segment_base(segment)? + offset))
+            }
+            CmpExpr::Float((expr, width)) => self.simplify(expr)?.into_float(*width),
             CmpExpr::Reg((name, width)) => {
                 let reg_number = self.int_register.get_number(name.as_raw_cstr()?)?;
                 let value = self.int_register.read(reg_number).map_err(|e| {
@@ -696,10 +996,29 @@ impl X86_64ArchitectureOperations {
             | CmpExpr::I32(_)
             | CmpExpr::U64(_)
             | CmpExpr::I64(_) => Ok(CmpValue::try_from(expr)?),
+            CmpExpr::Bytes((address, size)) => {
+                let address = match self.simplify(address)? {
+                    CmpValue::U64(a) => a,
+                    CmpValue::U32(a) => a as u64,
+                    _ => bail!("Can't dereference non-address"),
+                };
+                let size = match self.simplify(size)? {
+                    CmpValue::U64(s) => s as usize,
+                    CmpValue::U32(s) => s as usize,
+                    _ => bail!("Can't read non-integral number of bytes"),
+                };
+
+                if size == 0 {
+                    bail!("Can't compare empty byte sequence");
+                }
+
+                Ok(CmpValue::Bytes(self.read_virtual_memory(
+                    address,
+                    size.min(ROUTINE_OPERAND_SIZE),
+                )?))
+            }
             CmpExpr::Addr(a) => {
-                let address = self
-                    .processor_info_v2
-                    .logical_to_physical(*a, Access::Sim_Access_Read)?;
+                let address = self.virtual_to_physical(*a, Access::Sim_Access_Read)?;
                 let bytes: [u8; 8] =
                     read_phys_memory(self.cpu, address.address, size_of::<u64>() as i32)?
                         .to_le_bytes();
@@ -713,21 +1032,207 @@ impl X86_64ArchitectureOperations {
     }
 }
 
+/// A disassembler for 64-bit code, and for the 16-bit and 32-bit code executed before
+/// entering long mode and in compatibility mode code segments
 pub(crate) struct Disassembler {
+    code_width: CodeWidth,
+    long_mode: LongModeDisassembler,
+    legacy: x86::Disassembler,
+}
+
+impl Disassembler {
+    pub fn new() -> Self {
+        Self {
+            code_width: CodeWidth::Bits64,
+            long_mode: LongModeDisassembler::new(),
+            legacy: x86::Disassembler::new(),
+        }
+    }
+
+    /// Decode subsequent instructions as code of a width
+    pub fn set_code_width(&mut self, code_width: CodeWidth) {
+        self.code_width = code_width;
+        self.legacy.set_sixteen_bit(code_width == CodeWidth::Bits16);
+    }
+
+    fn current(&self) -> &dyn TracerDisassembler {
+        match self.code_width {
+            CodeWidth::Bits64 => &self.long_mode,
+            CodeWidth::Bits16 | CodeWidth::Bits32 => &self.legacy,
+        }
+    }
+
+    fn current_mut(&mut self) -> &mut dyn TracerDisassembler {
+        match self.code_width {
+            CodeWidth::Bits64 => &mut self.long_mode,
+            CodeWidth::Bits16 | CodeWidth::Bits32 => &mut self.legacy,
+        }
+    }
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TracerDisassembler for Disassembler {
+    fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
+        self.current_mut().disassemble(bytes)
+    }
+
+    fn disassemble_to_string(&mut self, bytes: &[u8]) -> Result<String> {
+        self.current_mut().disassemble_to_string(bytes)
+    }
+
+    fn last_len(&self) -> Option<usize> {
+        self.current().last_len()
+    }
+
+    fn last_was_control_flow(&self) -> bool {
+        self.current().last_was_control_flow()
+    }
+
+    fn last_was_call(&self) -> bool {
+        self.current().last_was_call()
+    }
+
+    fn last_was_ret(&self) -> bool {
+        self.current().last_was_ret()
+    }
+
+    fn last_was_cmp(&self) -> bool {
+        self.current().last_was_cmp()
+    }
+
+    fn last_sets_flags(&self) -> bool {
+        self.current().last_sets_flags()
+    }
+
+    fn last_was_conditional(&self) -> bool {
+        self.current().last_was_conditional()
+    }
+
+    fn last_was_indirect_jump(&self) -> bool {
+        self.current().last_was_indirect_jump()
+    }
+
+    fn cmp(&self) -> Vec<CmpExpr> {
+        self.current().cmp()
+    }
+
+    fn cmp_type(&self) -> Vec<CmpType> {
+        self.current().cmp_type()
+    }
+}
+
+pub(crate) struct LongModeDisassembler {
     decoder: InstDecoder,
     last: Option<Instruction>,
 }
 
-impl Disassembler {
+impl LongModeDisassembler {
     pub fn new() -> Self {
         Self {
             decoder: InstDecoder::default(),
             last: None,
         }
     }
+
+    /// The operands of a `cmps` or `scas` string comparison. A repeated comparison compares
+    /// the sequence of elements covered by the repeat count, assuming the direction flag is
+    /// clear.
+    fn string_cmp(&self, last: &Instruction) -> Vec<CmpExpr> {
+        let Some(width) = last.mem_size().and_then(|s| s.bytes_size()) else {
+            return vec![];
+        };
+        let reg = |name: &str| Box::new(CmpExpr::Reg((name.to_string(), 8)));
+        let element = |address: &str| {
+            if last.prefixes.rep_any() {
+                CmpExpr::Bytes((
+                    reg(address),
+                    Box::new(CmpExpr::Mul((reg("rcx"), Box::new(CmpExpr::U8(width))))),
+                ))
+            } else {
+                CmpExpr::Deref((reg(address), Some(width)))
+            }
+        };
+
+        if last.opcode() == Opcode::CMPS {
+            vec![element("rsi"), element("rdi")]
+        } else {
+            vec![CmpExpr::Reg(("rax".to_string(), width)), element("rdi")]
+        }
+    }
+
+    /// The width in bytes of the floating point operands of the last instruction, if it is a
+    /// floating point comparison. x87 registers are compared in double precision.
+    fn fp_cmp_width(&self) -> Option<u8> {
+        let last = self.last?;
+
+        match last.opcode() {
+            Opcode::CMPPD
+            | Opcode::CMPSD
+            | Opcode::COMISD
+            | Opcode::UCOMISD
+            | Opcode::VCOMISD
+            | Opcode::VUCOMISD => Some(8),
+            Opcode::CMPPS
+            | Opcode::CMPSS
+            | Opcode::COMISS
+            | Opcode::UCOMISS
+            | Opcode::VCOMISS
+            | Opcode::VUCOMISS => Some(4),
+            // Integer operands of FICOM are not logged
+            Opcode::FCOM
+            | Opcode::FCOMI
+            | Opcode::FCOMIP
+            | Opcode::FCOMP
+            | Opcode::FCOMPP
+            | Opcode::FTST
+            | Opcode::FUCOM
+            | Opcode::FUCOMI
+            | Opcode::FUCOMIP
+            | Opcode::FUCOMP
+            | Opcode::FUCOMPP => Some(last.mem_size().and_then(|s| s.bytes_size()).unwrap_or(8)),
+            _ => None,
+        }
+    }
+
+    /// The operands of a floating point comparison. x87 comparisons implicitly compare
+    /// `st(0)`, and packed comparisons compare only their lowest elements.
+    fn fp_cmp(&self, last: &Instruction, width: u8) -> Vec<CmpExpr> {
+        let st = |i: u8| CmpExpr::Reg((format!("st({i})"), 10));
+        let mut operands = (0..last.operand_count())
+            .filter_map(|i| {
+                let op = last.operand(i);
+                let width = op
+                    .width()
+                    .or_else(|| last.mem_size().and_then(|s| s.bytes_size()));
+                match op {
+                    // The comparison predicate is not an operand
+                    Operand::ImmediateI8 { .. } | Operand::ImmediateU8 { .. } => None,
+                    _ => CmpExpr::try_from((&op, width)).ok(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        match last.opcode() {
+            Opcode::FTST => operands = vec![st(0), CmpExpr::U32(0)],
+            _ if operands.is_empty() => operands = vec![st(0), st(1)],
+            _ if operands.len() == 1 => operands.insert(0, st(0)),
+            _ => {}
+        }
+
+        operands
+            .into_iter()
+            .take(2)
+            .map(|expr| CmpExpr::Float((Box::new(expr), width)))
+            .collect()
+    }
 }
 
-impl Default for Disassembler {
+impl Default for LongModeDisassembler {
     fn default() -> Self {
         Self::new()
     }
@@ -816,7 +1321,7 @@ impl TryFrom<(&Operand, Option<u8>)> for CmpExpr {
     }
 }
 
-impl TracerDisassembler for Disassembler {
+impl TracerDisassembler for LongModeDisassembler {
     /// Check if an instruction is a control flow instruction
     fn last_was_control_flow(&self) -> bool {
         if let Some(last) = self.last {
@@ -892,6 +1397,7 @@ impl TracerDisassembler for Disassembler {
                 last.opcode(),
                 Opcode::CMP
                     | Opcode::CMPPD
+                    | Opcode::CMPPS
                     | Opcode::CMPS
                     | Opcode::CMPSD
                     | Opcode::CMPSS
@@ -910,9 +1416,11 @@ impl TracerDisassembler for Disassembler {
                     | Opcode::FUCOMI
                     | Opcode::FUCOMIP
                     | Opcode::FUCOMP
+                    | Opcode::FUCOMPP
                     | Opcode::FXAM
                     | Opcode::PCMPEQB
                     | Opcode::PCMPEQD
+                    | Opcode::PCMPEQQ
                     | Opcode::PCMPEQW
                     | Opcode::PCMPGTB
                     | Opcode::PCMPGTD
@@ -926,23 +1434,73 @@ impl TracerDisassembler for Disassembler {
                     | Opcode::PMINSD
                     | Opcode::PMINUD
                     | Opcode::PMINUW
+                    | Opcode::SCAS
                     | Opcode::TEST
                     | Opcode::UCOMISD
                     | Opcode::UCOMISS
+                    | Opcode::VCOMISD
+                    | Opcode::VCOMISS
                     | Opcode::VPCMPB
                     | Opcode::VPCMPD
+                    | Opcode::VPCMPEQB
+                    | Opcode::VPCMPEQD
+                    | Opcode::VPCMPEQQ
+                    | Opcode::VPCMPEQW
+                    | Opcode::VPCMPGTB
+                    | Opcode::VPCMPGTD
+                    | Opcode::VPCMPGTQ
+                    | Opcode::VPCMPGTW
                     | Opcode::VPCMPQ
                     | Opcode::VPCMPUB
                     | Opcode::VPCMPUD
                     | Opcode::VPCMPUQ
                     | Opcode::VPCMPUW
                     | Opcode::VPCMPW
+                    | Opcode::VUCOMISD
+                    | Opcode::VUCOMISS
             );
         }
 
         false
     }
 
+    /// Check if an instruction is arithmetic which sets flags that are commonly tested by a
+    /// following conditional branch
+    fn last_sets_flags(&self) -> bool {
+        if let Some(last) = self.last {
+            return matches!(
+                last.opcode(),
+                Opcode::AND | Opcode::DEC | Opcode::INC | Opcode::SUB | Opcode::XOR
+            );
+        }
+
+        false
+    }
+
+    /// Check if an instruction is a conditional branch
+    fn last_was_conditional(&self) -> bool {
+        if let Some(last) = self.last {
+            return self.last_was_control_flow() && last.opcode().condition().is_some();
+        }
+
+        false
+    }
+
+    fn last_was_indirect_jump(&self) -> bool {
+        if let Some(last) = self.last {
+            // Direct jumps encode their target as a relative immediate
+            return last.opcode() == Opcode::JMP
+                && !matches!(
+                    last.operand(0),
+                    Operand::ImmediateI8 { .. }
+                        | Operand::ImmediateI16 { .. }
+                        | Operand::ImmediateI32 { .. }
+                );
+        }
+
+        false
+    }
+
     fn disassemble(&mut self, bytes: &[u8]) -> Result<()> {
         if let Ok(insn) = self.decoder.decode_slice(bytes) {
             self.last = Some(insn);
@@ -961,11 +1519,50 @@ impl TracerDisassembler for Disassembler {
         }
     }
 
+    fn last_len(&self) -> Option<usize> {
+        self.last
+            .as_ref()
+            .map(|last| last.len().to_const() as usize)
+    }
+
     fn cmp(&self) -> Vec<CmpExpr> {
         let mut cmp_exprs = Vec::new();
-        if self.last_was_cmp() {
+        if self.last_was_cmp() || self.last_sets_flags() {
             if let Some(last) = self.last {
-                for op_idx in 0..last.operand_count() {
+                if matches!(last.opcode(), Opcode::CMPS | Opcode::SCAS) {
+                    return self.string_cmp(&last);
+                }
+
+                if let Some(width) = self.fp_cmp_width() {
+                    return self.fp_cmp(&last, width);
+                }
+
+                // AVX compares have a destination operand before the compared operands
+                let first = if matches!(
+                    last.opcode(),
+                    Opcode::VPCMPB
+                        | Opcode::VPCMPD
+                        | Opcode::VPCMPEQB
+                        | Opcode::VPCMPEQD
+                        | Opcode::VPCMPEQQ
+                        | Opcode::VPCMPEQW
+                        | Opcode::VPCMPGTB
+                        | Opcode::VPCMPGTD
+                        | Opcode::VPCMPGTQ
+                        | Opcode::VPCMPGTW
+                        | Opcode::VPCMPQ
+                        | Opcode::VPCMPUB
+                        | Opcode::VPCMPUD
+                        | Opcode::VPCMPUQ
+                        | Opcode::VPCMPUW
+                        | Opcode::VPCMPW
+                ) {
+                    1
+                } else {
+                    0
+                };
+
+                for op_idx in first..last.operand_count() {
                     let op = last.operand(op_idx);
                     let width = if let Some(width) = op.width() {
                         Some(width)
@@ -978,13 +1575,40 @@ impl TracerDisassembler for Disassembler {
                         cmp_exprs.push(expr);
                     }
                 }
+
+                // The flags set by `dec` and `inc` compare the operand with the value which
+                // becomes zero
+                let value = match last.opcode() {
+                    Opcode::DEC => Some(1),
+                    Opcode::INC => Some(u64::MAX),
+                    _ => None,
+                };
+
+                if let Some(value) = value {
+                    let op = last.operand(0);
+                    let width = op
+                        .width()
+                        .or_else(|| last.mem_size().and_then(|s| s.bytes_size()));
+
+                    match width {
+                        Some(1) => cmp_exprs.push(CmpExpr::U8(value as u8)),
+                        Some(2) => cmp_exprs.push(CmpExpr::U16(value as u16)),
+                        Some(4) => cmp_exprs.push(CmpExpr::U32(value as u32)),
+                        Some(8) => cmp_exprs.push(CmpExpr::U64(value)),
+                        _ => {}
+                    }
+                }
             }
         }
         cmp_exprs
     }
 
     fn cmp_type(&self) -> Vec<CmpType> {
-        if self.last_was_cmp() {
+        if self.fp_cmp_width().is_some() {
+            return vec![CmpType::Equal, CmpType::Fp];
+        }
+
+        if self.last_was_cmp() || self.last_was_conditional() {
             if let Some(last) = self.last {
                 if let Some(condition) = last.opcode().condition() {
                     return match condition {
@@ -1028,3 +1652,41 @@ impl TracerDisassembler for Disassembler {
         vec![]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CodeWidth, Disassembler};
+    use crate::{
+        arch::decode_cache::{DecodeCache, DecodedInstruction},
+        traits::TracerDisassembler,
+    };
+
+    #[test]
+    fn test_decode_mode_switch() {
+        // `call rax` in 64-bit code, `dec ax` in 16-bit code
+        let bytes = [0x48, 0xff, 0xd0];
+        let mut disassembler = Disassembler::new();
+        let mut cache = DecodeCache::default();
+
+        let mut decode = |code_width: CodeWidth| {
+            if let Some(decoded) = cache.get(0x1000, code_width as u8, &bytes) {
+                return decoded;
+            }
+
+            disassembler.set_code_width(code_width);
+            disassembler
+                .disassemble(&bytes)
+                .expect("Failed to disassemble");
+            let decoded = DecodedInstruction::from_disassembler(&bytes, &disassembler);
+            cache.insert(0x1000, code_width as u8, decoded)
+        };
+
+        // Each mode keeps its own decoding of the same code after switching back and forth
+        for _ in 0..2 {
+            let long_mode = decode(CodeWidth::Bits64);
+            assert!(long_mode.call && long_mode.is_edge());
+            let real_mode = decode(CodeWidth::Bits16);
+            assert!(!real_mode.call && !real_mode.is_edge());
+        }
+    }
+}
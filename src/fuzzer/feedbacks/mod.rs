@@ -6,17 +6,20 @@ use libafl::{
     feedbacks::{Feedback, HasObserverName, IsNovel, MapFeedback, MapFeedbackMetadata, Reducer},
     inputs::HasTargetBytes,
     observers::UsesObserver,
-    prelude::{ExitKind, MapObserver, Observer, ObserversTuple, UsesInput},
-    state::{HasCorpus, HasNamedMetadata, State},
+    prelude::{ExitKind, MapObserver, Observer, ObserversTuple, Testcase, UsesInput},
+    state::{HasCorpus, HasMetadata, HasNamedMetadata, State},
 };
 use libafl_bolts::{AsIter, AsSlice, Named};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
+    cell::RefCell,
     fmt::Debug,
+    rc::Rc,
     sync::{mpsc::Sender, OnceLock},
 };
 
 use super::messages::FuzzerMessage;
+use crate::state::SolutionKind;
 
 #[derive(Clone, Debug)]
 pub(crate) struct ReportingMapFeedback<N, O, R, S, T> {
@@ -278,3 +281,63 @@ where
         Self { base, sender }
     }
 }
+
+#[derive(Clone, Debug)]
+/// A feedback which is never interesting by itself, and records the kind of solution reported
+/// by the simulation in the metadata of solutions found by the other objective feedbacks
+pub(crate) struct SolutionKindFeedback {
+    /// The kind of solution the last executed testcase was, if it was a solution
+    kind: Rc<RefCell<Option<SolutionKind>>>,
+}
+
+impl<S> Feedback<S> for SolutionKindFeedback
+where
+    S: State,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &<S>::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<<S>::Input>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        if let Some(kind) = self.kind.borrow_mut().take() {
+            testcase.add_metadata(kind);
+        }
+
+        Ok(())
+    }
+}
+
+impl Named for SolutionKindFeedback {
+    #[inline]
+    fn name(&self) -> &str {
+        "solution_kind"
+    }
+}
+
+impl SolutionKindFeedback {
+    #[must_use]
+    pub fn new(kind: Rc<RefCell<Option<SolutionKind>>>) -> Self {
+        Self { kind }
+    }
+}
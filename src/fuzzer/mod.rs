@@ -5,9 +5,11 @@
 
 use crate::{
     fuzzer::{
-        executors::inprocess::InProcessExecutor, feedbacks::ReportingMapFeedback,
+        executors::inprocess::InProcessExecutor,
+        feedbacks::{ReportingMapFeedback, SolutionKindFeedback},
         messages::FuzzerMessage,
     },
+    state::SolutionKind,
     Tsffs,
 };
 use anyhow::{anyhow, Result};
@@ -18,8 +20,8 @@ use libafl::{
         havoc_mutations, ondisk::OnDiskMetadataFormat, tokens_mutations, AFLppRedQueen, BytesInput,
         CachedOnDiskCorpus, Corpus, CrashFeedback, ExitKind, HasCurrentCorpusIdx, HasTargetBytes,
         HitcountsMapObserver, I2SRandReplace, MaxMapFeedback, OnDiskCorpus, RandBytesGenerator,
-        SimpleEventManager, SimpleMonitor, StdCmpValuesObserver, StdMOptMutator, StdMapObserver,
-        StdScheduledMutator, TimeFeedback, TimeObserver, Tokens,
+        SimpleEventManager, SimpleMonitor, StdCmpValuesObserver, StdMOptMutator,
+        StdScheduledMutator, TimeFeedback, TimeObserver, Tokens, VariableMapObserver,
     },
     schedulers::{
         powersched::PowerSchedule, IndexesLenTimeMinimizerScheduler, StdWeightedScheduler,
@@ -42,8 +44,14 @@ use libafl_bolts::{
 use libafl_targets::{AFLppCmpLogObserver, AFLppCmplogTracingStage};
 use simics::{api::AsConfObject, debug, trace, warn};
 use std::{
-    cell::RefCell, fmt::Debug, fs::write, io::stderr, slice::from_raw_parts_mut,
-    sync::mpsc::channel, thread::spawn,
+    cell::RefCell,
+    fmt::Debug,
+    fs::write,
+    io::stderr,
+    rc::Rc,
+    slice::from_raw_parts_mut,
+    sync::{atomic::AtomicUsize, mpsc::channel},
+    thread::spawn,
 };
 use tokenize::{tokenize_executable_file, tokenize_src_file};
 use tracing::{level_filters::LevelFilter, Level};
@@ -108,7 +116,7 @@ impl Tsffs {
 
         debug!(self.as_conf_object_mut(), "Starting fuzzer thread");
 
-        let (tx, orx) = channel::<ExitKind>();
+        let (tx, orx) = channel::<(ExitKind, Option<SolutionKind>)>();
         let (otx, rx) = channel::<Testcase>();
         let (stx, srx) = channel::<ShutdownMessage>();
         let (mtx, mrx) = channel::<FuzzerMessage>();
@@ -128,6 +136,21 @@ impl Tsffs {
 
         let client = RefCell::new((otx, orx));
 
+        // The coverage map is allocated when the object is finalized, and is allocated again
+        // here if its size was configured afterward
+        if self.coverage_map.get().map(|m| m.as_slice().len()) != Some(self.coverage_map_size) {
+            self.coverage_map.take();
+            self.coverage_map
+                .set(OwnedMutSlice::from(vec![0; self.coverage_map_size]))
+                .map_err(|_| anyhow!("Coverage map already set"))?;
+        }
+        self.coverage_map_len
+            .set(Box::leak(Box::new(AtomicUsize::new(
+                self.edge_indices
+                    .observed_len(self.coverage_map_size, self.coverage_dense_edges),
+            ))))
+            .map_err(|_| anyhow!("Coverage map length already set"))?;
+
         let coverage_map = unsafe {
             from_raw_parts_mut(
                 self.coverage_map
@@ -135,9 +158,13 @@ impl Tsffs {
                     .ok_or_else(|| anyhow!("Coverage map not set"))?
                     .as_mut_slice()
                     .as_mut_ptr(),
-                Self::COVERAGE_MAP_SIZE,
+                self.coverage_map_size,
             )
         };
+        let coverage_map_len = *self
+            .coverage_map_len
+            .get()
+            .ok_or_else(|| anyhow!("Coverage map length not set"))?;
 
         let aflpp_cmp_map = Box::leak(unsafe {
             Box::from_raw(
@@ -220,6 +247,10 @@ impl Tsffs {
                         .ok();
                 }
 
+                // The kind of solution the last testcase was, shared with the objective so it is
+                // recorded in the solution's metadata
+                let solution_kind = Rc::new(RefCell::new(None));
+
                 let mut harness = |input: &BytesInput| {
                     let testcase = BytesInput::new(input.target_bytes().as_slice().to_vec());
                     client
@@ -231,11 +262,13 @@ impl Tsffs {
                         })
                         .expect("Failed to send testcase message");
 
-                    let status = match client.borrow_mut().1.recv() {
+                    let (status, kind) = match client.borrow_mut().1.recv() {
                         Err(e) => panic!("Error receiving status: {e}"),
                         Ok(m) => m,
                     };
 
+                    *solution_kind.borrow_mut() = kind;
+
                     status
                 };
 
@@ -250,20 +283,26 @@ impl Tsffs {
                         })
                         .expect("Failed to send testcase message");
 
-                    let status = match client.borrow_mut().1.recv() {
+                    let (status, kind) = match client.borrow_mut().1.recv() {
                         Err(e) => panic!("Error receiving status: {e}"),
                         Ok(m) => m,
                     };
 
+                    *solution_kind.borrow_mut() = kind;
+
                     status
                 };
 
                 let mut tracing_harness = aflpp_cmp_harness;
 
-                let edges_observer = HitcountsMapObserver::new(StdMapObserver::from_mut_slice(
-                    Self::EDGES_OBSERVER_NAME,
-                    OwnedMutSlice::from(coverage_map),
-                ));
+                let edges_observer =
+                    HitcountsMapObserver::new(VariableMapObserver::from_mut_slice(
+                        Self::EDGES_OBSERVER_NAME,
+                        OwnedMutSlice::from(coverage_map),
+                        // The observer reads the length while the target is stopped, after
+                        // the last update of this iteration is sent over the channel
+                        coverage_map_len.as_ptr(),
+                    ));
 
                 let aflpp_cmp_observer = AFLppCmpLogObserver::new(
                     Self::AFLPP_CMP_OBSERVER_NAME,
@@ -286,6 +325,7 @@ impl Tsffs {
 
                 let crash_feedback = CrashFeedback::new();
                 let timeout_feedback = TimeFeedback::new(Self::TIMEOUT_FEEDBACK_NAME);
+                let solution_kind_feedback = SolutionKindFeedback::new(solution_kind.clone());
 
                 let solutions = OnDiskCorpus::with_meta_format(
                     solutions_directory.clone(),
@@ -312,7 +352,8 @@ impl Tsffs {
                 let generalization_stage = GeneralizationStage::new(&edges_observer);
 
                 let mut feedback = feedback_or!(map_feedback, time_feedback);
-                let mut objective = feedback_or_fast!(crash_feedback, timeout_feedback);
+                let mut objective =
+                    feedback_or_fast!(crash_feedback, timeout_feedback, solution_kind_feedback);
 
                 let mut state = StdState::new(
                     StdRand::with_seed(current_nanos()),
@@ -577,6 +618,11 @@ impl Tsffs {
                 .map_err(|e| anyhow!("Error receiving from fuzzer: {e}"))?
         };
 
+        if self.coverage_attribution {
+            self.coverage_attribution_index
+                .start_testcase(&testcase.testcase.generate_name(0), self.iterations);
+        }
+
         if self.keep_all_corpus {
             let testcase_name = testcase.testcase.generate_name(0);
             trace!(
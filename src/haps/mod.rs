@@ -85,6 +85,7 @@ impl Tsffs {
                     },
                     &self.source_file_cache,
                 )?;
+                self.resolve_cmplog_routines();
             }
             self.get_and_write_testcase()?;
             self.post_timeout_event()?;
@@ -105,12 +106,15 @@ impl Tsffs {
     fn finish_iteration(
         &mut self,
         exit_kind: ExitKind,
+        solution: Option<SolutionKind>,
         iteration_count: IterationCount,
         snapshot_restore_mode: SnapshotRestoreMode,
         missing_start_info_message: &str,
     ) -> Result<IterationControl> {
-        // 1) Count this iteration as complete.
+        // 1) Count this iteration as complete, and reject configuration changes which can no
+        // longer take effect. The check only runs when the configuration has changed.
         self.iterations += 1;
+        self.check_decode_time_filtering()?;
 
         // 2) Enforce iteration cap before scheduling/resuming work for next iteration.
         if self.iteration_limit != 0 && self.iterations >= self.iteration_limit {
@@ -154,7 +158,7 @@ impl Tsffs {
             .ok_or_else(|| anyhow!("No fuzzer tx channel"))?;
 
         // 4) Publish this iteration result back to the fuzzer loop.
-        fuzzer_tx.send(exit_kind)?;
+        fuzzer_tx.send((exit_kind, solution))?;
 
         // 5) Restore to initial snapshot according to the stop-specific restore policy.
         if match snapshot_restore_mode {
@@ -164,8 +168,9 @@ impl Tsffs {
             self.restore_initial_snapshot()?;
         }
 
-        // 6) Reset AFL edge chaining state for the next execution.
-        self.coverage_prev_loc = 0;
+        // 6) Reset AFL edge chaining state and cached translations for the next execution.
+        self.coverage_chains.clear();
+        self.clear_coverage_translations();
 
         // 7) Persist testcase bytes when start metadata is available.
         if self.start_info.get().is_some() {
@@ -209,6 +214,7 @@ impl Tsffs {
             // Normal stop path: report successful completion without solution/timeout counters.
             if let IterationControl::StopRequested = self.finish_iteration(
                 ExitKind::Ok,
+                None,
                 IterationCount::NoCount,
                 SnapshotRestoreMode::PolicyControlled,
                 "Missing start buffer or size, not writing testcase.",
@@ -280,6 +286,7 @@ impl Tsffs {
                     },
                     &self.source_file_cache,
                 )?;
+                self.resolve_cmplog_routines();
             }
 
             self.get_and_write_testcase()?;
@@ -322,6 +329,7 @@ impl Tsffs {
                     },
                     &self.source_file_cache,
                 )?;
+                self.resolve_cmplog_routines();
             }
 
             self.post_timeout_event()?;
@@ -369,6 +377,7 @@ impl Tsffs {
             // Manual stop behaves like normal completion for accounting purposes.
             if let IterationControl::StopRequested = self.finish_iteration(
                 ExitKind::Ok,
+                None,
                 IterationCount::NoCount,
                 SnapshotRestoreMode::PolicyControlled,
                 "Missing start buffer or size, not writing testcase. This may be due to using manual no-buffer harnessing.",
@@ -421,16 +430,19 @@ impl Tsffs {
                 return Ok(());
             }
 
-            let (exit_kind, iteration_count) = match kind {
+            let (exit_kind, iteration_count) = match &kind {
                 SolutionKind::Timeout => (ExitKind::Timeout, IterationCount::Timeout),
                 SolutionKind::Exception { .. }
                 | SolutionKind::Breakpoint { .. }
+                | SolutionKind::Fault(_)
+                | SolutionKind::SmramCallout { .. }
                 | SolutionKind::Manual => (ExitKind::Crash, IterationCount::Solution),
             };
 
             // Solution/timeout path: classify exit kind and increment corresponding counters.
             if let IterationControl::StopRequested = self.finish_iteration(
                 exit_kind,
+                Some(kind),
                 iteration_count,
                 SnapshotRestoreMode::Always,
                 "Missing start buffer or size, not writing testcase.",
@@ -473,7 +485,9 @@ impl Tsffs {
             StopReason::ManualStartWithoutBuffer { processor } => {
                 self.on_simulation_stopped_manual_start_without_buffer(processor)
             }
-            StopReason::ManualStop => self.on_simulation_stopped_manual_stop(),
+            StopReason::ManualStop | StopReason::SmmExit => {
+                self.on_simulation_stopped_manual_stop()
+            }
             StopReason::Solution { kind } => self.on_simulation_stopped_solution(kind),
         }
     }
@@ -489,7 +503,7 @@ impl Tsffs {
                 .get()
                 .ok_or_else(|| anyhow!("No fuzzer tx channel"))?;
 
-            fuzzer_tx.send(ExitKind::Ok)?;
+            fuzzer_tx.send((ExitKind::Ok, None))?;
 
             info!(
                 self.as_conf_object(),
@@ -541,11 +555,63 @@ impl Tsffs {
 
     /// Called on core exception HAP. Check to see if this exception is configured as a solution
     /// or all exceptions are solutions and trigger a stop if so
-    pub fn on_exception(&mut self, _obj: *mut ConfObject, exception: i64) -> Result<()> {
-        if self.all_exceptions_are_solutions || self.exceptions.contains(&exception) {
+    pub fn on_exception(&mut self, obj: *mut ConfObject, exception: i64) -> Result<()> {
+        let processor = object_is_processor(obj)?;
+
+        let name = if processor {
+            self.processors
+                .get(&get_processor_number(obj)?)
+                .and_then(|p| p.exception_name(exception))
+        } else {
+            None
+        };
+
+        // Faults during start-up are handled by the guest as usual, only faults while a
+        // testcase runs are solutions
+        let fault =
+            if processor && self.fault_handlers_are_solutions && self.have_initial_snapshot() {
+                self.processors
+                    .get_mut(&get_processor_number(obj)?)
+                    .map(|p| p.fault(exception))
+                    .transpose()?
+                    .flatten()
+            } else {
+                None
+            };
+
+        if let Some(fault) = fault {
+            info!(self.as_conf_object(), "Fault {fault} is a solution");
+
+            self.stop_simulation(StopReason::Solution {
+                kind: SolutionKind::Fault(fault),
+            })?;
+        } else if self.all_exceptions_are_solutions
+            || self.exceptions.contains(&exception)
+            || name.is_some_and(|n| self.exception_names.contains(n))
+        {
+            info!(
+                self.as_conf_object(),
+                "Exception {exception} ({}) is a solution",
+                name.unwrap_or("unnamed")
+            );
+
             self.stop_simulation(StopReason::Solution {
-                kind: SolutionKind::Exception { number: exception },
+                kind: SolutionKind::Exception {
+                    number: exception,
+                    name: name.map(|n| n.to_string()),
+                },
             })?;
+        } else if self.coverage_enabled && processor {
+            self.enter_exception_context(get_processor_number(obj)?);
+        }
+        Ok(())
+    }
+
+    /// Called on exception return HAP. Resume edge chaining for the interrupted context if
+    /// exception contexts are tracked separately
+    pub fn on_exception_return(&mut self, obj: *mut ConfObject, _exception: i64) -> Result<()> {
+        if self.coverage_enabled && object_is_processor(obj)? {
+            self.leave_exception_context(get_processor_number(obj)?);
         }
         Ok(())
     }
@@ -558,6 +624,10 @@ impl Tsffs {
         breakpoint: i64,
         transaction: *mut GenericTransaction,
     ) -> Result<()> {
+        if self.on_breakpoint_coverage_hit(breakpoint as i32)? {
+            return Ok(());
+        }
+
         if self.all_breakpoints_are_solutions || self.breakpoints.contains(&(breakpoint as i32)) {
             info!(
                 self.as_conf_object(),
@@ -649,6 +719,7 @@ impl Tsffs {
         value: i64,
     ) -> Result<()> {
         self.on_control_register_write_windows_symcov(trigger_obj, register_nr, value)?;
+        self.clear_coverage_translations();
 
         Ok(())
     }
@@ -1,7 +1,10 @@
 // Copyright (C) 2024 Intel Corporation
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{arch::ArchitectureHint, Tsffs};
+use crate::{
+    arch::{ArchitectureHint, ArchitectureOperations, Endianness},
+    Tsffs,
+};
 use simics::{debug, get_processor_number, interface, AsConfObject, ConfObject, Result};
 use std::{
     ffi::{c_char, CStr},
@@ -41,4 +44,26 @@ impl Tsffs {
 
         Ok(())
     }
+
+    /// Set the byte order of integers in memory for a particular processor. This overrides
+    /// the byte order reported by the processor object or implied by its architecture hint,
+    /// and is useful for big-endian configurations the processor does not report, such as
+    /// ARM BE8.
+    pub fn set_endianness(&mut self, cpu: *mut ConfObject, endianness: *mut c_char) -> Result<()> {
+        let endianness = unsafe { CStr::from_ptr(endianness) }.to_str()?;
+        let processor_number = get_processor_number(cpu)?;
+        debug!(
+            self.as_conf_object(),
+            "set_endianness({processor_number}, {endianness})"
+        );
+        let endianness = Endianness::from_str(endianness)?;
+        self.endianness_overrides
+            .insert(processor_number, endianness);
+
+        if let Some(architecture) = self.processors.get_mut(&processor_number) {
+            *architecture.endianness() = endianness;
+        }
+
+        Ok(())
+    }
 }
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    smm::Guid,
     state::{SolutionKind, StopReason},
     ManualStartAddress, ManualStartInfo, ManualStartSize, Tsffs,
 };
@@ -213,6 +214,55 @@ impl Tsffs {
         Ok(testcase.testcase.bytes().to_vec().try_into()?)
     }
 
+    /// Interface method to manually start fuzzing an SMM handler by taking a snapshot and
+    /// resuming execution of the simulation. Each testcase is written to an SMM communication
+    /// buffer in the layout of an `EFI_SMM_COMMUNICATE_HEADER` and a software SMI is triggered
+    /// by writing `apm_command` to `smm_apm_port`. Only code executed in SMM is traced,
+    /// and each iteration stops when the processor executes RSM.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - The CPU whose memory space should be written, and which the SMI is triggered
+    ///   on
+    /// * `communication_buffer` - The physical address of the SMM communication buffer
+    /// * `maximum_size` - The maximum size of the test case. The actual size of each test case
+    ///   is written to the `MessageLength` field of the header.
+    /// * `guid` - The GUID of the handler under test, in the form
+    ///   `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`
+    /// * `apm_command` - The command byte the firmware's SMM communication protocol writes
+    ///   to the APM control port to enter SMM, which differs between firmware builds
+    pub fn start_smm(
+        &mut self,
+        cpu: *mut ConfObject,
+        communication_buffer: GenericAddress,
+        maximum_size: u32,
+        guid: *mut c_char,
+        apm_command: u8,
+    ) -> Result<()> {
+        let guid: Guid = unsafe { CStr::from_ptr(guid) }.to_str()?.parse()?;
+
+        debug!(
+            self.as_conf_object(),
+            "start_smm({communication_buffer:#x}, {maximum_size:#x}, {guid}, {apm_command:#x})"
+        );
+
+        let communication =
+            self.set_smm_communication(cpu, communication_buffer, guid, apm_command)?;
+
+        self.stop_simulation(StopReason::ManualStart {
+            processor: cpu,
+            info: ManualStartInfo {
+                address: ManualStartAddress::Physical(communication.data_address()),
+                size: ManualStartSize::SizePtrAndMaxSize {
+                    address: ManualStartAddress::Physical(communication.length_address()),
+                    maximum_size: maximum_size.try_into()?,
+                },
+            },
+        })?;
+
+        Ok(())
+    }
+
     /// Interface method to manually signal to stop a testcase execution. When this
     /// method is called, the current testcase execution will be stopped as if it had
     /// finished executing normally, and the state will be restored according to
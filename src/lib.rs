@@ -32,8 +32,8 @@ use crate::interfaces::{config::config, fuzz::fuzz};
 use crate::state::{SnapshotRestorePolicy, SolutionKind, StopReason};
 #[cfg(simics_version = "6")]
 use crate::util::Utils;
-use anyhow::{anyhow, Result};
-use arch::{Architecture, ArchitectureHint, ArchitectureOperations};
+use anyhow::{anyhow, bail, Result};
+use arch::{Architecture, ArchitectureHint, ArchitectureOperations, Endianness};
 use fuzzer::{messages::FuzzerMessage, ShutdownMessage, Testcase};
 use indoc::indoc;
 use lcov2::Records;
@@ -43,7 +43,10 @@ use libafl_targets::AFLppCmpLogMap;
 use log::LogMessage;
 use magic::MagicNumber;
 use num_traits::FromPrimitive as _;
-use os::windows::WindowsOsInfo;
+use os::{
+    elf::{ElfModule, SymbolFile},
+    windows::WindowsOsInfo,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::to_writer;
 use simics::continue_simulation;
@@ -52,9 +55,10 @@ use simics::{
     get_processor_number, info, lookup_file, object_clock, run_alone, run_command, run_python,
     simics_init, sys::save_flags_t, trace, version_base, warn, write_configuration_to_file,
     AsConfObject, BreakpointId, ClassCreate, ClassObjectsFinalize, ConfObject,
-    CoreBreakpointMemopHap, CoreControlRegisterWriteHap, CoreExceptionHap, CoreMagicInstructionHap,
-    CoreSimulationStoppedHap, CpuInstrumentationSubscribeInterface, Event, EventClassFlag,
-    FromConfObject, HapHandle, Interface,
+    CoreBreakpointMemopHap, CoreControlRegisterWriteHap, CoreExceptionHap, CoreExceptionReturnHap,
+    CoreMagicInstructionHap, CoreSimulationStoppedHap, CpuCachedInstructionInterface,
+    CpuInstrumentationSubscribeInterface, Event, EventClassFlag, FromConfObject, HapHandle,
+    Interface, X86EnterSmmHap, X86LeaveSmmHap,
 };
 #[cfg(simics_version = "6")]
 use simics::{
@@ -65,6 +69,7 @@ use simics::{
 // which is necessary because this module is compatible with base versions which cross the
 // deprecation boundary
 use simics::{restore_snapshot, save_snapshot};
+use smm::{SmmCommunication, SmramRange};
 use source_cov::SourceCache;
 use std::{
     alloc::{alloc_zeroed, Layout},
@@ -75,13 +80,17 @@ use std::{
     path::PathBuf,
     ptr::null_mut,
     str::FromStr,
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        atomic::AtomicUsize,
+        mpsc::{Receiver, Sender},
+    },
     thread::JoinHandle,
     time::SystemTime,
 };
 use tracer::{
-    tsffs::{on_instruction_after, on_instruction_before},
-    ExecutionTrace,
+    tsffs::{on_cached_instruction, on_instruction_after, on_instruction_before},
+    AttributionRecord, CmplogRoutine, CoverageAttribution, CoverageIdentifier, CoverageMode,
+    CoverageRange, EdgeChain, EdgeIndices, ExecutionTrace, ModuleRange,
 };
 use typed_builder::TypedBuilder;
 use versions::{Requirement, Versioning};
@@ -93,6 +102,7 @@ pub(crate) mod interfaces;
 pub(crate) mod log;
 pub(crate) mod magic;
 pub(crate) mod os;
+pub(crate) mod smm;
 pub(crate) mod source_cov;
 pub(crate) mod state;
 pub(crate) mod tracer;
@@ -150,6 +160,38 @@ pub(crate) struct StartInfo {
     /// not be written, or a `size_ptr` and `max_size` in which case the size will be
     /// written back to `*size_ptr` and the maximum size will be `max_size`.
     pub size: StartSize,
+    #[builder(default)]
+    /// The physical location of each page of the buffer, in order. Virtual buffers larger
+    /// than a page may be mapped to scattered physical frames, so the buffer is written page
+    /// by page
+    pub pages: Vec<StartPage>,
+}
+
+impl StartInfo {
+    /// Get the physically contiguous parts of the buffer, in order. A buffer without
+    /// recorded pages is contiguous from its start address.
+    pub fn physical_pages(&self) -> Vec<StartPage> {
+        if self.pages.is_empty() {
+            return vec![StartPage {
+                offset: 0,
+                physical_address: self.address.physical_address(),
+                size: self.size.maximum_size(),
+            }];
+        }
+
+        self.pages.clone()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+/// A physically contiguous part of a testcase buffer, no larger than a page
+pub(crate) struct StartPage {
+    /// The offset of the part from the start of the buffer
+    pub offset: usize,
+    /// The physical address of the part
+    pub physical_address: u64,
+    /// The size of the part in bytes
+    pub size: usize,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -235,9 +277,27 @@ pub(crate) struct Tsffs {
     ///
     /// @tsffs.exceptions = [14]
     ///
-    /// would treat any page fault as a solution.
+    /// would treat any page fault as a solution. Exception numbers differ between processor
+    /// models, see `exception_names` to set exceptions by name.
     pub exceptions: BTreeSet<i64>,
     #[class(attribute(optional))]
+    /// The set of exceptions which are treated as solutions, by their architecture-level name.
+    /// Each processor maps the names to the exception numbers of its model, so the same
+    /// configuration works across processor models. For example, setting:
+    ///
+    /// @tsffs.exception_names = ["page-fault", "general-protection"]
+    ///
+    /// would treat any page fault or general protection fault on x86 as a solution, and
+    /// setting `["data-abort", "prefetch-abort"]` would do the same for ARM processors.
+    pub exception_names: BTreeSet<String>,
+    #[class(attribute(optional, default = true))]
+    /// Whether faults which enter a fault handler of the guest are treated as solutions. On ARM
+    /// M-profile processors, entering the HardFault, MemManage, BusFault, UsageFault or
+    /// SecureFault handler while a testcase runs is a solution, and the fault status registers
+    /// and stacked exception frame are decoded to report the fault type and faulting PC. Fault
+    /// handlers often spin forever, so without this the fault is only detected as a timeout.
+    pub fault_handlers_are_solutions: bool,
+    #[class(attribute(optional))]
     /// The set of breakpoints which are treated as solutions. For example, to set a solution
     /// breakpoint on the address $addr (note the breakpoint set from the Simics command is
     /// accessed through the simenv namespace):
@@ -340,6 +400,17 @@ pub(crate) struct Tsffs {
     /// not benefit from value-driven mutations or run too slowly when solving for comparison
     /// values.
     pub cmplog: bool,
+    #[class(attribute(optional))]
+    /// Memory and string comparison routines whose operands are logged when they are called,
+    /// in addition to comparison instructions. Each routine is a string in the form
+    /// `<location>:<kind>[:<calling convention>]`, where the location is an address,
+    /// `module!Symbol`, or `Symbol` (symbols require OS awareness or `symbol_files`), the kind
+    /// is one of `memcmp`, `strcmp`, or `strncmp`, and the calling convention is one of
+    /// `default`, `sysv64`, `win64`, or `cdecl`. The default convention on x86_64 is `win64`
+    /// when Windows OS awareness is in use and `sysv64` otherwise. For example:
+    ///
+    /// @tsffs.cmplog_routines = ["ntoskrnl.exe!RtlCompareMemory:memcmp:win64"]
+    pub cmplog_routines: Vec<CmplogRoutine>,
     #[class(attribute(optional, default = true))]
     /// Whether coverage reporting should be enabled. When enabled, new edge addresses will
     /// be logged.
@@ -443,6 +514,147 @@ pub(crate) struct Tsffs {
     /// Directory in which source files are located. Source files do not need to be arranged in
     /// the same directory structure as the compiled source, and are looked up by hash.
     pub symbolic_coverage_directory: PathBuf,
+    #[class(attribute(optional, default = CoverageMode::HitCount))]
+    /// The method used to identify edges in the coverage map. One of:
+    ///
+    /// - `hit-count`: Edges are identified by the current and previous location, and the
+    ///   number of times each edge is hit is counted
+    /// - `once`: Edges are identified by the current and previous location, and only whether
+    ///   each edge is hit is recorded
+    /// - `call-context`: Edges are additionally identified by the current calling context,
+    ///   which is more precise but uses more of the coverage map
+    /// - `ngram`: Edges are identified by the current location and the previous
+    ///   `coverage_ngram_size` locations, which is more precise but uses more of the coverage
+    ///   map
+    pub coverage_mode: CoverageMode,
+    #[class(attribute(optional, default = 4))]
+    /// The number of previous locations used to identify edges in `ngram` coverage mode
+    pub coverage_ngram_size: usize,
+    #[class(attribute(optional, default = false))]
+    /// Whether each unique edge should be assigned the next unused index in the coverage map
+    /// when it is first seen, instead of hashing its address into the map. Edges are
+    /// identified by the address of the branch instruction and its target (and the calling
+    /// context or previous locations, depending on `coverage_mode`), so distinct edges never
+    /// share an index until every index of the map is assigned. The part of the map observed
+    /// by the fuzzer extends as new edges are found, up to a hard cap of
+    /// `coverage_map_size` entries, after which new edges are hashed into the map.
+    pub coverage_dense_edges: bool,
+    #[class(attribute(optional, default = CoverageIdentifier::Virtual))]
+    /// How code locations are identified in the coverage map. One of:
+    ///
+    /// - `virtual`: Locations are identified by their virtual address
+    /// - `module`: Locations are identified by the module they are in and their offset into
+    ///   the module, using `coverage_module_ranges`, OS awareness, and `symbol_files`, so
+    ///   coverage is stable when modules are loaded at different addresses (e.g. with
+    ///   KASLR). Locations outside any known module are identified by their virtual address.
+    /// - `physical`: Locations are identified by their physical address
+    pub coverage_identifier: CoverageIdentifier,
+    #[class(attribute(optional))]
+    /// Modules and the virtual address ranges they are loaded at, used to identify locations
+    /// when `coverage_identifier` is `module`. Each module is a string in the form
+    /// `name=0x1000-0x2000` (the end is exclusive), for example:
+    ///
+    /// @tsffs.coverage_module_ranges = ["target.ko=0xffffffffc0000000-0xffffffffc0010000"]
+    pub coverage_module_ranges: Vec<ModuleRange>,
+    #[class(attribute(optional))]
+    /// ELF files to load function symbols from, for targets without OS awareness (for
+    /// example, Linux and bare metal targets). Symbols are used to find modules and symbols
+    /// for `coverage_ranges`, `breakpoint_coverage`, `cmplog_routines`, and the address
+    /// coverage report. Each file is a string in the form `path` or `path@0x1000`, where the
+    /// address is the load address of a position independent executable or shared library,
+    /// or the address of the `.text` section of a kernel module, for example:
+    ///
+    /// @tsffs.symbol_files = ["%simics%/test", "test-mod.ko@0xffffffffc0000000"]
+    pub symbol_files: Vec<SymbolFile>,
+    #[class(attribute(optional, default = Tsffs::COVERAGE_MAP_SIZE))]
+    /// The size of the coverage map in bytes. When `coverage_dense_edges` is enabled, this is
+    /// the maximum number of edges which can be tracked without collisions. Must be set
+    /// before fuzzing starts.
+    pub coverage_map_size: usize,
+    #[class(attribute(optional, default = true))]
+    /// Whether instructions should be classified once when they are decoded by the processor
+    /// model, so that only branches, calls, returns, and comparisons are instrumented. This
+    /// greatly reduces the overhead of tracing. It is disabled automatically when symbolic
+    /// coverage or execution traces are enabled, or when the processor model does not support
+    /// instrumenting cached instructions, in which case every instruction is instrumented.
+    /// Whether to filter is decided when each processor is traced, so this, symbolic coverage,
+    /// and the execution trace attributes must be set before the fuzzing loop starts or
+    /// `add_trace_processor` is called. Changing them afterward in a way that requires every
+    /// instruction to be instrumented is an error.
+    pub decode_time_filtering: bool,
+    #[class(attribute(optional, default = false))]
+    /// Whether each nested exception or interrupt context on a processor should have its own
+    /// edge chaining state. When enabled, an interrupt taken in the middle of the code under
+    /// test does not create edges between the interrupted code and the interrupt handler.
+    pub coverage_per_exception_context: bool,
+    #[class(attribute(optional))]
+    /// Ranges of code which contribute to coverage feedback, comparison logging, and coverage
+    /// reporting. If empty, all code executed on traced processors is included. Each range is
+    /// a string in one of the forms:
+    ///
+    /// - `0x1000-0x2000`: A virtual address range (the end is exclusive)
+    /// - `p:0x1000-0x2000`: A physical address range (the end is exclusive)
+    /// - `module.sys`: All code in a module (requires OS awareness or `symbol_files`)
+    /// - `module.sys!Symbol`: All code in a symbol (requires OS awareness or `symbol_files`)
+    ///
+    /// For example:
+    ///
+    /// @tsffs.coverage_include_ranges = ["0x7ff000-0x800000", "target.sys"]
+    pub coverage_include_ranges: Vec<CoverageRange>,
+    #[class(attribute(optional))]
+    /// Ranges of code which do not contribute to coverage feedback, comparison logging, or
+    /// coverage reporting, even if they are included by `coverage_include_ranges`. Ranges are
+    /// specified in the same forms as `coverage_include_ranges`. This is useful to exclude
+    /// code such as timer interrupt handlers and schedulers which pollute coverage.
+    pub coverage_exclude_ranges: Vec<CoverageRange>,
+    #[class(attribute(optional, default = true))]
+    /// Whether coverage should be attributed to the input which first reached it. When
+    /// enabled, each newly discovered edge (and each source line and function, if symbolic
+    /// coverage is enabled) is mapped to the corpus or solution file name, timestamp, and
+    /// iteration of the first input which reached it, and each new attribution is appended to
+    /// `coverage_attribution_path`.
+    pub coverage_attribution: bool,
+    #[class(attribute(optional, default = lookup_file("%simics%")?.join("coverage-attribution.jsonl")))]
+    /// The path to the file coverage attribution is saved to, as JSON Lines. If the file
+    /// already exists when fuzzing starts, existing attributions are loaded from it and
+    /// preserved.
+    pub coverage_attribution_path: PathBuf,
+    #[class(attribute(optional, default = false))]
+    /// Whether an address-level coverage report should be generated. The report groups the
+    /// addresses of covered blocks into contiguous regions and shows their disassembly with
+    /// hit and unhit instructions and block hit counts, and is useful for targets without
+    /// debug info. It is regenerated each time a new interesting input or solution is found.
+    pub address_coverage_report: bool,
+    #[class(attribute(optional, default = lookup_file("%simics%")?.join("address-coverage.txt")))]
+    /// The path to the file the address-level coverage report is saved to
+    pub address_coverage_report_path: PathBuf,
+    #[class(attribute(optional, default = false))]
+    /// Whether block coverage should be collected using temporary execution breakpoints
+    /// instead of instrumenting each executed instruction. A breakpoint is placed on the start
+    /// of each block in `breakpoint_coverage_blocks` and in the virtual, module, and symbol
+    /// ranges in `coverage_include_ranges` on each tracked processor, and is removed the first
+    /// time it is hit. This
+    /// records only whether each block has ever been hit, but allows VMP to remain enabled
+    /// and is much faster for large targets. Comparison logging is not available in this
+    /// mode.
+    pub breakpoint_coverage: bool,
+    #[class(attribute(optional))]
+    /// Virtual addresses of block starts to collect breakpoint coverage for, in addition to
+    /// the blocks found in `coverage_include_ranges`
+    pub breakpoint_coverage_blocks: Vec<u64>,
+    #[class(attribute(optional, default = 0xb2))]
+    /// The I/O port written to trigger a software SMI when fuzzing an SMM handler. This is the
+    /// APM control port of the chipset, which is 0xb2 on most Intel chipsets.
+    pub smm_apm_port: u64,
+    #[class(attribute(optional))]
+    /// Physical address ranges of SMRAM, in the form `0x7f000000-0x80000000` (the end is
+    /// exclusive). When fuzzing an SMM handler with any ranges set, a traced branch, call, or
+    /// return in SMM reaching code outside of SMRAM (a callout) is treated as a solution. Data
+    /// accesses outside of SMRAM are not checked, because handlers read their communication
+    /// buffer outside of SMRAM. For example:
+    ///
+    /// @tsffs.smram_ranges = ["0x7f000000-0x80000000"]
+    pub smram_ranges: Vec<SmramRange>,
 
     /// Handle for the core simulation stopped hap
     stop_hap_handle: HapHandle,
@@ -450,12 +662,18 @@ pub(crate) struct Tsffs {
     breakpoint_memop_hap_handle: HapHandle,
     /// Handle for exception HAP
     exception_hap_handle: HapHandle,
+    /// Handle for exception return HAP
+    exception_return_hap_handle: HapHandle,
     /// The handle for the registered magic HAP, used to
     /// listen for magic start and stop if `start_on_harness`
     /// or `stop_on_harness` are set.
     magic_hap_handle: HapHandle,
     /// Handle for the core control register write hap
     control_register_write_hap_handle: HapHandle,
+    /// Handle for the x86 SMM entry hap
+    smm_enter_hap_handle: HapHandle,
+    /// Handle for the x86 SMM exit hap
+    smm_leave_hap_handle: HapHandle,
 
     /// A mapping of architecture hints from CPU index to architecture hint. This architecture
     /// hint overrides the detected architecture of the CPU core. This is useful when the
@@ -463,12 +681,17 @@ pub(crate) struct Tsffs {
     /// CPU core is not known at the time the fuzzer is started. Specifically, x86 cores which
     /// report their architecture as x86_64 can be overridden to x86.
     pub architecture_hints: HashMap<i32, ArchitectureHint>,
+    /// A mapping of byte order overrides from CPU index to byte order. This overrides the
+    /// byte order reported by the CPU core or implied by its architecture hint, and is used
+    /// when reading and writing integers such as the testcase size in target memory.
+    pub endianness_overrides: HashMap<i32, Endianness>,
     // Threads and message channels
     /// Fuzzer thread
     fuzz_thread: OnceCell<JoinHandle<Result<()>>>,
     /// Message sender to the fuzzer thread. TSFFS sends exit kinds to the fuzzer thread to
-    /// report whether testcases resulted in normal exit, timeout, or solutions.
-    fuzzer_tx: OnceCell<Sender<ExitKind>>,
+    /// report whether testcases resulted in normal exit, timeout, or solutions, along with
+    /// the kind of solution to record in the solution's metadata.
+    fuzzer_tx: OnceCell<Sender<(ExitKind, Option<SolutionKind>)>>,
     /// Message receiver from the fuzzer thread. TSFFS receives new testcases and run configuration
     /// from the fuzzer thread.
     fuzzer_rx: OnceCell<Receiver<Testcase>>,
@@ -481,25 +704,54 @@ pub(crate) struct Tsffs {
     // Fuzzer coverage maps
     /// The coverage map
     coverage_map: OnceCell<OwnedMutSlice<'static, u8>>,
+    /// The number of entries at the start of the coverage map observed by the fuzzer, which
+    /// extends as new edges are assigned dense indices. It is shared with the fuzzer thread
+    /// and lives for the rest of the process once it is started.
+    coverage_map_len: OnceCell<&'static AtomicUsize>,
+    /// The coverage map index assigned to each unique edge
+    edge_indices: EdgeIndices,
     /// A pointer to the AFL++ comparison map
     aflpp_cmp_map_ptr: OnceCell<*mut AFLppCmpLogMap>,
     /// The owned AFL++ comparison map
     aflpp_cmp_map: OnceCell<&'static mut AFLppCmpLogMap>,
-    /// The previous location for coverage for calculating the hash of edges.
-    coverage_prev_loc: u64,
+    /// The edge chaining state for calculating the hash of edges for each processor. Each
+    /// processor has a stack of chains, one for each nested exception context if
+    /// `coverage_per_exception_context` is enabled, the last of which is active.
+    coverage_chains: HashMap<i32, Vec<EdgeChain>>,
     /// The registered timeout event which is registered and used to detect timeouts in
     /// virtual time
     timeout_event: OnceCell<Event>,
-    /// The set of edges which have been seen at least once.
-    edges_seen: HashSet<u64>,
-    /// A map of the new edges to their AFL indices seen since the last time the fuzzer
-    /// provided an update. This is not cleared every execution.
-    edges_seen_since_last: HashMap<u64, u64>,
+    /// The set of edges which have been seen at least once, by the coverage identifiers of
+    /// their source and target
+    edges_seen: HashSet<(u64, u64)>,
+    /// A map of the new edges to their AFL indices seen by the current testcase, by the
+    /// coverage identifiers of their source and target. This is reported if the fuzzer finds
+    /// the testcase interesting, and cleared when the next testcase starts.
+    edges_seen_since_last: HashMap<(u64, u64), u64>,
+    /// The number of times each edge has been hit across the full campaign, by processor and
+    /// the virtual address of its target. Only collected when the address coverage report is
+    /// enabled.
+    edge_hit_counts: HashMap<(i32, u64), u64>,
+    /// The physical page of each virtual page executed on, by processor, when coverage is
+    /// identified or filtered by physical address. `None` if the page is not mapped.
+    coverage_translations: HashMap<(i32, u64), Option<u64>>,
+    /// Armed coverage breakpoints, mapped to the processor and block they are placed on
+    breakpoint_coverage_ids: HashMap<BreakpointId, (i32, u64)>,
+    /// Blocks which currently have an armed coverage breakpoint, by processor
+    breakpoint_coverage_armed: HashSet<(i32, u64)>,
+    /// Blocks whose coverage breakpoint has been hit, by processor. These are never re-armed.
+    breakpoint_coverage_hit: HashSet<(i32, u64)>,
+    /// Whether armed coverage breakpoints still exist after the initial snapshot is restored.
+    /// Checked on the first restore.
+    breakpoint_coverage_kept_on_restore: Option<bool>,
     /// The set of PCs comprising the current execution trace. This is cleared every execution.
     execution_trace: ExecutionTrace,
     /// The current line coverage state comprising the total execution. This is not
     /// cleared and is persistent across the full campaign until the fuzzer stops.
     coverage: Records,
+    /// The first input to reach each edge, line, and function. This is not cleared and is
+    /// persistent across the full campaign until the fuzzer stops.
+    coverage_attribution_index: CoverageAttribution,
 
     /// The name of the fuzz snapshot, if saved
     snapshot_name: OnceCell<String>,
@@ -511,6 +763,14 @@ pub(crate) struct Tsffs {
     stop_reason: Option<StopReason>,
     /// The buffer and size information, if saved
     start_info: OnceCell<StartInfo>,
+    /// The SMM communication buffer testcases are sent to the handler under test through, if
+    /// an SMM handler is being fuzzed
+    smm_communication: Option<SmmCommunication>,
+    /// The processors which are currently executing the SMM handler under test
+    smm_processors: BTreeSet<i32>,
+    /// The physical page of each virtual page SMM code has executed on during the current
+    /// SMI, by processor
+    smm_translations: HashMap<(i32, u64), u64>,
 
     // #[builder(default = SystemTime::now())]
     /// The time the fuzzer was started at
@@ -525,11 +785,18 @@ pub(crate) struct Tsffs {
     coverage_enabled: bool,
     /// Whether cmplog is currently enabled
     cmplog_enabled: bool,
+    /// The resolved entry points of the configured comparison routines
+    cmplog_routine_addresses: HashMap<u64, CmplogRoutine>,
     /// The number of the processor which starts the fuzzing loop (via magic or manual methods)
     start_processor_number: OnceCell<i32>,
     /// Tracked processors. This always includes the start processor, and may include
     /// additional processors that are manually added by the user
     processors: HashMap<i32, Architecture>,
+    /// The tracked processors whose instructions are filtered at decode time
+    decode_time_filtered_processors: HashSet<i32>,
+    /// The attributes which decide whether instructions may be filtered at decode time, as
+    /// they were when the configuration was last checked successfully
+    checked_decode_time_filtering_config: Option<[bool; 6]>,
     /// A testcase to use for repro
     repro_testcase: Option<Vec<u8>>,
     /// Whether a bookmark has been set for repro mode
@@ -546,6 +813,8 @@ pub(crate) struct Tsffs {
     solutions: usize,
 
     windows_os_info: WindowsOsInfo,
+    /// Modules loaded from `symbol_files`
+    elf_modules: Vec<ElfModule>,
     cr3_cache: HashMap<i32, i64>,
     source_file_cache: SourceCache,
 }
@@ -583,6 +852,13 @@ impl ClassObjectsFinalize for Tsffs {
                     .on_exception(trigger_obj, exception_number)
                     .expect("Error calling breakpoint memop callback");
             })?;
+        tsffs.exception_return_hap_handle =
+            CoreExceptionReturnHap::add_callback(move |trigger_obj, exception_number| {
+                let tsffs: &'static mut Tsffs = instance.into();
+                tsffs
+                    .on_exception_return(trigger_obj, exception_number)
+                    .expect("Error calling exception return callback");
+            })?;
         tsffs.magic_hap_handle =
             CoreMagicInstructionHap::add_callback(move |trigger_obj, magic_number| {
                 let tsffs: &'static mut Tsffs = instance.into();
@@ -604,9 +880,24 @@ impl ClassObjectsFinalize for Tsffs {
                     .on_control_register_write(trigger_obj, register_nr, value)
                     .expect("Failed to execute on_control_register_write callback")
             })?;
+        // The simics crate generates the x86 SMM HAPs, `X86_Enter_SMM` and `X86_Leave_SMM`, from
+        // the HAP reference of the SIMICS base package at build time, like the core HAPs, so
+        // they are not in the crate's sources
+        tsffs.smm_enter_hap_handle = X86EnterSmmHap::add_callback(move |trigger_obj, phase| {
+            let tsffs: &'static mut Tsffs = instance.into();
+            tsffs
+                .on_smm_enter(trigger_obj, phase)
+                .expect("Failed to execute on_smm_enter callback")
+        })?;
+        tsffs.smm_leave_hap_handle = X86LeaveSmmHap::add_callback(move |trigger_obj, phase| {
+            let tsffs: &'static mut Tsffs = instance.into();
+            tsffs
+                .on_smm_leave(trigger_obj, phase)
+                .expect("Failed to execute on_smm_leave callback")
+        })?;
         tsffs
             .coverage_map
-            .set(OwnedMutSlice::from(vec![0; Tsffs::COVERAGE_MAP_SIZE]))
+            .set(OwnedMutSlice::from(vec![0; tsffs.coverage_map_size]))
             .map_err(|_e| anyhow!("Value already set"))?;
 
         tsffs
@@ -658,7 +949,7 @@ impl ClassObjectsFinalize for Tsffs {
 }
 
 impl Tsffs {
-    /// The size of the coverage map in bytes
+    /// The default size of the coverage map in bytes
     pub const COVERAGE_MAP_SIZE: usize = 128 * 1024;
     /// The name of the registered timeout event
     pub const TIMEOUT_EVENT_NAME: &'static str = "detector_timeout_event";
@@ -694,23 +985,65 @@ impl Tsffs {
         );
 
         if let Entry::Vacant(e) = self.processors.entry(cpu_number) {
-            let architecture = if let Some(hint) = self.architecture_hints.get(&cpu_number) {
+            let mut architecture = if let Some(hint) = self.architecture_hints.get(&cpu_number) {
                 hint.architecture(cpu)?
             } else {
                 Architecture::new(cpu)?
             };
+            if let Some(endianness) = self.endianness_overrides.get(&cpu_number) {
+                *architecture.endianness() = *endianness;
+            }
+            let decode_time_filtering = architecture.decode_time_filtering();
+            let unknown_exception_names = self
+                .exception_names
+                .iter()
+                .filter(|name| architecture.exception_numbers(name).is_empty())
+                .cloned()
+                .collect::<Vec<_>>();
             e.insert(architecture);
+
+            for name in unknown_exception_names {
+                warn!(
+                    self.as_conf_object(),
+                    "Processor {cpu_number} has no exception named '{name}'"
+                );
+            }
+
             let mut cpu_interface: CpuInstrumentationSubscribeInterface = get_interface(cpu)?;
-            cpu_interface.register_instruction_after_cb(
-                null_mut(),
-                Some(on_instruction_after),
-                self as *mut Self as *mut _,
-            )?;
-            cpu_interface.register_instruction_before_cb(
-                null_mut(),
-                Some(on_instruction_before),
-                self as *mut Self as *mut _,
-            )?;
+
+            // Breakpoint coverage does not instrument processors at all
+            if self.breakpoint_coverage {
+                debug!(
+                    self.as_conf_object(),
+                    "Using breakpoint coverage, not instrumenting processor {}", cpu_number
+                );
+            } else if self.decode_time_filtering_allowed()
+                && decode_time_filtering
+                && get_interface::<CpuCachedInstructionInterface>(cpu).is_ok()
+            {
+                debug!(
+                    self.as_conf_object(),
+                    "Filtering instructions at decode time for processor {}", cpu_number
+                );
+
+                cpu_interface.register_cached_instruction_cb(
+                    null_mut(),
+                    Some(on_cached_instruction),
+                    self as *mut Self as *mut _,
+                )?;
+                self.decode_time_filtered_processors.insert(cpu_number);
+            } else {
+                cpu_interface.register_instruction_after_cb(
+                    null_mut(),
+                    Some(on_instruction_after),
+                    self as *mut Self as *mut _,
+                )?;
+                cpu_interface.register_instruction_before_cb(
+                    null_mut(),
+                    Some(on_instruction_before),
+                    self as *mut Self as *mut _,
+                )?;
+            }
         }
 
         if is_start {
@@ -722,6 +1055,55 @@ impl Tsffs {
         Ok(())
     }
 
+    /// The attributes which decide whether instructions may be filtered at decode time
+    fn decode_time_filtering_config(&self) -> [bool; 6] {
+        [
+            self.decode_time_filtering,
+            self.symbolic_coverage,
+            self.save_all_execution_traces,
+            self.save_interesting_execution_traces,
+            self.save_solution_execution_traces,
+            self.save_timeout_execution_traces,
+        ]
+    }
+
+    /// Whether the configuration allows instructions to be filtered at decode time. Symbolic
+    /// coverage and execution traces need to observe every instruction, so instructions can
+    /// only be filtered when neither is enabled.
+    fn decode_time_filtering_allowed(&self) -> bool {
+        matches!(
+            self.decode_time_filtering_config(),
+            [true, false, false, false, false, false]
+        )
+    }
+
+    /// Check that no processor filters instructions at decode time when the configuration
+    /// requires every instruction to be instrumented. Whether to filter is decided when a
+    /// processor is added, so this catches attributes which are changed too late to take
+    /// effect. The check is skipped while the configuration is unchanged since it last passed.
+    pub fn check_decode_time_filtering(&mut self) -> Result<()> {
+        let config = self.decode_time_filtering_config();
+
+        if self.checked_decode_time_filtering_config == Some(config) {
+            return Ok(());
+        }
+
+        if !self.decode_time_filtered_processors.is_empty() && !self.decode_time_filtering_allowed()
+        {
+            bail!(
+                "Processors {:?} filter instructions at decode time, but decode_time_filtering, \
+                symbolic_coverage, or an execution trace attribute was changed after they were \
+                added so that every instruction must be instrumented. Set these attributes \
+                before the fuzzing loop starts and before calling add_trace_processor.",
+                self.decode_time_filtered_processors
+            )
+        }
+
+        self.checked_decode_time_filtering_config = Some(config);
+
+        Ok(())
+    }
+
     /// Return a reference to the saved "start processor" if there is one. There will be no
     /// "start processor" before a start harness (manual or magic) is executed.
     pub fn start_processor(&mut self) -> Option<&mut Architecture> {
@@ -739,16 +1121,31 @@ impl Tsffs {
             return Ok(());
         }
 
-        // Disable VMP if it is enabled
-        info!(self.as_conf_object(), "Disabling VMP");
-        if let Err(e) = run_command("disable-vmp") {
-            warn!(self.as_conf_object(), "Failed to disable VMP: {}", e);
+        self.check_decode_time_filtering()?;
+
+        // Disable VMP if it is enabled. Breakpoint coverage does not instrument instructions,
+        // so VMP can stay enabled
+        if !self.breakpoint_coverage {
+            info!(self.as_conf_object(), "Disabling VMP");
+            if let Err(e) = run_command("disable-vmp") {
+                warn!(self.as_conf_object(), "Failed to disable VMP: {}", e);
+            }
         }
 
         // Initialize the source cache for source/line lookups
         info!(self.as_conf_object(), "Initializing source cache");
         self.source_file_cache = SourceCache::new(&self.debuginfo_source_directory)?;
 
+        if self.coverage_attribution && self.coverage_attribution_path.is_file() {
+            info!(
+                self.as_conf_object(),
+                "Loading coverage attribution from {}",
+                self.coverage_attribution_path.display()
+            );
+            self.coverage_attribution_index =
+                CoverageAttribution::load(&self.coverage_attribution_path)?;
+        }
+
         self.log(LogMessage::startup())?;
 
         #[cfg(simics_version = "7")]
@@ -815,6 +1212,14 @@ impl Tsffs {
                 .map_err(|_| anyhow!("Micro checkpoint index already set"))?;
         }
 
+        self.load_symbol_files()?;
+        self.resolve_cmplog_routines();
+
+        // Breakpoints are armed after the snapshot is taken so they are not part of it
+        if self.breakpoint_coverage {
+            self.arm_breakpoint_coverage()?;
+        }
+
         Ok(())
     }
 
@@ -832,6 +1237,10 @@ impl Tsffs {
             discard_future()?;
         }
 
+        if self.breakpoint_coverage {
+            self.on_breakpoint_coverage_restore()?;
+        }
+
         Ok(())
     }
 
@@ -912,6 +1321,8 @@ impl Tsffs {
 
         start_processor.write_start(testcase.testcase.bytes(), &start_info)?;
 
+        self.trigger_smi()?;
+
         Ok(())
     }
 
@@ -1009,6 +1420,24 @@ impl Tsffs {
         Ok(())
     }
 
+    /// Append new attributions to the coverage attribution file
+    pub fn save_coverage_attribution(&mut self, records: &[AttributionRecord]) -> Result<()> {
+        if let Some(parent) = self.coverage_attribution_path.parent() {
+            if !parent.is_dir() {
+                create_dir_all(parent)?;
+            }
+        }
+
+        debug!(
+            self.as_conf_object(),
+            "Saving {} coverage attributions to {}",
+            records.len(),
+            self.coverage_attribution_path.display()
+        );
+
+        CoverageAttribution::append(&self.coverage_attribution_path, records)
+    }
+
     /// Save the current execution trace to a file
     pub fn save_execution_trace(&mut self) -> Result<()> {
         let mut hasher = DefaultHasher::new();
@@ -3,19 +3,35 @@
 
 //! Logging
 
-use crate::{fuzzer::messages::FuzzerMessage, Tsffs};
+use crate::{fuzzer::messages::FuzzerMessage, tracer::AttributionKind, Tsffs};
 use anyhow::{anyhow, Result};
 use chrono::Utc;
+use libafl::{inputs::Input, prelude::BytesInput};
 use serde::Serialize;
 use simics::{info, AsConfObject};
 use std::{fs::OpenOptions, io::Write, time::SystemTime};
 
 #[derive(Clone, Debug, Serialize)]
 pub(crate) struct LogMessageEdge {
+    pub source: u64,
     pub pc: u64,
     pub afl_idx: u64,
 }
 
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LogMessageCoverageMap {
+    /// The size of the coverage map
+    pub size: usize,
+    /// The number of unique edges seen
+    pub edges: usize,
+    /// The number of coverage map entries assigned to at least one edge
+    pub used: usize,
+    /// The number of edges which share a coverage map entry with another edge
+    pub collisions: usize,
+    /// The fraction of coverage map entries assigned to at least one edge
+    pub density: f64,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub(crate) struct LogMessageInteresting {
     pub indices: Vec<usize>,
@@ -52,6 +68,7 @@ pub(crate) enum LogMessage {
         solutions: usize,
         timeouts: usize,
         edges: usize,
+        coverage_map: LogMessageCoverageMap,
         timestamp: String,
     },
 }
@@ -90,12 +107,14 @@ impl LogMessage {
         solutions: usize,
         timeouts: usize,
         edges: usize,
+        coverage_map: LogMessageCoverageMap,
     ) -> Self {
         Self::Heartbeat {
             iterations,
             solutions,
             timeouts,
             edges,
+            coverage_map,
             timestamp: Utc::now().to_rfc3339(),
         }
     }
@@ -121,17 +140,20 @@ impl Tsffs {
                         "Interesting input for AFL indices {indices:?} with input {input:?}"
                     );
 
+                    self.attribute_coverage(input, AttributionKind::Corpus)?;
+
                     if !self.edges_seen_since_last.is_empty() {
                         let mut edges = self
                             .edges_seen_since_last
                             .iter()
-                            .map(|(p, a)| LogMessageEdge {
+                            .map(|((s, p), a)| LogMessageEdge {
+                                source: *s,
                                 pc: *p,
                                 afl_idx: *a,
                             })
                             .collect::<Vec<_>>();
 
-                        edges.sort_by(|e1, e2| e1.pc.cmp(&e2.pc));
+                        edges.sort_by_key(|e| (e.pc, e.source));
 
                         info!(
                             self.as_conf_object(),
@@ -156,6 +178,10 @@ impl Tsffs {
                     if self.symbolic_coverage {
                         self.save_symbolic_coverage()?;
                     }
+
+                    if self.address_coverage_report {
+                        self.save_address_coverage_report()?;
+                    }
                 }
                 FuzzerMessage::Crash { indices, input } => {
                     info!(
@@ -163,17 +189,20 @@ impl Tsffs {
                         "Solution input for AFL indices {indices:?} with input {input:?}"
                     );
 
+                    self.attribute_coverage(input, AttributionKind::Solution)?;
+
                     if !self.edges_seen_since_last.is_empty() {
                         let mut edges = self
                             .edges_seen_since_last
                             .iter()
-                            .map(|(p, a)| LogMessageEdge {
+                            .map(|((s, p), a)| LogMessageEdge {
+                                source: *s,
                                 pc: *p,
                                 afl_idx: *a,
                             })
                             .collect::<Vec<_>>();
 
-                        edges.sort_by(|e1, e2| e1.pc.cmp(&e2.pc));
+                        edges.sort_by_key(|e| (e.pc, e.source));
 
                         self.log(LogMessage::Solution {
                             timestamp: Utc::now().to_rfc3339(),
@@ -192,6 +221,10 @@ impl Tsffs {
                     if self.symbolic_coverage {
                         self.save_symbolic_coverage()?;
                     }
+
+                    if self.address_coverage_report {
+                        self.save_address_coverage_report()?;
+                    }
                 }
                 FuzzerMessage::Timeout { indices, input } => {
                     info!(
@@ -199,17 +232,20 @@ impl Tsffs {
                         "Timeout input for AFL indices {indices:?} with input {input:?}"
                     );
 
+                    self.attribute_coverage(input, AttributionKind::Timeout)?;
+
                     if !self.edges_seen_since_last.is_empty() {
                         let mut edges = self
                             .edges_seen_since_last
                             .iter()
-                            .map(|(p, a)| LogMessageEdge {
+                            .map(|((s, p), a)| LogMessageEdge {
+                                source: *s,
                                 pc: *p,
                                 afl_idx: *a,
                             })
                             .collect::<Vec<_>>();
 
-                        edges.sort_by(|e1, e2| e1.pc.cmp(&e2.pc));
+                        edges.sort_by_key(|e| (e.pc, e.source));
 
                         self.log(LogMessage::Timeout {
                             timestamp: Utc::now().to_rfc3339(),
@@ -228,6 +264,10 @@ impl Tsffs {
                     if self.symbolic_coverage {
                         self.save_symbolic_coverage()?;
                     }
+
+                    if self.address_coverage_report {
+                        self.save_address_coverage_report()?;
+                    }
                 }
             }
 
@@ -243,6 +283,7 @@ impl Tsffs {
                     self.solutions,
                     self.timeouts,
                     self.edges_seen.len(),
+                    self.coverage_map_stats(),
                 ))?;
 
                 // Set the last heartbeat time
@@ -253,6 +294,25 @@ impl Tsffs {
         Ok(())
     }
 
+    /// Attribute the coverage reached by the testcase `input` which is not yet attributed to
+    /// any input to the corpus or solution name generated for `input`, and append any new
+    /// attributions to the attribution file
+    fn attribute_coverage(&mut self, input: &[u8], kind: AttributionKind) -> Result<()> {
+        if !self.coverage_attribution {
+            return Ok(());
+        }
+
+        let name = BytesInput::new(input.to_vec()).generate_name(0);
+
+        let records = self.coverage_attribution_index.attribute(&name, kind);
+
+        if !records.is_empty() {
+            self.save_coverage_attribution(&records)?;
+        }
+
+        Ok(())
+    }
+
     pub fn log<I>(&mut self, item: I) -> Result<()>
     where
         I: Serialize,
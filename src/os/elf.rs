@@ -0,0 +1,217 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Symbols of ELF executables, libraries and kernel modules, for targets without OS awareness
+
+use anyhow::{anyhow, Error, Result};
+use goblin::elf::{
+    header::{ET_DYN, ET_REL},
+    program_header::{PF_X, PT_LOAD},
+    sym::STT_FUNC,
+    Elf,
+};
+use intervaltree::IntervalTree;
+use simics::{lookup_file, AttrValue, AttrValueType};
+use std::{
+    fmt::Display,
+    fs::read,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::tracer::filter::parse_address;
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+/// An ELF file to load symbols from, parsed from the form `path` or `path@0x1000`. The address
+/// is where the file is loaded: the address of virtual address 0 for position independent
+/// executables and shared libraries, or the address of the `.text` section for kernel modules.
+/// Executables which are not position independent are loaded at the addresses in the file.
+pub(crate) struct SymbolFile {
+    pub path: PathBuf,
+    pub base: Option<u64>,
+}
+
+impl FromStr for SymbolFile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        let (path, base) = match s.rsplit_once('@') {
+            Some((path, base)) => (path, Some(parse_address(base)?)),
+            None => (s, None),
+        };
+
+        if path.is_empty() {
+            return Err(anyhow!(
+                "Invalid symbol file {s}. Expected <path>[@<address>]"
+            ));
+        }
+
+        Ok(Self {
+            path: PathBuf::from(path),
+            base,
+        })
+    }
+}
+
+impl Display for SymbolFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+
+        if let Some(base) = self.base {
+            write!(f, "@{base:#x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<AttrValue> for SymbolFile {
+    type Error = Error;
+
+    fn try_from(value: AttrValue) -> Result<Self> {
+        String::try_from(value)?.parse()
+    }
+}
+
+impl From<SymbolFile> for AttrValueType {
+    fn from(value: SymbolFile) -> Self {
+        value.to_string().into()
+    }
+}
+
+#[derive(Debug)]
+/// The code and function symbols of a loaded ELF file
+pub(crate) struct ElfModule {
+    /// The file name of the module
+    pub name: String,
+    /// The virtual address range of the executable code of the module
+    pub range: Range<u64>,
+    /// The function symbols of the module by virtual address range
+    pub symbols: IntervalTree<u64, String>,
+}
+
+impl ElfModule {
+    /// Load the function symbols of an ELF file, resolving `%simics%` paths
+    pub fn load(file: &SymbolFile) -> Result<Self> {
+        let path = if file.path.starts_with("%simics%") {
+            lookup_file(&file.path.to_string_lossy())?
+        } else {
+            file.path.clone()
+        };
+
+        let contents = read(&path)
+            .map_err(|e| anyhow!("Failed to read symbol file {}: {e}", path.display()))?;
+
+        Self::parse(&path, &contents, file.base)
+    }
+
+    /// Parse the function symbols of an ELF file loaded at `base`
+    pub fn parse(path: &Path, contents: &[u8], base: Option<u64>) -> Result<Self> {
+        let elf = Elf::parse(contents)
+            .map_err(|e| anyhow!("Failed to parse ELF file {}: {e}", path.display()))?;
+
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+
+        let (bias, range, text_section) = if elf.header.e_type == ET_REL {
+            // Symbols of relocatable objects are relative to their section, and only the
+            // location of .text is known
+            let text = elf
+                .section_headers
+                .iter()
+                .position(|s| elf.shdr_strtab.get_at(s.sh_name) == Some(".text"))
+                .ok_or_else(|| anyhow!("No .text section in {}", path.display()))?;
+            let start = base.ok_or_else(|| {
+                anyhow!(
+                    "The address of the .text section of {} must be given",
+                    path.display()
+                )
+            })?;
+
+            (
+                start,
+                start..start + elf.section_headers[text].sh_size,
+                Some(text),
+            )
+        } else {
+            let bias = if elf.header.e_type == ET_DYN {
+                base.unwrap_or_default()
+            } else {
+                0
+            };
+
+            let code = elf
+                .program_headers
+                .iter()
+                .filter(|p| p.p_type == PT_LOAD && p.p_flags & PF_X != 0)
+                .map(|p| p.p_vaddr..p.p_vaddr + p.p_memsz)
+                .collect::<Vec<_>>();
+
+            let start = code.iter().map(|r| r.start).min().unwrap_or_default();
+            let end = code.iter().map(|r| r.end).max().unwrap_or_default();
+
+            (bias, start + bias..end + bias, None)
+        };
+
+        let symbols = elf
+            .syms
+            .iter()
+            .filter(|s| s.st_type() == STT_FUNC && s.st_size > 0 && s.st_shndx != 0)
+            .filter(|s| text_section.is_none_or(|text| s.st_shndx == text))
+            .filter_map(|s| {
+                let name = elf.strtab.get_at(s.st_name)?;
+                let start = s.st_value + bias;
+
+                Some((start..start + s.st_size, name.to_string()))
+            })
+            .collect::<IntervalTree<_, _>>();
+
+        Ok(Self {
+            name,
+            range,
+            symbols,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolFile;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_symbol_file() {
+        assert_eq!(
+            "%simics%/test"
+                .parse::<SymbolFile>()
+                .expect("Failed to parse symbol file"),
+            SymbolFile {
+                path: PathBuf::from("%simics%/test"),
+                base: None
+            }
+        );
+        assert_eq!(
+            "/lib/test-mod.ko@0xffffffffc0000000"
+                .parse::<SymbolFile>()
+                .expect("Failed to parse symbol file"),
+            SymbolFile {
+                path: PathBuf::from("/lib/test-mod.ko"),
+                base: Some(0xffffffffc0000000)
+            }
+        );
+        assert_eq!(
+            "test@4096"
+                .parse::<SymbolFile>()
+                .expect("Failed to parse symbol file")
+                .to_string(),
+            "test@0x1000"
+        );
+        assert!("@0x1000".parse::<SymbolFile>().is_err());
+        assert!("test@nope".parse::<SymbolFile>().is_err());
+    }
+}
@@ -4,6 +4,8 @@ use std::{collections::HashMap, path::PathBuf};
 
 use lcov2::Records;
 
+pub mod elf;
+pub mod symbols;
 pub mod windows;
 
 #[derive(Debug)]
@@ -0,0 +1,166 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Lookup of modules and symbols from OS awareness and ELF symbol files
+
+use anyhow::Result;
+use simics::{debug, AsConfObject};
+use std::ops::Range;
+
+use super::elf::ElfModule;
+use crate::Tsffs;
+
+/// Whether the module name `module` (a file name) names the module at `path`, ignoring case
+fn module_matches(path: &str, module: &str) -> bool {
+    path.rsplit(['/', '\\'])
+        .next()
+        .is_some_and(|m| m.eq_ignore_ascii_case(module))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A function symbol in a module
+pub(crate) struct CodeSymbol {
+    /// The file name of the module the symbol is in
+    pub module: String,
+    /// The (possibly mangled) name of the symbol
+    pub name: String,
+    /// The virtual address range of the symbol
+    pub range: Range<u64>,
+}
+
+impl Tsffs {
+    /// Load the symbols of each file in `symbol_files`. Called when fuzzing starts.
+    pub fn load_symbol_files(&mut self) -> Result<()> {
+        self.elf_modules = self
+            .symbol_files
+            .iter()
+            .map(ElfModule::load)
+            .collect::<Result<Vec<_>>>()?;
+
+        self.elf_modules.iter().for_each(|m| {
+            debug!(
+                self.as_conf_object(),
+                "Loaded {} symbols of {} at {:#x}-{:#x}",
+                m.symbols.iter().count(),
+                m.name,
+                m.range.start,
+                m.range.end
+            );
+        });
+
+        Ok(())
+    }
+
+    /// Find the module containing a virtual address on a processor, returning its name and
+    /// address range. Modules found by OS awareness are searched before symbol files.
+    pub fn module_at(&self, processor_number: i32, address: u64) -> Option<(&str, Range<u64>)> {
+        self.windows_os_info
+            .modules
+            .get(&processor_number)
+            .and_then(|modules| {
+                modules
+                    .iter()
+                    .find(|m| (m.base..m.base + m.size).contains(&address))
+            })
+            .map(|m| (m.base_name.as_str(), m.base..m.base + m.size))
+            .or_else(|| {
+                self.elf_modules
+                    .iter()
+                    .find(|m| m.range.contains(&address))
+                    .map(|m| (m.name.as_str(), m.range.clone()))
+            })
+    }
+
+    /// Find the function symbol containing a virtual address on a processor
+    pub fn symbol_at(&self, processor_number: i32, address: u64) -> Option<CodeSymbol> {
+        self.windows_os_info
+            .symbol_lookup_trees
+            .get(&processor_number)
+            .and_then(|lookup_tree| lookup_tree.query_point(address).next())
+            .map(|s| CodeSymbol {
+                module: s
+                    .value
+                    .module
+                    .rsplit(['/', '\\'])
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                name: s.value.name.clone(),
+                range: s.range.clone(),
+            })
+            .or_else(|| {
+                self.elf_modules.iter().find_map(|m| {
+                    m.symbols.query_point(address).next().map(|s| CodeSymbol {
+                        module: m.name.clone(),
+                        name: s.value.clone(),
+                        range: s.range.clone(),
+                    })
+                })
+            })
+    }
+
+    /// Return the address ranges of the function symbols named `symbol` on a processor, in the
+    /// module named `module` or in any module
+    pub fn symbol_ranges(
+        &self,
+        processor_number: i32,
+        module: Option<&str>,
+        symbol: &str,
+    ) -> Vec<Range<u64>> {
+        self.windows_os_info
+            .symbol_lookup_trees
+            .get(&processor_number)
+            .into_iter()
+            .flat_map(|lookup_tree| lookup_tree.iter())
+            .filter(|s| {
+                s.value.name == symbol
+                    && module.is_none_or(|module| module_matches(&s.value.module, module))
+            })
+            .map(|s| s.range.clone())
+            .chain(
+                self.elf_modules
+                    .iter()
+                    .filter(|m| module.is_none_or(|module| module_matches(&m.name, module)))
+                    .flat_map(|m| m.symbols.iter())
+                    .filter(|s| s.value == symbol)
+                    .map(|s| s.range.clone()),
+            )
+            .collect()
+    }
+
+    /// Return the address ranges of the function symbols in the module named `module` on a
+    /// processor
+    pub fn module_symbol_ranges(&self, processor_number: i32, module: &str) -> Vec<Range<u64>> {
+        let windows_module = self
+            .windows_os_info
+            .modules
+            .get(&processor_number)
+            .and_then(|modules| {
+                modules
+                    .iter()
+                    .find(|m| m.base_name.eq_ignore_ascii_case(module))
+            });
+
+        windows_module
+            .and_then(|module| {
+                self.windows_os_info
+                    .symbol_lookup_trees
+                    .get(&processor_number)
+                    .map(|lookup_tree| {
+                        lookup_tree
+                            .query(module.base..module.base + module.size)
+                            .map(|s| s.range.clone())
+                            .collect::<Vec<_>>()
+                    })
+            })
+            .into_iter()
+            .flatten()
+            .chain(
+                self.elf_modules
+                    .iter()
+                    .filter(|m| module_matches(&m.name, module))
+                    .flat_map(|m| m.symbols.iter().map(|s| s.range.clone())),
+            )
+            .collect()
+    }
+}
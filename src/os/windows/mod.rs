@@ -280,6 +280,12 @@ impl Tsffs {
             )?;
 
             self.cr3_cache.insert(processor_nr, value);
+            self.resolve_cmplog_routines();
+
+            // Arm breakpoints on blocks in newly loaded modules
+            if self.breakpoint_coverage && self.have_initial_snapshot() {
+                self.arm_breakpoint_coverage()?;
+            }
         }
 
         Ok(())
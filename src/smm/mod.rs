@@ -0,0 +1,373 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzing of System Management Mode (SMM) handlers in x86 firmware. Testcases are passed to
+//! the handler under test in an SMM communication buffer and each iteration is started by
+//! triggering a software SMI through the chipset's APM control port.
+
+use anyhow::{anyhow, bail, Error, Result};
+use simics::{
+    api::{get_attribute, get_interface, write_byte, Access, ConfObject, ProcessorInfoV2Interface},
+    debug, get_processor_number, info, object_is_processor, AsConfObject, AttrValue, AttrValueType,
+};
+use std::{collections::hash_map::Entry, fmt::Display, ops::Range, str::FromStr};
+
+use crate::{
+    arch::ArchitectureOperations,
+    state::{SolutionKind, StopReason},
+    tracer::filter::parse_range,
+    Tsffs,
+};
+
+/// The size of the `HeaderGuid` field at the start of an `EFI_SMM_COMMUNICATE_HEADER`
+const GUID_SIZE: u64 = 16;
+/// The size of the pages SMM code is translated in
+const PAGE_SIZE: u64 = 0x1000;
+/// The phase the SMM entry and exit HAPs are triggered with once the processor state has
+/// been switched. Each transition triggers them with phase 0 before the switch and phase 1
+/// after it.
+const SMM_TRANSITION_DONE: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An `EFI_GUID`, parsed from the registry format `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`
+pub(crate) struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+impl Guid {
+    /// The bytes of the GUID as laid out in memory, where the first three fields are
+    /// little-endian
+    pub fn to_bytes(self) -> [u8; GUID_SIZE as usize] {
+        let mut bytes = [0; GUID_SIZE as usize];
+        bytes[0..4].copy_from_slice(&self.data1.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.data2.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.data3.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.data4);
+        bytes
+    }
+}
+
+impl FromStr for Guid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().trim_start_matches('{').trim_end_matches('}');
+        let fields = s.split('-').collect::<Vec<_>>();
+
+        let [data1, data2, data3, data4, data5] = fields.as_slice() else {
+            bail!("Invalid GUID {s}. Expected xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx");
+        };
+
+        if data1.len() != 8
+            || data2.len() != 4
+            || data3.len() != 4
+            || data4.len() != 4
+            || data5.len() != 12
+            || !fields
+                .iter()
+                .all(|field| field.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            bail!("Invalid GUID {s}. Expected xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx");
+        }
+
+        let tail = u64::from_str_radix(&format!("{data4}{data5}"), 16)
+            .map_err(|e| anyhow!("Invalid GUID {s}: {e}"))?;
+
+        Ok(Self {
+            data1: u32::from_str_radix(data1, 16).map_err(|e| anyhow!("Invalid GUID {s}: {e}"))?,
+            data2: u16::from_str_radix(data2, 16).map_err(|e| anyhow!("Invalid GUID {s}: {e}"))?,
+            data3: u16::from_str_radix(data3, 16).map_err(|e| anyhow!("Invalid GUID {s}: {e}"))?,
+            data4: tail.to_be_bytes(),
+        })
+    }
+}
+
+impl Display for Guid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            self.data1, self.data2, self.data3, self.data4[0], self.data4[1]
+        )?;
+
+        self.data4[2..]
+            .iter()
+            .try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The SMM communication buffer testcases are written to, in the layout of an
+/// `EFI_SMM_COMMUNICATE_HEADER`: the GUID of the handler, the `UINTN` length of the message,
+/// and the message, which is the testcase
+pub(crate) struct SmmCommunication {
+    /// The physical address of the communication buffer
+    pub address: u64,
+    /// The size of the `MessageLength` field, which is the pointer width of the processor
+    pub length_size: u64,
+    /// The command byte written to the APM control port to trigger the SMI
+    pub apm_command: u8,
+}
+
+impl SmmCommunication {
+    /// The physical address of the `MessageLength` field, which the size of each testcase is
+    /// written to
+    pub fn length_address(&self) -> u64 {
+        self.address + GUID_SIZE
+    }
+
+    /// The physical address of the `Data` field, which each testcase is written to
+    pub fn data_address(&self) -> u64 {
+        self.address + GUID_SIZE + self.length_size
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A range of physical memory containing SMRAM, parsed from the form `0x7f000000-0x80000000`
+/// (the end is exclusive)
+pub(crate) struct SmramRange(Range<u64>);
+
+impl SmramRange {
+    /// Whether a physical address is in the range
+    pub fn contains(&self, address: u64) -> bool {
+        self.0.contains(&address)
+    }
+}
+
+impl FromStr for SmramRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self(parse_range(s)?))
+    }
+}
+
+impl Display for SmramRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#x}-{:#x}", self.0.start, self.0.end)
+    }
+}
+
+impl TryFrom<AttrValue> for SmramRange {
+    type Error = Error;
+
+    fn try_from(value: AttrValue) -> Result<Self> {
+        String::try_from(value)?.parse()
+    }
+}
+
+impl From<SmramRange> for AttrValueType {
+    fn from(value: SmramRange) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl Tsffs {
+    /// Set up the communication buffer an SMM handler is fuzzed through, and write the header
+    /// GUID so it is part of the initial snapshot. The message length and message are written
+    /// with each testcase.
+    pub fn set_smm_communication(
+        &mut self,
+        cpu: *mut ConfObject,
+        address: u64,
+        guid: Guid,
+        apm_command: u8,
+    ) -> Result<SmmCommunication> {
+        let mut processor_info_v2: ProcessorInfoV2Interface = get_interface(cpu)?;
+        let length_size = processor_info_v2.get_logical_address_width()? as u64 / u8::BITS as u64;
+        let physical_memory = processor_info_v2.get_physical_memory()?;
+
+        guid.to_bytes()
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, b)| write_byte(physical_memory, address + i as u64, *b))?;
+
+        let communication = SmmCommunication {
+            address,
+            length_size,
+            apm_command,
+        };
+
+        debug!(
+            self.as_conf_object(),
+            "SMM communication buffer for {guid}: {communication:?}"
+        );
+
+        self.smm_communication = Some(communication.clone());
+
+        Ok(communication)
+    }
+
+    /// Trigger a software SMI by writing the APM control port, after the testcase has been
+    /// written to the communication buffer
+    pub fn trigger_smi(&mut self) -> Result<()> {
+        let Some(command) = self.smm_communication.as_ref().map(|c| c.apm_command) else {
+            return Ok(());
+        };
+
+        self.smm_processors.clear();
+        self.smm_translations.clear();
+
+        let cpu = self
+            .start_processor()
+            .ok_or_else(|| anyhow!("No start processor"))?
+            .cpu();
+        let port_space: *mut ConfObject = get_attribute(cpu, "port_space")?.try_into()?;
+
+        write_byte(port_space, self.smm_apm_port, command)?;
+
+        Ok(())
+    }
+
+    /// Called on SMM entry HAP. Begin tracing the processor once it is in SMM if an SMM
+    /// handler is being fuzzed
+    pub fn on_smm_enter(&mut self, obj: *mut ConfObject, phase: i32) -> Result<()> {
+        if phase == SMM_TRANSITION_DONE
+            && self.smm_communication.is_some()
+            && self.coverage_enabled
+            && object_is_processor(obj)?
+        {
+            self.smm_processors.insert(get_processor_number(obj)?);
+        }
+
+        Ok(())
+    }
+
+    /// Called on SMM exit HAP. Stop tracing the processor once it has left SMM, and stop the
+    /// iteration once every processor has executed RSM
+    pub fn on_smm_leave(&mut self, obj: *mut ConfObject, phase: i32) -> Result<()> {
+        if phase != SMM_TRANSITION_DONE
+            || self.smm_communication.is_none()
+            || !object_is_processor(obj)?
+        {
+            return Ok(());
+        }
+
+        if self.smm_processors.remove(&get_processor_number(obj)?)
+            && self.smm_processors.is_empty()
+            && self.have_initial_snapshot()
+        {
+            self.stop_simulation(StopReason::SmmExit)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether code executed by a processor is traced. When an SMM handler is being fuzzed,
+    /// only code executed in SMM is traced.
+    pub fn smm_traced(&self, processor_number: i32) -> bool {
+        self.smm_communication.is_none() || self.smm_processors.contains(&processor_number)
+    }
+
+    /// Check that the target of an edge executed in SMM is in SMRAM, and stop with a solution
+    /// if SMM code calls out of SMRAM. Only code fetched at edge targets is checked, so code
+    /// reached by falling through from SMRAM and data accesses are not.
+    pub fn check_smram_callout(&mut self, processor_number: i32, address: u64) -> Result<()> {
+        if self.smram_ranges.is_empty()
+            || self.smm_communication.is_none()
+            || !self.smm_processors.contains(&processor_number)
+        {
+            return Ok(());
+        }
+
+        // SMM page tables do not change during an SMI, so each page is translated once
+        let page = address & !(PAGE_SIZE - 1);
+        let physical_page = match self.smm_translations.entry((processor_number, page)) {
+            Entry::Occupied(e) => *e.get(),
+            Entry::Vacant(e) => *e.insert(
+                self.processors
+                    .get_mut(&processor_number)
+                    .and_then(|arch| {
+                        arch.processor_info_v2()
+                            .logical_to_physical(page, Access::Sim_Access_Execute)
+                            .ok()
+                    })
+                    .filter(|block| block.valid != 0)
+                    .map(|block| block.address)
+                    .unwrap_or(page),
+            ),
+        };
+        let physical_address = physical_page + (address - page);
+
+        if self
+            .smram_ranges
+            .iter()
+            .any(|r| r.contains(physical_address))
+        {
+            return Ok(());
+        }
+
+        info!(
+            self.as_conf_object(),
+            "SMM code on processor {processor_number} called out of SMRAM to {physical_address:#x}"
+        );
+
+        // Stop tracing so the RSM which may follow does not replace the solution
+        self.smm_processors.clear();
+
+        self.stop_simulation(StopReason::Solution {
+            kind: SolutionKind::SmramCallout {
+                address: physical_address,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Guid, SmramRange};
+
+    #[test]
+    fn test_parse_guid() {
+        let guid = "ed32d533-99e6-4209-9cc0-2d72cdd998a7"
+            .parse::<Guid>()
+            .expect("Failed to parse GUID");
+
+        assert_eq!(
+            guid.to_bytes(),
+            [
+                0x33, 0xd5, 0x32, 0xed, 0xe6, 0x99, 0x09, 0x42, 0x9c, 0xc0, 0x2d, 0x72, 0xcd, 0xd9,
+                0x98, 0xa7
+            ]
+        );
+        assert_eq!(guid.to_string(), "ed32d533-99e6-4209-9cc0-2d72cdd998a7");
+        assert_eq!(
+            " {ED32D533-99E6-4209-9CC0-2D72CDD998A7} "
+                .parse::<Guid>()
+                .expect("Failed to parse GUID"),
+            guid
+        );
+        assert!("".parse::<Guid>().is_err());
+        assert!("ed32d533-99e6-4209-9cc0".parse::<Guid>().is_err());
+        assert!("ed32d533-99e6-4209-9cc0-2d72cdd998a7-00"
+            .parse::<Guid>()
+            .is_err());
+        assert!("ed32d53-399e6-4209-9cc0-2d72cdd998a7"
+            .parse::<Guid>()
+            .is_err());
+        assert!("ed32d533-99e6-4209-9cc0-2d72cdd998ag"
+            .parse::<Guid>()
+            .is_err());
+        assert!("+d32d533-99e6-4209-9cc0-2d72cdd998a7"
+            .parse::<Guid>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_smram_range() {
+        let range = "0x7f000000-0x80000000"
+            .parse::<SmramRange>()
+            .expect("Failed to parse SMRAM range");
+
+        assert!(range.contains(0x7f000000));
+        assert!(range.contains(0x7fffffff));
+        assert!(!range.contains(0x80000000));
+        assert!(!range.contains(0x7effffff));
+        assert_eq!(range.to_string(), "0x7f000000-0x80000000");
+        assert!("0x80000000-0x7f000000".parse::<SmramRange>().is_err());
+        assert!("0x7f000000".parse::<SmramRange>().is_err());
+    }
+}
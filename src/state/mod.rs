@@ -4,6 +4,7 @@
 //! Definitions for tracking the state of the fuzzer
 
 use anyhow::{anyhow, Error, Result};
+use libafl_bolts::impl_serdeany;
 use serde::{Deserialize, Serialize};
 use serde_json::{from_str, to_string};
 use simics::api::ConfObject;
@@ -13,19 +14,34 @@ use std::{
     str::FromStr,
 };
 
-use crate::{magic::MagicNumber, ManualStartInfo};
+use crate::{arch::Fault, magic::MagicNumber, ManualStartInfo};
 
 pub(crate) mod policy;
 pub(crate) use policy::SnapshotRestorePolicy;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+/// The reason a testcase is a solution, recorded in the metadata of the solution
 pub(crate) enum SolutionKind {
     Timeout,
-    Exception { number: i64 },
-    Breakpoint { number: i64 },
+    Exception {
+        number: i64,
+        /// The architecture-level name of the exception, if the processor names it
+        name: Option<String>,
+    },
+    Breakpoint {
+        number: i64,
+    },
+    /// A fault which entered a fault handler of the guest, decoded by the processor
+    Fault(Fault),
+    /// A branch, call, or return executed in SMM reached code outside SMRAM (a callout)
+    SmramCallout {
+        address: u64,
+    },
     Manual,
 }
 
+impl_serdeany!(SolutionKind);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Definition of all the reasons the simulator could be stopped by the fuzzer. In general,
 /// callbacks in the fuzzer, for example [`Driver::on_magic_instruction`] may be called
@@ -44,6 +60,8 @@ pub(crate) enum StopReason {
         processor: *mut ConfObject,
     },
     ManualStop,
+    /// Every processor executing the SMM handler under test has executed RSM
+    SmmExit,
     Solution {
         kind: SolutionKind,
     },
@@ -0,0 +1,411 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Attribution of coverage to the input which first reached it
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fs::{read_to_string, OpenOptions},
+    io::Write,
+    mem::replace,
+    path::Path,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// The kind of testcase an attribution was made to
+pub(crate) enum AttributionKind {
+    /// The input was saved to the corpus directory
+    Corpus,
+    /// The input was saved to the solutions directory as a crash
+    Solution,
+    /// The input was saved to the solutions directory as a timeout
+    Timeout,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The input which first reached some piece of coverage
+pub(crate) struct Attribution {
+    /// The file name of the input in the corpus or solutions directory
+    pub input: String,
+    /// The kind of input
+    pub kind: AttributionKind,
+    /// The time the coverage was attributed to the input
+    pub timestamp: String,
+    /// The fuzzer iteration during which the coverage was attributed
+    pub iteration: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The input which first reached an edge
+pub(crate) struct EdgeAttribution {
+    /// The index of the edge in the AFL map
+    pub afl_idx: u64,
+    #[serde(flatten)]
+    pub first: Attribution,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A single attribution, saved as one line of the attribution file
+pub(crate) enum AttributionRecord {
+    Edge {
+        /// The coverage identifier of the source of the edge. Attributions saved before
+        /// sources were recorded load with a source of zero.
+        #[serde(default)]
+        source: u64,
+        /// The coverage identifier of the target of the edge
+        pc: u64,
+        #[serde(flatten)]
+        first: EdgeAttribution,
+    },
+    Line {
+        file: String,
+        line: usize,
+        #[serde(flatten)]
+        first: Attribution,
+    },
+    Function {
+        name: String,
+        #[serde(flatten)]
+        first: Attribution,
+    },
+}
+
+/// The number of finished testcases whose unattributed coverage is kept until the fuzzer
+/// reports whether they were saved
+const MAX_PENDING_TESTCASES: usize = 64;
+
+#[derive(Debug, Default)]
+/// Coverage not yet attributed which was reached by a single testcase
+struct TestcaseCoverage {
+    /// The fuzzer iteration the testcase ran in
+    iteration: usize,
+    /// Edge source and target to its AFL index
+    edges: HashMap<(u64, u64), u64>,
+    /// Source file and line number
+    lines: HashSet<(String, usize)>,
+    /// Function (symbol) name
+    functions: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+/// A persistent index from each piece of coverage to the input that first reached it
+pub(crate) struct CoverageAttribution {
+    /// Edge source and target to the input that first reached it
+    pub edges: BTreeMap<(u64, u64), EdgeAttribution>,
+    /// Source file to line number to the input that first reached it. Only populated when
+    /// symbolic coverage is enabled.
+    pub lines: BTreeMap<String, BTreeMap<usize, Attribution>>,
+    /// Function (symbol) name to the input that first reached it. Only populated when
+    /// symbolic coverage is enabled.
+    pub functions: BTreeMap<String, Attribution>,
+    /// The name of the current testcase and the coverage it has reached which is not yet
+    /// attributed
+    current: Option<(String, TestcaseCoverage)>,
+    /// Finished testcases by name, oldest first, with the coverage they reached which was
+    /// not yet attributed when they ran. Each is attributed if the fuzzer reports it was saved
+    /// and dropped otherwise, in which case its coverage can be attributed to a later input.
+    pending: VecDeque<(String, TestcaseCoverage)>,
+}
+
+impl CoverageAttribution {
+    /// Load a previously saved attribution index
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut attribution = Self::default();
+
+        read_to_string(path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .try_for_each(|l| {
+                attribution.insert(from_str(l)?);
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+        Ok(attribution)
+    }
+
+    /// Append new attributions to the saved attribution index
+    pub fn append<P>(path: P, records: &[AttributionRecord]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        for record in records {
+            writeln!(file, "{}", to_string(record)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add an attribution to the index, unless the coverage is already attributed
+    fn insert(&mut self, record: AttributionRecord) -> bool {
+        match record {
+            AttributionRecord::Edge { source, pc, first } => {
+                if self.edges.contains_key(&(source, pc)) {
+                    return false;
+                }
+
+                self.edges.insert((source, pc), first);
+            }
+            AttributionRecord::Line { file, line, first } => {
+                let lines = self.lines.entry(file).or_default();
+
+                if lines.contains_key(&line) {
+                    return false;
+                }
+
+                lines.insert(line, first);
+            }
+            AttributionRecord::Function { name, first } => {
+                if self.functions.contains_key(&name) {
+                    return false;
+                }
+
+                self.functions.insert(name, first);
+            }
+        }
+
+        true
+    }
+
+    /// Start recording the coverage reached by a new testcase. The coverage reached by the
+    /// previous testcase is kept until the fuzzer reports whether it was saved.
+    pub fn start_testcase(&mut self, input: &str, iteration: usize) {
+        if let Some(finished) = self.current.take() {
+            if self.pending.len() >= MAX_PENDING_TESTCASES {
+                self.pending.pop_front();
+            }
+
+            self.pending.push_back(finished);
+        }
+
+        self.current = Some((
+            input.to_string(),
+            TestcaseCoverage {
+                iteration,
+                ..Default::default()
+            },
+        ));
+    }
+
+    /// Record a hit of an edge from `source` to `pc`. The edge will be attributed to the
+    /// current testcase if it is saved and the edge has not already been attributed.
+    pub fn edge_hit(&mut self, source: u64, pc: u64, afl_idx: u64) {
+        if let Some((_, coverage)) = self.current.as_mut() {
+            if !self.edges.contains_key(&(source, pc)) {
+                coverage.edges.insert((source, pc), afl_idx);
+            }
+        }
+    }
+
+    /// Record a hit of a source line. The line will be attributed to the current testcase if
+    /// it is saved and the line has not already been attributed.
+    pub fn line_hit(&mut self, file: &Path, line: usize) {
+        let file = file.to_string_lossy();
+
+        if let Some((_, coverage)) = self.current.as_mut() {
+            if !self
+                .lines
+                .get(file.as_ref())
+                .is_some_and(|l| l.contains_key(&line))
+            {
+                coverage.lines.insert((file.to_string(), line));
+            }
+        }
+    }
+
+    /// Record a hit of a function. The function will be attributed to the current testcase if
+    /// it is saved and the function has not already been attributed.
+    pub fn function_hit(&mut self, name: &str) {
+        if let Some((_, coverage)) = self.current.as_mut() {
+            if !self.functions.contains_key(name) {
+                coverage.functions.insert(name.to_string());
+            }
+        }
+    }
+
+    /// Attribute all edges, lines, and functions reached by the testcase with the given input
+    /// name which have not yet been attributed to that input. Returns the new attributions,
+    /// which are empty if the testcase is not known.
+    pub fn attribute(&mut self, input: &str, kind: AttributionKind) -> Vec<AttributionRecord> {
+        let coverage = if let Some(position) = self.pending.iter().position(|(n, _)| n == input) {
+            self.pending.remove(position).map(|(_, c)| c)
+        } else if self.current.as_ref().is_some_and(|(n, _)| n == input) {
+            self.current.as_mut().map(|(_, c)| {
+                let iteration = c.iteration;
+                replace(
+                    c,
+                    TestcaseCoverage {
+                        iteration,
+                        ..Default::default()
+                    },
+                )
+            })
+        } else {
+            None
+        };
+
+        let Some(coverage) = coverage else {
+            return Vec::new();
+        };
+
+        let first = Attribution {
+            input: input.to_string(),
+            kind,
+            timestamp: Utc::now().to_rfc3339(),
+            iteration: coverage.iteration,
+        };
+
+        let records = coverage
+            .edges
+            .into_iter()
+            .map(|((source, pc), afl_idx)| AttributionRecord::Edge {
+                source,
+                pc,
+                first: EdgeAttribution {
+                    afl_idx,
+                    first: first.clone(),
+                },
+            })
+            .chain(
+                coverage
+                    .lines
+                    .into_iter()
+                    .map(|(file, line)| AttributionRecord::Line {
+                        file,
+                        line,
+                        first: first.clone(),
+                    }),
+            )
+            .chain(
+                coverage
+                    .functions
+                    .into_iter()
+                    .map(|name| AttributionRecord::Function {
+                        name,
+                        first: first.clone(),
+                    }),
+            )
+            .collect::<Vec<_>>();
+
+        records
+            .into_iter()
+            .filter(|r| self.insert(r.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttributionKind, AttributionRecord, CoverageAttribution};
+
+    fn edges(records: &[AttributionRecord]) -> Vec<(u64, u64)> {
+        let mut edges = records
+            .iter()
+            .filter_map(|r| match r {
+                AttributionRecord::Edge { source, pc, .. } => Some((*source, *pc)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        edges.sort();
+        edges
+    }
+
+    #[test]
+    fn test_attribute_saved_testcase_after_next_starts() {
+        let mut attribution = CoverageAttribution::default();
+
+        attribution.start_testcase("a", 1);
+        attribution.edge_hit(0x100, 0x1000, 1);
+        attribution.edge_hit(0x100, 0x2000, 2);
+        attribution.start_testcase("b", 2);
+        attribution.edge_hit(0x100, 0x1000, 1);
+
+        let records = attribution.attribute("a", AttributionKind::Corpus);
+        assert_eq!(edges(&records), vec![(0x100, 0x1000), (0x100, 0x2000)]);
+        assert!(records.iter().all(|r| match r {
+            AttributionRecord::Edge { first, .. } =>
+                first.first.input == "a" && first.first.iteration == 1,
+            _ => false,
+        }));
+
+        // Already attributed to the first testcase
+        assert!(attribution
+            .attribute("b", AttributionKind::Corpus)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unsaved_testcase_coverage_attributed_later() {
+        let mut attribution = CoverageAttribution::default();
+
+        attribution.start_testcase("a", 1);
+        attribution.edge_hit(0x100, 0x1000, 1);
+        attribution.start_testcase("b", 2);
+        attribution.edge_hit(0x100, 0x1000, 1);
+        attribution.edge_hit(0x100, 0x3000, 3);
+        attribution.start_testcase("c", 3);
+
+        // "a" was never saved, so its edge is attributed to "b"
+        let records = attribution.attribute("b", AttributionKind::Solution);
+        assert_eq!(edges(&records), vec![(0x100, 0x1000), (0x100, 0x3000)]);
+        assert!(attribution
+            .attribute("a", AttributionKind::Corpus)
+            .is_empty());
+        assert!(attribution
+            .attribute("unknown", AttributionKind::Corpus)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_attribute_current_testcase() {
+        let mut attribution = CoverageAttribution::default();
+
+        attribution.start_testcase("a", 7);
+        attribution.function_hit("main");
+        attribution.line_hit("test.c".as_ref(), 10);
+
+        let records = attribution.attribute("a", AttributionKind::Timeout);
+        assert_eq!(records.len(), 2);
+        assert!(attribution.functions.contains_key("main"));
+        assert_eq!(attribution.lines["test.c"][&10].iteration, 7);
+    }
+
+    #[test]
+    fn test_attribute_edges_to_same_target() {
+        let mut attribution = CoverageAttribution::default();
+
+        attribution.start_testcase("a", 1);
+        attribution.edge_hit(0x100, 0x1000, 1);
+        attribution.start_testcase("b", 2);
+        attribution.edge_hit(0x100, 0x1000, 1);
+        // A new edge to a target already reached from another source
+        attribution.edge_hit(0x200, 0x1000, 2);
+
+        assert_eq!(
+            edges(&attribution.attribute("a", AttributionKind::Corpus)),
+            vec![(0x100, 0x1000)]
+        );
+        assert_eq!(
+            edges(&attribution.attribute("b", AttributionKind::Corpus)),
+            vec![(0x200, 0x1000)]
+        );
+    }
+
+    #[test]
+    fn test_load_edge_without_source() {
+        let record = serde_json::from_str::<AttributionRecord>(
+            r#"{"Edge":{"pc":4096,"afl_idx":1,"input":"a","kind":"Corpus","timestamp":"","iteration":1}}"#,
+        )
+        .expect("Failed to parse attribution");
+
+        assert_eq!(edges(&[record]), vec![(0, 0x1000)]);
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hit-once block coverage using temporary execution breakpoints, which does not require
+//! instrumenting every instruction and allows VMP to remain enabled
+
+use anyhow::{anyhow, Result};
+use libafl_bolts::{AsMutSlice, AsSlice};
+use simics::{
+    api::{
+        breakpoint, delete_breakpoint, disable_breakpoint, enable_breakpoint, get_attribute,
+        Access, AsConfObject, BreakpointFlag, BreakpointKind, ConfObject,
+    },
+    debug, info, warn, BreakpointId,
+};
+use std::{collections::BTreeSet, ops::Range};
+
+use super::{
+    hash_index,
+    report::{read_code_bytes, MAX_INSTRUCTION_SIZE},
+    CoverageRange,
+};
+use crate::{
+    arch::{Architecture, ArchitectureOperations, PAGE_SIZE},
+    Tsffs,
+};
+
+/// The maximum number of blocks found by sweeping a single range
+const MAX_RANGE_BLOCKS: usize = 1 << 18;
+
+/// Linearly disassemble a range of code, adding the start address of each basic block to
+/// `blocks`. The start of the range and each instruction following a branch, call, or return
+/// begins a new block. Branch targets in the middle of a block are not found. Code is read a
+/// page at a time and unmapped pages are skipped. Sweeping stops at the first bytes which
+/// cannot be decoded, because the rest of the range is likely data, and their address is
+/// returned.
+fn sweep_blocks(
+    arch: &mut Architecture,
+    range: Range<u64>,
+    blocks: &mut BTreeSet<u64>,
+) -> Option<u64> {
+    let mut address = range.start;
+    let mut leader = true;
+    let mut found = 0;
+
+    while address < range.end && found < MAX_RANGE_BLOCKS {
+        let page_start = address;
+        let page_end = ((address & !(PAGE_SIZE - 1)) + PAGE_SIZE).min(range.end);
+
+        // Read past the end of the page so an instruction crossing into the next page decodes
+        let Ok(bytes) = read_code_bytes(
+            arch,
+            page_start,
+            (page_end - page_start) as usize + MAX_INSTRUCTION_SIZE,
+        ) else {
+            address = page_end;
+            leader = true;
+            continue;
+        };
+
+        while address < page_end && found < MAX_RANGE_BLOCKS {
+            let disassembler = arch.disassembler();
+
+            let Some(len) = bytes
+                .get((address - page_start) as usize..)
+                .and_then(|b| disassembler.disassemble(b).ok())
+                .and_then(|_| disassembler.last_len())
+                .filter(|l| *l > 0)
+            else {
+                return Some(address);
+            };
+
+            if leader && blocks.insert(address) {
+                found += 1;
+            }
+
+            leader = disassembler.last_was_control_flow()
+                || disassembler.last_was_call()
+                || disassembler.last_was_ret();
+            address += len as u64;
+        }
+    }
+
+    None
+}
+
+/// Whether a breakpoint exists. Disabling a breakpoint fails only if it does not exist.
+fn breakpoint_exists(breakpoint: BreakpointId) -> bool {
+    disable_breakpoint(breakpoint).is_ok() && enable_breakpoint(breakpoint).is_ok()
+}
+
+impl Tsffs {
+    /// Collect the start addresses of the blocks which breakpoint coverage is collected for on a
+    /// processor. Blocks are collected from `breakpoint_coverage_blocks`, and by sweeping each
+    /// virtual, module, and symbol range in `coverage_include_ranges`. Module ranges are swept
+    /// function by function using OS awareness or `symbol_files`, because sweeping a full
+    /// image would treat data as code.
+    fn collect_breakpoint_coverage_blocks(
+        &mut self,
+        processor_number: i32,
+    ) -> Result<BTreeSet<u64>> {
+        let mut blocks = self
+            .breakpoint_coverage_blocks
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        let mut ranges = Vec::new();
+
+        for range in self.coverage_include_ranges.iter() {
+            match range {
+                CoverageRange::Virtual(range) => ranges.push(range.clone()),
+                CoverageRange::Physical(_) => {
+                    warn!(
+                        self.as_conf_object(),
+                        "Physical range {range} is not supported for breakpoint coverage"
+                    );
+                }
+                CoverageRange::Module(module) => {
+                    ranges.extend(self.module_symbol_ranges(processor_number, module))
+                }
+                CoverageRange::Symbol { module, symbol } => {
+                    ranges.extend(self.symbol_ranges(processor_number, Some(module), symbol))
+                }
+            }
+        }
+
+        let arch = self
+            .processors
+            .get_mut(&processor_number)
+            .ok_or_else(|| anyhow!("No architecture for processor {processor_number}"))?;
+
+        let mut undecodable = Vec::new();
+
+        for range in ranges {
+            if let Some(address) = sweep_blocks(arch, range.clone(), &mut blocks) {
+                undecodable.push((range, address));
+            }
+        }
+
+        for (range, address) in undecodable {
+            debug!(
+                self.as_conf_object(),
+                "Stopped sweeping {:#x}-{:#x} at undecodable bytes at {address:#x}",
+                range.start,
+                range.end
+            );
+        }
+
+        Ok(blocks)
+    }
+
+    /// Group the tracked processors by the context their virtual breakpoints are placed in,
+    /// returning each context and the numbers of its processors in ascending order. Processors
+    /// sharing a context also share its breakpoints, so each context is armed once and its
+    /// blocks are recorded for its lowest numbered processor.
+    fn breakpoint_coverage_contexts(&self) -> Result<Vec<(*mut ConfObject, Vec<i32>)>> {
+        let mut contexts: Vec<(*mut ConfObject, Vec<i32>)> = Vec::new();
+        let mut processors = self.processors.iter().collect::<Vec<_>>();
+        processors.sort_by_key(|(n, _)| **n);
+
+        for (processor_number, arch) in processors {
+            let context = get_attribute(arch.cpu(), "current_context")?
+                .as_object()
+                .ok_or_else(|| anyhow!("Processor {processor_number} has no current context"))?;
+
+            match contexts.iter_mut().find(|(c, _)| *c == context) {
+                Some((_, members)) => members.push(*processor_number),
+                None => contexts.push((context, vec![*processor_number])),
+            }
+        }
+
+        Ok(contexts)
+    }
+
+    /// Place an execution breakpoint on each block start of each tracked processor which has
+    /// not already been hit or armed. This is called once the initial snapshot is taken, and
+    /// again when the set of loaded modules changes, so only newly discovered blocks are armed.
+    pub fn arm_breakpoint_coverage(&mut self) -> Result<()> {
+        let mut armed = 0;
+
+        for (context, members) in self.breakpoint_coverage_contexts()? {
+            let processor_number = members[0];
+            let mut blocks = BTreeSet::new();
+
+            for member in members {
+                blocks.extend(self.collect_breakpoint_coverage_blocks(member)?);
+            }
+
+            for block in blocks {
+                let key = (processor_number, block);
+
+                if self.breakpoint_coverage_hit.contains(&key)
+                    || self.breakpoint_coverage_armed.contains(&key)
+                {
+                    continue;
+                }
+
+                let id = breakpoint(
+                    context,
+                    BreakpointKind::Sim_Break_Virtual,
+                    Access::Sim_Access_Execute,
+                    block,
+                    1,
+                    BreakpointFlag::Sim_Breakpoint_Simulation
+                        | BreakpointFlag::Sim_Breakpoint_Private,
+                )?;
+
+                self.breakpoint_coverage_ids.insert(id, key);
+                self.breakpoint_coverage_armed.insert(key);
+                armed += 1;
+            }
+        }
+
+        if armed > 0 {
+            info!(
+                self.as_conf_object(),
+                "Armed {} coverage breakpoints ({} armed, {} hit)",
+                armed,
+                self.breakpoint_coverage_armed.len(),
+                self.breakpoint_coverage_hit.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Called after the initial snapshot is restored. Coverage breakpoints are created after
+    /// the snapshot is taken, so restoring it cannot re-create a breakpoint deleted when its
+    /// block was hit. It could only remove breakpoints which did not exist when it was taken,
+    /// so the first restore checks whether the armed breakpoints still exist. If they do not,
+    /// they are armed again after every restore.
+    pub fn on_breakpoint_coverage_restore(&mut self) -> Result<()> {
+        let kept = match self.breakpoint_coverage_kept_on_restore {
+            Some(kept) => kept,
+            // Nothing can be checked until a breakpoint is armed
+            None if self.breakpoint_coverage_ids.is_empty() => return Ok(()),
+            None => {
+                let kept = self
+                    .breakpoint_coverage_ids
+                    .keys()
+                    .all(|id| breakpoint_exists(*id));
+
+                if kept {
+                    info!(
+                        self.as_conf_object(),
+                        "Coverage breakpoints are kept when the snapshot is restored"
+                    );
+                } else {
+                    warn!(
+                        self.as_conf_object(),
+                        "Coverage breakpoints are removed when the snapshot is restored, so \
+                        unhit blocks are armed again after every restore"
+                    );
+                }
+
+                self.breakpoint_coverage_kept_on_restore = Some(kept);
+                kept
+            }
+        };
+
+        if !kept {
+            self.breakpoint_coverage_ids
+                .drain()
+                .filter(|(id, _)| breakpoint_exists(*id))
+                .try_for_each(|(id, _)| delete_breakpoint(id))?;
+            self.breakpoint_coverage_armed.clear();
+            self.arm_breakpoint_coverage()?;
+        }
+
+        Ok(())
+    }
+
+    /// Check whether a breakpoint is a coverage breakpoint and, if so, record a hit of its
+    /// block in the coverage map and delete the breakpoint. Returns whether the breakpoint was
+    /// a coverage breakpoint, in which case it should not be handled further.
+    pub fn on_breakpoint_coverage_hit(&mut self, breakpoint: BreakpointId) -> Result<bool> {
+        if !self.coverage_enabled {
+            // Leave the breakpoint armed until it is hit while fuzzing
+            return Ok(self.breakpoint_coverage_ids.contains_key(&breakpoint));
+        }
+
+        let Some(key) = self.breakpoint_coverage_ids.remove(&breakpoint) else {
+            return Ok(false);
+        };
+
+        let (processor_number, block) = key;
+
+        self.breakpoint_coverage_armed.remove(&key);
+        self.breakpoint_coverage_hit.insert(key);
+
+        let len = self
+            .coverage_map
+            .get()
+            .ok_or_else(|| {
+                anyhow!("Coverage map not initialized. This is a bug in the fuzzer or the target")
+            })?
+            .as_slice()
+            .len() as u64;
+        // Blocks are identified as edges from and to their own start address
+        let id = self.coverage_identifier(processor_number, block);
+        let afl_idx = self.coverage_map_index((id, id, 0), hash_index(id, len))?;
+        let coverage_map = self.coverage_map.get_mut().ok_or_else(|| {
+            anyhow!("Coverage map not initialized. This is a bug in the fuzzer or the target")
+        })?;
+        coverage_map.as_mut_slice()[afl_idx as usize] = 1;
+
+        if self.coverage_reporting && self.edges_seen.insert((id, id)) {
+            self.edges_seen_since_last.insert((id, id), afl_idx);
+        }
+
+        if self.coverage_attribution {
+            self.coverage_attribution_index.edge_hit(id, id, afl_idx);
+        }
+
+        if self.address_coverage_report {
+            *self.edge_hit_counts.entry(key).or_default() += 1;
+        }
+
+        debug!(
+            self.as_conf_object(),
+            "Coverage breakpoint {breakpoint} hit at {block:#x} on processor {processor_number}"
+        );
+
+        delete_breakpoint(breakpoint)?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Assignment of coverage map indices to edges and statistics about coverage map usage
+
+use anyhow::{anyhow, Result};
+use libafl_bolts::AsSlice;
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use crate::{log::LogMessageCoverageMap, Tsffs};
+
+/// The identity of an edge: the address of the control flow instruction at its source, the
+/// address of its target, and a context which distinguishes otherwise identical edges in the
+/// call-context and n-gram coverage modes
+pub(crate) type EdgeKey = (u64, u64, u64);
+
+#[derive(Debug, Default)]
+/// The coverage map index of each unique edge seen during the campaign
+pub(crate) struct EdgeIndices {
+    /// The index assigned to each edge
+    indices: HashMap<EdgeKey, u64>,
+    /// Whether each index in the coverage map has been assigned to at least one edge
+    used: Vec<bool>,
+    /// The number of indices assigned to at least one edge
+    used_count: usize,
+    /// The number of edges assigned an index already assigned to a different edge
+    collisions: usize,
+}
+
+impl EdgeIndices {
+    /// Return the coverage map index of an edge in a map with `capacity` entries, assigning
+    /// one if the edge has not been seen before. When `dense` is set, each new edge is
+    /// assigned the next unused index until every index is assigned, after which new edges
+    /// fall back to `hashed_index` and may collide. Otherwise, `hashed_index` is always used.
+    /// Every edge is tracked either way, so collisions are counted once per colliding edge.
+    pub fn index(&mut self, key: EdgeKey, capacity: usize, hashed_index: u64, dense: bool) -> u64 {
+        if let Some(index) = self.indices.get(&key) {
+            return if dense { *index } else { hashed_index };
+        }
+
+        if self.used.len() != capacity {
+            self.used.resize(capacity, false);
+        }
+
+        let index = if dense && self.indices.len() < capacity {
+            self.indices.len() as u64
+        } else {
+            hashed_index
+        };
+
+        if let Some(used) = self.used.get_mut(index as usize) {
+            if *used {
+                self.collisions += 1;
+            } else {
+                *used = true;
+                self.used_count += 1;
+            }
+        }
+
+        self.indices.insert(key, index);
+
+        index
+    }
+
+    /// The number of entries at the start of a map with `capacity` entries which are observed
+    /// by the fuzzer. For dense maps, this is the number of edges assigned an index so far,
+    /// up to a hard cap of `capacity`. Edges found after the cap is reached share entries
+    /// with earlier edges instead of growing the map.
+    pub fn observed_len(&self, capacity: usize, dense: bool) -> usize {
+        if dense {
+            self.indices.len().clamp(1, capacity)
+        } else {
+            capacity
+        }
+    }
+
+    /// Statistics about the usage of a map with `capacity` entries
+    pub fn stats(&self, capacity: usize) -> LogMessageCoverageMap {
+        LogMessageCoverageMap {
+            size: capacity,
+            edges: self.indices.len(),
+            used: self.used_count,
+            collisions: self.collisions,
+            density: if capacity == 0 {
+                0.0
+            } else {
+                self.used_count as f64 / capacity as f64
+            },
+        }
+    }
+}
+
+impl Tsffs {
+    /// Return the coverage map index for an edge, assigning one if the edge is new, and extend
+    /// the part of the map observed by the fuzzer to include it, up to the size of the map
+    pub fn coverage_map_index(&mut self, key: EdgeKey, hashed_index: u64) -> Result<u64> {
+        let capacity = self
+            .coverage_map
+            .get()
+            .ok_or_else(|| {
+                anyhow!("Coverage map not initialized. This is a bug in the fuzzer or the target")
+            })?
+            .as_slice()
+            .len();
+
+        let index = self
+            .edge_indices
+            .index(key, capacity, hashed_index, self.coverage_dense_edges);
+
+        if let Some(len) = self.coverage_map_len.get() {
+            len.store(
+                self.edge_indices
+                    .observed_len(capacity, self.coverage_dense_edges),
+                Ordering::Relaxed,
+            );
+        }
+
+        Ok(index)
+    }
+
+    /// Statistics about the usage of the coverage map
+    pub fn coverage_map_stats(&self) -> LogMessageCoverageMap {
+        self.edge_indices.stats(
+            self.coverage_map
+                .get()
+                .map(|m| m.as_slice().len())
+                .unwrap_or(self.coverage_map_size),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EdgeIndices;
+
+    #[test]
+    fn test_edge_indices_dense() {
+        let mut indices = EdgeIndices::default();
+
+        assert_eq!(indices.observed_len(4, true), 1);
+        assert_eq!(indices.index((1, 2, 0), 4, 3, true), 0);
+        assert_eq!(indices.index((1, 3, 0), 4, 3, true), 1);
+        // The same edge keeps its index, and a different context is a different edge
+        assert_eq!(indices.index((1, 2, 0), 4, 3, true), 0);
+        assert_eq!(indices.index((1, 2, 1), 4, 3, true), 2);
+        assert_eq!(indices.observed_len(4, true), 3);
+
+        let stats = indices.stats(4);
+        assert_eq!((stats.edges, stats.used, stats.collisions), (3, 3, 0));
+    }
+
+    #[test]
+    fn test_edge_indices_dense_full() {
+        let mut indices = EdgeIndices::default();
+
+        (0..4).for_each(|i| {
+            indices.index((i, i, 0), 4, 0, true);
+        });
+
+        // New edges fall back to their hashed index once every index is assigned
+        assert_eq!(indices.index((4, 4, 0), 4, 2, true), 2);
+        assert_eq!(indices.index((5, 5, 0), 4, 3, true), 3);
+        assert_eq!(indices.index((4, 4, 0), 4, 2, true), 2);
+        assert_eq!(indices.observed_len(4, true), 4);
+
+        let stats = indices.stats(4);
+        assert_eq!((stats.edges, stats.used, stats.collisions), (6, 4, 2));
+    }
+
+    #[test]
+    fn test_edge_indices_hashed() {
+        let mut indices = EdgeIndices::default();
+
+        assert_eq!(indices.index((1, 2, 0), 8, 5, false), 5);
+        assert_eq!(indices.index((1, 3, 0), 8, 5, false), 5);
+        assert_eq!(indices.index((1, 4, 0), 8, 6, false), 6);
+        // Hits of a known edge are not collisions
+        assert_eq!(indices.index((1, 3, 0), 8, 5, false), 5);
+        assert_eq!(indices.observed_len(8, false), 8);
+
+        let stats = indices.stats(8);
+        assert_eq!((stats.edges, stats.used, stats.collisions), (3, 2, 1));
+        assert_eq!(stats.density, 0.25);
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Address range filters restricting which code contributes to coverage and cmplog
+
+use anyhow::{anyhow, bail, Error, Result};
+use simics::{AttrValue, AttrValueType};
+use std::{fmt::Display, ops::Range, str::FromStr};
+
+use crate::Tsffs;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A range of code, specified either by address or by name when OS awareness or ELF symbol
+/// files are available.
+///
+/// Parsed from one of the following forms:
+///
+/// - `0x1000-0x2000`: A virtual address range (the end is exclusive)
+/// - `p:0x1000-0x2000`: A physical address range (the end is exclusive)
+/// - `module.sys`: All code in a module
+/// - `module.sys!Symbol`: All code in a symbol in a module
+pub(crate) enum CoverageRange {
+    Virtual(Range<u64>),
+    Physical(Range<u64>),
+    Module(String),
+    Symbol { module: String, symbol: String },
+}
+
+impl CoverageRange {
+    /// Whether any of the ranges requires a physical address to check
+    pub fn any_physical(ranges: &[Self]) -> bool {
+        ranges.iter().any(|r| matches!(r, Self::Physical(_)))
+    }
+}
+
+pub(crate) fn parse_address(s: &str) -> Result<u64> {
+    let s = s.trim();
+
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    }
+    .map_err(|e| anyhow!("Invalid address {s}: {e}"))
+}
+
+pub(crate) fn parse_range(s: &str) -> Result<Range<u64>> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Invalid range {s}. Expected <start>-<end>"))?;
+    let range = parse_address(start)?..parse_address(end)?;
+
+    if range.is_empty() {
+        bail!("Invalid range {s}. The end must be greater than the start");
+    }
+
+    Ok(range)
+}
+
+impl FromStr for CoverageRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            bail!("Empty coverage range");
+        }
+
+        if let Some(physical) = s.strip_prefix("p:") {
+            Ok(Self::Physical(parse_range(physical)?))
+        } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+            Ok(Self::Virtual(parse_range(s)?))
+        } else if let Some((module, symbol)) = s.split_once('!') {
+            Ok(Self::Symbol {
+                module: module.to_string(),
+                symbol: symbol.to_string(),
+            })
+        } else {
+            Ok(Self::Module(s.to_string()))
+        }
+    }
+}
+
+impl Display for CoverageRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Virtual(range) => write!(f, "{:#x}-{:#x}", range.start, range.end),
+            Self::Physical(range) => write!(f, "p:{:#x}-{:#x}", range.start, range.end),
+            Self::Module(module) => write!(f, "{module}"),
+            Self::Symbol { module, symbol } => write!(f, "{module}!{symbol}"),
+        }
+    }
+}
+
+impl TryFrom<AttrValue> for CoverageRange {
+    type Error = Error;
+
+    fn try_from(value: AttrValue) -> Result<Self> {
+        String::try_from(value)?.parse()
+    }
+}
+
+impl From<CoverageRange> for AttrValueType {
+    fn from(value: CoverageRange) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl Tsffs {
+    /// Check whether an address executed on a processor matches a coverage range
+    fn coverage_range_matches(
+        &self,
+        processor_number: i32,
+        range: &CoverageRange,
+        address: u64,
+        physical_address: Option<u64>,
+    ) -> bool {
+        match range {
+            CoverageRange::Virtual(range) => range.contains(&address),
+            CoverageRange::Physical(range) => {
+                physical_address.is_some_and(|physical| range.contains(&physical))
+            }
+            CoverageRange::Module(module) => self
+                .module_at(processor_number, address)
+                .is_some_and(|(name, _)| name.eq_ignore_ascii_case(module)),
+            CoverageRange::Symbol { module, symbol } => self
+                .symbol_at(processor_number, address)
+                .is_some_and(|s| s.name == *symbol && s.module.eq_ignore_ascii_case(module)),
+        }
+    }
+
+    /// Check whether an address executed on a processor should contribute to coverage,
+    /// cmplog, and coverage reporting according to the configured include and exclude ranges.
+    /// Addresses are included if no include ranges are configured or they match at least one
+    /// include range, and they do not match any exclude range. When an SMM handler is being
+    /// fuzzed, only addresses executed in SMM are included.
+    pub fn coverage_address_included(&mut self, processor_number: i32, address: u64) -> bool {
+        if !self.smm_traced(processor_number) {
+            return false;
+        }
+
+        if self.coverage_include_ranges.is_empty() && self.coverage_exclude_ranges.is_empty() {
+            return true;
+        }
+
+        let physical_address = if CoverageRange::any_physical(&self.coverage_include_ranges)
+            || CoverageRange::any_physical(&self.coverage_exclude_ranges)
+        {
+            self.physical_code_address(processor_number, address)
+        } else {
+            None
+        };
+
+        (self.coverage_include_ranges.is_empty()
+            || self.coverage_include_ranges.iter().any(|r| {
+                self.coverage_range_matches(processor_number, r, address, physical_address)
+            }))
+            && !self.coverage_exclude_ranges.iter().any(|r| {
+                self.coverage_range_matches(processor_number, r, address, physical_address)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CoverageRange;
+
+    #[test]
+    fn test_parse_coverage_range() {
+        assert_eq!(
+            "0x1000-0x2000"
+                .parse::<CoverageRange>()
+                .expect("Failed to parse coverage range"),
+            CoverageRange::Virtual(0x1000..0x2000)
+        );
+        assert_eq!(
+            " 4096-0X2000 "
+                .parse::<CoverageRange>()
+                .expect("Failed to parse coverage range"),
+            CoverageRange::Virtual(0x1000..0x2000)
+        );
+        assert_eq!(
+            "p:0x1000-0x2000"
+                .parse::<CoverageRange>()
+                .expect("Failed to parse coverage range"),
+            CoverageRange::Physical(0x1000..0x2000)
+        );
+        assert_eq!(
+            "target.sys"
+                .parse::<CoverageRange>()
+                .expect("Failed to parse coverage range"),
+            CoverageRange::Module("target.sys".to_string())
+        );
+        assert_eq!(
+            "target.sys!TimerDpc"
+                .parse::<CoverageRange>()
+                .expect("Failed to parse coverage range"),
+            CoverageRange::Symbol {
+                module: "target.sys".to_string(),
+                symbol: "TimerDpc".to_string()
+            }
+        );
+        assert!("".parse::<CoverageRange>().is_err());
+        assert!("0x2000-0x1000".parse::<CoverageRange>().is_err());
+        assert!("0x1000-0x1000".parse::<CoverageRange>().is_err());
+        assert!("0x1000".parse::<CoverageRange>().is_err());
+        assert!("p:0x1000-nope".parse::<CoverageRange>().is_err());
+    }
+
+    #[test]
+    fn test_display_coverage_range() {
+        for range in [
+            "0x1000-0x2000",
+            "p:0x1000-0x2000",
+            "target.sys",
+            "target.sys!TimerDpc",
+        ] {
+            assert_eq!(
+                range
+                    .parse::<CoverageRange>()
+                    .expect("Failed to parse coverage range")
+                    .to_string(),
+                range
+            );
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Identifiers for code locations which are stable when code is loaded at different addresses
+
+use anyhow::{anyhow, bail, Error, Result};
+use simics::{api::Access, AttrValue, AttrValueType};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Range,
+    str::FromStr,
+};
+
+use super::filter::parse_range;
+use crate::{
+    arch::{ArchitectureOperations, PAGE_SIZE},
+    Tsffs,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
+pub(crate) enum CoverageIdentifier {
+    /// Locations are identified by their virtual address
+    #[default]
+    Virtual,
+    /// Locations are identified by the module they are in and their offset into the module,
+    /// falling back to their virtual address outside of any known module
+    Module,
+    /// Locations are identified by their physical address, falling back to their virtual
+    /// address if it is not mapped
+    Physical,
+}
+
+impl CoverageIdentifier {
+    const AS_STRING: &'static [(&'static str, Self)] = &[
+        ("virtual", Self::Virtual),
+        ("module", Self::Module),
+        ("physical", Self::Physical),
+    ];
+}
+
+impl FromStr for CoverageIdentifier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let as_string = Self::AS_STRING.iter().cloned().collect::<HashMap<_, _>>();
+
+        as_string.get(s).cloned().ok_or_else(|| {
+            anyhow!(
+                "Invalid coverage identifier {}. Expected one of {}",
+                s,
+                Self::AS_STRING
+                    .iter()
+                    .map(|i| i.0)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+}
+
+impl Display for CoverageIdentifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let to_string = Self::AS_STRING
+            .iter()
+            .map(|(k, v)| (v, k))
+            .collect::<HashMap<_, _>>();
+        if let Some(name) = to_string.get(self) {
+            write!(f, "{}", name)
+        } else {
+            panic!("Invalid state for enum");
+        }
+    }
+}
+
+impl TryFrom<AttrValue> for CoverageIdentifier {
+    type Error = Error;
+
+    fn try_from(value: AttrValue) -> Result<Self> {
+        String::try_from(value)?.parse()
+    }
+}
+
+impl From<CoverageIdentifier> for AttrValueType {
+    fn from(value: CoverageIdentifier) -> Self {
+        value.to_string().into()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A user-declared module loaded at a virtual address range, parsed from the form
+/// `name=0x1000-0x2000` (the end is exclusive)
+pub(crate) struct ModuleRange {
+    pub name: String,
+    pub range: Range<u64>,
+}
+
+impl FromStr for ModuleRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, range) = s
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid module range {s}. Expected <name>=<start>-<end>"))?;
+        let name = name.trim();
+
+        if name.is_empty() {
+            bail!("Invalid module range {s}. The module name must not be empty");
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            range: parse_range(range)?,
+        })
+    }
+}
+
+impl Display for ModuleRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}={:#x}-{:#x}",
+            self.name, self.range.start, self.range.end
+        )
+    }
+}
+
+impl TryFrom<AttrValue> for ModuleRange {
+    type Error = Error;
+
+    fn try_from(value: AttrValue) -> Result<Self> {
+        String::try_from(value)?.parse()
+    }
+}
+
+impl From<ModuleRange> for AttrValueType {
+    fn from(value: ModuleRange) -> Self {
+        value.to_string().into()
+    }
+}
+
+/// Combine a module name and an offset into the module into a single identifier. The upper
+/// half of the identifier is a hash of the (case-insensitive) module name and the upper half
+/// of the offset, and the lower half is the lower half of the offset. Offsets of 4 GiB or
+/// more are folded into the hash instead of carrying into it, so they cannot produce the
+/// identifier of an offset into another module.
+fn module_identifier(name: &str, offset: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.to_ascii_lowercase().hash(&mut hasher);
+
+    if offset > u32::MAX as u64 {
+        (offset >> 32).hash(&mut hasher);
+    }
+
+    (hasher.finish() << 32) | (offset & u32::MAX as u64)
+}
+
+impl Tsffs {
+    /// Find the module containing a virtual address on a processor and the offset of the
+    /// address into it, using user-declared module ranges first, then OS awareness and
+    /// `symbol_files`
+    fn module_offset(&self, processor_number: i32, address: u64) -> Option<(&str, u64)> {
+        self.coverage_module_ranges
+            .iter()
+            .find(|m| m.range.contains(&address))
+            .map(|m| (m.name.as_str(), address - m.range.start))
+            .or_else(|| {
+                self.module_at(processor_number, address)
+                    .map(|(name, range)| (name, address - range.start))
+            })
+    }
+
+    /// Return the identifier used for coverage of a virtual address executed on a processor
+    /// according to `coverage_identifier`
+    pub fn coverage_identifier(&mut self, processor_number: i32, address: u64) -> u64 {
+        match self.coverage_identifier {
+            CoverageIdentifier::Virtual => address,
+            CoverageIdentifier::Module => self
+                .module_offset(processor_number, address)
+                .map(|(name, offset)| module_identifier(name, offset))
+                .unwrap_or(address),
+            CoverageIdentifier::Physical => self
+                .physical_code_address(processor_number, address)
+                .unwrap_or(address),
+        }
+    }
+
+    /// Translate a virtual address executed on a processor to a physical address, translating
+    /// each page once and caching the physical page until the cache is cleared. Returns `None`
+    /// if the address is not mapped.
+    pub fn physical_code_address(&mut self, processor_number: i32, address: u64) -> Option<u64> {
+        let page = address & !(PAGE_SIZE - 1);
+        let physical_page = match self.coverage_translations.entry((processor_number, page)) {
+            Entry::Occupied(e) => *e.get(),
+            Entry::Vacant(e) => *e.insert(
+                self.processors
+                    .get_mut(&processor_number)
+                    .and_then(|arch| {
+                        arch.processor_info_v2()
+                            .logical_to_physical(page, Access::Sim_Access_Execute)
+                            .ok()
+                    })
+                    .filter(|block| block.valid != 0)
+                    .map(|block| block.address),
+            ),
+        };
+
+        physical_page.map(|physical_page| physical_page + (address - page))
+    }
+
+    /// Forget the cached physical pages of executed code. Called when a control register is
+    /// written, which may switch page tables, and when a snapshot is restored.
+    pub fn clear_coverage_translations(&mut self) {
+        self.coverage_translations.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{module_identifier, ModuleRange};
+
+    #[test]
+    fn test_parse_module_range() {
+        assert_eq!(
+            " target.ko = 0xffffffffc0000000-0xffffffffc0010000 "
+                .parse::<ModuleRange>()
+                .expect("Failed to parse module range"),
+            ModuleRange {
+                name: "target.ko".to_string(),
+                range: 0xffffffffc0000000..0xffffffffc0010000
+            }
+        );
+        assert!("target.ko".parse::<ModuleRange>().is_err());
+        assert!("=0x1000-0x2000".parse::<ModuleRange>().is_err());
+        assert!("target.ko=0x2000-0x1000".parse::<ModuleRange>().is_err());
+        assert!("target.ko=0x1000".parse::<ModuleRange>().is_err());
+
+        let range = "target.ko=0x1000-0x2000"
+            .parse::<ModuleRange>()
+            .expect("Failed to parse module range");
+        assert_eq!(range.to_string(), "target.ko=0x1000-0x2000");
+        assert_eq!(
+            range
+                .to_string()
+                .parse::<ModuleRange>()
+                .expect("Failed to parse module range"),
+            range
+        );
+    }
+
+    #[test]
+    fn test_module_identifier() {
+        let id = module_identifier("target.ko", 0x1234);
+
+        assert_eq!(id & 0xffffffff, 0x1234);
+        assert_eq!(module_identifier("TARGET.KO", 0x1234), id);
+        assert_ne!(module_identifier("other.ko", 0x1234), id);
+
+        // Offsets of 4 GiB or more keep their lower half and change the module hash rather
+        // than carrying into it
+        let high = module_identifier("target.ko", 0x1_0000_1234);
+        assert_eq!(high & 0xffffffff, 0x1234);
+        assert_ne!(high, id);
+        assert_ne!(high >> 32, (id >> 32) + 1);
+        assert_ne!(module_identifier("target.ko", 0x2_0000_1234), high);
+    }
+}
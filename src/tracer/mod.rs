@@ -6,23 +6,49 @@ use cpp_demangle::{DemangleOptions, Symbol};
 use ffi2::ffi;
 use libafl::prelude::CmpValues;
 use libafl_bolts::{AsMutSlice, AsSlice};
-use libafl_targets::{AFLppCmpLogOperands, AFL_CMP_TYPE_INS, CMPLOG_MAP_H};
+use libafl_targets::{
+    AFLppCmpLogFnOperands, AFLppCmpLogOperands, AFL_CMP_TYPE_INS, AFL_CMP_TYPE_RTN, CMPLOG_MAP_H,
+    CMPLOG_MAP_RTN_EXTENDED_H,
+};
 use rustc_demangle::try_demangle;
 use serde::{Deserialize, Serialize};
 use simics::{
     api::{
-        get_processor_number, sys::instruction_handle_t, AsConfObject, AttrValue, AttrValueType,
-        ConfObject,
+        get_processor_number,
+        sys::{cached_instruction_handle_t, instruction_handle_t},
+        AsConfObject, AttrValue, AttrValueType, ConfObject,
     },
-    get_interface, trace, ProcessorInfoV2Interface,
+    get_interface, trace, CpuCachedInstructionInterface, ProcessorInfoV2Interface,
 };
 use std::{
-    collections::HashMap, ffi::c_void, fmt::Display, hash::Hash, num::Wrapping,
-    slice::from_raw_parts, str::FromStr,
+    collections::{HashMap, VecDeque},
+    ffi::c_void,
+    fmt::Display,
+    hash::Hash,
+    num::Wrapping,
+    rc::Rc,
+    slice::from_raw_parts,
+    str::FromStr,
 };
 use typed_builder::TypedBuilder;
 
-use crate::{arch::ArchitectureOperations, Tsffs};
+use crate::{
+    arch::{decode_cache::DecodedInstruction, ArchitectureOperations},
+    Tsffs,
+};
+
+pub(crate) mod attribution;
+pub(crate) mod breakpoints;
+pub(crate) mod edges;
+pub(crate) mod filter;
+pub(crate) mod identifier;
+pub(crate) mod report;
+pub(crate) mod routines;
+pub(crate) use attribution::{AttributionKind, AttributionRecord, CoverageAttribution};
+pub(crate) use edges::EdgeIndices;
+pub(crate) use filter::CoverageRange;
+pub(crate) use identifier::{CoverageIdentifier, ModuleRange};
+pub(crate) use routines::CmplogRoutine;
 
 #[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct ExecutionTraceSymbol {
@@ -88,6 +114,13 @@ pub(crate) enum CmpExpr {
     U64(u64),
     I64(i64),
     Addr(u64),
+    /// A sequence of bytes at an address, with a length in bytes
+    Bytes((Box<CmpExpr>, Box<CmpExpr>)),
+    /// A floating point value of a width in bytes, from the low bytes of a value
+    Float((Box<CmpExpr>, u8)),
+    /// An offset into the segment named by a segment register, which is the linear address
+    /// at the offset from the base of the segment
+    Segment((String, Box<CmpExpr>)),
 }
 
 #[allow(unused)]
@@ -114,6 +147,11 @@ pub(crate) enum CmpValue {
     I32(i32),
     U64(u64),
     I64(i64),
+    /// A single precision floating point value, as its bit pattern
+    F32(u32),
+    /// A double precision floating point value, as its bit pattern
+    F64(u64),
+    Bytes(Vec<u8>),
     Expr(Box<CmpExpr>),
 }
 
@@ -134,6 +172,96 @@ impl TryFrom<&CmpExpr> for CmpValue {
     }
 }
 
+impl CmpValue {
+    /// The width in bytes of an integral value and the value zero or sign extended to 64 bits
+    fn extended(&self) -> Option<(usize, u64)> {
+        Some(match self {
+            CmpValue::U8(v) => (1, *v as u64),
+            CmpValue::I8(v) => (1, *v as i64 as u64),
+            CmpValue::U16(v) => (2, *v as u64),
+            CmpValue::I16(v) => (2, *v as i64 as u64),
+            CmpValue::U32(v) => (4, *v as u64),
+            CmpValue::I32(v) => (4, *v as i64 as u64),
+            CmpValue::U64(v) => (8, *v),
+            CmpValue::I64(v) => (8, *v as u64),
+            _ => return None,
+        })
+    }
+
+    /// An integral value repeated to fill `len` bytes
+    fn repeated(&self, len: usize) -> Option<Vec<u8>> {
+        let (width, value) = self.extended()?;
+
+        Some(
+            value.to_le_bytes()[..width]
+                .iter()
+                .cycle()
+                .take(len)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Reinterpret the low bytes of a value as a floating point value `width` bytes wide
+    pub(crate) fn into_float(self, width: u8) -> Result<CmpValue> {
+        let bits = match &self {
+            CmpValue::F32(_) | CmpValue::F64(_) => return Ok(self),
+            CmpValue::Bytes(bytes) => {
+                let mut buffer = [0u8; 8];
+                let len = bytes.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&bytes[..len]);
+                u64::from_le_bytes(buffer)
+            }
+            value => {
+                value
+                    .extended()
+                    .ok_or_else(|| anyhow!("Can't convert {value:?} to floating point"))?
+                    .1
+            }
+        };
+
+        Ok(match width {
+            4 => CmpValue::F32(bits as u32),
+            8 => CmpValue::F64(bits),
+            _ => bail!("Can't convert to floating point of width {width}"),
+        })
+    }
+
+    /// Combine the operands of a comparison into values for the comparison map. Integral
+    /// operands of different widths, like a register and a sign extended immediate, are
+    /// extended to the width of the wider operand. An integral operand compared with a
+    /// sequence of bytes, like the accumulator scanned for by `rep scas`, is repeated to the
+    /// length of the sequence. Floating point operands are logged as their bit patterns.
+    pub(crate) fn pair(left: &CmpValue, right: &CmpValue) -> Option<CmpValues> {
+        match (left, right) {
+            (CmpValue::F32(l), CmpValue::F32(r)) => Some(CmpValues::U32((*l, *r))),
+            (CmpValue::F64(l), CmpValue::F64(r)) => Some(CmpValues::U64((*l, *r))),
+            (CmpValue::F32(l), CmpValue::F64(r)) => {
+                Some(CmpValues::U64(((f32::from_bits(*l) as f64).to_bits(), *r)))
+            }
+            (CmpValue::F64(l), CmpValue::F32(r)) => {
+                Some(CmpValues::U64((*l, (f32::from_bits(*r) as f64).to_bits())))
+            }
+            (CmpValue::Bytes(l), CmpValue::Bytes(r)) => {
+                Some(CmpValues::Bytes((l.clone(), r.clone())))
+            }
+            (CmpValue::Bytes(l), r) => Some(CmpValues::Bytes((l.clone(), r.repeated(l.len())?))),
+            (l, CmpValue::Bytes(r)) => Some(CmpValues::Bytes((l.repeated(r.len())?, r.clone()))),
+            (l, r) => {
+                let (l_width, l) = l.extended()?;
+                let (r_width, r) = r.extended()?;
+
+                Some(match l_width.max(r_width) {
+                    1 => CmpValues::U8((l as u8, r as u8)),
+                    2 => CmpValues::U16((l as u16, r as u16)),
+                    4 => CmpValues::U32((l as u32, r as u32)),
+                    _ => CmpValues::U64((l, r)),
+                })
+            }
+        }
+    }
+}
+
 fn cmp_shape(cmp: &CmpValues) -> Result<u32> {
     match cmp {
         CmpValues::U8(_) => Ok(0),
@@ -185,7 +313,20 @@ pub(crate) struct TraceEntry {
     /// The target of an edge in the trace
     edge: Option<u64>,
     #[builder(default, setter(into, strip_option))]
+    /// The address of the control flow instruction at the source of an edge in the trace
+    source: Option<u64>,
+    #[builder(default, setter(into, strip_option))]
     cmp: Option<(u64, Vec<CmpType>, CmpValues)>,
+    #[builder(default, setter(into))]
+    /// The address of an indirect jump through a jump table and the operands of the bounds
+    /// check of the table index which preceded it
+    jump_table: Option<(u64, CmpValues)>,
+    #[builder(default)]
+    /// Whether the control flow instruction at the source of an edge is a call
+    call: bool,
+    #[builder(default)]
+    /// Whether the control flow instruction at the source of an edge is a return
+    ret: bool,
 }
 
 impl Default for TraceEntry {
@@ -196,13 +337,27 @@ impl Default for TraceEntry {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub(crate) enum CoverageMode {
+    /// Edges are identified by the current and previous location, and the number of times
+    /// each edge is hit is counted
     HitCount,
+    /// Edges are identified by the current and previous location, and only whether each
+    /// edge is hit is recorded
     Once,
+    /// Edges are identified by the current and previous location and a hash of the current
+    /// calling context, maintained across calls and returns
+    CallContext,
+    /// Edges are identified by the current location and the last `coverage_ngram_size`
+    /// locations
+    NGram,
 }
 
 impl CoverageMode {
-    const AS_STRING: &'static [(&'static str, Self)] =
-        &[("hit-count", Self::HitCount), ("once", Self::Once)];
+    const AS_STRING: &'static [(&'static str, Self)] = &[
+        ("hit-count", Self::HitCount),
+        ("once", Self::Once),
+        ("call-context", Self::CallContext),
+        ("ngram", Self::NGram),
+    ];
 }
 
 impl Default for CoverageMode {
@@ -259,21 +414,168 @@ impl From<CoverageMode> for AttrValueType {
     }
 }
 
+/// The maximum depth of the call stack tracked for call-context-sensitive coverage. Calls
+/// beyond this depth do not change the calling context.
+const MAX_CALL_CONTEXT_DEPTH: usize = 1024;
+/// The maximum depth of nested exception contexts tracked per processor. Exceptions beyond
+/// this depth share the edge chaining state of the deepest context.
+const MAX_EXCEPTION_CONTEXT_DEPTH: usize = 16;
+/// The largest jump table index for which the other cases of the table are logged. Bounds
+/// checks against larger values are assumed not to guard a jump table.
+const MAX_JUMP_TABLE_INDEX: u64 = 1024;
+
+#[derive(Debug, Clone, Default)]
+/// Edge chaining state used to compute the hash of edges. One chain is kept for each traced
+/// processor, and optionally for each nested exception context on each processor, so that
+/// interleaved execution does not create phantom edges.
+pub(crate) struct EdgeChain {
+    /// The previous location for coverage for calculating the hash of edges.
+    prev_loc: u64,
+    /// The most recent previous locations, most recent first, for calculating the hash of
+    /// edges in n-gram coverage mode
+    history: VecDeque<u64>,
+    /// The hashed call targets of the current call stack in call context coverage mode
+    call_stack: Vec<u64>,
+    /// The hash of the current calling context in call context coverage mode
+    call_context: u64,
+}
+
+impl EdgeChain {
+    /// The context which distinguishes edges with the same source and target according to
+    /// the coverage mode
+    fn context(&self, mode: CoverageMode) -> u64 {
+        match mode {
+            CoverageMode::HitCount | CoverageMode::Once => 0,
+            CoverageMode::CallContext => self.call_context,
+            // Rotate each previous location by its distance so that repeated locations do not
+            // cancel each other out
+            CoverageMode::NGram => self
+                .history
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, loc)| acc ^ loc.rotate_left(i as u32)),
+        }
+    }
+
+    /// Compute the hashed index into the coverage map of length `len` for an edge ending at
+    /// `pc` according to the coverage mode
+    fn index(&self, mode: CoverageMode, pc: u64, len: u64) -> u64 {
+        match mode {
+            CoverageMode::NGram => (pc ^ self.context(mode)) % len,
+            _ => (pc ^ self.prev_loc ^ self.context(mode)) % len,
+        }
+    }
+
+    /// Advance the chain past an edge ending at `pc`
+    fn advance(&mut self, mode: CoverageMode, ngram_size: usize, pc: u64, len: u64) {
+        self.prev_loc = (pc >> 1) % len;
+
+        if mode == CoverageMode::NGram {
+            self.history.push_front(self.prev_loc);
+            self.history.truncate(ngram_size.max(1));
+        }
+    }
+
+    /// Update the calling context after an edge ending at `pc` caused by a call or return
+    fn call_or_return(&mut self, pc: u64, call: bool, ret: bool) {
+        if call && self.call_stack.len() < MAX_CALL_CONTEXT_DEPTH {
+            // Hash the call target into the context
+            let context = pc.wrapping_mul(0x9e3779b97f4a7c15);
+            self.call_context ^= context;
+            self.call_stack.push(context);
+        } else if ret {
+            if let Some(context) = self.call_stack.pop() {
+                self.call_context ^= context;
+            }
+        }
+    }
+}
+
 impl Tsffs {
-    fn log_pc(&mut self, pc: u64) -> Result<()> {
+    /// Return the active edge chain for a processor
+    fn edge_chain(&mut self, processor_number: i32) -> &mut EdgeChain {
+        let chains = self.coverage_chains.entry(processor_number).or_default();
+
+        if chains.is_empty() {
+            chains.push(EdgeChain::default());
+        }
+
+        chains
+            .last_mut()
+            .expect("Edge chain stack is never empty after insertion")
+    }
+
+    /// Enter a new exception context on a processor, which uses a fresh edge chain until the
+    /// exception returns
+    pub fn enter_exception_context(&mut self, processor_number: i32) {
+        if !self.coverage_per_exception_context || !self.processors.contains_key(&processor_number)
+        {
+            return;
+        }
+
+        // Ensure the interrupted context has a chain to return to
+        self.edge_chain(processor_number);
+
+        let chains = self.coverage_chains.entry(processor_number).or_default();
+
+        if chains.len() < MAX_EXCEPTION_CONTEXT_DEPTH {
+            chains.push(EdgeChain::default());
+        }
+    }
+
+    /// Leave the current exception context on a processor, resuming the edge chain of the
+    /// interrupted context
+    pub fn leave_exception_context(&mut self, processor_number: i32) {
+        if !self.coverage_per_exception_context {
+            return;
+        }
+
+        if let Some(chains) = self.coverage_chains.get_mut(&processor_number) {
+            if chains.len() > 1 {
+                chains.pop();
+            }
+        }
+    }
+
+    /// Log an edge from the control flow instruction at `source` to `pc` on a processor to the
+    /// coverage map, returning the index of the edge in the map
+    fn log_pc(&mut self, processor_number: i32, source: u64, pc: u64) -> Result<u64> {
+        let len = self
+            .coverage_map
+            .get()
+            .ok_or_else(|| {
+                anyhow!("Coverage map not initialized. This is a bug in the fuzzer or the target")
+            })?
+            .as_slice()
+            .len() as u64;
+        let mode = self.coverage_mode;
+        let ngram_size = self.coverage_ngram_size;
+        let chain = self.edge_chain(processor_number);
+        let hashed_index = chain.index(mode, pc, len);
+        let context = chain.context(mode);
+        chain.advance(mode, ngram_size, pc, len);
+        let afl_idx = self.coverage_map_index((source, pc, context), hashed_index)?;
+
         let coverage_map = self.coverage_map.get_mut().ok_or_else(|| {
             anyhow!("Coverage map not initialized. This is a bug in the fuzzer or the target")
         })?;
-        let afl_idx = (pc ^ self.coverage_prev_loc) % coverage_map.as_slice().len() as u64;
-        let mut cur_byte: Wrapping<u8> = Wrapping(coverage_map.as_slice()[afl_idx as usize]);
-        cur_byte += 1;
-        coverage_map.as_mut_slice()[afl_idx as usize] = cur_byte.0;
-        self.coverage_prev_loc = (pc >> 1) % coverage_map.as_slice().len() as u64;
 
-        Ok(())
+        if mode == CoverageMode::Once {
+            coverage_map.as_mut_slice()[afl_idx as usize] = 1;
+        } else {
+            let mut cur_byte: Wrapping<u8> = Wrapping(coverage_map.as_slice()[afl_idx as usize]);
+            cur_byte += 1;
+            coverage_map.as_mut_slice()[afl_idx as usize] = cur_byte.0;
+        }
+
+        Ok(afl_idx)
     }
 
     fn log_cmp(&mut self, pc: u64, types: Vec<CmpType>, cmp: CmpValues) -> Result<()> {
+        if let CmpValues::Bytes((left, right)) = cmp {
+            return self.log_cmp_routine(pc, left, right);
+        }
+
         // Consistently hash pc to the same header index
         let aflpp_cmp_map = self.aflpp_cmp_map.get_mut().ok_or_else(|| {
             anyhow!("AFL++ cmp map not initialized. This is a bug in the fuzzer or the target")
@@ -310,10 +612,221 @@ impl Tsffs {
 
         Ok(())
     }
+
+    /// Log synthetic comparisons of the index used by a jump table dispatch at `pc` against
+    /// the other indices of the table. Only the bounds check of the index is otherwise
+    /// visible, so the values which select each case are never compared.
+    fn log_jump_table(&mut self, pc: u64, bounds: CmpValues) -> Result<()> {
+        let Some((left, right)) = bounds.to_u64_tuple() else {
+            return Ok(());
+        };
+
+        // The bounds check may compare the index and the bound in either order, but the index
+        // is never larger than the bound when the table is used
+        let (index, bound) = (left.min(right), left.max(right));
+
+        if bound > MAX_JUMP_TABLE_INDEX {
+            return Ok(());
+        }
+
+        trace!(
+            self.as_conf_object(),
+            "Jump table at {pc:#x} dispatched index {index} of at most {bound}"
+        );
+
+        // Only the last CMPLOG_MAP_H hits of a comparison are kept, so the cases following
+        // the current index are logged first to cover other cases on later executions
+        let cases = bound + 1;
+
+        for case in (1..cases)
+            .take(CMPLOG_MAP_H)
+            .map(|offset| (index + offset) % cases)
+        {
+            let cmp = match bounds {
+                CmpValues::U8(_) => CmpValues::U8((index as u8, case as u8)),
+                CmpValues::U16(_) => CmpValues::U16((index as u16, case as u16)),
+                CmpValues::U32(_) => CmpValues::U32((index as u32, case as u32)),
+                _ => CmpValues::U64((index, case)),
+            };
+            self.log_cmp(pc, vec![CmpType::Equal], cmp)?;
+        }
+
+        Ok(())
+    }
+
+    /// Log the buffers compared by a comparison routine called from `pc`
+    fn log_cmp_routine(&mut self, pc: u64, left: Vec<u8>, right: Vec<u8>) -> Result<()> {
+        let aflpp_cmp_map = self.aflpp_cmp_map.get_mut().ok_or_else(|| {
+            anyhow!("AFL++ cmp map not initialized. This is a bug in the fuzzer or the target")
+        })?;
+        let pc_index = hash_index(pc, aflpp_cmp_map.headers().len() as u64);
+
+        let hits = aflpp_cmp_map.headers_mut()[pc_index as usize].hits();
+
+        aflpp_cmp_map.headers_mut()[pc_index as usize].set_hits(hits + 1);
+        aflpp_cmp_map.headers_mut()[pc_index as usize]
+            .set_shape(routines::ROUTINE_OPERAND_SIZE as u32);
+        aflpp_cmp_map.headers_mut()[pc_index as usize].set__type(AFL_CMP_TYPE_RTN);
+        aflpp_cmp_map.headers_mut()[pc_index as usize].set_attribute(CmpType::Equal as u32);
+
+        let mut v0 = [0u8; routines::ROUTINE_OPERAND_SIZE];
+        let mut v1 = [0u8; routines::ROUTINE_OPERAND_SIZE];
+        let v0_len = left.len().min(v0.len());
+        let v1_len = right.len().min(v1.len());
+        v0[..v0_len].copy_from_slice(&left[..v0_len]);
+        v1[..v1_len].copy_from_slice(&right[..v1_len]);
+
+        aflpp_cmp_map.values_mut().fn_operands_mut()[pc_index as usize]
+            [hits as usize % CMPLOG_MAP_RTN_EXTENDED_H] =
+            AFLppCmpLogFnOperands::new(&v0, v0_len as u8, &v1, v1_len as u8);
+
+        if hits == 0 {
+            trace!(
+                self.as_conf_object(),
+                "Logged first hit of routine comparison at {pc:#x} with values {left:?} and {right:?}"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The user data of the callbacks registered on a cached instruction
+struct CachedInstruction {
+    tsffs: *mut Tsffs,
+    decoded: Rc<DecodedInstruction>,
+}
+
+impl CachedInstruction {
+    /// Allocate the user data for a callback on a cached instruction, which must be freed with
+    /// `free_cached_instruction`
+    fn into_raw(tsffs: &mut Tsffs, decoded: Rc<DecodedInstruction>) -> *mut c_void {
+        Box::into_raw(Box::new(Self {
+            tsffs: tsffs as *mut Tsffs,
+            decoded,
+        })) as *mut c_void
+    }
+
+    /// Run an instruction callback with the decoding of the cached instruction provided to the
+    /// architecture of the processor
+    ///
+    /// # Safety
+    ///
+    /// `user_data` must have been allocated by `into_raw` and not yet freed
+    unsafe fn with_decoded<F>(cpu: *mut ConfObject, user_data: *mut c_void, callback: F)
+    where
+        F: FnOnce(&mut Tsffs) -> Result<()>,
+    {
+        let cached = &*(user_data as *const Self);
+        let tsffs = &mut *cached.tsffs;
+        let processor_number = get_processor_number(cpu).ok();
+
+        if let Some(arch) = processor_number.and_then(|n| tsffs.processors.get_mut(&n)) {
+            arch.decode_cache().set_current(cached.decoded.clone());
+        }
+
+        callback(tsffs).expect("Failed to execute cached instruction callback");
+
+        // The decoding is only used by the instruction it was provided for
+        if let Some(arch) = processor_number.and_then(|n| tsffs.processors.get_mut(&n)) {
+            arch.decode_cache().take_current();
+        }
+    }
+}
+
+/// Callback after a cached instruction which can produce an edge is executed
+unsafe extern "C" fn on_cached_instruction_after(
+    obj: *mut ConfObject,
+    cpu: *mut ConfObject,
+    handle: *mut instruction_handle_t,
+    user_data: *mut c_void,
+) {
+    CachedInstruction::with_decoded(cpu, user_data, |tsffs| {
+        tsffs.on_instruction_after(obj, cpu, handle)
+    });
+}
+
+/// Callback before a cached instruction which can produce a comparison is executed
+unsafe extern "C" fn on_cached_instruction_before(
+    obj: *mut ConfObject,
+    cpu: *mut ConfObject,
+    handle: *mut instruction_handle_t,
+    user_data: *mut c_void,
+) {
+    CachedInstruction::with_decoded(cpu, user_data, |tsffs| {
+        tsffs.on_instruction_before(obj, cpu, handle)
+    });
+}
+
+/// Free the user data of a callback on a cached instruction when the processor model drops it
+unsafe extern "C" fn free_cached_instruction(
+    _obj: *mut ConfObject,
+    _cpu: *mut ConfObject,
+    user_data: *mut c_void,
+) {
+    drop(Box::from_raw(user_data as *mut CachedInstruction));
 }
 
 #[ffi(from_ptr, expect, self_ty = "*mut c_void")]
 impl Tsffs {
+    #[ffi(arg(rest), arg(self))]
+    /// Callback when an instruction is decoded and cached by the processor model. Only used
+    /// when instructions are filtered at decode time, in which case the instruction is
+    /// classified once here and the per-instruction callbacks are only attached to
+    /// instructions which can produce an edge or a comparison.
+    ///
+    /// # Arguments
+    ///
+    /// * `obj`
+    /// * `cpu` - The processor the instruction is being cached by
+    /// * `cached_instruction` - An opaque handle to the cached instruction
+    /// * `handle` - An opaque handle to the instruction being cached
+    pub fn on_cached_instruction(
+        &mut self,
+        _obj: *mut ConfObject,
+        cpu: *mut ConfObject,
+        cached_instruction: *mut cached_instruction_handle_t,
+        handle: *mut instruction_handle_t,
+    ) -> Result<()> {
+        let processor_number = get_processor_number(cpu)?;
+
+        let Some(arch) = self.processors.get_mut(&processor_number) else {
+            return Ok(());
+        };
+
+        let Ok(decoded) = arch.decode(handle) else {
+            // Instructions which cannot be decoded can never be traced
+            return Ok(());
+        };
+
+        let mut cpu_cached_instruction: CpuCachedInstructionInterface = get_interface(cpu)?;
+
+        // The decoding is passed to the callbacks so the instruction does not need to be read
+        // and looked up again each time it executes. It is freed when the processor model
+        // drops the cached instruction.
+        if decoded.is_edge() {
+            cpu_cached_instruction.register_instruction_after_cb(
+                cached_instruction,
+                Some(on_cached_instruction_after),
+                CachedInstruction::into_raw(self, decoded.clone()),
+                Some(free_cached_instruction),
+            )?;
+        }
+
+        // NOTE: Some architectures report comparison operands without classifying the
+        // instruction as a comparison
+        if self.cmplog && (decoded.cmp || decoded.sets_flags || !decoded.cmp_exprs.is_empty()) {
+            cpu_cached_instruction.register_instruction_before_cb(
+                cached_instruction,
+                Some(on_cached_instruction_before),
+                CachedInstruction::into_raw(self, decoded),
+                Some(free_cached_instruction),
+            )?;
+        }
+
+        Ok(())
+    }
+
     #[ffi(arg(rest), arg(self))]
     /// Callback after each instruction executed
     ///
@@ -334,16 +847,77 @@ impl Tsffs {
             if let Some(arch) = self.processors.get_mut(&processor_number) {
                 match arch.trace_pc(handle) {
                     Ok(r) => {
+                        // Arithmetic which sets flags is logged when a conditional branch
+                        // consumes the flags
+                        if let Some((pc, types, cmp)) = r.cmp {
+                            if self.cmplog
+                                && self.cmplog_enabled
+                                && self.coverage_address_included(processor_number, pc)
+                            {
+                                self.log_cmp(pc, types, cmp)?;
+                            }
+                        }
+
+                        if let Some((pc, bounds)) = r.jump_table {
+                            if self.cmplog
+                                && self.cmplog_enabled
+                                && self.coverage_address_included(processor_number, pc)
+                            {
+                                self.log_jump_table(pc, bounds)?;
+                            }
+                        }
+
                         if let Some(pc) = r.edge {
-                            if self.coverage_reporting && self.edges_seen.insert(pc) {
-                                let coverage_map = self.coverage_map.get_mut().ok_or_else(|| {
-                                    anyhow!("Coverage map not initialized. This is a bug in the fuzzer or the target")
-                                })?;
-                                let afl_idx = (pc ^ self.coverage_prev_loc)
-                                    % coverage_map.as_slice().len() as u64;
-                                self.edges_seen_since_last.insert(pc, afl_idx);
+                            if self.cmplog && self.cmplog_enabled {
+                                self.log_cmplog_routine(
+                                    processor_number,
+                                    r.source.unwrap_or(pc),
+                                    pc,
+                                )?;
+                            }
+
+                            self.check_smram_callout(processor_number, pc)?;
+
+                            let included = self.coverage_address_included(processor_number, pc);
+
+                            // Calls and returns into or out of excluded code still update the
+                            // calling context so the call stack stays balanced
+                            if !included && self.coverage_mode != CoverageMode::CallContext {
+                                return Ok(());
+                            }
+
+                            let target = self.coverage_identifier(processor_number, pc);
+
+                            if included {
+                                let source = self.coverage_identifier(
+                                    processor_number,
+                                    r.source.unwrap_or_default(),
+                                );
+                                let afl_idx = self.log_pc(processor_number, source, target)?;
+
+                                if self.coverage_reporting
+                                    && self.edges_seen.insert((source, target))
+                                {
+                                    self.edges_seen_since_last.insert((source, target), afl_idx);
+                                }
+
+                                if self.coverage_attribution {
+                                    self.coverage_attribution_index
+                                        .edge_hit(source, target, afl_idx);
+                                }
+
+                                if self.address_coverage_report {
+                                    *self
+                                        .edge_hit_counts
+                                        .entry((processor_number, pc))
+                                        .or_default() += 1;
+                                }
+                            }
+
+                            if self.coverage_mode == CoverageMode::CallContext {
+                                self.edge_chain(processor_number)
+                                    .call_or_return(target, r.call, r.ret);
                             }
-                            self.log_pc(pc)?;
                         }
                     }
                     Err(_) => {
@@ -379,7 +953,9 @@ impl Tsffs {
                 match arch.trace_cmp(handle) {
                     Ok(r) => {
                         if let Some((pc, types, cmp)) = r.cmp {
-                            self.log_cmp(pc, types.clone(), cmp.clone())?;
+                            if self.coverage_address_included(processor_number, pc) {
+                                self.log_cmp(pc, types.clone(), cmp.clone())?;
+                            }
                         }
                     }
                     Err(_) => {
@@ -440,10 +1016,20 @@ impl Tsffs {
                                 if pc_lines.contains(&(function_start_line.start_line as usize)) {
                                     // Increment function hit counter if we just hit the fn
                                     record.increment_function_data(&symbol_for_query.value.name);
+
+                                    if self.coverage_attribution {
+                                        self.coverage_attribution_index
+                                            .function_hit(&symbol_for_query.value.name);
+                                    }
                                 }
 
                                 pc_lines.iter().for_each(|line| {
                                     record.increment_line(*line);
+
+                                    if self.coverage_attribution {
+                                        self.coverage_attribution_index
+                                            .line_hit(&function_start_line.file_path, *line);
+                                    }
                                 });
                             }
                             ExecutionTraceSymbol {
@@ -482,10 +1068,20 @@ impl Tsffs {
                         if pc_lines.contains(&(function_start_line.start_line as usize)) {
                             // Increment function hit counter if we just hit the fn
                             record.increment_function_data(&symbol_for_query.value.name);
+
+                            if self.coverage_attribution {
+                                self.coverage_attribution_index
+                                    .function_hit(&symbol_for_query.value.name);
+                            }
                         }
 
                         pc_lines.iter().for_each(|line| {
                             record.increment_line(*line);
+
+                            if self.coverage_attribution {
+                                self.coverage_attribution_index
+                                    .line_hit(&function_start_line.file_path, *line);
+                            }
                         });
                     }
                 }
@@ -540,3 +1136,137 @@ impl Tsffs {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CmpExpr, CmpValue, CoverageMode, EdgeChain};
+    use crate::{arch::risc_v::Disassembler, traits::TracerDisassembler};
+    use libafl::prelude::CmpValues;
+
+    /// Update the calling context of a chain after a RISC-V instruction jumps to `target`
+    fn jump(chain: &mut EdgeChain, disassembler: &mut Disassembler, bytes: &[u8], target: u64) {
+        disassembler
+            .disassemble(bytes)
+            .expect("Failed to disassemble instruction");
+        chain.call_or_return(
+            target,
+            disassembler.last_was_call(),
+            disassembler.last_was_ret(),
+        );
+    }
+
+    #[test]
+    fn test_riscv_call_context_restored_after_return() {
+        let mut disassembler = Disassembler::new();
+        let mut chain = EdgeChain::default();
+        let outside = chain.context(CoverageMode::CallContext);
+
+        // jal ra, 16
+        jump(
+            &mut chain,
+            &mut disassembler,
+            &[0xef, 0x00, 0x00, 0x01],
+            0x1010,
+        );
+        let inside = chain.context(CoverageMode::CallContext);
+        assert_ne!(inside, outside);
+
+        // j 16 is a jump, which does not change the context
+        jump(
+            &mut chain,
+            &mut disassembler,
+            &[0x6f, 0x00, 0x00, 0x01],
+            0x1020,
+        );
+        assert_eq!(chain.context(CoverageMode::CallContext), inside);
+
+        // jalr x0, 0(ra)
+        jump(
+            &mut chain,
+            &mut disassembler,
+            &[0x67, 0x80, 0x00, 0x00],
+            0x1004,
+        );
+        assert_eq!(chain.context(CoverageMode::CallContext), outside);
+        assert!(chain.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_riscv_compressed_call_context_restored_after_return() {
+        let mut disassembler = Disassembler::new();
+        let mut chain = EdgeChain::default();
+        let outside = chain.context(CoverageMode::CallContext);
+
+        // c.jalr a0
+        jump(&mut chain, &mut disassembler, &[0x02, 0x95], 0x2000);
+        assert_ne!(chain.context(CoverageMode::CallContext), outside);
+
+        // c.jr ra
+        jump(&mut chain, &mut disassembler, &[0x82, 0x80], 0x1002);
+        assert_eq!(chain.context(CoverageMode::CallContext), outside);
+        assert!(chain.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_pair_integral_operands() {
+        assert_eq!(
+            CmpValue::pair(&CmpValue::U8(0x41), &CmpValue::U8(0x42)),
+            Some(CmpValues::U8((0x41, 0x42)))
+        );
+        // Immediates are sign extended to the width of the register they are compared with
+        assert_eq!(
+            CmpValue::pair(&CmpValue::U32(0x1234), &CmpValue::I16(-1)),
+            Some(CmpValues::U32((0x1234, 0xffff_ffff)))
+        );
+        assert_eq!(
+            CmpValue::pair(&CmpValue::I8(-2), &CmpValue::U64(1)),
+            Some(CmpValues::U64((0xffff_ffff_ffff_fffe, 1)))
+        );
+        assert_eq!(
+            CmpValue::pair(&CmpValue::U16(0x100), &CmpValue::U8(0xff)),
+            Some(CmpValues::U16((0x100, 0xff)))
+        );
+        assert_eq!(
+            CmpValue::pair(
+                &CmpValue::Expr(Box::new(CmpExpr::Addr(0x1000))),
+                &CmpValue::U8(0)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pair_byte_and_float_operands() {
+        assert_eq!(
+            CmpValue::pair(
+                &CmpValue::Bytes(b"abcd".to_vec()),
+                &CmpValue::Bytes(b"abce".to_vec())
+            ),
+            Some(CmpValues::Bytes((b"abcd".to_vec(), b"abce".to_vec())))
+        );
+        // An integral operand compared with bytes is repeated to their length
+        assert_eq!(
+            CmpValue::pair(&CmpValue::Bytes(b"abcd".to_vec()), &CmpValue::U8(b'a')),
+            Some(CmpValues::Bytes((b"abcd".to_vec(), b"aaaa".to_vec())))
+        );
+        assert_eq!(
+            CmpValue::pair(&CmpValue::U16(0x6261), &CmpValue::Bytes(b"abc".to_vec())),
+            Some(CmpValues::Bytes((b"aba".to_vec(), b"abc".to_vec())))
+        );
+        assert_eq!(
+            CmpValue::pair(
+                &CmpValue::F32(1.5f32.to_bits()),
+                &CmpValue::F32(2.5f32.to_bits())
+            ),
+            Some(CmpValues::U32((1.5f32.to_bits(), 2.5f32.to_bits())))
+        );
+        // Single precision operands are widened to compare with double precision operands
+        assert_eq!(
+            CmpValue::pair(
+                &CmpValue::F32(1.5f32.to_bits()),
+                &CmpValue::F64(2.5f64.to_bits())
+            ),
+            Some(CmpValues::U64((1.5f64.to_bits(), 2.5f64.to_bits())))
+        );
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Address-level coverage reports for targets without debug info
+
+use anyhow::{anyhow, Error, Result};
+use simics::{api::Access, debug, read_byte, AsConfObject};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::{create_dir_all, write},
+};
+
+use crate::{
+    arch::{Architecture, ArchitectureOperations, PAGE_SIZE},
+    Tsffs,
+};
+
+/// The maximum number of bytes read when decoding a single instruction
+pub(super) const MAX_INSTRUCTION_SIZE: usize = 16;
+/// The maximum number of instructions decoded from the start of a hit block
+const MAX_BLOCK_INSTRUCTIONS: usize = 256;
+/// Blocks separated by no more than this many bytes are grouped into the same region, and the
+/// instructions between them are shown as unhit
+const MAX_REGION_GAP: u64 = 0x80;
+
+/// A single decoded instruction in the report
+struct ReportInstruction {
+    address: u64,
+    bytes: Vec<u8>,
+    disassembly: String,
+    hit: bool,
+}
+
+/// Read up to `len` bytes of code at a virtual address, translating the address once for
+/// each page the range touches. Reading stops at the first unmapped page, so fewer bytes than
+/// requested are returned when the range runs into one.
+pub(super) fn read_code_bytes(
+    arch: &mut Architecture,
+    address: u64,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(len);
+    let physical_memory = arch.processor_info_v2().get_physical_memory()?;
+    let end = address.saturating_add(len as u64);
+    let mut page_address = address;
+
+    while page_address < end {
+        let page_end = (page_address & !(PAGE_SIZE - 1))
+            .saturating_add(PAGE_SIZE)
+            .min(end);
+        let physical_address = arch
+            .processor_info_v2()
+            .logical_to_physical(page_address, Access::Sim_Access_Execute)?;
+
+        if physical_address.valid == 0 {
+            break;
+        }
+
+        // Bytes are read individually so they are in memory order regardless of the
+        // processor's byte order
+        for offset in 0..page_end - page_address {
+            bytes.push(read_byte(
+                physical_memory,
+                physical_address.address + offset,
+            )?);
+        }
+
+        page_address = page_end;
+    }
+
+    if bytes.is_empty() {
+        Err(anyhow!("Address {address:#x} is not mapped"))
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Read up to `MAX_INSTRUCTION_SIZE` bytes of code at a virtual address
+pub(super) fn read_instruction_bytes(arch: &mut Architecture, address: u64) -> Result<Vec<u8>> {
+    read_code_bytes(arch, address, MAX_INSTRUCTION_SIZE)
+}
+
+/// Decode the instruction at `address`, returning it along with whether it ends a basic block
+fn decode_instruction(
+    arch: &mut Architecture,
+    address: u64,
+    hit: bool,
+) -> Result<(ReportInstruction, bool)> {
+    let bytes = read_instruction_bytes(arch, address)?;
+    let disassembler = arch.disassembler();
+    disassembler.disassemble(&bytes)?;
+    let len = disassembler
+        .last_len()
+        .filter(|l| *l > 0)
+        .ok_or_else(|| anyhow!("No length for instruction at {address:#x}"))?;
+    let ends_block = disassembler.last_was_control_flow()
+        || disassembler.last_was_call()
+        || disassembler.last_was_ret();
+    let disassembly = disassembler.disassemble_to_string(&bytes)?;
+
+    Ok((
+        ReportInstruction {
+            address,
+            bytes: bytes[..len.min(bytes.len())].to_vec(),
+            disassembly,
+            hit,
+        },
+        ends_block,
+    ))
+}
+
+impl Tsffs {
+    /// Look up the symbol name and offset into the symbol for an address on a processor, if any
+    /// symbol source is available
+    fn address_coverage_symbol(
+        &self,
+        processor_number: i32,
+        address: u64,
+    ) -> Option<(String, u64)> {
+        self.symbol_at(processor_number, address).map(|symbol| {
+            (
+                format!("{}!{}", symbol.module, symbol.name),
+                address - symbol.range.start,
+            )
+        })
+    }
+
+    /// Decode the hit blocks of a processor, grouping them into regions of nearby code and
+    /// filling small gaps between blocks with unhit instructions. Returns the regions and the
+    /// blocks which could not be read or decoded.
+    fn address_coverage_regions(
+        &mut self,
+        processor_number: i32,
+        blocks: &[u64],
+    ) -> Result<(Vec<Vec<ReportInstruction>>, Vec<(u64, Error)>)> {
+        let mut regions: Vec<Vec<ReportInstruction>> = Vec::new();
+        let mut unreadable = Vec::new();
+        // The end address of the last decoded block
+        let mut last_end: Option<u64> = None;
+
+        let arch = self
+            .processors
+            .get_mut(&processor_number)
+            .ok_or_else(|| anyhow!("No architecture for processor {processor_number}"))?;
+
+        for (index, block) in blocks.iter().enumerate() {
+            let next_block = blocks.get(index + 1).cloned();
+
+            match last_end {
+                // The block was entered in the middle of an instruction decoded as part of
+                // the previous block, which is already shown as hit
+                Some(end) if *block < end => continue,
+                // Fill the gap between the previous block and this one with unhit instructions
+                Some(end) if *block - end <= MAX_REGION_GAP => {
+                    let mut address = end;
+
+                    while address < *block {
+                        let Ok((instruction, _)) = decode_instruction(arch, address, false) else {
+                            break;
+                        };
+
+                        address += instruction.bytes.len() as u64;
+
+                        if let Some(region) = regions.last_mut() {
+                            region.push(instruction);
+                        }
+                    }
+                }
+                _ => regions.push(Vec::new()),
+            }
+
+            let mut address = *block;
+            let mut decoded = 0;
+
+            loop {
+                match decode_instruction(arch, address, true) {
+                    Ok((instruction, ends_block)) => {
+                        address += instruction.bytes.len() as u64;
+                        decoded += 1;
+
+                        if let Some(region) = regions.last_mut() {
+                            region.push(instruction);
+                        }
+
+                        if ends_block
+                            || decoded >= MAX_BLOCK_INSTRUCTIONS
+                            || next_block.is_some_and(|n| address >= n)
+                        {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        if decoded == 0 {
+                            unreadable.push((*block, e));
+                        }
+
+                        break;
+                    }
+                }
+            }
+
+            last_end = Some(address);
+        }
+
+        regions.retain(|r| !r.is_empty());
+
+        Ok((regions, unreadable))
+    }
+
+    /// Generate and save a text report of covered addresses on each processor, grouping the
+    /// start addresses of hit blocks into contiguous regions and annotating the disassembly of
+    /// each region with hit and unhit instructions and block hit counts. Blocks are recorded
+    /// by virtual address regardless of `coverage_identifier`. Code is read from memory at
+    /// the time the report is generated.
+    pub fn save_address_coverage_report(&mut self) -> Result<()> {
+        let mut blocks = BTreeMap::<i32, Vec<u64>>::new();
+
+        self.edge_hit_counts
+            .keys()
+            .for_each(|(processor_number, block)| {
+                blocks.entry(*processor_number).or_default().push(*block)
+            });
+
+        blocks.values_mut().for_each(|b| b.sort_unstable());
+
+        let mut report = String::new();
+        writeln!(report, "Address coverage report")?;
+
+        for (processor_number, blocks) in blocks {
+            let (regions, unreadable) = self.address_coverage_regions(processor_number, &blocks)?;
+
+            let total_instructions = regions.iter().map(|r| r.len()).sum::<usize>();
+            let hit_instructions = regions
+                .iter()
+                .flat_map(|r| r.iter())
+                .filter(|i| i.hit)
+                .count();
+
+            writeln!(report)?;
+            writeln!(report, "Processor {processor_number}")?;
+            writeln!(
+                report,
+                "Blocks: {}, Regions: {}, Instructions hit: {} / {}",
+                blocks.len(),
+                regions.len(),
+                hit_instructions,
+                total_instructions
+            )?;
+
+            for region in regions.iter() {
+                let (Some(first), Some(last)) = (region.first(), region.last()) else {
+                    continue;
+                };
+
+                writeln!(report)?;
+                writeln!(
+                    report,
+                    "Region {:#x}-{:#x} ({} / {} instructions hit)",
+                    first.address,
+                    last.address + last.bytes.len() as u64,
+                    region.iter().filter(|i| i.hit).count(),
+                    region.len()
+                )?;
+
+                let mut last_symbol = None;
+
+                for instruction in region {
+                    let symbol =
+                        self.address_coverage_symbol(processor_number, instruction.address);
+
+                    if let Some((name, offset)) = symbol.as_ref() {
+                        if last_symbol.as_ref() != Some(name) {
+                            writeln!(report, "  {name}+{offset:#x}:")?;
+                        }
+                    }
+
+                    last_symbol = symbol.map(|(name, _)| name);
+
+                    let hits = self
+                        .edge_hit_counts
+                        .get(&(processor_number, instruction.address))
+                        .map(|h| format!("[{h}]"))
+                        .unwrap_or_default();
+
+                    writeln!(
+                        report,
+                        "  {} {:#018x} {:>12} {:<32} {}",
+                        if instruction.hit { "+" } else { "-" },
+                        instruction.address,
+                        hits,
+                        instruction
+                            .bytes
+                            .iter()
+                            .map(|b| format!("{b:02x}"))
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        instruction.disassembly
+                    )?;
+                }
+            }
+
+            if !unreadable.is_empty() {
+                writeln!(report)?;
+                writeln!(report, "Unreadable blocks:")?;
+
+                for (block, e) in unreadable {
+                    writeln!(report, "  {block:#018x}: {e}")?;
+                }
+            }
+        }
+
+        if let Some(parent) = self.address_coverage_report_path.parent() {
+            if !parent.is_dir() {
+                create_dir_all(parent)?;
+            }
+        }
+
+        write(&self.address_coverage_report_path, report)?;
+
+        debug!(
+            self.as_conf_object(),
+            "Address coverage report saved to {}",
+            self.address_coverage_report_path.display()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Comparison logging for memory and string comparison routines, which compare buffers that
+//! instruction-level comparison logging cannot see
+
+use anyhow::{anyhow, bail, Error, Result};
+use libafl::prelude::CmpValues;
+use simics::{debug, AsConfObject, AttrValue, AttrValueType};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use super::{filter::parse_address, CmpType};
+use crate::{
+    arch::{Architecture, ArchitectureOperations, CallingConvention, PAGE_SIZE},
+    Tsffs,
+};
+
+/// The maximum number of bytes of each operand logged for a routine comparison
+pub(crate) const ROUTINE_OPERAND_SIZE: usize = 31;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
+/// The arguments and comparison semantics of a comparison routine
+pub(crate) enum CmplogRoutineKind {
+    /// `memcmp(a, b, n)`: Compares `n` bytes. Also covers `CompareMem` and
+    /// `RtlCompareMemory`.
+    Memcmp,
+    /// `strcmp(a, b)`: Compares NUL-terminated strings
+    Strcmp,
+    /// `strncmp(a, b, n)`: Compares NUL-terminated strings of at most `n` bytes
+    Strncmp,
+}
+
+impl CmplogRoutineKind {
+    const AS_STRING: &'static [(&'static str, Self)] = &[
+        ("memcmp", Self::Memcmp),
+        ("strcmp", Self::Strcmp),
+        ("strncmp", Self::Strncmp),
+    ];
+}
+
+impl FromStr for CmplogRoutineKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let as_string = Self::AS_STRING.iter().cloned().collect::<HashMap<_, _>>();
+
+        as_string.get(s).cloned().ok_or_else(|| {
+            anyhow!(
+                "Invalid comparison routine kind {}. Expected one of {}",
+                s,
+                Self::AS_STRING
+                    .iter()
+                    .map(|i| i.0)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+}
+
+impl Display for CmplogRoutineKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let to_string = Self::AS_STRING
+            .iter()
+            .map(|(k, v)| (v, k))
+            .collect::<HashMap<_, _>>();
+        if let Some(name) = to_string.get(self) {
+            write!(f, "{}", name)
+        } else {
+            panic!("Invalid state for enum");
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+/// The location of the entry point of a comparison routine
+pub(crate) enum CmplogRoutineLocation {
+    /// A virtual address
+    Address(u64),
+    /// A symbol, optionally in a specific module, resolved using debug info
+    Symbol {
+        module: Option<String>,
+        symbol: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+/// A comparison routine whose operands are logged on entry. Parsed from the form
+/// `<location>:<kind>[:<calling convention>]`, where the location is one of:
+///
+/// - `0xfffff80012345678`: A virtual address
+/// - `module.sys!Symbol`: A symbol in a module
+/// - `Symbol`: A symbol in any module
+pub(crate) struct CmplogRoutine {
+    pub location: CmplogRoutineLocation,
+    pub kind: CmplogRoutineKind,
+    pub convention: CallingConvention,
+}
+
+impl FromStr for CmplogRoutine {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().split(':');
+
+        let (Some(location), Some(kind)) = (parts.next(), parts.next()) else {
+            bail!("Invalid comparison routine {s}. Expected <location>:<kind>[:<convention>]");
+        };
+
+        let convention = parts
+            .next()
+            .map(|c| c.parse())
+            .transpose()?
+            .unwrap_or_default();
+
+        if parts.next().is_some() {
+            bail!("Invalid comparison routine {s}. Expected <location>:<kind>[:<convention>]");
+        }
+
+        let location = if location.starts_with(|c: char| c.is_ascii_digit()) {
+            CmplogRoutineLocation::Address(parse_address(location)?)
+        } else if let Some((module, symbol)) = location.split_once('!') {
+            CmplogRoutineLocation::Symbol {
+                module: Some(module.to_string()),
+                symbol: symbol.to_string(),
+            }
+        } else {
+            CmplogRoutineLocation::Symbol {
+                module: None,
+                symbol: location.to_string(),
+            }
+        };
+
+        Ok(Self {
+            location,
+            kind: kind.parse()?,
+            convention,
+        })
+    }
+}
+
+impl Display for CmplogRoutine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            CmplogRoutineLocation::Address(address) => write!(f, "{address:#x}")?,
+            CmplogRoutineLocation::Symbol {
+                module: Some(module),
+                symbol,
+            } => write!(f, "{module}!{symbol}")?,
+            CmplogRoutineLocation::Symbol {
+                module: None,
+                symbol,
+            } => write!(f, "{symbol}")?,
+        }
+
+        write!(f, ":{}:{}", self.kind, self.convention)
+    }
+}
+
+impl TryFrom<AttrValue> for CmplogRoutine {
+    type Error = Error;
+
+    fn try_from(value: AttrValue) -> Result<Self> {
+        String::try_from(value)?.parse()
+    }
+}
+
+impl From<CmplogRoutine> for AttrValueType {
+    fn from(value: CmplogRoutine) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl Tsffs {
+    /// Resolve the entry points of the configured comparison routines. Routines declared by
+    /// symbol are resolved using the debug info loaded for the start processor by OS awareness
+    /// or from `symbol_files`, so this is called again each time OS awareness information is
+    /// collected.
+    pub fn resolve_cmplog_routines(&mut self) {
+        let Some(processor_number) = self.start_processor_number.get().cloned() else {
+            return;
+        };
+
+        let mut resolved = HashMap::new();
+
+        for routine in self.cmplog_routines.iter() {
+            match &routine.location {
+                CmplogRoutineLocation::Address(address) => {
+                    resolved.insert(*address, routine.clone());
+                }
+                CmplogRoutineLocation::Symbol { module, symbol } => {
+                    for range in self.symbol_ranges(processor_number, module.as_deref(), symbol) {
+                        resolved.insert(range.start, routine.clone());
+                    }
+                }
+            }
+        }
+
+        debug!(
+            self.as_conf_object(),
+            "Resolved {} comparison routine entry points",
+            resolved.len()
+        );
+
+        self.cmplog_routine_addresses = resolved;
+    }
+
+    /// Read a comparison operand of at most `ROUTINE_OPERAND_SIZE` bytes from a processor.
+    /// String operands are read a page at a time and end at the first NUL byte or the first
+    /// unreadable page.
+    fn read_routine_operand(
+        &mut self,
+        processor_number: i32,
+        address: u64,
+        size: usize,
+        string: bool,
+    ) -> Result<Vec<u8>> {
+        let arch = self
+            .processors
+            .get_mut(&processor_number)
+            .ok_or_else(|| anyhow!("No architecture for processor {processor_number}"))?;
+        let size = size.min(ROUTINE_OPERAND_SIZE);
+
+        if !string {
+            return arch.read_virtual_memory(address, size);
+        }
+
+        let mut bytes = Vec::with_capacity(size);
+
+        while bytes.len() < size {
+            let start = address + bytes.len() as u64;
+            let page_end = (start & !(PAGE_SIZE - 1)) + PAGE_SIZE;
+            let len = ((page_end - start) as usize).min(size - bytes.len());
+
+            let Ok(chunk) = arch.read_virtual_memory(start, len) else {
+                break;
+            };
+
+            if let Some(nul) = chunk.iter().position(|b| *b == 0) {
+                bytes.extend_from_slice(&chunk[..nul]);
+                break;
+            }
+
+            bytes.extend(chunk);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Read the operands of a call to a comparison routine, returning `None` if the
+    /// comparison is empty
+    fn read_cmplog_routine_operands(
+        &mut self,
+        processor_number: i32,
+        routine: &CmplogRoutine,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        // Code running under Windows OS awareness uses the Microsoft x64 calling convention
+        let windows = self
+            .windows_os_info
+            .modules
+            .get(&processor_number)
+            .is_some_and(|modules| !modules.is_empty());
+
+        let arch = self
+            .processors
+            .get_mut(&processor_number)
+            .ok_or_else(|| anyhow!("No architecture for processor {processor_number}"))?;
+
+        let convention = match routine.convention {
+            CallingConvention::Default if windows && matches!(arch, Architecture::X86_64(_)) => {
+                CallingConvention::Win64
+            }
+            convention => convention,
+        };
+
+        let left = arch.function_argument(convention, 0)?;
+        let right = arch.function_argument(convention, 1)?;
+        let size = match routine.kind {
+            CmplogRoutineKind::Memcmp | CmplogRoutineKind::Strncmp => {
+                arch.function_argument(convention, 2)? as usize
+            }
+            CmplogRoutineKind::Strcmp => ROUTINE_OPERAND_SIZE,
+        };
+
+        if size == 0 {
+            return Ok(None);
+        }
+
+        let string = routine.kind != CmplogRoutineKind::Memcmp;
+        let left = self.read_routine_operand(processor_number, left, size, string)?;
+        let right = self.read_routine_operand(processor_number, right, size, string)?;
+
+        Ok(Some((left, right)))
+    }
+
+    /// Log the operands of a comparison routine if `pc` is the entry point of a configured
+    /// routine. `call_site` is the address of the call to the routine, which identifies the
+    /// comparison in the comparison map. Calls whose operands cannot be read are not logged.
+    pub fn log_cmplog_routine(
+        &mut self,
+        processor_number: i32,
+        call_site: u64,
+        pc: u64,
+    ) -> Result<()> {
+        let Some(routine) = self.cmplog_routine_addresses.get(&pc).cloned() else {
+            return Ok(());
+        };
+
+        match self.read_cmplog_routine_operands(processor_number, &routine) {
+            Ok(Some((left, right))) => self.log_cmp(
+                call_site,
+                vec![CmpType::Equal],
+                CmpValues::Bytes((left, right)),
+            ),
+            Ok(None) => Ok(()),
+            Err(e) => {
+                debug!(
+                    self.as_conf_object(),
+                    "Not logging comparison routine {routine} called from {call_site:#x}: {e}"
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CmplogRoutine, CmplogRoutineKind, CmplogRoutineLocation};
+    use crate::arch::CallingConvention;
+
+    #[test]
+    fn test_parse_cmplog_routine() {
+        assert_eq!(
+            "0xfffff80012345678:memcmp"
+                .parse::<CmplogRoutine>()
+                .expect("Failed to parse comparison routine"),
+            CmplogRoutine {
+                location: CmplogRoutineLocation::Address(0xfffff80012345678),
+                kind: CmplogRoutineKind::Memcmp,
+                convention: CallingConvention::Default,
+            }
+        );
+        assert_eq!(
+            " ntoskrnl.exe!RtlCompareMemory:memcmp:win64 "
+                .parse::<CmplogRoutine>()
+                .expect("Failed to parse comparison routine"),
+            CmplogRoutine {
+                location: CmplogRoutineLocation::Symbol {
+                    module: Some("ntoskrnl.exe".to_string()),
+                    symbol: "RtlCompareMemory".to_string(),
+                },
+                kind: CmplogRoutineKind::Memcmp,
+                convention: CallingConvention::Win64,
+            }
+        );
+        assert_eq!(
+            "strncmp:strncmp:sysv"
+                .parse::<CmplogRoutine>()
+                .expect("Failed to parse comparison routine"),
+            CmplogRoutine {
+                location: CmplogRoutineLocation::Symbol {
+                    module: None,
+                    symbol: "strncmp".to_string(),
+                },
+                kind: CmplogRoutineKind::Strncmp,
+                convention: CallingConvention::SysV64,
+            }
+        );
+
+        assert!("strcmp".parse::<CmplogRoutine>().is_err());
+        assert!("strcmp:strcmp:cdecl:extra"
+            .parse::<CmplogRoutine>()
+            .is_err());
+        assert!("strcmp:strcpy".parse::<CmplogRoutine>().is_err());
+        assert!("strcmp:strcmp:fastcall".parse::<CmplogRoutine>().is_err());
+        assert!("0xzz:memcmp".parse::<CmplogRoutine>().is_err());
+    }
+
+    #[test]
+    fn test_cmplog_routine_round_trip() {
+        for routine in [
+            "0x1000:memcmp:default",
+            "test.efi!CompareMem:memcmp:win64",
+            "strcmp:strcmp:cdecl",
+        ] {
+            let parsed = routine
+                .parse::<CmplogRoutine>()
+                .expect("Failed to parse comparison routine");
+            assert_eq!(parsed.to_string(), routine);
+            assert_eq!(
+                parsed
+                    .to_string()
+                    .parse::<CmplogRoutine>()
+                    .expect("Failed to parse comparison routine"),
+                parsed
+            );
+        }
+    }
+}
@@ -9,10 +9,27 @@ use anyhow::Result;
 pub trait TracerDisassembler {
     fn disassemble(&mut self, bytes: &[u8]) -> Result<()>;
     fn disassemble_to_string(&mut self, bytes: &[u8]) -> Result<String>;
+    /// The length in bytes of the last disassembled instruction, if any
+    fn last_len(&self) -> Option<usize>;
     fn last_was_control_flow(&self) -> bool;
     fn last_was_call(&self) -> bool;
     fn last_was_ret(&self) -> bool;
     fn last_was_cmp(&self) -> bool;
+    /// Whether the last instruction is arithmetic which sets flags that a following
+    /// conditional branch may compare
+    fn last_sets_flags(&self) -> bool {
+        false
+    }
+    /// Whether the last instruction is a conditional branch on flags set by an earlier
+    /// instruction
+    fn last_was_conditional(&self) -> bool {
+        false
+    }
+    /// Whether the last instruction is a jump to an address in a register or memory, as used
+    /// to dispatch through a jump table
+    fn last_was_indirect_jump(&self) -> bool {
+        false
+    }
     fn cmp(&self) -> Vec<CmpExpr>;
     fn cmp_type(&self) -> Vec<CmpType>;
 }
@@ -0,0 +1,106 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! The public SIMICS release does not include a MIPS processor model, so this test runs
+//! only when one is installed. Set `TSFFS_TEST_MIPS_PACKAGE` to the number of the package
+//! which provides the model and `TSFFS_TEST_MIPS_CPU_CLASS` to the class of the processor,
+//! then run the test with `cargo test -- --ignored`.
+
+use anyhow::{anyhow, ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use serde_json::Value;
+use simics_test::TestEnvSpec;
+use std::{
+    env::var,
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[ignore = "requires a MIPS processor model, which is not in the public SIMICS release"]
+fn test_mips_bare_magic_latest() -> Result<()> {
+    let package_number: isize = var("TSFFS_TEST_MIPS_PACKAGE")?.parse()?;
+    let cpu_class = var("TSFFS_TEST_MIPS_CPU_CLASS")?;
+    let log_path =
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_mips_bare_magic_latest-log.json");
+    remove_file(&log_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_mips_bare_magic_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(package_number)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("mips-bare")])
+        .build()
+        .to_env()?
+        .test(&formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 1000
+            @tsffs.log_path = "{log_path}"
+            @tsffs.heartbeat_interval = 0
+
+            @phys_mem = SIM_create_object("memory-space", "phys_mem", [])
+            @ram_image = SIM_create_object("image", "ram_image", [["size", 0x1000000]])
+            @ram = SIM_create_object("ram", "ram", [["image", ram_image]])
+            @phys_mem.map = [[0x0, ram, 0, 0, 0x1000000]]
+            @cpu = SIM_create_object("{cpu_class}", "cpu", [["physical_memory", phys_mem], ["freq_mhz", 100]])
+
+            load-binary phys_mem (lookup-file "%simics%/test-el.elf") -pa
+            @cpu.iface.processor_info.set_program_counter(0x80100000)
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, log_path = log_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    // The harness is run from the start snapshot on each iteration, and the edges of the
+    // password check are traced
+    let heartbeats = read_to_string(&log_path)?
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter_map(|m| m.get("Heartbeat").cloned())
+        .collect::<Vec<_>>();
+    let heartbeat = heartbeats
+        .last()
+        .ok_or_else(|| anyhow!("No heartbeat was logged"))?;
+    let iterations = heartbeat["iterations"].as_u64().unwrap_or_default();
+    let edges = heartbeat["edges"].as_u64().unwrap_or_default();
+
+    ensure!(
+        iterations > 1,
+        "The harness was not run repeatedly: {heartbeat}"
+    );
+    ensure!(edges > 0, "No edges were traced: {heartbeat}");
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzes the user-space test program built without PIE, so its code is at the default base
+//! address, and collects block coverage of the functions in its ELF symbols with breakpoints
+//! instead of instrumenting each instruction. Each block must be recorded exactly once across
+//! snapshot restores.
+
+use anyhow::{ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use simics_test::TestEnvSpec;
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_riscv_64_userspace_breakpoint_coverage_latest() -> Result<()> {
+    let report_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("test_riscv_64_userspace_breakpoint_coverage_latest-address-coverage.txt");
+    remove_file(&report_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_riscv_64_userspace_breakpoint_coverage_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2050)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2053)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("riscv-64")])
+        .build()
+        .to_env()?
+        .test(formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.exceptions = [14]
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 1000
+            @tsffs.breakpoint_coverage = True
+            @tsffs.symbol_files = ["%simics%/test-no-pie"]
+            @tsffs.coverage_include_ranges = ["test-no-pie"]
+            @tsffs.address_coverage_report = True
+            @tsffs.address_coverage_report_path = "{report_path}"
+
+            load-target "risc-v-simple/linux" namespace = riscv machine:hardware:storage:disk1:image = "test.fs.craff"
+
+            script-branch {{
+                bp.time.wait-for seconds = 15
+                board.console.con.input "mkdir /mnt/disk0\r\n"
+                bp.time.wait-for seconds = 1.0
+                board.console.con.input "mount /dev/vdb /mnt/disk0\r\n"
+                bp.time.wait-for seconds = 1.0
+                board.console.con.capture-start out.txt
+                board.console.con.input "/mnt/disk0/test-no-pie\r\n"
+            }}
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, report_path = report_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    ensure!(
+        output_str.contains("Coverage breakpoints are kept when the snapshot is restored")
+            || output_str
+                .contains("Coverage breakpoints are removed when the snapshot is restored"),
+        "Coverage breakpoints were not checked after restoring the snapshot"
+    );
+
+    let report = read_to_string(&report_path)?;
+    let mut symbols = HashSet::new();
+    let mut symbol = None;
+    let mut blocks = 0;
+
+    for line in report.lines().map(str::trim) {
+        if line.ends_with(':') && line.contains('!') {
+            symbol = line.split('+').next();
+        } else if let Some(instruction) = line.strip_prefix("+ ") {
+            ensure!(
+                symbol.is_some_and(|s| s.starts_with("test-no-pie!")),
+                "Instruction outside of test-no-pie was covered: {line}"
+            );
+            symbols.extend(symbol);
+
+            // Each block is recorded once, when its breakpoint is hit and removed
+            if let Some(hits) = instruction
+                .split_whitespace()
+                .nth(1)
+                .filter(|h| h.starts_with('['))
+            {
+                ensure!(hits == "[1]", "Block was recorded more than once: {line}");
+                blocks += 1;
+            }
+        }
+    }
+
+    ensure!(blocks > 0, "No blocks were covered:\n{report}");
+    ensure!(
+        symbols.contains("test-no-pie!main"),
+        "No blocks in main were covered:\n{report}"
+    );
+
+    Ok(())
+}
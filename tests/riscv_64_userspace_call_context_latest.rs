@@ -0,0 +1,109 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{anyhow, ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use serde_json::Value;
+use simics_test::TestEnvSpec;
+use std::{
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_riscv_64_userspace_call_context_latest() -> Result<()> {
+    let log_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("test_riscv_64_userspace_call_context_latest-log.json");
+    remove_file(&log_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_riscv_64_userspace_call_context_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2050)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2053)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("riscv-64")])
+        .build()
+        .to_env()?
+        .test(formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.exceptions = [14]
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 1000
+            @tsffs.coverage_mode = "call-context"
+            @tsffs.log_path = "{log_path}"
+            @tsffs.coverage_reporting = True
+            @tsffs.heartbeat_interval = 0
+
+            load-target "risc-v-simple/linux" namespace = riscv machine:hardware:storage:disk1:image = "test.fs.craff"
+
+            script-branch {{
+                bp.time.wait-for seconds = 15
+                board.console.con.input "mkdir /mnt/disk0\r\n"
+                bp.time.wait-for seconds = 1.0
+                board.console.con.input "mount /dev/vdb /mnt/disk0\r\n"
+                bp.time.wait-for seconds = 1.0
+                board.console.con.capture-start out.txt
+                board.console.con.input "/mnt/disk0/test\r\n"
+            }}
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, log_path = log_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    // Each heartbeat counts the unique (source, target) edges and the coverage map entries
+    // assigned to edges, which are also distinguished by their calling context
+    let heartbeats = read_to_string(&log_path)?
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter_map(|m| m.get("Heartbeat").cloned())
+        .collect::<Vec<_>>();
+    let heartbeat = heartbeats
+        .last()
+        .ok_or_else(|| anyhow!("No heartbeat was logged"))?;
+    let edges = heartbeat["edges"].as_u64().unwrap_or_default();
+    let contexts = heartbeat["coverage_map"]["edges"]
+        .as_u64()
+        .unwrap_or_default();
+
+    ensure!(edges > 0, "No edges were seen: {heartbeat}");
+    ensure!(
+        contexts > edges,
+        "No edge was seen in more than one calling context: {heartbeat}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzes the user-space test program built without PIE, so its code is at the default base
+//! address, and counts coverage only for the code of the program itself, not the kernel or
+//! shared libraries. The program is found by its ELF symbols, and `main` is excluded so only
+//! `check` and the stubs it calls are covered.
+
+use anyhow::{ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use simics_test::TestEnvSpec;
+use std::{
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_riscv_64_userspace_coverage_ranges_latest() -> Result<()> {
+    let report_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("test_riscv_64_userspace_coverage_ranges_latest-address-coverage.txt");
+    remove_file(&report_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_riscv_64_userspace_coverage_ranges_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2050)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2053)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("riscv-64")])
+        .build()
+        .to_env()?
+        .test(formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.exceptions = [14]
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 1000
+            @tsffs.symbol_files = ["%simics%/test-no-pie"]
+            @tsffs.coverage_include_ranges = ["0x10000-0x20000", "test-no-pie"]
+            @tsffs.coverage_exclude_ranges = ["test-no-pie!main"]
+            @tsffs.address_coverage_report = True
+            @tsffs.address_coverage_report_path = "{report_path}"
+
+            load-target "risc-v-simple/linux" namespace = riscv machine:hardware:storage:disk1:image = "test.fs.craff"
+
+            script-branch {{
+                bp.time.wait-for seconds = 15
+                board.console.con.input "mkdir /mnt/disk0\r\n"
+                bp.time.wait-for seconds = 1.0
+                board.console.con.input "mount /dev/vdb /mnt/disk0\r\n"
+                bp.time.wait-for seconds = 1.0
+                board.console.con.capture-start out.txt
+                board.console.con.input "/mnt/disk0/test-no-pie\r\n"
+            }}
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, report_path = report_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    let report = read_to_string(&report_path)?;
+    let mut symbol = None;
+    let mut check_hit = false;
+
+    for line in report.lines().map(str::trim) {
+        if line.ends_with(':') && line.contains('!') {
+            symbol = line.split('+').next();
+        } else if let Some(instruction) = line.strip_prefix("+ ") {
+            let address = instruction
+                .split_whitespace()
+                .next()
+                .and_then(|a| u64::from_str_radix(a.trim_start_matches("0x"), 16).ok());
+
+            ensure!(
+                address.is_some_and(|a| (0x10000..0x20000).contains(&a)),
+                "Instruction outside the included range was covered: {line}"
+            );
+            ensure!(
+                symbol != Some("test-no-pie!main"),
+                "Instruction in an excluded symbol was covered: {line}"
+            );
+
+            check_hit |= symbol == Some("test-no-pie!check");
+        }
+    }
+
+    ensure!(
+        check_hit,
+        "No instructions in check were covered:\n{report}"
+    );
+
+    Ok(())
+}
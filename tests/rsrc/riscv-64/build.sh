@@ -39,6 +39,9 @@ docker cp \
 docker cp \
     "${CONTAINER_NAME}:/test/usr/test" \
     "${SCRIPT_DIR}/test"
+docker cp \
+    "${CONTAINER_NAME}:/test/usr/test-no-pie" \
+    "${SCRIPT_DIR}/test-no-pie"
 docker cp \
     "${CONTAINER_NAME}:/test/usr/test-mod" \
     "${SCRIPT_DIR}/test-mod"
@@ -58,5 +61,6 @@ mkfs.fat test.fs
 mcopy -i test.fs test-mod-userspace ::test-mod-userspace
 mcopy -i test.fs test-mod ::test-mod
 mcopy -i test.fs test ::test
+mcopy -i test.fs test-no-pie ::test-no-pie
 mcopy -i test.fs test-mod.ko ::test-mod.ko
 "${CRAFF}" -o test.fs.craff test.fs
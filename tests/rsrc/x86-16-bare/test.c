@@ -0,0 +1,44 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include "tsffs.h"
+
+const char *password = "fuzzing!";
+
+int check(unsigned char *buffer, size_t size) {
+  if (size < 8) {
+    return 0;
+  }
+
+  for (size_t i = 0; i < 8; i++) {
+    if (buffer[i] != password[i]) {
+      return 0;
+    }
+  }
+
+  return 1;
+}
+
+int main(void) {
+  // We have a size and a buffer of that size. The address of the buffer and the
+  // address of the size variable will be passed to the fuzzer. On each iteration
+  // of the fuzzer, up to the initial size bytes of fuzzer input data will be
+  // written to the buffer, and the current testcase size in bytes will be written
+  // to the size variable. Pointers in 16-bit code are offsets into the data
+  // segment.
+  unsigned char buffer[8] = {'A', 'A', 'A', 'A', 'A', 'A', 'A', 'A'};
+  size_t size = sizeof(buffer);
+
+  HARNESS_START(buffer, &size);
+
+  if (check(buffer, size)) {
+    HARNESS_ASSERT();
+  }
+
+  HARNESS_STOP();
+
+  return 0;
+}
@@ -0,0 +1,131 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use simics_test::TestEnvSpec;
+use std::{
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_x86_64_magic_address_report_latest() -> Result<()> {
+    let report_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("test_x86_64_magic_address_report_latest-address-coverage.txt");
+    remove_file(&report_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_x86_64_magic_address_report_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(1030)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2096)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(8112)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("x86_64-uefi")])
+        .build()
+        .to_env()?
+        .test(formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.exceptions = [14]
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 100
+            @tsffs.coverage_reporting = True
+            @tsffs.address_coverage_report = True
+            @tsffs.address_coverage_report_path = "{report_path}"
+            @tsffs.coverage_identifier = "physical"
+            @tsffs.corpus_directory = "%simics%/corpus2"
+
+            load-target "qsp-x86/uefi-shell" namespace = qsp machine:hardware:storage:disk0:image = "minimal_boot_disk.craff"
+
+            script-branch {{
+                bp.time.wait-for seconds = 15
+                qsp.serconsole.con.input "\n"
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "FS0:\n"
+                bp.time.wait-for seconds = .5
+                local $manager = (start-agent-manager)
+                qsp.serconsole.con.input ("SimicsAgent.efi --download " + (lookup-file "%simics%/test-cov.efi") + "\n")
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "test-cov.efi\n"
+            }}
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, report_path = report_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    let report = read_to_string(&report_path)?;
+    let mut lines = report.lines();
+
+    ensure!(
+        lines.next() == Some("Address coverage report"),
+        "No report header in:\n{report}"
+    );
+    ensure!(
+        report.lines().any(|l| l.starts_with("Processor ")),
+        "No processor section in:\n{report}"
+    );
+    ensure!(
+        report.lines().any(|l| l.starts_with("Region 0x")),
+        "No regions in:\n{report}"
+    );
+
+    // Blocks are recorded by virtual address even though coverage is identified by physical
+    // address, so each hit block is disassembled and has a hit count
+    let hit_instructions = report
+        .lines()
+        .filter(|l| l.trim_start().starts_with("+ 0x"))
+        .collect::<Vec<_>>();
+
+    ensure!(
+        !hit_instructions.is_empty(),
+        "No hit instructions in:\n{report}"
+    );
+    ensure!(
+        hit_instructions.iter().any(|l| l
+            .split_whitespace()
+            .nth(2)
+            .is_some_and(|h| h.starts_with('['))),
+        "No hit counts in:\n{report}"
+    );
+    ensure!(
+        !report.contains("Unreadable blocks:"),
+        "Blocks could not be decoded in:\n{report}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use serde_json::Value;
+use simics_test::TestEnvSpec;
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_x86_64_magic_attribution_latest() -> Result<()> {
+    let attribution_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("test_x86_64_magic_attribution_latest-coverage-attribution.jsonl");
+    remove_file(&attribution_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_x86_64_magic_attribution_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(1030)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2096)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(8112)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("x86_64-uefi")])
+        .build()
+        .to_env()?
+        .test(formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.exceptions = [14]
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 100
+            @tsffs.coverage_reporting = True
+            @tsffs.coverage_attribution = True
+            @tsffs.coverage_attribution_path = "{attribution_path}"
+            @tsffs.corpus_directory = "%simics%/corpus2"
+
+            load-target "qsp-x86/uefi-shell" namespace = qsp machine:hardware:storage:disk0:image = "minimal_boot_disk.craff"
+
+            script-branch {{
+                bp.time.wait-for seconds = 15
+                qsp.serconsole.con.input "\n"
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "FS0:\n"
+                bp.time.wait-for seconds = .5
+                local $manager = (start-agent-manager)
+                qsp.serconsole.con.input ("SimicsAgent.efi --download " + (lookup-file "%simics%/test-cov.efi") + "\n")
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "test-cov.efi\n"
+            }}
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, attribution_path = attribution_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    let records = read_to_string(&attribution_path)?
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let edges = records
+        .iter()
+        .filter_map(|r| r.get("Edge"))
+        .collect::<Vec<_>>();
+
+    ensure!(!edges.is_empty(), "No edges were attributed");
+
+    let mut seen = HashSet::new();
+
+    for edge in edges {
+        let key = edge["source"].as_u64().zip(edge["pc"].as_u64());
+        ensure!(
+            key.is_some_and(|key| seen.insert(key)),
+            "Edge attributed twice: {edge}"
+        );
+        ensure!(edge["afl_idx"].is_u64(), "Edge has no AFL index: {edge}");
+        ensure!(
+            edge["input"].as_str().is_some_and(|i| !i.is_empty()),
+            "Edge has no input: {edge}"
+        );
+        ensure!(
+            ["Corpus", "Solution", "Timeout"].contains(&edge["kind"].as_str().unwrap_or_default()),
+            "Edge has no input kind: {edge}"
+        );
+        ensure!(
+            edge["iteration"].as_u64().is_some_and(|i| i < 100),
+            "Edge attributed outside the fuzzing iterations: {edge}"
+        );
+    }
+
+    Ok(())
+}
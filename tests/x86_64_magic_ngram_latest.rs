@@ -0,0 +1,116 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{anyhow, ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use serde_json::Value;
+use simics_test::TestEnvSpec;
+use std::{
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_x86_64_magic_ngram_latest() -> Result<()> {
+    let log_path =
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_x86_64_magic_ngram_latest-log.json");
+    remove_file(&log_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_x86_64_magic_ngram_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(1030)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2096)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(8112)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("x86_64-uefi")])
+        .build()
+        .to_env()?
+        .test(formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.exceptions = [14]
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 1000
+            @tsffs.coverage_mode = "ngram"
+            @tsffs.coverage_ngram_size = 4
+            @tsffs.log_path = "{log_path}"
+            @tsffs.coverage_reporting = True
+            @tsffs.heartbeat_interval = 0
+
+            load-target "qsp-x86/uefi-shell" namespace = qsp machine:hardware:storage:disk0:image = "minimal_boot_disk.craff"
+
+            script-branch {{
+                bp.time.wait-for seconds = 15
+                qsp.serconsole.con.input "\n"
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "FS0:\n"
+                bp.time.wait-for seconds = .5
+                local $manager = (start-agent-manager)
+                qsp.serconsole.con.input ("SimicsAgent.efi --download " + (lookup-file "%simics%/test.efi") + "\n")
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "test.efi\n"
+            }}
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, log_path = log_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    // Each heartbeat counts the unique (source, target) edges and the coverage map entries
+    // assigned to edges, which are also distinguished by the previous locations
+    let heartbeats = read_to_string(&log_path)?
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter_map(|m| m.get("Heartbeat").cloned())
+        .collect::<Vec<_>>();
+    let heartbeat = heartbeats
+        .last()
+        .ok_or_else(|| anyhow!("No heartbeat was logged"))?;
+    let edges = heartbeat["edges"].as_u64().unwrap_or_default();
+    let contexts = heartbeat["coverage_map"]["edges"]
+        .as_u64()
+        .unwrap_or_default();
+
+    ensure!(edges > 0, "No edges were seen: {heartbeat}");
+    ensure!(
+        contexts > edges,
+        "No edge was seen after more than one sequence of locations: {heartbeat}"
+    );
+
+    Ok(())
+}
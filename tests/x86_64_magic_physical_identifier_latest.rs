@@ -0,0 +1,126 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use serde_json::Value;
+use simics_test::TestEnvSpec;
+use std::{
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+/// The widest physical address x86_64 supports, in bits
+const PHYSICAL_ADDRESS_BITS: u32 = 52;
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_x86_64_magic_physical_identifier_latest() -> Result<()> {
+    let log_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("test_x86_64_magic_physical_identifier_latest-log.json");
+    remove_file(&log_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_x86_64_magic_physical_identifier_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(1030)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2096)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(8112)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("x86_64-uefi")])
+        .build()
+        .to_env()?
+        .test(formatdoc! {r#"
+            load-module tsffs
+            init-tsffs
+
+            @tsffs.log_level = 2
+            @tsffs.start_on_harness = True
+            @tsffs.stop_on_harness = True
+            @tsffs.timeout = 3.0
+            @tsffs.exceptions = [14]
+            @tsffs.generate_random_corpus = True
+            @tsffs.iteration_limit = 100
+            @tsffs.coverage_reporting = True
+            @tsffs.coverage_identifier = "physical"
+            @tsffs.corpus_directory = "%simics%/corpus2"
+            @tsffs.log_path = "{log_path}"
+
+            load-target "qsp-x86/uefi-shell" namespace = qsp machine:hardware:storage:disk0:image = "minimal_boot_disk.craff"
+
+            script-branch {{
+                bp.time.wait-for seconds = 15
+                qsp.serconsole.con.input "\n"
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "FS0:\n"
+                bp.time.wait-for seconds = .5
+                local $manager = (start-agent-manager)
+                qsp.serconsole.con.input ("SimicsAgent.efi --download " + (lookup-file "%simics%/test-cov.efi") + "\n")
+                bp.time.wait-for seconds = .5
+                qsp.serconsole.con.input "test-cov.efi\n"
+            }}
+
+            script-branch {{
+                bp.time.wait-for seconds = 240
+                quit 1
+            }}
+
+            run
+        "#, log_path = log_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    // Edges are reported by the physical addresses of their source and target, which fit
+    // in the physical address width, unlike module identifiers, which are hashes
+    let edges = read_to_string(&log_path)?
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter_map(|m| m.get("Interesting").cloned())
+        .flat_map(|m| {
+            m["message"]["edges"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    ensure!(!edges.is_empty(), "No edges were reported");
+
+    for edge in edges {
+        let physical = |address: &Value| {
+            address
+                .as_u64()
+                .is_some_and(|a| a >> PHYSICAL_ADDRESS_BITS == 0)
+        };
+
+        ensure!(
+            physical(&edge["source"]) && physical(&edge["pc"]) && edge["pc"] != 0,
+            "Edge is not identified by physical addresses: {edge}"
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+// Copyright (C) 2024 Intel Corporation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzes an SMM handler of the QSP UEFI firmware through its SMM communication buffer. The
+//! buffer, the handler, and the SMI command depend on the firmware build, so set
+//! `TSFFS_TEST_SMM_BUFFER` to the physical address of the communication buffer,
+//! `TSFFS_TEST_SMM_GUID` to the GUID of the handler, `TSFFS_TEST_SMM_APM_COMMAND` to the APM
+//! command which triggers SMM communication, and `TSFFS_TEST_SMRAM_RANGE` to the SMRAM range
+//! (for example `0x7f000000-0x80000000`), then run the test with `cargo test -- --ignored`.
+
+use anyhow::{anyhow, ensure, Result};
+use indoc::formatdoc;
+use ispm_wrapper::data::ProjectPackage;
+use serde_json::Value;
+use simics_test::TestEnvSpec;
+use std::{
+    env::var,
+    fs::{read_to_string, remove_file},
+    path::PathBuf,
+};
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[ignore = "requires the SMM communication buffer, handler GUID, and APM command of the firmware"]
+fn test_x86_64_smm_latest() -> Result<()> {
+    let buffer = var("TSFFS_TEST_SMM_BUFFER")?;
+    let guid = var("TSFFS_TEST_SMM_GUID")?;
+    let apm_command = var("TSFFS_TEST_SMM_APM_COMMAND")?;
+    let smram_range = var("TSFFS_TEST_SMRAM_RANGE")?;
+    let log_path =
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_x86_64_smm_latest-log.json");
+    remove_file(&log_path).ok();
+
+    let output = TestEnvSpec::builder()
+        .name("test_x86_64_smm_latest")
+        .package_crates([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .packages([
+            ProjectPackage::builder()
+                .package_number(1000)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(1030)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(2096)
+                .version("latest")
+                .build(),
+            ProjectPackage::builder()
+                .package_number(8112)
+                .version("latest")
+                .build(),
+        ])
+        .cargo_target_tmpdir(env!("CARGO_TARGET_TMPDIR"))
+        .directories([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rsrc")
+            .join("x86_64-uefi")])
+        .build()
+        .to_env()?
+        .test_python(&formatdoc! {r#"
+            import cli
+            import simics
+
+            simics.SIM_load_module("tsffs")
+
+            tsffs = simics.SIM_create_object(simics.SIM_get_class("tsffs"), "tsffs", [])
+            simics.SIM_set_log_level(tsffs, 2)
+            tsffs.start_on_harness = False
+            tsffs.stop_on_harness = False
+            tsffs.timeout = 3.0
+            tsffs.generate_random_corpus = True
+            tsffs.iteration_limit = 1000
+            tsffs.smram_ranges = ["{smram_range}"]
+            tsffs.log_path = "{log_path}"
+            tsffs.heartbeat_interval = 0
+
+            simics.SIM_load_target(
+                "qsp-x86/uefi-shell",  # Target
+                "qsp",  # Namespace
+                [],  # Presets
+                [  # Cmdline args
+                    ["machine:hardware:storage:disk0:image", "minimal_boot_disk.craff"],
+                    ["machine:hardware:processor:class", "x86-goldencove-server"],
+                ],
+            )
+
+
+            def start_script_branch():
+                # Wait for the firmware to initialize SMM and boot to the UEFI shell
+                cli.global_cmds.wait_for_global_time(seconds=15.0, _relative=True)
+                print("Starting SMM fuzzing...")
+                tsffs.iface.fuzz.start_smm(
+                    conf.qsp.mb.cpu0.core[0][0],
+                    {buffer},
+                    0x1000,
+                    "{guid}",
+                    {apm_command},
+                )
+
+
+            def exit_script_branch():
+                cli.global_cmds.wait_for_global_time(seconds=240.0, _relative=True)
+                simics.SIM_quit(1)
+
+
+            cli.sb_create(start_script_branch)
+            cli.sb_create(exit_script_branch)
+
+            simics.SIM_continue(0)
+            # NOTE: If running from CLI, omit this!
+            simics.SIM_main_loop()
+        "#, log_path = log_path.display()})?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    println!("{output_str}");
+
+    // Each iteration ends when the handler executes RSM, and only code executed in SMM is
+    // traced, so the edges seen are edges of SMM code
+    let heartbeats = read_to_string(&log_path)?
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter_map(|m| m.get("Heartbeat").cloned())
+        .collect::<Vec<_>>();
+    let heartbeat = heartbeats
+        .last()
+        .ok_or_else(|| anyhow!("No heartbeat was logged"))?;
+    let iterations = heartbeat["iterations"].as_u64().unwrap_or_default();
+    let edges = heartbeat["edges"].as_u64().unwrap_or_default();
+
+    ensure!(
+        iterations > 1,
+        "The SMM handler was not run repeatedly: {heartbeat}"
+    );
+    ensure!(edges > 0, "No SMM code was traced: {heartbeat}");
+
+    Ok(())
+}